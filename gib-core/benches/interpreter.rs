@@ -0,0 +1,32 @@
+//! Interpreter throughput benchmarks, run with `cargo bench -p gib-core`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use gib_core::GameBoy;
+
+static CPU_INSTRS: &[u8] = include_bytes!("../../roms/blargg/cpu_instrs.gb");
+
+/// Boots the given ROM and lets it run for a while, to get past the initialization code.
+fn booted(rom: &[u8]) -> GameBoy {
+    let mut gb = GameBoy::new();
+    gb.load_rom(rom).unwrap();
+
+    for _ in 0..10 {
+        gb.run_for_vblank().unwrap();
+    }
+    gb
+}
+
+fn run_frame(c: &mut Criterion) {
+    let mut gb = booted(CPU_INSTRS);
+
+    c.bench_function("run_frame", |b| b.iter(|| gb.run_for_vblank().unwrap()));
+}
+
+fn step_instruction(c: &mut Criterion) {
+    let mut gb = booted(CPU_INSTRS);
+
+    c.bench_function("step_instruction", |b| b.iter(|| gb.step().unwrap()));
+}
+
+criterion_group!(benches, run_frame, step_instruction);
+criterion_main!(benches);
@@ -0,0 +1,95 @@
+//! Generates the instruction dispatch tables used by the CPU interpreter.
+//!
+//! Every opcode gets its own handler, which calls into `CPU::op`/`CPU::op_cb` with a constant
+//! opcode: once inlined, only the relevant match arm survives and executing an instruction
+//! boils down to an indirect call through the table. The metadata for CB-prefixed opcodes is
+//! generated here as well, since it follows a regular pattern.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+const CB_REGS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const CB_SHIFTS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const CB_BIT_OPS: [&str; 3] = ["BIT", "RES", "SET"];
+
+fn cb_mnemonic(op: usize) -> String {
+    let reg = CB_REGS[op & 0x7];
+
+    if op < 0x40 {
+        format!("{} {}", CB_SHIFTS[op >> 3], reg)
+    } else {
+        format!("{} {},{}", CB_BIT_OPS[(op >> 6) - 1], (op >> 3) & 0x7, reg)
+    }
+}
+
+fn cb_cycles(op: usize) -> u8 {
+    // (HL) operands take an extra read, and an extra write unless the opcode is BIT
+    match (op & 0x7, op >> 6) {
+        (6, 1) => 12,
+        (6, _) => 16,
+        _ => 8,
+    }
+}
+
+fn main() {
+    let mut out = String::new();
+
+    out.push_str("// Generated by build.rs, do not edit.\n\n");
+
+    // Opcode handlers
+    for op in 0..256 {
+        writeln!(
+            out,
+            "fn op_{0:02x}(cpu: &mut CPU) -> Result<(), dbg::TraceEvent> {{ cpu.op(0x{0:02X}) }}",
+            op
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "fn op_cb_{0:02x}(cpu: &mut CPU) -> Result<(), dbg::TraceEvent> {{ cpu.op_cb(0x{0:02X}) }}",
+            op
+        )
+        .unwrap();
+    }
+
+    // Dispatch tables
+    out.push_str("\n/// Handlers of the base opcodes, indexed by opcode.\n");
+    out.push_str("pub static OP_HANDLERS: [OpHandler; 256] = [\n");
+    for op in 0..256 {
+        writeln!(out, "    op_{:02x},", op).unwrap();
+    }
+    out.push_str("];\n");
+
+    out.push_str("\n/// Handlers of the CB-prefixed opcodes, indexed by the byte following the prefix.\n");
+    out.push_str("pub static CB_HANDLERS: [OpHandler; 256] = [\n");
+    for op in 0..256 {
+        writeln!(out, "    op_cb_{:02x},", op).unwrap();
+    }
+    out.push_str("];\n");
+
+    // CB-prefixed opcodes metadata
+    out.push_str("\n/// Metadata of the CB-prefixed opcodes. Size and cycles include the prefix.\n");
+    out.push_str("pub const CB_OPCODES: [OpcodeInfo; 256] = [\n");
+    for op in 0..256 {
+        let loc = if op & 0x7 == 6 { "Memory(HL)" } else { "Register" };
+
+        writeln!(
+            out,
+            "    OpcodeInfo({:?}, {}, {}, 2, {}, {}),",
+            cb_mnemonic(op),
+            loc,
+            loc,
+            cb_cycles(op),
+            cb_cycles(op)
+        )
+        .unwrap();
+    }
+    out.push_str("];\n");
+
+    let dest = Path::new(&env::var("OUT_DIR").unwrap()).join("dispatch.rs");
+    fs::write(dest, out).unwrap();
+
+    println!("cargo:rerun-if-changed=build.rs");
+}
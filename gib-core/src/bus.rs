@@ -1,66 +1,114 @@
+use super::cart::{self, Cartridge, NoMbc};
+use super::cheat::Cheats;
+use super::config::RamFill;
 use super::dbg;
 use super::io::{InterruptSource, IrqController, Joypad, Serial, Timer, APU, PPU};
-use super::mem::{MemR, MemRW, MemW, Memory};
+use super::mem::{BankedMemory, MemR, MemRW, MemW, Memory};
 
-use std::convert::TryFrom;
-
-pub enum MbcType {
-    None,
-    MBC1,
-}
-
-pub struct McbTypeError(u8);
-
-impl TryFrom<u8> for MbcType {
-    type Error = McbTypeError;
-
-    fn try_from(n: u8) -> Result<Self, Self::Error> {
-        match n {
-            0x00 => Ok(MbcType::None),
-            0x01..=0x03 => Ok(MbcType::MBC1),
-            _ => Err(McbTypeError(n)),
-        }
-    }
+/// How the bus handles accesses to unmapped memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusPolicy {
+    /// Report accesses to unmapped memory as bus faults, useful for debugging.
+    Strict,
+    /// Behave like hardware: reads return 0xFF (open bus) and writes are ignored.
+    Lenient,
 }
 
 pub struct Bus {
-    rom_banks: Vec<Memory>,
-    pub rom_nn: usize,
+    pub cart: Box<dyn Cartridge>,
+    boot_rom: Option<Vec<u8>>,
+
+    /// Whether CGB functions are available to the cartridge
+    pub cgb: bool,
 
-    pub eram: Memory,
     pub hram: Memory,
     pub wram_00: Memory,
-    pub wram_nn: Memory,
+    pub wram_nn: BankedMemory,
+    svbk: u8,
+
+    /// Whether the CPU runs at double speed (CGB only)
+    pub double_speed: bool,
+    key1_prepare: bool,
+    ppu_phase: bool,
 
     pub apu: APU,
+    apu_pending: u32,
     pub ppu: PPU,
     pub tim: Timer,
     pub sdt: Serial,
     pub joy: Joypad,
     pub itr: IrqController,
 
-    mbc: MbcType,
+    /// Report CPU accesses outside HRAM during OAM DMA transfers as trace events
+    pub trap_dma_conflicts: bool,
+
+    /// Report CPU accesses to VRAM/OAM while locked by the PPU as trace events
+    pub trap_ppu_conflicts: bool,
+
+    /// Report bank selects beyond the size of the cartridge memory as trace events
+    pub trap_mbc_ops: bool,
+
+    /// Report writes to CGB registers which are not emulated, in CGB mode, as trace events
+    pub trap_cgb_ops: bool,
+
+    /// Emulate the OAM corruption bug of monochrome models
+    pub oam_bug: bool,
+
+    /// Handling of accesses to unmapped memory
+    pub policy: BusPolicy,
+
+    /// Memory locations monitored by the debugger
+    pub watchpoints: dbg::Watchpoints,
+
+    /// Log of CPU accesses to IO registers, if enabled
+    pub io_log: Option<dbg::IoLog>,
+
+    /// Trace being streamed, which also collects accesses to IO registers, if enabled
+    pub trace_stream: Option<dbg::TraceStream>,
+
+    /// Cheat codes patching ROM reads and RAM
+    pub cheats: Cheats,
 }
 
 impl Default for Bus {
     fn default() -> Bus {
         Bus {
-            rom_banks: vec![],
-            rom_nn: 1,
+            cart: Box::new(NoMbc::default()),
+            boot_rom: None,
+
+            cgb: false,
 
-            eram: Memory::new(0x2000),
             hram: Memory::new(127),
             wram_00: Memory::new(0x1000),
-            wram_nn: Memory::new(0x1000),
+            wram_nn: BankedMemory::new(0x1000, 7),
+            svbk: 0x00,
+
+            double_speed: false,
+            key1_prepare: false,
+            ppu_phase: false,
 
             apu: APU::default(),
+            apu_pending: 0,
             ppu: PPU::new(),
             tim: Timer::new(),
             sdt: Serial::new(),
             joy: Joypad::new(),
             itr: IrqController::new(),
 
-            mbc: MbcType::None,
+            trap_dma_conflicts: false,
+            trap_ppu_conflicts: false,
+            trap_mbc_ops: false,
+            trap_cgb_ops: false,
+            oam_bug: false,
+
+            policy: BusPolicy::Lenient,
+
+            watchpoints: dbg::Watchpoints::new(),
+
+            io_log: None,
+            trace_stream: None,
+
+            cheats: Cheats::new(),
         }
     }
 }
@@ -71,32 +119,135 @@ impl Bus {
     }
 
     pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), dbg::TraceEvent> {
-        for chunk in rom.chunks(0x4000) {
-            let mut mem = Memory::new(0x4000);
+        self.cart = cart::from_rom(rom)?;
+        let cgb = cart::Header::parse(rom)?.cgb;
+        self.set_cgb_mode(cgb);
+        Ok(())
+    }
+
+    /// Makes CGB functions available to the cartridge or not.
+    pub fn set_cgb_mode(&mut self, cgb: bool) {
+        self.cgb = cgb;
+        self.ppu.set_cgb_mode(cgb);
+        self.apu.set_cgb_mode(cgb);
+    }
+
+    /// Notifies that the CPU has put `addr` on the bus without accessing it (eg. with a
+    /// 16-bit INC/DEC). On DMG, this corrupts OAM if `addr` is in 0xFE00..=0xFEFF while
+    /// the PPU is scanning it.
+    pub fn trigger_oam_bug(&mut self, addr: u16) {
+        if self.oam_bug && !self.cgb && addr & 0xFF00 == 0xFE00 {
+            self.ppu.corrupt_oam();
+        }
+    }
+
+    /// Fills all the banks of work RAM according to `fill`.
+    pub fn fill_wram(&mut self, fill: RamFill) {
+        let mut bytes = fill.bytes();
 
-            for (i, b) in chunk.iter().enumerate() {
-                mem.write(i as u16, *b)?;
+        for addr in 0..0x1000 {
+            self.wram_00.poke(addr, bytes());
+        }
+        for bank in 0..7 {
+            self.wram_nn.select(bank);
+
+            for addr in 0..0x1000 {
+                self.wram_nn.poke(addr, bytes());
             }
-            self.rom_banks.push(mem);
         }
 
-        // Check MBC type in the ROM header
-        self.mbc = MbcType::try_from(rom[0x147])
-            .map_err(|McbTypeError(n)| dbg::TraceEvent::UnsupportedMbcType(n))?;
+        self.select_wram_bank(self.svbk);
+    }
+
+    /// Maps a DMG (256 bytes) or CGB (2304 bytes) boot ROM over the cartridge, and puts
+    /// the peripherals in their power-on state for it to initialize.
+    pub fn load_boot_rom(&mut self, rom: &[u8]) -> Result<(), dbg::TraceEvent> {
+        match rom.len() {
+            0x100 | 0x900 => self.boot_rom = Some(rom.to_vec()),
+            n => return Err(dbg::TraceEvent::InvalidBootRomSize(n)),
+        }
+
+        self.ppu = PPU::power_on();
+        self.tim = Timer::power_on();
+        self.sync_apu();
+        self.apu.write(0xFF26, 0x00)?;
 
         Ok(())
     }
 
-    /// Advances the system peripheral/memory bus by a single M-cycle.
+    /// Returns true if the boot ROM is mapped over the cartridge.
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    /// Returns the bank currently mapped at `addr` in the banked areas (switchable ROM,
+    /// external RAM and WRAM), numbered as in RGBDS symbol files, or 0 elsewhere.
+    pub fn bank_at(&self, addr: u16) -> usize {
+        match addr {
+            0x4000..=0x7FFF => self.cart.rom_bank(),
+            0xA000..=0xBFFF => self.cart.ram_bank().unwrap_or(0),
+            0xD000..=0xDFFF => usize::from(self.svbk.max(1)),
+            _ => 0,
+        }
+    }
+
+    /// Performs the RAM writes of the enabled GameShark codes, as done at every V-Blank.
+    pub fn apply_ram_cheats(&mut self) {
+        for (addr, val) in self.cheats.ram_writes(self.cart.ram_bank()) {
+            self.poke(addr, val);
+        }
+    }
+
+    /// Returns a view of the memory map with ROM bank `bank` mapped at 0x4000-0x7FFF.
+    pub fn with_rom_bank(&self, bank: usize) -> RomBankView<'_> {
+        RomBankView { bus: self, bank }
+    }
+
+    /// Returns true if a speed switch has been requested through KEY1, to be
+    /// performed by the next STOP instruction.
+    pub fn speed_switch_prepared(&self) -> bool {
+        self.key1_prepare
+    }
+
+    /// Performs the speed switch prepared through KEY1.
+    pub fn switch_speed(&mut self) {
+        // Some ROMs (eg. Blargg's test ROMs) might request a speed switch on DMG too,
+        // in which case the STOP is simply ignored.
+        if self.cgb {
+            self.double_speed = !self.double_speed;
+        }
+        self.key1_prepare = false;
+        self.ppu_phase = false;
+    }
+
+    /// Advances the system peripheral/memory bus by a single CPU M-cycle.
+    ///
+    /// In double speed mode, the timer, serial port and OAM DMA keep up with the CPU,
+    /// while the PPU and APU run at normal speed and are only advanced every other M-cycle.
+    ///
+    /// Only the APU is caught up lazily, between its events. The PPU and timer are still
+    /// stepped every M-cycle, since they raise interrupts with cycle precision.
     pub fn tick(&mut self) -> Result<(), dbg::TraceEvent> {
         if let Some((src, dst)) = self.ppu.advance_dma_xfer() {
-            let b = self.read(src)?;
+            let b = self.read_mapped(src)?;
             self.ppu.write_to_oam(dst, b)?;
         }
 
-        self.ppu.tick();
-        self.apu.tick();
+        // TODO catch the PPU up lazily as well, it's about a quarter of the time spent here.
+        // The timer isn't worth it, stepping it costs nothing measurable.
+        self.ppu_phase = self.double_speed && !self.ppu_phase;
+        if !self.ppu_phase {
+            self.ppu.tick();
+            self.joy.tick();
+        }
         self.tim.tick();
+        self.sdt.tick();
+
+        // The APU is only caught up when its next event is due or when it's written to
+        self.apu_pending += if self.double_speed { 2 } else { 4 };
+        if self.apu_pending >= self.apu.next_event() {
+            self.sync_apu();
+        }
 
         // Fetch interrupt requests from interrupt sources
         if let Some(irq) = self.ppu.get_and_clear_irq() {
@@ -111,47 +262,168 @@ impl Bus {
         if let Some(irq) = self.sdt.get_and_clear_irq() {
             self.itr.set_irq(irq.into());
         }
+        if let Some(irq) = self.joy.get_and_clear_irq() {
+            self.itr.set_irq(irq.into());
+        }
 
         Ok(())
     }
 
-    fn ram_enable(&mut self, _val: u8) -> Result<(), dbg::TraceEvent> {
-        // TODO handle this just in case some ROMs rely on uncorrect behavior
-        Ok(())
+    /// Brings the APU up to date with the rest of the system.
+    ///
+    /// The APU advances in whole M-cycles at normal speed, so in double speed mode
+    /// the half M-cycle which may be left over is carried to the next sync.
+    pub fn sync_apu(&mut self) {
+        let cycles = self.apu_pending & !0x3;
+
+        self.apu.advance(cycles);
+        self.apu_pending -= cycles;
     }
 
-    fn rom_select(&mut self, val: u8) -> Result<(), dbg::TraceEvent> {
-        self.rom_nn = match val {
-            0x00 => 0x01,
-            v @ 0x01..=0x1F => usize::from(v),
-            v => return Err(dbg::TraceEvent::InvalidMbcOp(dbg::McbOp::RomBank, v)),
+    /// Checks whether the CPU is allowed to access `addr`. While an OAM DMA transfer is in
+    /// progress, OAM and the memory bus the transfer is reading from (either the external
+    /// bus or VRAM) are unavailable, which in practice leaves HRAM and IO registers.
+    ///
+    /// Returns `Ok(false)` if the access should be dropped.
+    fn check_dma_conflict(&self, addr: u16) -> Result<bool, dbg::TraceEvent> {
+        let src = match self.ppu.dma_source() {
+            Some(src) => src,
+            None => return Ok(true),
         };
-        Ok(())
+
+        let conflict = match addr {
+            0x8000..=0x9FFF => (0x8000..=0x9FFF).contains(&src),
+            0xFE00..=0xFFFF => (0xFE00..=0xFE9F).contains(&addr),
+            _ => !(0x8000..=0x9FFF).contains(&src),
+        };
+
+        if !conflict {
+            Ok(true)
+        } else if self.trap_dma_conflicts {
+            Err(dbg::TraceEvent::DmaBusConflict(addr))
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Checks whether the CPU is allowed to access `addr`, since the PPU locks VRAM
+    /// during mode 3 and OAM during modes 2 and 3, while it's reading them.
+    ///
+    /// Returns `Ok(false)` if the access should be dropped.
+    fn check_ppu_conflict(&self, addr: u16) -> Result<bool, dbg::TraceEvent> {
+        let conflict = match addr {
+            0x8000..=0x9FFF => !self.ppu.vram_accessible(),
+            0xFE00..=0xFE9F => !self.ppu.oam_accessible(),
+            _ => false,
+        };
+
+        if !conflict {
+            Ok(true)
+        } else if self.trap_ppu_conflicts {
+            Err(dbg::TraceEvent::PpuBusConflict(addr))
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Handles an access to unmapped memory according to the bus policy.
+    fn open_bus(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
+        match self.policy {
+            BusPolicy::Strict => Err(dbg::TraceEvent::BusFault(addr)),
+            BusPolicy::Lenient => Ok(0xFF),
+        }
     }
 
-    fn ram_rom_select(&mut self, val: u8) -> Result<(), dbg::TraceEvent> {
-        Err(dbg::TraceEvent::InvalidMbcOp(dbg::McbOp::RamBank, val))
+    /// Notifies the debugging facilities of a CPU access to `addr`.
+    fn monitor(&self, access: dbg::MemAccess, addr: u16, val: u8) {
+        if !self.watchpoints.is_empty() {
+            // Writes are monitored before being performed, so `addr` still holds the old value
+            let old = match access {
+                dbg::MemAccess::Read => val,
+                dbg::MemAccess::Write => self.peek(addr),
+            };
+            self.watchpoints.check(access, addr, old, val);
+        }
+
+        if let 0xFF00..=0xFF7F | 0xFFFF = addr {
+            if let Some(ref log) = self.io_log {
+                log.record(access, addr, val);
+            }
+            if let Some(ref stream) = self.trace_stream {
+                stream.record_io(access, addr, val);
+            }
+        }
     }
 
-    fn mode_select(&mut self, val: u8) -> Result<(), dbg::TraceEvent> {
-        Err(dbg::TraceEvent::InvalidMbcOp(dbg::McbOp::RamBank, val))
+    /// Returns the boot ROM byte mapped at `addr`, if any. The cartridge header
+    /// at 0x0100-0x01FF is always visible, even with a CGB boot ROM.
+    fn boot_rom_at(&self, addr: u16) -> Option<u8> {
+        match self.boot_rom {
+            Some(ref rom) if !(0x0100..=0x01FF).contains(&addr) => {
+                rom.get(usize::from(addr)).cloned()
+            }
+            _ => None,
+        }
     }
 
-    fn write_to_cgb_functions(&mut self, addr: u16, _val: u8) -> Result<(), dbg::TraceEvent> {
+    /// Returns true if external RAM is present and enabled.
+    fn eram_mapped(&self) -> bool {
+        self.cart.ram_bank().is_some()
+    }
+
+    fn write_to_cgb_functions(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
         match addr {
-            0xFF4D => Err(dbg::TraceEvent::CgbSpeedSwitchReq),
+            0xFF4D => {
+                self.key1_prepare = val & 0x01 != 0;
+                Ok(())
+            }
+            0xFF4F if self.cgb => self.ppu.write(addr, val),
+            0xFF68..=0xFF6C if self.cgb => self.ppu.write(addr, val),
+            0xFF70 if self.cgb => {
+                self.select_wram_bank(val);
+                Ok(())
+            }
+            _ if self.cgb && self.trap_cgb_ops => Err(dbg::TraceEvent::UnsupportedCgbOp(addr)),
             _ => Ok(()),
         }
     }
+
+    /// Writes to the cartridge ROM area, where the MBC registers are mapped.
+    fn write_to_mbc(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
+        self.cart.write(addr, val)?;
+
+        // Invalid operations are collected anyway, not to report stale ones later on
+        match self.cart.take_invalid_op() {
+            Some((op, val)) if self.trap_mbc_ops => Err(dbg::TraceEvent::InvalidMbcOp(op, val)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the value of the KEY1 register: current speed and pending switch.
+    fn key1(&self) -> u8 {
+        (if self.double_speed { 0x80 } else { 0x00 })
+            | (if self.key1_prepare { 0x01 } else { 0x00 })
+            | 0x7E
+    }
+
+    /// Maps the WRAM bank selected by `svbk` at 0xD000-0xDFFF. Bank 0 selects bank 1.
+    fn select_wram_bank(&mut self, svbk: u8) {
+        self.svbk = svbk & 0x07;
+        self.wram_nn.select(usize::from(self.svbk.max(1)) - 1);
+    }
 }
 
-impl MemR for Bus {
-    fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
+impl Bus {
+    /// Reads from the memory map, bypassing the OAM DMA bus restrictions.
+    fn read_mapped(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
+        if let Some(b) = self.boot_rom_at(addr) {
+            return Ok(b);
+        }
+
         match addr {
-            0x0000..=0x3FFF => self.rom_banks[0].read(addr),
-            0x4000..=0x7FFF => self.rom_banks[self.rom_nn].read(addr - 0x4000),
+            0x0000..=0x7FFF => Ok(self.cheats.patch_rom(addr, self.cart.read(addr)?)),
             0x8000..=0x9FFF => self.ppu.read(addr),
-            0xA000..=0xBFFF => self.eram.read(addr - 0xA000),
+            0xA000..=0xBFFF if self.eram_mapped() => self.cart.read(addr),
             0xC000..=0xCFFF => self.wram_00.read(addr - 0xC000),
             0xD000..=0xDFFF => self.wram_nn.read(addr - 0xD000),
             0xE000..=0xEFFF => self.wram_00.read(addr - 0xE000),
@@ -162,22 +434,100 @@ impl MemR for Bus {
             0xFF04..=0xFF07 => self.tim.read(addr),
             0xFF10..=0xFF3F => self.apu.read(addr),
             0xFF40..=0xFF4B => self.ppu.read(addr),
+            0xFF4D if self.cgb => Ok(self.key1()),
+            0xFF4F if self.cgb => self.ppu.read(addr),
+            0xFF68..=0xFF6C if self.cgb => self.ppu.read(addr),
+            0xFF70 if self.cgb => Ok(self.svbk | 0xF8),
             0xFF80..=0xFFFE => self.hram.read(addr - 0xFF80),
             0xFF0F | 0xFFFF => self.itr.read(addr),
-            _ => Ok(0xFF),
+            _ => self.open_bus(addr),
         }
     }
 }
 
+impl MemR for Bus {
+    fn peek(&self, addr: u16) -> u8 {
+        if let Some(b) = self.boot_rom_at(addr) {
+            return b;
+        }
+
+        match addr {
+            0x0000..=0x7FFF => self.cart.peek(addr),
+            0x8000..=0x9FFF => self.ppu.peek(addr),
+            0xA000..=0xBFFF if self.eram_mapped() => self.cart.peek(addr),
+            0xC000..=0xCFFF => self.wram_00.peek(addr - 0xC000),
+            0xD000..=0xDFFF => self.wram_nn.peek(addr - 0xD000),
+            0xE000..=0xEFFF => self.wram_00.peek(addr - 0xE000),
+            0xF000..=0xFDFF => self.wram_nn.peek(addr - 0xF000),
+            0xFE00..=0xFE9F => self.ppu.peek(addr),
+            0xFF00..=0xFF00 => self.joy.peek(addr),
+            0xFF01..=0xFF02 => self.sdt.peek(addr),
+            0xFF04..=0xFF07 => self.tim.peek(addr),
+            0xFF10..=0xFF3F => self.apu.peek(addr),
+            0xFF40..=0xFF4B => self.ppu.peek(addr),
+            0xFF4D if self.cgb => self.key1(),
+            0xFF4F if self.cgb => self.ppu.peek(addr),
+            0xFF68..=0xFF6C if self.cgb => self.ppu.peek(addr),
+            0xFF70 if self.cgb => self.svbk | 0xF8,
+            0xFF80..=0xFFFE => self.hram.peek(addr - 0xFF80),
+            0xFF0F | 0xFFFF => self.itr.peek(addr),
+            _ => 0xFF,
+        }
+    }
+
+    fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
+        let val = if self.check_dma_conflict(addr)? && self.check_ppu_conflict(addr)? {
+            self.read_mapped(addr)?
+        } else {
+            0xFF
+        };
+
+        self.monitor(dbg::MemAccess::Read, addr, val);
+        Ok(val)
+    }
+}
+
 impl MemW for Bus {
+    fn poke(&mut self, addr: u16, val: u8) {
+        match addr {
+            // Writes to the ROM area would end up in the MBC registers
+            0x0000..=0x7FFF => (),
+            0x8000..=0x9FFF => self.ppu.poke(addr, val),
+            0xA000..=0xBFFF if self.eram_mapped() => self.cart.poke(addr, val),
+            0xC000..=0xCFFF => self.wram_00.poke(addr - 0xC000, val),
+            0xD000..=0xDFFF => self.wram_nn.poke(addr - 0xD000, val),
+            0xE000..=0xEFFF => self.wram_00.poke(addr - 0xE000, val),
+            0xF000..=0xFDFF => self.wram_nn.poke(addr - 0xF000, val),
+            0xFE00..=0xFE9F => self.ppu.poke(addr, val),
+            0xFF00..=0xFF00 => self.joy.poke(addr, val),
+            0xFF01..=0xFF02 => self.sdt.poke(addr, val),
+            0xFF04..=0xFF07 => self.tim.poke(addr, val),
+            0xFF10..=0xFF3F => {
+                self.sync_apu();
+                self.apu.poke(addr, val)
+            }
+            0xFF40..=0xFF4B => self.ppu.poke(addr, val),
+            0xFF4D => self.key1_prepare = val & 0x01 != 0,
+            0xFF4F if self.cgb => self.ppu.poke(addr, val),
+            0xFF68..=0xFF6C if self.cgb => self.ppu.poke(addr, val),
+            0xFF70 if self.cgb => self.select_wram_bank(val),
+            0xFF80..=0xFFFE => self.hram.poke(addr - 0xFF80, val),
+            0xFF0F | 0xFFFF => self.itr.poke(addr, val),
+            _ => (),
+        }
+    }
+
     fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
+        self.monitor(dbg::MemAccess::Write, addr, val);
+
+        if !self.check_dma_conflict(addr)? || !self.check_ppu_conflict(addr)? {
+            return Ok(());
+        }
+
         match addr {
-            0x0000..=0x1FFF => self.ram_enable(val),
-            0x2000..=0x3FFF => self.rom_select(val),
-            0x4000..=0x5FFF => self.ram_rom_select(val),
-            0x6000..=0x7FFF => self.mode_select(val),
+            0x0000..=0x7FFF => self.write_to_mbc(addr, val),
             0x8000..=0x9FFF => self.ppu.write(addr, val),
-            0xA000..=0xBFFF => self.eram.write(addr - 0xA000, val),
+            0xA000..=0xBFFF if self.eram_mapped() => self.cart.write(addr, val),
             0xC000..=0xCFFF => self.wram_00.write(addr - 0xC000, val),
             0xD000..=0xDFFF => self.wram_nn.write(addr - 0xD000, val),
             0xE000..=0xEFFF => self.wram_00.write(addr - 0xE000, val),
@@ -186,15 +536,850 @@ impl MemW for Bus {
             0xFF00..=0xFF00 => self.joy.write(addr, val),
             0xFF01..=0xFF02 => self.sdt.write(addr, val),
             0xFF04..=0xFF07 => self.tim.write(addr, val),
-            0xFF10..=0xFF3F => self.apu.write(addr, val),
+            0xFF10..=0xFF3F => {
+                self.sync_apu();
+                self.apu.write(addr, val)
+            }
             0xFF40..=0xFF4B => self.ppu.write(addr, val),
             0xFF4C..=0xFF4F => self.write_to_cgb_functions(addr, val),
+            0xFF50 => {
+                // The boot ROM stays unmapped until the next power cycle
+                self.boot_rom = None;
+                Ok(())
+            }
             0xFF51..=0xFF7F => self.write_to_cgb_functions(addr, val),
             0xFF80..=0xFFFE => self.hram.write(addr - 0xFF80, val),
             0xFF0F | 0xFFFF => self.itr.write(addr, val),
-            _ => Ok(()),
+            _ => self.open_bus(addr).map(|_| ()),
         }
     }
 }
 
 impl MemRW for Bus {}
+
+/// Read-only view of the memory map, where the switchable ROM area shows a given bank
+/// in place of the one selected by the cartridge. Used to inspect unmapped banks.
+pub struct RomBankView<'a> {
+    bus: &'a Bus,
+    bank: usize,
+}
+
+impl<'a> MemR for RomBankView<'a> {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x4000..=0x7FFF => self.bus.cart.peek_rom(self.bank, addr - 0x4000),
+            _ => self.bus.peek(addr),
+        }
+    }
+
+    fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
+        Ok(self.peek(addr))
+    }
+}
+
+savestate_fields!(
+    Bus,
+    cart,
+    boot_rom,
+    cgb,
+    hram,
+    wram_00,
+    wram_nn,
+    svbk,
+    double_speed,
+    key1_prepare,
+    ppu_phase,
+    apu,
+    apu_pending,
+    ppu,
+    tim,
+    sdt,
+    joy,
+    itr,
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::cheat::Cheat;
+    use crate::io::{Palette, BG_MAP_SIZE, OAM_ENTRIES};
+    use crate::savestate::{Savestate, StateWriter};
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Runs the bus for a whole frame, and copies the rendered frame to `vbuf`.
+    fn render_frame(bus: &mut Bus, vbuf: &mut [u8]) {
+        for _ in 0..70224 / 4 {
+            bus.tick().unwrap();
+        }
+        bus.ppu.rasterize(vbuf);
+    }
+
+    #[test]
+    fn unmapped_accesses_follow_bus_policy() {
+        let mut bus = Bus::new();
+
+        assert_eq!(bus.read(0xFEA0).unwrap(), 0xFF);
+        assert!(bus.write(0xFEA0, 0x00).is_ok());
+
+        bus.policy = BusPolicy::Strict;
+
+        match bus.read(0xFEA0) {
+            Err(dbg::TraceEvent::BusFault(0xFEA0)) => (),
+            _ => panic!("unmapped read not reported"),
+        }
+        assert!(bus.write(0xFF03, 0x00).is_err());
+
+        // Missing external RAM is unmapped too
+        bus.load_rom(&[0; 0x8000]).unwrap();
+        assert!(bus.read(0xA000).is_err());
+    }
+
+    #[test]
+    fn invalid_mbc_and_cgb_ops_are_trapped_on_request() {
+        let mut bus = Bus::new();
+
+        let mut rom = vec![0; 0x10000];
+        rom[0x143] = 0x80;
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x01;
+        bus.load_rom(&rom).unwrap();
+
+        // Bank 6 doesn't exist in a 64KB ROM, and HDMA is not emulated
+        assert!(bus.write(0x2000, 0x06).is_ok());
+        assert!(bus.write(0xFF55, 0x00).is_ok());
+
+        bus.trap_mbc_ops = true;
+        bus.trap_cgb_ops = true;
+
+        assert!(bus.write(0x2000, 0x02).is_ok());
+        match bus.write(0x2000, 0x06) {
+            Err(dbg::TraceEvent::InvalidMbcOp(dbg::McbOp::RomBank, 0x06)) => (),
+            _ => panic!("invalid bank select not reported"),
+        }
+        match bus.write(0xFF55, 0x00) {
+            Err(dbg::TraceEvent::UnsupportedCgbOp(0xFF55)) => (),
+            _ => panic!("unsupported CGB register not reported"),
+        }
+
+        // Out of CGB mode, the CGB registers are simply not there
+        bus.set_cgb_mode(false);
+        assert!(bus.write(0xFF55, 0x00).is_ok());
+    }
+
+    #[test]
+    fn banks_follow_the_current_mappings() {
+        let mut bus = Bus::new();
+
+        let mut rom = vec![0; 0x10000];
+        rom[0x143] = 0x80;
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x01;
+        rom[0x8010] = 0xAB;
+        bus.load_rom(&rom).unwrap();
+
+        assert_eq!(bus.bank_at(0x0150), 0);
+        assert_eq!(bus.bank_at(0x4000), 1);
+        assert_eq!(bus.bank_at(0xD000), 1);
+
+        bus.write(0x2000, 0x03).unwrap();
+        bus.write(0xFF70, 0x05).unwrap();
+
+        assert_eq!(bus.bank_at(0x7FFF), 3);
+        assert_eq!(bus.bank_at(0xDFFF), 5);
+        assert_eq!(bus.bank_at(0xC000), 0);
+
+        // Unmapped banks can still be inspected
+        assert_eq!(bus.peek(0x4010), 0x00);
+        assert_eq!(bus.with_rom_bank(2).peek(0x4010), 0xAB);
+        assert_eq!(bus.with_rom_bank(9).peek(0x4010), 0xFF);
+        assert_eq!(bus.with_rom_bank(2).peek(0x0143), 0x80);
+    }
+
+    #[test]
+    fn accesses_are_checked_against_watchpoints() {
+        let mut bus = Bus::new();
+
+        bus.watchpoints
+            .add(dbg::Watchpoint::new(0xC100..=0xC1FF, true, true));
+
+        bus.write(0xC000, 0x12).unwrap();
+        assert_eq!(bus.watchpoints.take_hit(), None);
+
+        bus.write(0xC180, 0x34).unwrap();
+        let hit = bus.watchpoints.take_hit().unwrap();
+        assert_eq!(hit.access, dbg::MemAccess::Write);
+        assert_eq!((hit.addr, hit.val), (0xC180, 0x34));
+
+        // The access itself is still performed
+        assert_eq!(bus.read(0xC180).unwrap(), 0x34);
+        let hit = bus.watchpoints.take_hit().unwrap();
+        assert_eq!(hit.access, dbg::MemAccess::Read);
+
+        // Change watchpoints compare against the value held before the write
+        bus.watchpoints
+            .add(dbg::Watchpoint::changes(0xC000..=0xC000));
+
+        bus.write(0xC000, 0x12).unwrap();
+        assert_eq!(bus.watchpoints.take_hit(), None);
+
+        bus.write(0xC000, 0x56).unwrap();
+        assert_eq!(bus.watchpoints.take_hit().map(|hit| hit.val), Some(0x56));
+    }
+
+    #[test]
+    fn peek_and_poke_have_no_side_effects() {
+        let mut bus = Bus::new();
+
+        // Cartridge without external RAM
+        bus.load_rom(&[0; 0x8000]).unwrap();
+
+        bus.policy = BusPolicy::Strict;
+        bus.watchpoints
+            .add(dbg::Watchpoint::new(0x0000..=0xFFFF, true, true));
+
+        // Unmapped memory and missing external RAM read as open bus, without faults
+        assert_eq!(bus.peek(0xFEA0), 0xFF);
+        assert_eq!(bus.peek(0xA000), 0xFF);
+
+        // Writing DMA doesn't start a transfer
+        bus.poke(0xFF46, 0xC0);
+        bus.tick().unwrap();
+        bus.tick().unwrap();
+        assert_eq!(bus.peek(0xFF46), 0xC0);
+        assert_eq!(bus.ppu.dma_source(), None);
+
+        // MBC registers are left untouched
+        let mut rom = vec![0; 0x10000];
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x01;
+        bus.load_rom(&rom).unwrap();
+
+        bus.poke(0x2000, 0x02);
+        assert_eq!(bus.cart.rom_bank(), 1);
+
+        assert_eq!(bus.watchpoints.take_hit(), None);
+
+        // OAM and WRAM are accessible even during OAM DMA transfers
+        bus.poke(0xC000, 0x12);
+        bus.write(0xFF46, 0xC0).unwrap();
+        bus.tick().unwrap();
+        bus.tick().unwrap();
+        bus.poke(0xFE01, 0x34);
+
+        assert_eq!(bus.peek(0xC000), 0x12);
+        assert_eq!(bus.peek(0xFE01), 0x34);
+    }
+
+    #[test]
+    fn boot_rom_is_mapped_until_disabled() {
+        let mut bus = Bus::new();
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x0000] = 0x11;
+        rom[0x0100] = 0x22;
+        bus.load_rom(&rom).unwrap();
+
+        assert!(bus.load_boot_rom(&[0xAA; 0x200]).is_err());
+        bus.load_boot_rom(&[0xBB; 0x100]).unwrap();
+
+        assert_eq!(bus.read(0x0000).unwrap(), 0xBB);
+        assert_eq!(bus.read(0x0100).unwrap(), 0x22);
+
+        bus.write(0xFF50, 0x01).unwrap();
+
+        assert!(!bus.boot_rom_mapped());
+        assert_eq!(bus.read(0x0000).unwrap(), 0x11);
+    }
+
+    #[test]
+    fn cheats_patch_rom_reads_and_ram() {
+        let mut bus = Bus::new();
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x081F] = 0x02;
+        bus.load_rom(&rom).unwrap();
+
+        bus.cheats.add(Cheat::new("3E8-1FF-E62", "ROM").unwrap());
+        bus.cheats.add(Cheat::new("0107E4C1", "RAM").unwrap());
+
+        // The CPU sees the patched ROM, while the debugger sees the original
+        assert_eq!(bus.read(0x081F).unwrap(), 0x3E);
+        assert_eq!(bus.peek(0x081F), 0x02);
+
+        assert_eq!(bus.peek(0xC1E4), 0x00);
+        bus.apply_ram_cheats();
+        assert_eq!(bus.peek(0xC1E4), 0x07);
+    }
+
+    #[test]
+    fn cgb_memories_are_banked() {
+        let mut bus = Bus::new();
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0x80;
+        bus.load_rom(&rom).unwrap();
+
+        // Banks 0 and 1 of WRAM both map bank 1
+        for bank in 0..8 {
+            bus.write(0xFF70, bank).unwrap();
+            bus.write(0xD000, 0x10 + bank).unwrap();
+        }
+
+        bus.write(0xFF70, 0x00).unwrap();
+        assert_eq!(bus.read(0xD000).unwrap(), 0x11);
+        bus.write(0xFF70, 0x05).unwrap();
+        assert_eq!(bus.read(0xD000).unwrap(), 0x15);
+        assert_eq!(bus.read(0xF000).unwrap(), 0x15);
+        assert_eq!(bus.read(0xFF70).unwrap(), 0xFD);
+
+        bus.write(0x8000, 0x12).unwrap();
+        bus.write(0x9800, 0x34).unwrap();
+        bus.write(0xFF4F, 0x01).unwrap();
+        assert_eq!(bus.read(0x8000).unwrap(), 0x00);
+        assert_eq!(bus.read(0x9800).unwrap(), 0x00);
+        assert_eq!(bus.read(0xFF4F).unwrap(), 0xFF);
+
+        bus.write(0xFF4F, 0x00).unwrap();
+        assert_eq!(bus.read(0x8000).unwrap(), 0x12);
+        assert_eq!(bus.read(0x9800).unwrap(), 0x34);
+    }
+
+    #[test]
+    fn cgb_palettes_color_the_screen() {
+        let mut bus = Bus::new();
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0x80;
+        bus.load_rom(&rom).unwrap();
+
+        // Set color 0 of BG palette 0 to red, using auto-increment
+        bus.write(0xFF68, 0x80).unwrap();
+        bus.write(0xFF69, 0x1F).unwrap();
+        bus.write(0xFF69, 0x00).unwrap();
+        assert_eq!(bus.read(0xFF68).unwrap(), 0xC2);
+
+        bus.write(0xFF68, 0x01).unwrap();
+        assert_eq!(bus.read(0xFF69).unwrap(), 0x00);
+
+        let mut vbuf = vec![0; 160 * 144 * 4];
+        render_frame(&mut bus, &mut vbuf);
+        assert_eq!(vbuf[0..3], [0xFF, 0x00, 0x00]);
+
+        // Tiles in VRAM bank 1 are selected through the BG map attributes
+        bus.write(0xFF4F, 0x01).unwrap();
+        bus.write(0x8000, 0xFF).unwrap();
+        bus.write(0x9800, 0x08).unwrap();
+
+        bus.write(0xFF68, 0x82).unwrap();
+        bus.write(0xFF69, 0xE0).unwrap();
+        bus.write(0xFF69, 0x03).unwrap();
+
+        render_frame(&mut bus, &mut vbuf);
+        assert_eq!(vbuf[0..3], [0x00, 0xFF, 0x00]);
+    }
+
+    #[test]
+    fn lines_are_rendered_with_the_current_registers() {
+        let mut bus = Bus::new();
+        let mut vbuf = vec![0; 160 * 144 * 4];
+
+        // Tile 1 is black, and it is displayed right after tile 0 in the BG map
+        for addr in 0x8010..0x8020 {
+            bus.write(addr, 0xFF).unwrap();
+        }
+        bus.write(0x9801, 0x01).unwrap();
+
+        // Scroll the BG after the first line has been rendered
+        for _ in 0..78 {
+            bus.tick().unwrap();
+        }
+        bus.write(0xFF43, 0x08).unwrap();
+        for _ in 0..114 {
+            bus.tick().unwrap();
+        }
+
+        // The frame is displayed once complete
+        while bus.read(0xFF44).unwrap() != 144 {
+            bus.tick().unwrap();
+        }
+
+        bus.ppu.rasterize(&mut vbuf);
+        assert_eq!(vbuf[0..3], [0xFF, 0xFF, 0xFF]);
+        assert_eq!(vbuf[160 * 4..160 * 4 + 3], [0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn registers_are_latched_per_line() {
+        let mut bus = Bus::new();
+
+        for _ in 0..14 {
+            bus.tick().unwrap();
+        }
+
+        // Shake the screen during H-Blank
+        for ly in 0..4 {
+            bus.write(0xFF43, ly * 3).unwrap();
+            bus.write(0xFF42, ly).unwrap();
+
+            for _ in 0..114 {
+                bus.tick().unwrap();
+            }
+        }
+
+        let regs = bus.ppu.line_registers();
+        assert_eq!(regs[0].bgp, 0xFC);
+        assert_eq!(regs[2].scx, 6);
+        assert_eq!(regs[3].scy, 3);
+    }
+
+    #[test]
+    fn palettes_are_editable_by_the_debugger() {
+        let mut bus = Bus::new();
+        bus.set_cgb_mode(true);
+
+        bus.write(0xFF47, 0xE4).unwrap();
+        bus.ppu.set_palette_entry(Palette::Bgp, 2, 0x00);
+        assert_eq!(bus.read(0xFF47).unwrap(), 0xC4);
+        assert_eq!(bus.ppu.palette_entry(Palette::Bgp, 1), 0x01);
+        assert_eq!(bus.ppu.palette_colors(Palette::Bgp)[2], [0xFF; 3]);
+
+        // Pure red as color 1 of CGB sprite palette 3, at index 3 * 8 + 1 * 2
+        bus.ppu.set_palette_entry(Palette::CgbObj(3), 1, 0x001F);
+        bus.write(0xFF6A, 0x1A).unwrap();
+        assert_eq!(bus.read(0xFF6B).unwrap(), 0x1F);
+        assert_eq!(
+            bus.ppu.palette_colors(Palette::CgbObj(3))[1],
+            [0xFF, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn whole_bg_map_is_rasterized() {
+        let mut bus = Bus::new();
+        let mut vbuf = vec![0; BG_MAP_SIZE * BG_MAP_SIZE * 4];
+
+        // Tile 1 is black, and it is displayed at (8, 8) in the BG map at 0x9C00
+        for addr in 0x8010..0x8020 {
+            bus.write(addr, 0xFF).unwrap();
+        }
+        bus.write(0x9C21, 0x01).unwrap();
+
+        bus.ppu.rasterize_bg_map(true, &mut vbuf);
+
+        let pixel = |x: usize, y: usize| vbuf[(y * BG_MAP_SIZE + x) * 4];
+        assert_eq!(pixel(7, 7), 0xFF);
+        assert_eq!(pixel(8, 8), 0x00);
+        assert_eq!(pixel(15, 15), 0x00);
+        assert_eq!(pixel(16, 8), 0xFF);
+    }
+
+    #[test]
+    fn sprites_are_rasterized_side_by_side() {
+        let mut bus = Bus::new();
+        let width = OAM_ENTRIES * 8;
+        let mut vbuf = vec![0xFF; width * 16 * 4];
+
+        // Tile 1 has its leftmost column set, and sprite 1 flips it horizontally
+        for addr in (0x8010..0x8020).step_by(2) {
+            bus.write(addr, 0x80).unwrap();
+        }
+        bus.write(0xFE06, 0x01).unwrap();
+        bus.write(0xFE07, 0x20).unwrap();
+
+        bus.ppu.rasterize_sprites(&mut vbuf);
+
+        let pixel = |x: usize, y: usize| &vbuf[(y * width + x) * 4..(y * width + x + 1) * 4];
+        assert_eq!(pixel(8, 0)[3], 0x00);
+        assert_eq!(pixel(15, 0), [0x00, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(15, 8)[3], 0x00);
+    }
+
+    #[test]
+    fn mode_3_is_longer_with_fine_scrolling() {
+        let mut bus = Bus::new();
+
+        // Without scrolling, line 0 is drawn in 172 dots
+        for _ in 0..77 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0xFF41).unwrap() & 0x03, 0x03);
+        bus.tick().unwrap();
+        assert_eq!(bus.read(0xFF41).unwrap() & 0x03, 0x00);
+
+        // Pixels discarded for fine scrolling add up to the length of mode 3
+        bus.write(0xFF43, 0x05).unwrap();
+        for _ in 0..114 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0xFF41).unwrap() & 0x03, 0x03);
+        for _ in 0..2 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0xFF41).unwrap() & 0x03, 0x00);
+    }
+
+    #[test]
+    fn sprites_are_limited_and_prioritized_per_line() {
+        let mut bus = Bus::new();
+        let mut vbuf = vec![0; 160 * 144 * 4];
+
+        bus.write(0xFF40, 0x93).unwrap();
+        bus.write(0xFF47, 0xE4).unwrap();
+        bus.write(0xFF48, 0xE4).unwrap();
+
+        // Tile 1 is black, tile 2 is light gray
+        for addr in 0x8010..0x8020 {
+            bus.write(addr, 0xFF).unwrap();
+        }
+        for addr in (0x8020..0x8030).step_by(2) {
+            bus.write(addr, 0xFF).unwrap();
+        }
+
+        // Eleven sprites side by side on line 0, only the first ten are drawn
+        for i in 0..11 {
+            let base = 0xFE00 + i * 4;
+            bus.write(base, 16).unwrap();
+            bus.write(base + 1, 8 + 8 * i as u8).unwrap();
+            bus.write(base + 2, 0x01).unwrap();
+        }
+
+        // On line 16, the sprite with the lower X is drawn on top despite its OAM index,
+        // even though both are fetched at the same time
+        for (i, &(x, tid)) in [(6, 0x02), (2, 0x01)].iter().enumerate() {
+            let base = 0xFE00 + (11 + i as u16) * 4;
+            bus.write(base, 32).unwrap();
+            bus.write(base + 1, x).unwrap();
+            bus.write(base + 2, tid).unwrap();
+        }
+
+        render_frame(&mut bus, &mut vbuf);
+
+        let pixel = |x: usize, y: usize| vbuf[(y * 160 + x) * 4];
+        assert_eq!(pixel(72, 0), 0x00);
+        assert_eq!(pixel(80, 0), 0xFF);
+        assert_eq!(pixel(1, 16), 0x00);
+        assert_eq!(pixel(2, 16), 0xAA);
+    }
+
+    #[test]
+    fn window_is_shown_once_ly_matches_wy() {
+        let mut bus = Bus::new();
+        let mut vbuf = vec![0; 160 * 144 * 4];
+
+        // BG map at 0x9C00 is white, Window map at 0x9800 is black
+        bus.write(0xFF40, 0xB9).unwrap();
+        for addr in 0x8010..0x8020 {
+            bus.write(addr, 0xFF).unwrap();
+        }
+        for addr in 0x9800..0x9C00 {
+            bus.write(addr, 0x01).unwrap();
+        }
+        bus.write(0xFF4A, 16).unwrap();
+        bus.write(0xFF4B, 7).unwrap();
+
+        // Moving WY past LY doesn't hide the Window for the rest of the frame
+        for _ in 0..14 + 40 * 114 {
+            bus.tick().unwrap();
+        }
+        bus.write(0xFF4A, 100).unwrap();
+
+        // The frame is displayed once complete
+        while bus.read(0xFF44).unwrap() != 144 {
+            bus.tick().unwrap();
+        }
+
+        bus.ppu.rasterize(&mut vbuf);
+
+        let pixel = |x: usize, y: usize| vbuf[(y * 160 + x) * 4];
+        assert_eq!(pixel(0, 8), 0xFF);
+        assert_eq!(pixel(0, 16), 0x00);
+        assert_eq!(pixel(0, 50), 0x00);
+    }
+
+    #[test]
+    fn oam_is_corrupted_during_oam_scan() {
+        let mut bus = Bus::new();
+
+        for addr in 0xFE00..0xFEA0 {
+            bus.write(addr, addr as u8).unwrap();
+        }
+
+        // Move to the third row of the OAM scan on line 0 (T-states 8-11): line 0 starts
+        // 15 ticks after power-on, and each tick lasts 4 T-states
+        for _ in 0..17 {
+            bus.tick().unwrap();
+        }
+
+        bus.trigger_oam_bug(0xFE00);
+        assert_eq!(bus.peek(0xFE10), 0x10);
+
+        bus.oam_bug = true;
+        bus.trigger_oam_bug(0xFF80);
+        assert_eq!(bus.peek(0xFE10), 0x10);
+
+        // First word: ((a ^ c) & (b ^ c)) ^ c, with a = 0x1110, b = 0x0908, c = 0x0D0C
+        bus.trigger_oam_bug(0xFEFF);
+        assert_eq!(bus.peek(0xFE10), 0x08);
+        assert_eq!(bus.peek(0xFE11), 0x09);
+        for i in 2..8 {
+            assert_eq!(bus.peek(0xFE10 + i), 0x08 + i as u8);
+        }
+    }
+
+    #[test]
+    fn frames_are_presented_at_vblank() {
+        let mut bus = Bus::new();
+        let mut vbuf = vec![0; 160 * 144 * 4];
+
+        let presented = Arc::new(AtomicUsize::new(0));
+        let counter = presented.clone();
+        bus.ppu.on_frame(move |frame| {
+            assert_eq!(frame[0], 0x00);
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        // Half-drawn frames are never shown
+        bus.write(0xFF47, 0xFF).unwrap();
+        for _ in 0..14 + 72 * 114 {
+            bus.tick().unwrap();
+        }
+
+        bus.ppu.rasterize(&mut vbuf);
+        assert_eq!(vbuf[0], 0xFF);
+        assert!(bus.ppu.take_frame().is_none());
+
+        // The frame is presented as soon as V-Blank starts
+        while bus.read(0xFF44).unwrap() != 144 {
+            bus.tick().unwrap();
+        }
+
+        assert_eq!(presented.load(Ordering::SeqCst), 1);
+        assert_eq!(bus.ppu.take_frame().map(|frame| frame[0]), Some(0x00));
+        assert!(bus.ppu.take_frame().is_none());
+    }
+
+    #[test]
+    fn line_breaks_are_latched_once_per_line() {
+        let mut bus = Bus::new();
+
+        bus.ppu.set_break_line(Some(2));
+
+        for _ in 0..100 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.ppu.take_line_hit(), None);
+
+        for _ in 0..200 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.ppu.take_line_hit(), Some(2));
+        assert_eq!(bus.ppu.take_line_hit(), None);
+    }
+
+    #[test]
+    fn frames_are_blended_with_the_previous_ones() {
+        let mut bus = Bus::new();
+        let mut vbuf = vec![0; 160 * 144 * 4];
+
+        bus.write(0xFF47, 0xFF).unwrap();
+        bus.ppu.set_frame_blending(0.5);
+
+        render_frame(&mut bus, &mut vbuf);
+        assert_eq!(vbuf[0], 0x80);
+
+        render_frame(&mut bus, &mut vbuf);
+        assert_eq!(vbuf[0], 0x40);
+
+        bus.ppu.set_frame_blending(0.0);
+        bus.ppu.rasterize(&mut vbuf);
+        assert_eq!(vbuf[0], 0x00);
+    }
+
+    #[test]
+    fn lcd_controller_stops_while_the_lcd_is_off() {
+        let mut bus = Bus::new();
+        let mut vbuf = vec![0; 160 * 144 * 4];
+
+        for _ in 0..1000 {
+            bus.tick().unwrap();
+        }
+        assert_ne!(bus.read(0xFF44).unwrap(), 0x00);
+
+        bus.write(0xFF40, 0x11).unwrap();
+        for _ in 0..1000 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0xFF44).unwrap(), 0x00);
+        assert_eq!(bus.read(0xFF41).unwrap() & 0x03, 0);
+
+        // The first line after turning the LCD on has no OAM scan
+        bus.write(0xFF40, 0x91).unwrap();
+        bus.tick().unwrap();
+        assert_eq!(bus.read(0xFF44).unwrap(), 0x00);
+        assert_eq!(bus.read(0xFF41).unwrap() & 0x03, 0);
+
+        for _ in 0..20 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0xFF41).unwrap() & 0x03, 3);
+
+        for _ in 0..94 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0xFF44).unwrap(), 0x01);
+        assert_eq!(bus.read(0xFF41).unwrap() & 0x03, 2);
+
+        // The first frame is not displayed
+        bus.ppu.rasterize(&mut vbuf);
+        assert!(vbuf.iter().all(|b| *b == 0xFF));
+    }
+
+    #[test]
+    fn vram_and_oam_are_locked_while_drawing() {
+        let mut bus = Bus::new();
+
+        bus.write(0x8000, 0x12).unwrap();
+        bus.write(0xFE00, 0x34).unwrap();
+
+        // Mode 2: OAM is locked
+        for _ in 0..16 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0x8000).unwrap(), 0x12);
+        assert_eq!(bus.read(0xFE00).unwrap(), 0xFF);
+
+        // Mode 3: VRAM is locked too, and writes are ignored
+        for _ in 0..20 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0x8000).unwrap(), 0xFF);
+        bus.write(0x8000, 0x56).unwrap();
+
+        bus.trap_ppu_conflicts = true;
+        match bus.read(0xFE00) {
+            Err(dbg::TraceEvent::PpuBusConflict(0xFE00)) => (),
+            _ => panic!("OAM access during mode 3 not reported"),
+        }
+
+        // Both are accessible again in H-Blank
+        for _ in 0..50 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0x8000).unwrap(), 0x12);
+        assert_eq!(bus.read(0xFE00).unwrap(), 0x34);
+    }
+
+    #[test]
+    fn speed_switch_halves_ppu_rate() {
+        let mut bus = Bus::new();
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0x80;
+        bus.load_rom(&rom).unwrap();
+
+        bus.write(0xFF4D, 0x01).unwrap();
+        assert!(bus.speed_switch_prepared());
+        assert_eq!(bus.read(0xFF4D).unwrap(), 0x7F);
+
+        bus.switch_speed();
+        assert!(bus.double_speed);
+        assert_eq!(bus.read(0xFF4D).unwrap(), 0xFE);
+
+        // The last 15 M-cycles of the frame now take twice as many CPU cycles
+        for _ in 0..15 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0xFF44).unwrap(), 153);
+
+        for _ in 0..15 {
+            bus.tick().unwrap();
+        }
+        assert_eq!(bus.read(0xFF44).unwrap(), 0);
+    }
+
+    #[test]
+    fn apu_keeps_time_through_writes_in_double_speed() {
+        let mut buses = [Bus::new(), Bus::new()];
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0x80;
+
+        for bus in buses.iter_mut() {
+            bus.load_rom(&rom).unwrap();
+            bus.write(0xFF4D, 0x01).unwrap();
+            bus.switch_speed();
+
+            // Square 2, stopped by the length counter on its next clock
+            bus.write(0xFF16, 0x3F).unwrap();
+            bus.write(0xFF17, 0xF0).unwrap();
+            bus.write(0xFF18, 0x00).unwrap();
+            bus.write(0xFF19, 0xC7).unwrap();
+        }
+
+        // The first one is also written to on every M-cycle, half of which end
+        // halfway through an APU M-cycle
+        for _ in 0..20_000 {
+            buses[0].write(0xFF24, 0x77).unwrap();
+
+            for bus in buses.iter_mut() {
+                bus.tick().unwrap();
+            }
+        }
+
+        let states: Vec<_> = buses
+            .iter_mut()
+            .map(|bus| {
+                let mut w = StateWriter::new();
+
+                bus.sync_apu();
+                bus.apu.save_state(&mut w);
+                w.into_inner()
+            })
+            .collect();
+
+        // Both the frame sequencer and the channel timers are in step
+        assert!(!buses[0].apu.channel_status()[1].enabled);
+        assert_eq!(states[0], states[1]);
+    }
+
+    #[test]
+    fn cpu_is_restricted_during_oam_dma() {
+        let mut bus = Bus::new();
+
+        // Keep the PPU from locking OAM while the transfer is checked
+        bus.write(0xFF40, 0x00).unwrap();
+
+        bus.write(0xC000, 0x12).unwrap();
+        bus.write(0x8000, 0x34).unwrap();
+        bus.write(0xFF80, 0x56).unwrap();
+
+        // Start a transfer from WRAM, which is on the external bus
+        bus.write(0xFF46, 0xC0).unwrap();
+        for _ in 0..2 {
+            bus.tick().unwrap();
+        }
+
+        assert_eq!(bus.read(0xC000).unwrap(), 0xFF);
+        assert_eq!(bus.read(0xFE00).unwrap(), 0xFF);
+        assert_eq!(bus.read(0x8000).unwrap(), 0x34);
+        assert_eq!(bus.read(0xFF80).unwrap(), 0x56);
+        assert_eq!(bus.read(0xFF46).unwrap(), 0xC0);
+
+        bus.write(0xC000, 0x00).unwrap();
+
+        bus.trap_dma_conflicts = true;
+        assert!(bus.read(0xC001).is_err());
+        assert!(bus.read(0xFF80).is_ok());
+
+        // After 160 cycles the transfer is over and OAM holds the copied data
+        for _ in 0..160 {
+            bus.tick().unwrap();
+        }
+
+        assert_eq!(bus.read(0xC000).unwrap(), 0x12);
+        assert_eq!(bus.read(0xFE00).unwrap(), 0x12);
+    }
+}
@@ -0,0 +1,380 @@
+use super::dbg;
+use super::{Cartridge, Header, MemR, MemRW, MemW, Memory};
+
+/// A cartridge with an MBC1 controller: up to 2MB of ROM and 32KB of RAM.
+///
+/// MBC1 multicarts (MBC1M) wire the controller differently: only 4 bits of the ROM bank
+/// register are used, and the secondary register provides bank bits 4-5 instead of 5-6.
+pub struct Mbc1 {
+    rom_banks: Vec<Memory>,
+    rom_00: usize,
+    rom_nn: usize,
+
+    eram: Memory,
+    eram_size: usize,
+    eram_nn: usize,
+    eram_enabled: bool,
+
+    // Banking registers
+    bank_lo: u8,
+    bank_hi: u8,
+    ram_banking: bool,
+
+    multicart: bool,
+
+    /// Last bank select beyond the size of ROM or RAM
+    invalid_op: Option<(dbg::McbOp, u8)>,
+}
+
+impl Mbc1 {
+    pub fn new(rom: &[u8]) -> Result<Mbc1, dbg::TraceEvent> {
+        // MBC1 can address up to 4 banks of external RAM
+        let eram_size = Header::parse(rom)?.ram_size.min(0x8000);
+
+        Ok(Mbc1 {
+            rom_banks: super::rom_banks(rom)?,
+            rom_00: 0,
+            rom_nn: 1,
+
+            eram: Memory::new(eram_size as u16),
+            eram_size,
+            eram_nn: 0,
+            eram_enabled: false,
+
+            bank_lo: 1,
+            bank_hi: 0,
+            ram_banking: false,
+
+            multicart: is_multicart(rom),
+
+            invalid_op: None,
+        })
+    }
+
+    fn ram_enable(&mut self, val: u8) {
+        self.eram_enabled = val & 0x0F == 0x0A;
+    }
+
+    fn rom_select(&mut self, val: u8) {
+        // Bank 0 can't be selected in the lower 5 bits, which makes banks 0x20/0x40/0x60
+        // inaccessible from the switchable area: they alias to 0x21/0x41/0x61.
+        self.bank_lo = match val & 0x1F {
+            0x00 => 0x01,
+            v => v,
+        };
+        self.update_banks();
+
+        let lo_mask = if self.multicart { 0x0F } else { 0x1F };
+
+        if usize::from(self.bank_lo & lo_mask) >= self.rom_banks.len() {
+            self.invalid_op = Some((dbg::McbOp::RomBank, val));
+        }
+    }
+
+    fn ram_rom_select(&mut self, val: u8) {
+        self.bank_hi = val & 0x03;
+        self.update_banks();
+
+        // Larger ROMs need the register for the upper bank bits, smaller ones can only
+        // use it to select a RAM bank
+        let hi = usize::from(self.bank_hi);
+        let hi_shift = if self.multicart { 4 } else { 5 };
+
+        if self.rom_banks.len() > 1 << hi_shift {
+            if (hi << hi_shift) >= self.rom_banks.len() {
+                self.invalid_op = Some((dbg::McbOp::RomBank, val));
+            }
+        } else if hi != 0 && (hi << 13) >= self.eram_size {
+            self.invalid_op = Some((dbg::McbOp::RamBank, val));
+        }
+    }
+
+    fn mode_select(&mut self, val: u8) {
+        self.ram_banking = val & 0x01 != 0;
+        self.update_banks();
+    }
+
+    /// Recomputes the mapped ROM and RAM banks from the banking registers.
+    fn update_banks(&mut self) {
+        let rom_banks = self.rom_banks.len().max(1);
+        let hi = usize::from(self.bank_hi);
+
+        // On multicarts, bit 4 of the ROM bank register is not connected
+        let (lo, hi_shift) = if self.multicart {
+            (usize::from(self.bank_lo & 0x0F), 4)
+        } else {
+            (usize::from(self.bank_lo), 5)
+        };
+
+        // The upper 2 bits select ROM bank bits 5-6 for the switchable area. In RAM banking
+        // mode, they also apply to the area at 0x0000 and select the RAM bank.
+        self.rom_nn = ((hi << hi_shift) | lo) % rom_banks;
+
+        if self.ram_banking {
+            self.rom_00 = (hi << hi_shift) % rom_banks;
+            self.eram_nn = hi;
+        } else {
+            self.rom_00 = 0;
+            self.eram_nn = 0;
+        }
+    }
+
+    /// Returns true if the mapped banks exist, eg. after loading a state.
+    fn banks_in_range(&self) -> bool {
+        self.rom_00 < self.rom_banks.len() && self.rom_nn < self.rom_banks.len() && self.eram_nn < 4
+    }
+
+    /// Translates an address in range 0xA000..=0xBFFF to an offset into external RAM,
+    /// returning `None` if the RAM is disabled or not present.
+    fn eram_offset(&self, addr: u16) -> Option<u16> {
+        if !self.eram_enabled || self.eram_size == 0 {
+            return None;
+        }
+
+        let offset = (self.eram_nn << 13) | usize::from(addr - 0xA000);
+        Some((offset % self.eram_size) as u16)
+    }
+}
+
+impl Cartridge for Mbc1 {
+    fn mbc_name(&self) -> &'static str {
+        if self.multicart {
+            "MBC1M"
+        } else {
+            "MBC1"
+        }
+    }
+
+    fn rom_bank(&self) -> usize {
+        self.rom_nn
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        self.eram_offset(0xA000).map(|_| self.eram_nn)
+    }
+
+    fn rom_banks(&self) -> usize {
+        self.rom_banks.len()
+    }
+
+    fn peek_rom(&self, bank: usize, addr: u16) -> u8 {
+        self.rom_banks.get(bank).map_or(0xFF, |b| b.peek(addr))
+    }
+
+    fn take_invalid_op(&mut self) -> Option<(dbg::McbOp, u8)> {
+        self.invalid_op.take()
+    }
+}
+
+impl MemR for Mbc1 {
+    fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
+        match addr {
+            0x0000..=0x3FFF => self.rom_banks[self.rom_00].read(addr),
+            0x4000..=0x7FFF => self.rom_banks[self.rom_nn].read(addr - 0x4000),
+            0xA000..=0xBFFF => match self.eram_offset(addr) {
+                Some(offset) => self.eram.read(offset),
+                None => Ok(0xFF),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl MemW for Mbc1 {
+    fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enable(val),
+            0x2000..=0x3FFF => self.rom_select(val),
+            0x4000..=0x5FFF => self.ram_rom_select(val),
+            0x6000..=0x7FFF => self.mode_select(val),
+            0xA000..=0xBFFF => {
+                if let Some(offset) = self.eram_offset(addr) {
+                    self.eram.write(offset, val)?;
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+impl MemRW for Mbc1 {}
+
+savestate_fields!(
+    Mbc1 where Mbc1::banks_in_range,
+    rom_00,
+    rom_nn,
+    eram,
+    eram_nn,
+    eram_enabled,
+    bank_lo,
+    bank_hi,
+    ram_banking,
+);
+
+/// Detects MBC1 multicarts, which are 1MB images made of 256KB games, each one
+/// with its own header. Besides the menu in bank 0x00, at least one of the games
+/// in banks 0x10, 0x20 and 0x30 has to carry the Nintendo logo.
+fn is_multicart(rom: &[u8]) -> bool {
+    rom.len() == 0x10_0000 && (1..4).any(|n| super::has_logo(rom, n * 0x4_0000))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a 2MB MBC1 cartridge with 32KB of RAM, where each bank starts with its own number.
+    fn mbc1_cart() -> Mbc1 {
+        let mut rom = vec![0; 0x20_0000];
+        for (n, bank) in rom.chunks_mut(0x4000).enumerate() {
+            bank[0] = n as u8;
+        }
+        rom[0x147] = 0x03;
+        rom[0x148] = 0x06;
+        rom[0x149] = 0x03;
+
+        Mbc1::new(&rom).unwrap()
+    }
+
+    #[test]
+    fn mbc1_rom_banking() {
+        let mut cart = mbc1_cart();
+
+        assert_eq!(cart.read(0x0000).unwrap(), 0x00);
+        assert_eq!(cart.read(0x4000).unwrap(), 0x01);
+
+        cart.write(0x2000, 0x05).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x05);
+
+        // Bank 0 can't be mapped in the switchable area, and neither can 0x20/0x40/0x60
+        cart.write(0x2000, 0x00).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x01);
+
+        cart.write(0x4000, 0x01).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x21);
+
+        cart.write(0x2000, 0x1F).unwrap();
+        cart.write(0x4000, 0x03).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x7F);
+        assert_eq!(cart.rom_bank(), 0x7F);
+
+        // In RAM banking mode, the upper bits also apply to the fixed area
+        assert_eq!(cart.read(0x0000).unwrap(), 0x00);
+        cart.write(0x6000, 0x01).unwrap();
+        assert_eq!(cart.read(0x0000).unwrap(), 0x60);
+        cart.write(0x6000, 0x00).unwrap();
+        assert_eq!(cart.read(0x0000).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn mbc1_multicart_rom_banking() {
+        let mut rom = vec![0; 0x10_0000];
+        for (n, bank) in rom.chunks_mut(0x4000).enumerate() {
+            bank[0] = n as u8;
+        }
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x05;
+
+        // Without additional headers, this is a regular 1MB cartridge
+        let mut cart = Mbc1::new(&rom).unwrap();
+        cart.write(0x2000, 0x12).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x12);
+
+        rom[0x4_0104..0x4_0134].copy_from_slice(&super::super::NINTENDO_LOGO);
+
+        let mut cart = Mbc1::new(&rom).unwrap();
+        assert_eq!(cart.mbc_name(), "MBC1M");
+
+        // Bit 4 of the ROM bank register is ignored, but still prevents mapping bank 0
+        cart.write(0x2000, 0x12).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x02);
+        cart.write(0x2000, 0x10).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x00);
+
+        cart.write(0x2000, 0x03).unwrap();
+        cart.write(0x4000, 0x02).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x23);
+
+        // In RAM banking mode, the game at 0x0000 is selected too
+        cart.write(0x6000, 0x01).unwrap();
+        assert_eq!(cart.read(0x0000).unwrap(), 0x20);
+    }
+
+    #[test]
+    fn mbc1_small_roms_are_mirrored() {
+        let mut rom = vec![0; 0x1_0000];
+        for (n, bank) in rom.chunks_mut(0x4000).enumerate() {
+            bank[0] = n as u8;
+        }
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x01;
+
+        let mut cart = Mbc1::new(&rom).unwrap();
+
+        cart.write(0x2000, 0x03).unwrap();
+        assert!(cart.take_invalid_op().is_none());
+
+        // Mirrored selects are still reported, as well as those of a missing RAM
+        cart.write(0x2000, 0x06).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x02);
+
+        match cart.take_invalid_op() {
+            Some((dbg::McbOp::RomBank, 0x06)) => (),
+            op => panic!("unexpected invalid op {:?}", op),
+        }
+        assert!(cart.take_invalid_op().is_none());
+
+        cart.write(0x4000, 0x01).unwrap();
+
+        match cart.take_invalid_op() {
+            Some((dbg::McbOp::RamBank, 0x01)) => (),
+            op => panic!("unexpected invalid op {:?}", op),
+        }
+    }
+
+    #[test]
+    fn mbc1_ram_banking() {
+        let mut cart = mbc1_cart();
+
+        // RAM is disabled on startup
+        cart.write(0xA000, 0x12).unwrap();
+        assert_eq!(cart.read(0xA000).unwrap(), 0xFF);
+        assert_eq!(cart.ram_bank(), None);
+
+        cart.write(0x0000, 0x0A).unwrap();
+        cart.write(0xA000, 0x12).unwrap();
+        assert_eq!(cart.read(0xA000).unwrap(), 0x12);
+
+        // RAM banks are only switched in RAM banking mode
+        cart.write(0x4000, 0x02).unwrap();
+        assert_eq!(cart.read(0xA000).unwrap(), 0x12);
+
+        cart.write(0x6000, 0x01).unwrap();
+        assert_eq!(cart.read(0xA000).unwrap(), 0x00);
+        assert_eq!(cart.ram_bank(), Some(2));
+        cart.write(0xA000, 0x34).unwrap();
+
+        cart.write(0x4000, 0x00).unwrap();
+        assert_eq!(cart.read(0xA000).unwrap(), 0x12);
+        cart.write(0x4000, 0x02).unwrap();
+        assert_eq!(cart.read(0xA000).unwrap(), 0x34);
+
+        cart.write(0x0000, 0x00).unwrap();
+        assert_eq!(cart.read(0xA000).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn states_with_missing_banks_are_rejected() {
+        use crate::savestate::{Savestate, StateReader, StateWriter};
+
+        let mut w = StateWriter::new();
+        mbc1_cart().save_state(&mut w);
+
+        // ROM bank 0xC8 is selected in the switchable area, which follows the one at 0x0000
+        let mut state = w.into_inner();
+        state[8..16].copy_from_slice(&0xC8u64.to_le_bytes());
+
+        let mut cart = mbc1_cart();
+        assert!(cart.load_state(&mut StateReader::new(&state)).is_err());
+    }
+}
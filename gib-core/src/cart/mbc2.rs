@@ -0,0 +1,175 @@
+use super::dbg;
+use super::{Cartridge, MemR, MemRW, MemW, Memory};
+
+/// A cartridge with an MBC2 controller: up to 256KB of ROM and a built-in RAM of 512 4-bit cells.
+pub struct Mbc2 {
+    rom_banks: Vec<Memory>,
+    rom_nn: usize,
+
+    eram: Memory,
+    eram_enabled: bool,
+
+    /// Last ROM bank select beyond the size of ROM
+    invalid_op: Option<(dbg::McbOp, u8)>,
+}
+
+impl Mbc2 {
+    pub fn new(rom: &[u8]) -> Result<Mbc2, dbg::TraceEvent> {
+        Ok(Mbc2 {
+            rom_banks: super::rom_banks(rom)?,
+            rom_nn: 1,
+
+            eram: Memory::new(0x200),
+            eram_enabled: false,
+
+            invalid_op: None,
+        })
+    }
+
+    /// Handles writes to the MBC registers: bit 8 of the address selects between
+    /// RAM enable (cleared) and ROM bank select (set).
+    fn write_to_regs(&mut self, addr: u16, val: u8) {
+        if addr & 0x0100 == 0 {
+            self.eram_enabled = val & 0x0F == 0x0A;
+        } else {
+            let bank = match val & 0x0F {
+                0x00 => 0x01,
+                v => usize::from(v),
+            };
+            if bank >= self.rom_banks.len() {
+                self.invalid_op = Some((dbg::McbOp::RomBank, val));
+            }
+            self.rom_nn = bank % self.rom_banks.len().max(1);
+        }
+    }
+
+    /// Returns true if the mapped ROM bank exists, eg. after loading a state.
+    fn banks_in_range(&self) -> bool {
+        self.rom_nn < self.rom_banks.len()
+    }
+}
+
+impl Cartridge for Mbc2 {
+    fn mbc_name(&self) -> &'static str {
+        "MBC2"
+    }
+
+    fn rom_bank(&self) -> usize {
+        self.rom_nn
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        if self.eram_enabled {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn rom_banks(&self) -> usize {
+        self.rom_banks.len()
+    }
+
+    fn peek_rom(&self, bank: usize, addr: u16) -> u8 {
+        self.rom_banks.get(bank).map_or(0xFF, |b| b.peek(addr))
+    }
+
+    fn take_invalid_op(&mut self) -> Option<(dbg::McbOp, u8)> {
+        self.invalid_op.take()
+    }
+}
+
+impl MemR for Mbc2 {
+    fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
+        match addr {
+            0x0000..=0x3FFF => self.rom_banks[0].read(addr),
+            0x4000..=0x7FFF => self.rom_banks[self.rom_nn].read(addr - 0x4000),
+
+            // Only the lower 9 address bits are used, so RAM is echoed across the whole area.
+            // Cells are 4 bits wide, with the upper nibble left open.
+            0xA000..=0xBFFF => {
+                if self.eram_enabled {
+                    Ok(self.eram.read(addr & 0x1FF)? | 0xF0)
+                } else {
+                    Ok(0xFF)
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl MemW for Mbc2 {
+    fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
+        match addr {
+            0x0000..=0x3FFF => self.write_to_regs(addr, val),
+            0x4000..=0x7FFF => (),
+            0xA000..=0xBFFF => {
+                if self.eram_enabled {
+                    self.eram.write(addr & 0x1FF, val & 0x0F)?;
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+impl MemRW for Mbc2 {}
+
+savestate_fields!(Mbc2 where Mbc2::banks_in_range, rom_nn, eram, eram_enabled);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a 256KB MBC2 cartridge, where each bank starts with its own number.
+    fn mbc2_cart() -> Mbc2 {
+        let mut rom = vec![0; 0x4_0000];
+        for (n, bank) in rom.chunks_mut(0x4000).enumerate() {
+            bank[0] = n as u8;
+        }
+        rom[0x147] = 0x05;
+        rom[0x148] = 0x03;
+
+        Mbc2::new(&rom).unwrap()
+    }
+
+    #[test]
+    fn mbc2_rom_banking() {
+        let mut cart = mbc2_cart();
+
+        assert_eq!(cart.read(0x4000).unwrap(), 0x01);
+
+        // Writes with address bit 8 set select the ROM bank
+        cart.write(0x2100, 0x0F).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x0F);
+
+        cart.write(0x0100, 0x00).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x01);
+
+        // ...while the others don't
+        cart.write(0x2000, 0x05).unwrap();
+        assert_eq!(cart.read(0x4000).unwrap(), 0x01);
+    }
+
+    #[test]
+    fn mbc2_builtin_ram() {
+        let mut cart = mbc2_cart();
+
+        cart.write(0xA000, 0x0C).unwrap();
+        assert_eq!(cart.read(0xA000).unwrap(), 0xFF);
+
+        // RAM is enabled by writes with address bit 8 cleared
+        cart.write(0x0000, 0x0A).unwrap();
+        cart.write(0xA000, 0xAC).unwrap();
+        assert_eq!(cart.read(0xA000).unwrap(), 0xFC);
+
+        // The 512 cells are echoed across the whole external RAM area
+        assert_eq!(cart.read(0xA200).unwrap(), 0xFC);
+        assert_eq!(cart.read(0xBE00).unwrap(), 0xFC);
+
+        cart.write(0x3E00, 0x00).unwrap();
+        assert_eq!(cart.read(0xA000).unwrap(), 0xFF);
+    }
+}
@@ -0,0 +1,169 @@
+mod mbc1;
+mod mbc2;
+mod nombc;
+
+use super::dbg;
+use super::mem::{MemR, MemRW, MemW, Memory};
+use super::savestate::Savestate;
+
+pub use mbc1::*;
+pub use mbc2::*;
+pub use nombc::*;
+
+/// Logo displayed by the boot ROM, which must be present in the cartridge header.
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Returns true if the header starting at `offset` in `rom` contains the Nintendo logo.
+fn has_logo(rom: &[u8], offset: usize) -> bool {
+    rom.get(offset + 0x104..offset + 0x134) == Some(&NINTENDO_LOGO[..])
+}
+
+/// A cartridge plugged into the system, mapped at 0x0000-0x7FFF (ROM and memory bank
+/// controller registers) and 0xA000-0xBFFF (external RAM).
+///
+/// The saved state of a cartridge includes its RAM and banking registers, but not the ROM.
+pub trait Cartridge: MemRW + Savestate {
+    /// Returns the name of the memory bank controller.
+    fn mbc_name(&self) -> &'static str;
+
+    /// Returns the ROM bank currently mapped at 0x4000-0x7FFF.
+    fn rom_bank(&self) -> usize;
+
+    /// Returns the external RAM bank currently mapped at 0xA000-0xBFFF,
+    /// or `None` if external RAM is not accessible.
+    fn ram_bank(&self) -> Option<usize>;
+
+    /// Returns the number of ROM banks of the cartridge.
+    fn rom_banks(&self) -> usize;
+
+    /// Reads byte `addr` (relative to the start of the bank) of ROM bank `bank`,
+    /// whether it's currently mapped or not. Banks beyond the ROM read as 0xFF.
+    fn peek_rom(&self, bank: usize, addr: u16) -> u8;
+
+    /// Returns the last bank select since the previous call which exceeded the memory
+    /// of the cartridge, and was therefore mirrored. Likely a bug in the game.
+    fn take_invalid_op(&mut self) -> Option<(dbg::McbOp, u8)> {
+        None
+    }
+}
+
+/// Cartridge information found in the ROM header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// Cartridge type, describing the memory bank controller and other hardware
+    pub cart_type: u8,
+    /// Number of 16KB ROM banks
+    pub rom_banks: usize,
+    /// Size of the external RAM, in bytes
+    pub ram_size: usize,
+    /// Whether the cartridge supports CGB functions
+    pub cgb: bool,
+}
+
+impl Header {
+    /// Parses the header of `rom`, checking the image size against the declared one.
+    pub fn parse(rom: &[u8]) -> Result<Header, dbg::TraceEvent> {
+        if rom.len() < 0x150 {
+            return Err(dbg::TraceEvent::RomSizeMismatch(0x8000, rom.len()));
+        }
+
+        let rom_banks = match rom[0x148] {
+            n @ 0x00..=0x08 => 2 << n,
+            n => return Err(dbg::TraceEvent::UnsupportedRomSize(n)),
+        };
+
+        let ram_size = match rom[0x149] {
+            0x00 => 0,
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x2_0000,
+            0x05 => 0x1_0000,
+            n => return Err(dbg::TraceEvent::UnsupportedRamSize(n)),
+        };
+
+        // Truncated or oversized images are likely bad dumps
+        if rom.len() != rom_banks * 0x4000 {
+            return Err(dbg::TraceEvent::RomSizeMismatch(rom_banks * 0x4000, rom.len()));
+        }
+
+        Ok(Header {
+            cart_type: rom[0x147],
+            rom_banks,
+            ram_size,
+            cgb: rom[0x143] & 0x80 != 0,
+        })
+    }
+}
+
+/// Creates the cartridge described by the header of `rom`.
+pub fn from_rom(rom: &[u8]) -> Result<Box<dyn Cartridge>, dbg::TraceEvent> {
+    match Header::parse(rom)?.cart_type {
+        0x00 => Ok(Box::new(NoMbc::new(rom)?)),
+        0x01..=0x03 => Ok(Box::new(Mbc1::new(rom)?)),
+        0x05..=0x06 => Ok(Box::new(Mbc2::new(rom)?)),
+        n => Err(dbg::TraceEvent::UnsupportedMbcType(n)),
+    }
+}
+
+/// Splits a ROM image into 16KB banks, as many as declared in its header.
+fn rom_banks(rom: &[u8]) -> Result<Vec<Memory>, dbg::TraceEvent> {
+    let header = Header::parse(rom)?;
+    let mut banks = vec![];
+
+    for chunk in rom.chunks(0x4000).take(header.rom_banks) {
+        let mut mem = Memory::new(0x4000);
+
+        for (i, b) in chunk.iter().enumerate() {
+            mem.write(i as u16, *b)?;
+        }
+        banks.push(mem);
+    }
+
+    Ok(banks)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rom_image(len: usize, rom_size: u8, ram_size: u8) -> Vec<u8> {
+        let mut rom = vec![0; len];
+        rom[0x148] = rom_size;
+        rom[0x149] = ram_size;
+        rom
+    }
+
+    #[test]
+    fn header_describes_memory_sizes() {
+        let header = Header::parse(&rom_image(0x4_0000, 0x03, 0x03)).unwrap();
+
+        assert_eq!(header.rom_banks, 16);
+        assert_eq!(header.ram_size, 0x8000);
+        assert!(!header.cgb);
+    }
+
+    #[test]
+    fn bad_images_are_rejected() {
+        match Header::parse(&[0; 0x100]) {
+            Err(dbg::TraceEvent::RomSizeMismatch(0x8000, 0x100)) => (),
+            _ => panic!("truncated header accepted"),
+        }
+        match Header::parse(&rom_image(0x8000, 0x01, 0x00)) {
+            Err(dbg::TraceEvent::RomSizeMismatch(0x1_0000, 0x8000)) => (),
+            _ => panic!("truncated image accepted"),
+        }
+        match Header::parse(&rom_image(0x1_0000, 0x00, 0x00)) {
+            Err(dbg::TraceEvent::RomSizeMismatch(0x8000, 0x1_0000)) => (),
+            _ => panic!("oversized image accepted"),
+        }
+        match Header::parse(&rom_image(0x8000, 0x00, 0x07)) {
+            Err(dbg::TraceEvent::UnsupportedRamSize(0x07)) => (),
+            _ => panic!("invalid RAM size accepted"),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use super::dbg;
+use super::{Cartridge, Header, MemR, MemRW, MemW, Memory};
+
+/// A cartridge without memory bank controller: 32KB of ROM and up to 8KB of RAM.
+pub struct NoMbc {
+    rom_banks: Vec<Memory>,
+    eram: Memory,
+    eram_size: usize,
+}
+
+impl Default for NoMbc {
+    fn default() -> NoMbc {
+        NoMbc {
+            rom_banks: vec![],
+            eram: Memory::new(0x2000),
+            eram_size: 0x2000,
+        }
+    }
+}
+
+impl NoMbc {
+    pub fn new(rom: &[u8]) -> Result<NoMbc, dbg::TraceEvent> {
+        let eram_size = Header::parse(rom)?.ram_size.min(0x2000);
+
+        Ok(NoMbc {
+            rom_banks: super::rom_banks(rom)?,
+            eram: Memory::new(eram_size as u16),
+            eram_size,
+        })
+    }
+
+    /// Translates an address in range 0xA000..=0xBFFF to an offset into external RAM,
+    /// returning `None` if no RAM is present. Smaller RAMs are mirrored across the area.
+    fn eram_offset(&self, addr: u16) -> Option<u16> {
+        if self.eram_size == 0 {
+            None
+        } else {
+            Some(((usize::from(addr) - 0xA000) % self.eram_size) as u16)
+        }
+    }
+
+    fn read_rom(&self, bank: usize, addr: u16) -> Result<u8, dbg::TraceEvent> {
+        match self.rom_banks.get(bank) {
+            Some(mem) => mem.read(addr),
+            None => Ok(0xFF),
+        }
+    }
+}
+
+impl Cartridge for NoMbc {
+    fn mbc_name(&self) -> &'static str {
+        "None"
+    }
+
+    fn rom_bank(&self) -> usize {
+        1
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        if self.eram_size != 0 {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn rom_banks(&self) -> usize {
+        self.rom_banks.len()
+    }
+
+    fn peek_rom(&self, bank: usize, addr: u16) -> u8 {
+        self.rom_banks.get(bank).map_or(0xFF, |b| b.peek(addr))
+    }
+}
+
+impl MemR for NoMbc {
+    fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
+        match addr {
+            0x0000..=0x3FFF => self.read_rom(0, addr),
+            0x4000..=0x7FFF => self.read_rom(1, addr - 0x4000),
+            0xA000..=0xBFFF => match self.eram_offset(addr) {
+                Some(offset) => self.eram.read(offset),
+                None => Ok(0xFF),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl MemW for NoMbc {
+    fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
+        match addr {
+            // ROM-only cartridges ignore writes to the MBC registers
+            0x0000..=0x7FFF => Ok(()),
+            0xA000..=0xBFFF => match self.eram_offset(addr) {
+                Some(offset) => self.eram.write(offset, val),
+                None => Ok(()),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl MemRW for NoMbc {}
+
+savestate_fields!(NoMbc, eram);
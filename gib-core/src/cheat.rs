@@ -0,0 +1,280 @@
+//! Cheat codes in the formats of the Game Genie and GameShark cartridge adapters.
+//!
+//! Game Genie codes patch the ROM as the CPU reads it, optionally only when the
+//! location holds an expected value, so that the patch is skipped in other banks.
+//! GameShark codes write a RAM location at every V-Blank instead.
+
+use std::fmt;
+
+/// A decoded cheat code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatCode {
+    /// Reads of `addr` in ROM return `val`, if the ROM holds `compare` there when given
+    GameGenie {
+        addr: u16,
+        val: u8,
+        compare: Option<u8>,
+    },
+
+    /// `val` is written to `addr` at every V-Blank. For external RAM, only while
+    /// `bank` is mapped.
+    GameShark { bank: u8, addr: u16, val: u8 },
+}
+
+impl CheatCode {
+    /// Decodes a Game Genie code (`ABC-DEF` or `ABC-DEF-GHI`) or a GameShark one (`ABCDEFGH`).
+    pub fn parse(code: &str) -> Option<CheatCode> {
+        let code = code.trim();
+
+        let digits = code
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<_>>>()?;
+
+        match digits.len() {
+            6 | 9 => {
+                let d = |i: usize| u16::from(digits[i]);
+
+                let val = (digits[0] << 4) | digits[1];
+                let addr = ((d(5) << 12) | (d(2) << 8) | (d(3) << 4) | d(4)) ^ 0xF000;
+
+                // The compare byte is rotated and scrambled, while the 8th digit is unused
+                let compare = if digits.len() == 9 {
+                    Some(((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA)
+                } else {
+                    None
+                };
+
+                if addr < 0x8000 {
+                    Some(CheatCode::GameGenie { addr, val, compare })
+                } else {
+                    None
+                }
+            }
+            8 if !code.contains('-') => {
+                let byte = |i: usize| (digits[i] << 4) | digits[i + 1];
+
+                let addr = (u16::from(byte(6)) << 8) | u16::from(byte(4));
+
+                if (0xA000..=0xDFFF).contains(&addr) {
+                    Some(CheatCode::GameShark {
+                        bank: byte(0),
+                        addr,
+                        val: byte(2),
+                    })
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A cheat code as entered, along with a description and whether it is active.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub code: String,
+    pub name: String,
+    pub enabled: bool,
+    decoded: CheatCode,
+}
+
+impl Cheat {
+    /// Creates an enabled cheat, if `code` is a valid Game Genie or GameShark code.
+    pub fn new<S: Into<String>>(code: &str, name: S) -> Option<Cheat> {
+        Some(Cheat {
+            decoded: CheatCode::parse(code)?,
+            code: code.trim().to_uppercase(),
+            name: name.into(),
+            enabled: true,
+        })
+    }
+
+    pub fn decoded(&self) -> CheatCode {
+        self.decoded
+    }
+}
+
+/// The cheats of a game.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Cheats(Vec<Cheat>);
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats::default()
+    }
+
+    /// Parses a list of cheats as written by `Display`, one per line: `+` or `-`
+    /// whether it is enabled, the code and its description.
+    /// Empty lines, comments starting with `#` and invalid lines are ignored.
+    pub fn parse(text: &str) -> Cheats {
+        let mut cheats = Cheats::new();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(3, ' ');
+            let enabled = parts.next() == Some("+");
+
+            if let Some(mut cheat) = parts.next().and_then(|code| Cheat::new(code, "")) {
+                cheat.name = parts.next().unwrap_or("").trim().to_string();
+                cheat.enabled = enabled;
+                cheats.add(cheat);
+            }
+        }
+
+        cheats
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.0.push(cheat);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.0.len() {
+            self.0.remove(index);
+        }
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.0.get_mut(index) {
+            cheat.enabled = enabled;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = CheatCode> + '_ {
+        self.0.iter().filter(|c| c.enabled).map(|c| c.decoded)
+    }
+
+    /// Returns the value read from `addr` in ROM, given the one `rom` holds there.
+    pub fn patch_rom(&self, addr: u16, rom: u8) -> u8 {
+        for code in self.enabled() {
+            if let CheatCode::GameGenie {
+                addr: a,
+                val,
+                compare,
+            } = code
+            {
+                if a == addr && compare.is_none_or(|cmp| cmp == rom) {
+                    return val;
+                }
+            }
+        }
+        rom
+    }
+
+    /// Returns the RAM writes to perform at V-Blank, given the external RAM bank
+    /// currently mapped.
+    pub fn ram_writes(&self, ram_bank: Option<usize>) -> Vec<(u16, u8)> {
+        self.enabled()
+            .filter_map(|code| match code {
+                CheatCode::GameShark { bank, addr, val } => {
+                    if addr < 0xC000 && ram_bank != Some(usize::from(bank)) {
+                        None
+                    } else {
+                        Some((addr, val))
+                    }
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Cheats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for cheat in &self.0 {
+            let enabled = if cheat.enabled { '+' } else { '-' };
+
+            write!(f, "{} {}", enabled, cheat.code)?;
+
+            if !cheat.name.is_empty() {
+                write!(f, " {}", cheat.name)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn codes_are_decoded() {
+        assert_eq!(
+            CheatCode::parse("3E8-1FF-E62"),
+            Some(CheatCode::GameGenie {
+                addr: 0x081F,
+                val: 0x3E,
+                compare: Some(0x02),
+            })
+        );
+        assert_eq!(
+            CheatCode::parse("3e8-1ff"),
+            Some(CheatCode::GameGenie {
+                addr: 0x081F,
+                val: 0x3E,
+                compare: None,
+            })
+        );
+        assert_eq!(
+            CheatCode::parse("01FFE4C1"),
+            Some(CheatCode::GameShark {
+                bank: 0x01,
+                addr: 0xC1E4,
+                val: 0xFF,
+            })
+        );
+
+        assert_eq!(CheatCode::parse("3E8-1FF-E6"), None);
+        assert_eq!(CheatCode::parse("01FF0080"), None);
+        assert_eq!(CheatCode::parse("XYZ-123"), None);
+    }
+
+    #[test]
+    fn codes_patch_memory() {
+        let mut cheats = Cheats::new();
+        cheats.add(Cheat::new("3E8-1FF-E62", "Compare").unwrap());
+        cheats.add(Cheat::new("018A00A0", "Bank 1").unwrap());
+        cheats.add(Cheat::new("0107E4C1", "Lives").unwrap());
+
+        assert_eq!(cheats.patch_rom(0x081F, 0x02), 0x3E);
+        assert_eq!(cheats.patch_rom(0x081F, 0x03), 0x03);
+        assert_eq!(cheats.patch_rom(0x0820, 0x02), 0x02);
+
+        assert_eq!(
+            cheats.ram_writes(Some(1)),
+            vec![(0xA000, 0x8A), (0xC1E4, 0x07)]
+        );
+        assert_eq!(cheats.ram_writes(Some(0)), vec![(0xC1E4, 0x07)]);
+
+        cheats.set_enabled(0, false);
+        assert_eq!(cheats.patch_rom(0x081F, 0x02), 0x02);
+    }
+
+    #[test]
+    fn cheats_are_parsed_back() {
+        let mut cheats = Cheats::new();
+        cheats.add(Cheat::new("0107e4c1", "Infinite lives").unwrap());
+        cheats.add(Cheat::new("3E8-1FF", "").unwrap());
+        cheats.set_enabled(1, false);
+
+        let text = cheats.to_string();
+        assert_eq!(text, "+ 0107E4C1 Infinite lives\n- 3E8-1FF\n");
+        assert_eq!(Cheats::parse(&text), cheats);
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Game Boy hardware model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    /// Original Game Boy, with the early revision of the boot ROM
+    Dmg0,
+    /// Original Game Boy
+    Dmg,
+    /// Game Boy Pocket
+    Mgb,
+    /// Game Boy Color
+    Cgb,
+}
+
+impl Model {
+    pub fn all() -> &'static [Model] {
+        use Model::*;
+
+        &[Dmg0, Dmg, Mgb, Cgb]
+    }
+
+    /// Returns the values of AF, BC, DE and HL left by the boot ROM.
+    pub fn boot_registers(self) -> (u16, u16, u16, u16) {
+        match self {
+            Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x01B0, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFFB0, 0x0013, 0x00D8, 0x014D),
+            Model::Cgb => (0x1180, 0x0000, 0xFF56, 0x000D),
+        }
+    }
+
+    /// Returns the fraction of charge kept by the capacitors of the audio high-pass filter
+    /// on each clock cycle.
+    pub fn hpf_charge_factor(self) -> f32 {
+        match self {
+            Model::Cgb => 0.998_943,
+            _ => 0.999_958,
+        }
+    }
+
+    /// Returns the contents of work RAM found on power-on.
+    pub fn wram_fill(self) -> RamFill {
+        match self {
+            Model::Cgb => RamFill::Zeros,
+            _ => RamFill::Random(0x2F5E_9C1B),
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Model::Dmg0 => write!(f, "DMG0"),
+            Model::Dmg => write!(f, "DMG"),
+            Model::Mgb => write!(f, "MGB"),
+            Model::Cgb => write!(f, "CGB"),
+        }
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Model, String> {
+        Model::all()
+            .iter()
+            .find(|m| m.to_string().eq_ignore_ascii_case(s))
+            .cloned()
+            .ok_or_else(|| format!("unknown hardware model: {}", s))
+    }
+}
+
+/// Pattern used to fill RAM on power-on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamFill {
+    Zeros,
+    Ones,
+    /// Pseudo-random bytes generated from the given seed
+    Random(u32),
+}
+
+impl RamFill {
+    /// Returns a generator of the bytes making up the pattern.
+    pub fn bytes(self) -> impl FnMut() -> u8 {
+        // Xorshift32, whose state must be non-zero
+        let mut state = match self {
+            RamFill::Random(seed) => seed.max(1),
+            _ => 0,
+        };
+
+        move || match self {
+            RamFill::Zeros => 0x00,
+            RamFill::Ones => 0xFF,
+            RamFill::Random(_) => {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            }
+        }
+    }
+}
+
+/// Colors used to display the four shades of the monochrome models,
+/// from the lightest to the darkest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmgPalette(pub [[u8; 3]; 4]);
+
+impl DmgPalette {
+    pub const GRAYSCALE: DmgPalette = DmgPalette([
+        [0xFF, 0xFF, 0xFF],
+        [0xAA, 0xAA, 0xAA],
+        [0x55, 0x55, 0x55],
+        [0x00, 0x00, 0x00],
+    ]);
+
+    /// Greenish tint of the original Game Boy LCD
+    pub const GREEN: DmgPalette = DmgPalette([
+        [0x9B, 0xBC, 0x0F],
+        [0x8B, 0xAC, 0x0F],
+        [0x30, 0x62, 0x30],
+        [0x0F, 0x38, 0x0F],
+    ]);
+
+    pub fn presets() -> &'static [(&'static str, DmgPalette)] {
+        &[
+            ("grayscale", DmgPalette::GRAYSCALE),
+            ("green", DmgPalette::GREEN),
+        ]
+    }
+}
+
+impl Default for DmgPalette {
+    fn default() -> DmgPalette {
+        DmgPalette::GRAYSCALE
+    }
+}
+
+impl FromStr for DmgPalette {
+    type Err = String;
+
+    /// Parses either the name of a preset, or four comma-separated colors
+    /// in RRGGBB hexadecimal format.
+    fn from_str(s: &str) -> Result<DmgPalette, String> {
+        if let Some((_, pal)) = DmgPalette::presets()
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+        {
+            return Ok(*pal);
+        }
+
+        let colors = s
+            .split(',')
+            .map(|c| match u32::from_str_radix(c.trim(), 16) {
+                Ok(rgb) if c.trim().len() == 6 => {
+                    Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+                }
+                _ => Err(format!("invalid color: {}", c)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if colors.len() != 4 {
+            return Err(format!("expected 4 colors, found {}", colors.len()));
+        }
+
+        let mut pal = DmgPalette::default();
+        pal.0.copy_from_slice(&colors[..]);
+        Ok(pal)
+    }
+}
+
+/// Hardware configuration of a Game Boy instance.
+#[derive(Debug, Clone)]
+pub struct HardwareConfig {
+    pub model: Model,
+
+    /// Boot ROM run on power-on. If `None`, the boot ROM is skipped and CPU and IO
+    /// registers are directly initialized with the values it would leave behind.
+    pub boot_rom: Option<Vec<u8>>,
+
+    /// Contents of work RAM on power-on
+    pub wram_fill: RamFill,
+
+    /// Colors of the screen, on monochrome models
+    pub dmg_palette: DmgPalette,
+}
+
+impl HardwareConfig {
+    /// Creates the configuration of a `model` unit, skipping the boot ROM.
+    pub fn new(model: Model) -> HardwareConfig {
+        HardwareConfig {
+            model,
+            boot_rom: None,
+            wram_fill: model.wram_fill(),
+            dmg_palette: DmgPalette::default(),
+        }
+    }
+}
+
+impl Default for HardwareConfig {
+    fn default() -> HardwareConfig {
+        HardwareConfig::new(Model::Dmg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn models_are_parsed_case_insensitively() {
+        assert_eq!("cgb".parse::<Model>(), Ok(Model::Cgb));
+        assert_eq!("DMG0".parse::<Model>(), Ok(Model::Dmg0));
+        assert!("sgb".parse::<Model>().is_err());
+    }
+
+    #[test]
+    fn random_ram_fill_is_deterministic() {
+        let (mut a, mut b) = (RamFill::Random(1).bytes(), RamFill::Random(1).bytes());
+
+        for _ in 0..16 {
+            assert_eq!(a(), b());
+        }
+
+        let mut ones = RamFill::Ones.bytes();
+        assert_eq!(ones(), 0xFF);
+    }
+
+    #[test]
+    fn dmg_palettes_are_parsed() {
+        assert_eq!("Green".parse::<DmgPalette>(), Ok(DmgPalette::GREEN));
+
+        let pal = "FFFFFF, c0c0c0,808080,000000"
+            .parse::<DmgPalette>()
+            .unwrap();
+        assert_eq!(pal.0[1], [0xC0, 0xC0, 0xC0]);
+
+        assert!("FFFFFF,000000".parse::<DmgPalette>().is_err());
+        assert!("FFFFFF,C0C0C0,808080,00000G".parse::<DmgPalette>().is_err());
+    }
+}
@@ -1,7 +1,9 @@
 use super::dbg;
+use super::debug::StackFrame;
 use super::io::Latch;
 use super::mem::MemRW;
-use super::opcodes::OPCODES;
+use super::opcodes::{CB_HANDLERS, CB_OPCODES, OPCODES, OP_HANDLERS};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 use std::collections::HashSet;
 
@@ -39,7 +41,9 @@ pub enum CpuState {
     FetchByte0,
     FetchByte1,
     FetchMemory,
+    FetchMemory1,
     Writeback,
+    Writeback1,
     Delay(u8),
 }
 
@@ -63,6 +67,7 @@ pub struct CPU {
 
     // Misc
     pub halted: Latch<bool>,
+    pub stopped: bool,
     pub intr_enabled: Latch<bool>,
 
     // Execution-related members
@@ -79,11 +84,13 @@ pub struct CPU {
     // Debug
     paused: bool,
     breakpoints: HashSet<u16>,
-    pub call_stack: Vec<u16>,
+    pub call_stack: Vec<StackFrame>,
 
     // Hacks/workarounds
     pub halt_bug: bool,
-    ignore_next_halt: bool,
+
+    // Address put on the bus by a 16-bit increment/decrement, which can corrupt OAM
+    pub idu_addr: Option<u16>,
 }
 
 impl Default for CPU {
@@ -97,6 +104,7 @@ impl Default for CPU {
             pc: 0x0100,
 
             halted: Latch::new(false),
+            stopped: false,
             intr_enabled: Latch::new(false),
 
             state: CpuState::FetchOpcode,
@@ -111,42 +119,66 @@ impl Default for CPU {
 
             paused: false,
             breakpoints: HashSet::new(),
-            call_stack: vec![0x0100],
+            call_stack: vec![StackFrame::root(0x0100)],
 
             halt_bug: false,
-            ignore_next_halt: false,
+
+            idu_addr: None,
         }
     }
 }
 
 impl CPU {
+    /// Creates a CPU in the state left by the boot ROM, ready to run the cartridge.
     pub fn new() -> CPU {
         CPU::default()
     }
 
+    /// Creates a CPU in its power-on state, ready to run the boot ROM.
+    pub fn power_on() -> CPU {
+        CPU {
+            af: 0x0000,
+            bc: 0x0000,
+            de: 0x0000,
+            hl: 0x0000,
+            sp: 0x0000,
+            pc: 0x0000,
+
+            call_stack: vec![StackFrame::root(0x0000)],
+
+            ..CPU::default()
+        }
+    }
+
     pub fn tick(&mut self, bus: &mut impl MemRW) -> Result<(), dbg::TraceEvent> {
         use CpuState::*;
 
         let saved_ctx = self.clone();
 
-        self.intr_enabled.tick();
         self.halted.tick();
 
-        if *self.halted.value() {
+        if *self.halted.value() || self.stopped {
             return Ok(());
         }
 
-        self.remaining_cycles -= 4;
+        self.remaining_cycles = self.remaining_cycles.saturating_sub(4);
 
         let res = match self.state {
             FetchOpcode => self.fetch_opcode(bus),
             FetchByte0 => self.fetch_immediate(bus),
             FetchByte1 => self.fetch_immediate(bus),
             FetchMemory => self.fetch_memory(bus),
+            FetchMemory1 => self.fetch_memory(bus),
             Writeback => self.writeback(bus),
+            Writeback1 => self.writeback(bus),
             Delay(0) => {
-                self.state = CpuState::FetchOpcode;
-                self.executing = false;
+                // Internal delays might precede the memory accesses of an instruction
+                if self.write_op.is_some() {
+                    self.state = CpuState::Writeback;
+                } else {
+                    self.state = CpuState::FetchOpcode;
+                    self.executing = false;
+                }
                 Ok(())
             }
             Delay(n) => {
@@ -163,27 +195,13 @@ impl CPU {
         // }
 
         match res {
-            Err(dbg::TraceEvent::CgbSpeedSwitchReq) => {
-                // A speed switch in CGB is followed by a STOP which should be ignored.
-                // Some ROMs (eg. Blargg's test ROMs) might call this on DMG, in which
-                // case it should be ignored.
-                self.ignore_next_halt = true;
-                Ok(())
-            }
             Err(e) => {
                 // Restore previous state on error. Note that this is for debugging purposes only,
                 // the side effects of the instruction (eg. memory writes) are NOT rolled back.
                 *self = saved_ctx;
                 Err(e)
             }
-            Ok(()) => {
-                // See above for the CGB workaround
-                if *self.halted.loaded() && self.ignore_next_halt {
-                    self.ignore_next_halt = false;
-                    self.halted.reset(false);
-                }
-                Ok(())
-            }
+            Ok(()) => Ok(()),
         }
     }
 
@@ -196,6 +214,10 @@ impl CPU {
             self.resume();
         }
 
+        // An IME enable requested by EI takes effect only after the following instruction,
+        // ie. the one being fetched now. Interrupts are checked at the end of it.
+        self.intr_enabled.tick();
+
         // Fetch opcode and reset internal state
         self.opcode = self.fetch_pc(bus)?;
         self.info = OPCODES[self.opcode as usize];
@@ -204,7 +226,7 @@ impl CPU {
         self.write_op = None;
         self.executing = true;
         self.branch_taken = false;
-        self.remaining_cycles = self.info.5 - 4;
+        self.remaining_cycles = self.info.5.saturating_sub(4);
 
         // Check if we need to fetch more bytes, otherwise execute directly
         if self.info.3 > 1 {
@@ -234,10 +256,10 @@ impl CPU {
 
                     if self.operand & 0x7 == 0x6 {
                         self.info.2 = Memory(HL);
-
-                        let cy = if self.operand & 0xC0 == 0x40 { 4 } else { 8 };
-                        self.remaining_cycles += cy;
                     }
+
+                    // The prefix fetch is already accounted for in the PREFIX CB entry
+                    self.remaining_cycles += CB_OPCODES[self.opcode as usize].4 - self.info.5;
                 }
 
                 // Check if we need to fetch more bytes, otherwise execute directly
@@ -271,17 +293,25 @@ impl CPU {
 
         // Operand location in memory is codified in the opcode.
         // This handles all possible memory addressings.
-        self.operand = match self.info.2 {
-            Memory(C) => bus.read(0xFF00 + u16::from(self.c()))?.into(),
-            Memory(IO) => bus.read(0xFF00 + self.operand)?.into(),
-            Memory(BC) => bus.read(self.bc)?.into(),
-            Memory(DE) => bus.read(self.de)?.into(),
-            Memory(HL) => bus.read(self.hl)?.into(),
-            Memory(A16) => bus.read(self.operand)?.into(),
-            Memory(SP) => {
-                let r = self.fetch_word(bus, self.sp)?;
-                self.sp += 2;
-                r
+        self.operand = match (self.state, self.info.2) {
+            (CpuState::FetchMemory, Memory(C)) => bus.read(0xFF00 + u16::from(self.c()))?.into(),
+            (CpuState::FetchMemory, Memory(IO)) => bus.read(0xFF00 + self.operand)?.into(),
+            (CpuState::FetchMemory, Memory(BC)) => bus.read(self.bc)?.into(),
+            (CpuState::FetchMemory, Memory(DE)) => bus.read(self.de)?.into(),
+            (CpuState::FetchMemory, Memory(HL)) => bus.read(self.hl)?.into(),
+            (CpuState::FetchMemory, Memory(A16)) => bus.read(self.operand)?.into(),
+
+            // 16-bit pops take one memory cycle per byte, low byte first
+            (CpuState::FetchMemory, Memory(SP)) => {
+                self.operand = bus.read(self.sp)?.into();
+                self.sp = self.sp.wrapping_add(1);
+                self.state = CpuState::FetchMemory1;
+                return Ok(());
+            }
+            (CpuState::FetchMemory1, Memory(SP)) => {
+                let hi = bus.read(self.sp)?;
+                self.sp = self.sp.wrapping_add(1);
+                (u16::from(hi) << 8) | self.operand
             }
             _ => unreachable!(),
         };
@@ -291,83 +321,180 @@ impl CPU {
     }
 
     fn exec(&mut self) -> Result<(), dbg::TraceEvent> {
+        use WritebackOp::*;
+
+        // 16-bit increments and decrements put the register on the address bus
+        self.idu_addr = match (self.cb_mode, self.opcode) {
+            (false, 0x03) | (false, 0x0B) => Some(self.bc),
+            (false, 0x13) | (false, 0x1B) => Some(self.de),
+            (false, 0x23) | (false, 0x2B) => Some(self.hl),
+            (false, 0x33) | (false, 0x3B) => Some(self.sp),
+            _ => None,
+        };
+
         // Execute operation
-        if !self.cb_mode {
-            self.op()?;
+        let handler = if !self.cb_mode {
+            OP_HANDLERS[self.opcode as usize]
         } else {
-            self.op_cb()?;
-        }
+            CB_HANDLERS[self.opcode as usize]
+        };
+        handler(self)?;
 
         // Adjust remaining cycles based on the branching information
         if self.branch_taken {
             self.remaining_cycles += self.info.4 - self.info.5;
         }
 
-        // If nothing needs to be written to memory, we are done
-        if self.write_op.is_some() {
+        // Pushes happen in the last two cycles of an instruction, while returns are
+        // followed by an internal delay while PC is being set: whatever is left
+        // comes before the memory accesses.
+        let pre_delay = match self.write_op {
+            Some(Push(_)) => self.remaining_cycles.saturating_sub(8) / 4,
+            Some(Return) => self.remaining_cycles.saturating_sub(12) / 4,
+            _ => 0,
+        };
+
+        if self.write_op.is_some() && pre_delay > 0 {
+            self.state = CpuState::Delay(pre_delay - 1);
+        } else if self.write_op.is_some() {
             self.state = CpuState::Writeback;
-        } else if self.remaining_cycles > 0 {
-            self.state = CpuState::Delay((self.remaining_cycles - 1) / 4)
         } else {
-            self.state = CpuState::FetchOpcode;
-            self.executing = false;
+            self.end_instruction();
         }
 
         Ok(())
     }
 
     fn writeback(&mut self, bus: &mut impl MemRW) -> Result<(), dbg::TraceEvent> {
+        use CpuState::*;
         use WritebackOp::*;
 
-        // After a writeback operation, reset state machine for the next instruction
+        // 16-bit operations take one memory cycle per byte
+        let res = match (self.state, self.write_op) {
+            (Writeback, Some(Write8(dest, d8))) => {
+                self.write_op = None;
+                bus.write(dest, d8)
+            }
+            (Writeback, Some(Write16(dest, d16))) => {
+                self.state = Writeback1;
+                bus.write(dest, d16 as u8)
+            }
+            (Writeback1, Some(Write16(dest, d16))) => {
+                self.write_op = None;
+                bus.write(dest.wrapping_add(1), (d16 >> 8) as u8)
+            }
+            (Writeback, Some(Push(d16))) => {
+                self.sp = self.sp.wrapping_sub(1);
+                self.state = Writeback1;
+                bus.write(self.sp, (d16 >> 8) as u8)
+            }
+            (Writeback1, Some(Push(d16))) => {
+                self.write_op = None;
+                self.sp = self.sp.wrapping_sub(1);
+                bus.write(self.sp, d16 as u8)
+            }
+            (Writeback, Some(Return)) => {
+                // This is basically a POP PC operation
+                self.operand = bus.read(self.sp)?.into();
+                self.sp = self.sp.wrapping_add(1);
+                self.state = Writeback1;
+                Ok(())
+            }
+            (Writeback1, Some(Return)) => {
+                self.write_op = None;
+                self.pc = (u16::from(bus.read(self.sp)?) << 8) | self.operand;
+                self.sp = self.sp.wrapping_add(1);
+                Ok(())
+            }
+            _ => unreachable!(),
+        };
+
+        // After the last writeback operation, reset state machine for the next instruction
+        if self.write_op.is_none() {
+            self.end_instruction();
+        }
+
+        res
+    }
+
+    /// Waits for the remaining cycles of the current instruction, if any,
+    /// before fetching the next one.
+    fn end_instruction(&mut self) {
         if self.remaining_cycles > 0 {
             self.state = CpuState::Delay((self.remaining_cycles - 1) / 4);
         } else {
             self.state = CpuState::FetchOpcode;
             self.executing = false;
         }
+    }
 
-        match self.write_op {
-            Some(Write8(dest, d8)) => bus.write(dest, d8),
-            Some(Write16(dest, d16)) => self.store_word(bus, dest, d16),
-            Some(Push(d16)) => {
-                self.sp -= 2;
-                self.store_word(bus, self.sp, d16)
-            }
-            Some(Return) => {
-                // This is basically a POP PC operation
-                self.pc = self.fetch_word(bus, self.sp)?;
-                self.sp += 2;
-                Ok(())
-            }
-            None => Ok(()),
+    /// Checks for pending interrupt requests and services the highest-priority one, if any.
+    ///
+    /// Any pending request wakes the CPU up from HALT mode. If IME is set, it is also cleared,
+    /// the request is acknowledged in IF and the CPU jumps to the corresponding vector.
+    ///
+    /// Returns `true` if an interrupt service routine is being entered.
+    pub fn service_irqs(&mut self, bus: &mut impl MemRW) -> Result<bool, dbg::TraceEvent> {
+        let ien = bus.read(0xFFFF)?;
+        let ifg = bus.read(0xFF0F)?;
+
+        // Only the lower 5 bits are mapped to actual interrupt sources
+        let pending = ien & ifg & 0x1F;
+
+        if pending == 0 {
+            return Ok(false);
+        }
+
+        self.halted.reset(false);
+
+        // If IME = 0, simply leave HALT mode
+        if !*self.intr_enabled.value() {
+            return Ok(false);
         }
+
+        // Lower bits have higher priority
+        let id = pending.trailing_zeros() as u16;
+
+        self.intr_enabled.reset(false);
+        bus.write(0xFF0F, ifg & !(1 << id) & 0x1F)?;
+
+        self.push_frame(0x40 + 0x08 * id, true);
+        self.jump_to_isr(0x40 + 0x08 * id);
+
+        Ok(true)
     }
 
-    pub fn jump_to_isr(&mut self, bus: &mut impl MemRW, addr: u16) -> Result<(), dbg::TraceEvent> {
-        // Push PC onto the stack
-        self.sp -= 2;
-        self.store_word(bus, self.sp, self.pc)?;
+    /// Returns true if IME has been enabled by EI, but has not yet taken effect.
+    pub fn ime_pending(&self) -> bool {
+        *self.intr_enabled.loaded() && !*self.intr_enabled.value()
+    }
 
-        // Jump to ISR
+    /// Enters STOP mode. If a speed switch has been prepared through KEY1, the system
+    /// performs it and wakes the CPU up right away.
+    pub fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    pub fn jump_to_isr(&mut self, addr: u16) {
+        // The dispatch takes 5 cycles: two wait states, PC being pushed onto
+        // the stack one byte at a time, and finally the jump to the ISR.
+        self.write_op = Some(WritebackOp::Push(self.pc));
         self.pc = addr;
 
-        // Add 5 wait states to match hardware behavior
         self.executing = true;
-        self.state = CpuState::Delay(4);
-
-        Ok(())
+        self.remaining_cycles = 20;
+        self.state = CpuState::Delay(1);
     }
 
     pub fn fetch_pc(&mut self, bus: &mut impl MemRW) -> Result<u8, dbg::TraceEvent> {
         let v = bus.read(self.pc)?;
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
         Ok(v)
     }
 
     pub fn fetch_word(&mut self, bus: &mut impl MemRW, addr: u16) -> Result<u16, dbg::TraceEvent> {
         let lo = u16::from(bus.read(addr)?);
-        let hi = u16::from(bus.read(addr + 1)?);
+        let hi = u16::from(bus.read(addr.wrapping_add(1))?);
         Ok((hi << 8) | lo)
     }
 
@@ -378,7 +505,7 @@ impl CPU {
         val: u16,
     ) -> Result<(), dbg::TraceEvent> {
         bus.write(addr, val as u8)?;
-        bus.write(addr + 1, (val >> 8) as u8)
+        bus.write(addr.wrapping_add(1), (val >> 8) as u8)
     }
 
     fn resume(&mut self) {
@@ -410,6 +537,126 @@ impl CPU {
     }
 }
 
+impl Savestate for CPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        for reg in &[self.af, self.bc, self.de, self.hl, self.sp, self.pc] {
+            reg.save_state(w);
+        }
+
+        self.halted.save_state(w);
+        self.stopped.save_state(w);
+        self.intr_enabled.save_state(w);
+
+        let (state, delay) = match self.state {
+            CpuState::FetchOpcode => (0u8, 0u8),
+            CpuState::FetchByte0 => (1, 0),
+            CpuState::FetchByte1 => (2, 0),
+            CpuState::FetchMemory => (3, 0),
+            CpuState::FetchMemory1 => (4, 0),
+            CpuState::Writeback => (5, 0),
+            CpuState::Writeback1 => (6, 0),
+            CpuState::Delay(n) => (7, n),
+        };
+        state.save_state(w);
+        delay.save_state(w);
+
+        self.opcode.save_state(w);
+        self.cb_mode.save_state(w);
+        self.operand.save_state(w);
+
+        let (op, addr, val) = match self.write_op {
+            None => (0u8, 0u16, 0u16),
+            Some(WritebackOp::Write8(addr, val)) => (1, addr, u16::from(val)),
+            Some(WritebackOp::Write16(addr, val)) => (2, addr, val),
+            Some(WritebackOp::Push(val)) => (3, 0, val),
+            Some(WritebackOp::Return) => (4, 0, 0),
+        };
+        op.save_state(w);
+        addr.save_state(w);
+        val.save_state(w);
+
+        self.executing.save_state(w);
+        self.branch_taken.save_state(w);
+        self.remaining_cycles.save_state(w);
+        self.halt_bug.save_state(w);
+        self.idu_addr.save_state(w);
+    }
+
+    /// Restores the CPU state. The decoded opcode info is recomputed from
+    /// the opcode, and the call stack restarts from the current PC.
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        for reg in &mut [
+            &mut self.af,
+            &mut self.bc,
+            &mut self.de,
+            &mut self.hl,
+            &mut self.sp,
+            &mut self.pc,
+        ] {
+            reg.load_state(r)?;
+        }
+
+        self.halted.load_state(r)?;
+        self.stopped.load_state(r)?;
+        self.intr_enabled.load_state(r)?;
+
+        let (mut state, mut delay) = (0u8, 0u8);
+        state.load_state(r)?;
+        delay.load_state(r)?;
+
+        self.state = match state {
+            0 => CpuState::FetchOpcode,
+            1 => CpuState::FetchByte0,
+            2 => CpuState::FetchByte1,
+            3 => CpuState::FetchMemory,
+            4 => CpuState::FetchMemory1,
+            5 => CpuState::Writeback,
+            6 => CpuState::Writeback1,
+            7 => CpuState::Delay(delay),
+            _ => return Err(dbg::TraceEvent::InvalidSavestate("CPU state")),
+        };
+
+        self.opcode.load_state(r)?;
+        self.cb_mode.load_state(r)?;
+        self.operand.load_state(r)?;
+
+        let (mut op, mut addr, mut val) = (0u8, 0u16, 0u16);
+        op.load_state(r)?;
+        addr.load_state(r)?;
+        val.load_state(r)?;
+
+        self.write_op = match op {
+            0 => None,
+            1 => Some(WritebackOp::Write8(addr, val as u8)),
+            2 => Some(WritebackOp::Write16(addr, val)),
+            3 => Some(WritebackOp::Push(val)),
+            4 => Some(WritebackOp::Return),
+            _ => return Err(dbg::TraceEvent::InvalidSavestate("writeback op")),
+        };
+
+        self.executing.load_state(r)?;
+        self.branch_taken.load_state(r)?;
+        self.remaining_cycles.load_state(r)?;
+        self.halt_bug.load_state(r)?;
+        self.idu_addr.load_state(r)?;
+
+        // CB opcodes are decoded through the PREFIX CB entry, see `fetch_immediate`
+        if self.cb_mode {
+            self.info = OPCODES[0xCB];
+
+            if self.opcode & 0x7 == 0x6 {
+                self.info.2 = OperandLocation::Memory(MemoryAddressing::HL);
+            }
+        } else {
+            self.info = OPCODES[self.opcode as usize];
+        }
+
+        self.call_stack = vec![StackFrame::root(self.pc)];
+
+        Ok(())
+    }
+}
+
 #[rustfmt::skip]
 impl CPU {
     pub fn c(&self) -> u8 { self.bc as u8 }
@@ -1,43 +1,87 @@
-use super::dbg;
+use super::decode::{decode, Instruction};
 use super::mem::MemR;
-use super::opcodes::OPCODES;
 use super::CPU;
 
+/// Entry of the debugger's shadow call stack.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum Immediate {
-    Imm8(u8),
-    Imm16(u16),
+pub struct StackFrame {
+    /// Address of the called function or interrupt vector
+    pub entry: u16,
+    /// Address the function is expected to return to
+    pub return_addr: u16,
+    /// Stack location holding the return address
+    pub sp: u16,
+    /// Whether the frame has been entered by an interrupt
+    pub interrupt: bool,
 }
 
-#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
-pub struct Instruction {
-    pub opcode: u8,
-    pub mnemonic: &'static str,
-    pub imm: Option<Immediate>,
-    pub size: u8,
+impl StackFrame {
+    /// Returns the bottom frame, which is never popped.
+    pub fn root(entry: u16) -> StackFrame {
+        StackFrame {
+            entry,
+            return_addr: entry,
+            sp: 0xFFFF,
+            interrupt: false,
+        }
+    }
 }
 
 impl CPU {
-    pub fn disasm(&self, mem: &impl MemR, addr: u16) -> Result<Instruction, dbg::TraceEvent> {
-        let opcode = mem.read(addr)?;
-        let info = &OPCODES[opcode as usize];
-
-        let imm: Option<Immediate> = match info.3 {
-            1 => None,
-            2 => Some(Immediate::Imm8(mem.read(addr + 1)?)),
-            3 => {
-                let lo = u16::from(mem.read(addr + 1)?);
-                let hi = u16::from(mem.read(addr + 2)?);
-                Some(Immediate::Imm16((hi << 8) | lo))
-            }
-            _ => unreachable!(),
-        };
-
-        Ok(Instruction {
-            opcode,
-            mnemonic: info.0,
-            imm,
-            size: info.3,
-        })
+    /// Tracks a call to `entry`, whose return address is about to be pushed onto the stack.
+    pub fn push_frame(&mut self, entry: u16, interrupt: bool) {
+        let sp = self.sp.wrapping_sub(2);
+
+        // Anything at or below the new return address has been discarded, eg. because
+        // the program has reset SP or popped a return address manually.
+        self.call_stack.retain(|f| f.sp > sp);
+
+        self.call_stack.push(StackFrame {
+            entry,
+            return_addr: self.pc,
+            sp,
+            interrupt,
+        });
+    }
+
+    /// Tracks a return, whose address is about to be popped from the stack.
+    pub fn pop_frame(&mut self) {
+        let sp = self.sp;
+
+        // Drop frames whose return address has already been discarded, then the
+        // current one, if it actually owns the address being popped.
+        // Otherwise this is likely a jump in disguise (eg. PUSH HL; RET).
+        self.call_stack.retain(|f| f.sp >= sp);
+
+        if self.call_stack.len() > 1 && self.call_stack.last().map(|f| f.sp) == Some(sp) {
+            self.call_stack.pop();
+        }
+    }
+
+    /// Returns the function currently being executed, according to the call stack.
+    pub fn current_function(&self) -> Option<u16> {
+        self.call_stack.last().map(|f| f.entry)
+    }
+
+    /// Enables or disables interrupts right away, discarding any IME change pending
+    /// after EI. Only meant to be used from a debugger, between instructions.
+    pub fn set_ime(&mut self, enable: bool) {
+        self.intr_enabled.reset(enable);
+    }
+
+    /// Enters or leaves HALT mode right away. Only meant to be used from a debugger.
+    pub fn set_halted(&mut self, halted: bool) {
+        self.halted.reset(halted);
+    }
+
+    /// Disassembles the instruction at `addr`, reading memory without side effects.
+    pub fn disasm(&self, mem: &impl MemR, addr: u16) -> Instruction {
+        let bytes = [
+            mem.peek(addr),
+            mem.peek(addr.wrapping_add(1)),
+            mem.peek(addr.wrapping_add(2)),
+        ];
+
+        decode(&bytes)
     }
 }
@@ -0,0 +1,419 @@
+use super::opcodes::{CB_OPCODES, OPCODES};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Immediate {
+    Imm8(u8),
+    Imm16(u16),
+}
+
+/// Registers which can be named by an operand.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+impl Register {
+    fn parse(name: &str) -> Option<Register> {
+        use Register::*;
+
+        Some(match name {
+            "A" => A,
+            "B" => B,
+            "C" => C,
+            "D" => D,
+            "E" => E,
+            "H" => H,
+            "L" => L,
+            "AF" => AF,
+            "BC" => BC,
+            "DE" => DE,
+            "HL" => HL,
+            "SP" => SP,
+            _ => return None,
+        })
+    }
+
+    /// Returns true for register pairs and SP.
+    pub fn is_wide(self) -> bool {
+        use Register::*;
+
+        matches!(self, AF | BC | DE | HL | SP)
+    }
+}
+
+/// Conditions of conditional jumps, calls and returns.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Condition {
+    NZ,
+    Z,
+    NC,
+    C,
+}
+
+/// Operand of an instruction, with any immediate value already decoded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operand {
+    /// A register: `B`, `HL`, ...
+    Reg(Register),
+    /// Memory pointed by a register pair: `(HL)`, ...
+    Indirect(Register),
+    /// Memory pointed by HL, which is incremented afterwards: `(HL+)`
+    IndirectInc,
+    /// Memory pointed by HL, which is decremented afterwards: `(HL-)`
+    IndirectDec,
+    /// IO register at 0xFF00 + C: `(C)`
+    HighC,
+    /// IO register at 0xFF00 + a8: `(a8)`
+    High(u8),
+    /// Memory at an absolute address: `(a16)`
+    Direct(u16),
+    /// Immediate value: `d8`, `d16`
+    Imm8(u8),
+    Imm16(u16),
+    /// Jump or call target: `a16`
+    Absolute(u16),
+    /// Jump target, relative to the end of the instruction: `r8`
+    Relative(i8),
+    /// Signed immediate added to SP: `r8` in `ADD SP,r8`, `SP+r8` in `LD HL,SP+r8`
+    SpOffset(i8),
+    /// Branch condition: `NZ`, `Z`, `NC`, `C`
+    Cond(Condition),
+    /// Bit number of `BIT`, `RES` and `SET`
+    Bit(u8),
+    /// Restart vector of `RST`
+    Vector(u8),
+}
+
+/// How an instruction affects a flag.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlagEffect {
+    #[default]
+    Unaffected,
+    Reset,
+    Set,
+    /// Depends on the result
+    Affected,
+}
+
+/// Effects of an instruction on the Z, N, H and C flags.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FlagEffects {
+    pub z: FlagEffect,
+    pub n: FlagEffect,
+    pub h: FlagEffect,
+    pub c: FlagEffect,
+}
+
+impl FlagEffects {
+    /// Parses effects written like the opcode tables in the Pan Docs, eg. `"Z0HC"`:
+    /// `-` unaffected, `0` reset, `1` set, and a flag name if it depends on the result.
+    fn parse(s: &str) -> FlagEffects {
+        let effect = |c| match c {
+            b'-' => FlagEffect::Unaffected,
+            b'0' => FlagEffect::Reset,
+            b'1' => FlagEffect::Set,
+            _ => FlagEffect::Affected,
+        };
+        let s = s.as_bytes();
+
+        FlagEffects {
+            z: effect(s[0]),
+            n: effect(s[1]),
+            h: effect(s[2]),
+            c: effect(s[3]),
+        }
+    }
+}
+
+/// A decoded instruction.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub imm: Option<Immediate>,
+    pub size: u8,
+    /// Operands, destination first
+    pub operands: [Option<Operand>; 2],
+    /// Cycles taken, if branching for conditional instructions
+    pub cycles: u8,
+    /// Cycles taken by conditional instructions when not branching
+    pub cycles_not_taken: u8,
+    pub flags: FlagEffects,
+}
+
+impl Instruction {
+    /// Returns true for unused opcodes, which lock up the CPU.
+    pub fn is_illegal(&self) -> bool {
+        self.mnemonic == "-"
+    }
+
+    /// Returns true for jumps, calls, returns and restarts.
+    pub fn is_branch(&self) -> bool {
+        ["JP", "JR", "CALL", "RET", "RST"]
+            .iter()
+            .any(|b| self.mnemonic.starts_with(b))
+    }
+
+    /// Returns true for instructions which only branch if a condition holds.
+    pub fn is_conditional(&self) -> bool {
+        self.cycles != self.cycles_not_taken
+    }
+}
+
+/// Decodes the instruction at the beginning of `bytes`, from the same opcode tables
+/// the CPU executes. Missing bytes (eg. at the end of memory) are read as 0xFF.
+pub fn decode(bytes: &[u8]) -> Instruction {
+    let byte = |i: usize| bytes.get(i).cloned().unwrap_or(0xFF);
+
+    let opcode = byte(0);
+    let mut info = &OPCODES[opcode as usize];
+
+    // CB-prefixed instructions are described by the byte following the prefix
+    if opcode == 0xCB {
+        info = &CB_OPCODES[byte(1) as usize];
+    }
+
+    let imm = match info.3 {
+        1 => None,
+        2 => Some(Immediate::Imm8(byte(1))),
+        3 => {
+            let lo = u16::from(byte(1));
+            let hi = u16::from(byte(2));
+            Some(Immediate::Imm16((hi << 8) | lo))
+        }
+        _ => unreachable!(),
+    };
+
+    let mut operands = [None, None];
+    let mut args = info.0.splitn(2, ' ');
+    let name = args.next().unwrap_or("");
+
+    if let Some(args) = args.next() {
+        for (i, arg) in args.split(',').enumerate().take(2) {
+            operands[i] = operand(name, i, arg, imm);
+        }
+    }
+
+    Instruction {
+        opcode,
+        mnemonic: info.0,
+        imm,
+        size: info.3,
+        operands,
+        cycles: info.4,
+        cycles_not_taken: info.5,
+        flags: flag_effects(name, &operands),
+    }
+}
+
+/// Decodes the `i`-th argument `arg` of an instruction called `name`.
+fn operand(name: &str, i: usize, arg: &str, imm: Option<Immediate>) -> Option<Operand> {
+    let imm8 = match imm {
+        Some(Immediate::Imm8(d8)) => d8,
+        _ => 0,
+    };
+    let imm16 = match imm {
+        Some(Immediate::Imm16(d16)) => d16,
+        _ => 0,
+    };
+
+    let branch = matches!(name, "JP" | "JR" | "CALL" | "RET");
+
+    Some(match arg {
+        "NZ" if branch => Operand::Cond(Condition::NZ),
+        "Z" if branch => Operand::Cond(Condition::Z),
+        "NC" if branch => Operand::Cond(Condition::NC),
+        "C" if branch => Operand::Cond(Condition::C),
+        "(HL+)" => Operand::IndirectInc,
+        "(HL-)" => Operand::IndirectDec,
+        "(C)" => Operand::HighC,
+        "(a8)" => Operand::High(imm8),
+        "(a16)" => Operand::Direct(imm16),
+        "d8" => Operand::Imm8(imm8),
+        "d16" => Operand::Imm16(imm16),
+        "a16" => Operand::Absolute(imm16),
+        "r8" if name == "JR" => Operand::Relative(imm8 as i8),
+        "r8" | "SP+r8" => Operand::SpOffset(imm8 as i8),
+        _ if name == "RST" => Operand::Vector(u8::from_str_radix(&arg[..2], 16).ok()?),
+        _ if name == "STOP" => Operand::Imm8(imm8),
+        _ if i == 0 && (name == "BIT" || name == "RES" || name == "SET") => {
+            Operand::Bit(arg.parse().ok()?)
+        }
+        _ if arg.starts_with('(') => {
+            Operand::Indirect(Register::parse(arg.trim_matches(|c| c == '(' || c == ')'))?)
+        }
+        _ => Operand::Reg(Register::parse(arg)?),
+    })
+}
+
+/// Returns the effects on the flags of an instruction called `name`.
+fn flag_effects(name: &str, operands: &[Option<Operand>; 2]) -> FlagEffects {
+    let wide = match operands[0] {
+        Some(Operand::Reg(r)) => r.is_wide(),
+        _ => false,
+    };
+
+    FlagEffects::parse(match name {
+        "ADD" if operands[0] == Some(Operand::Reg(Register::SP)) => "00HC",
+        "ADD" if wide => "-0HC",
+        "ADD" | "ADC" => "Z0HC",
+        "SUB" | "SBC" | "CP" => "Z1HC",
+        "AND" => "Z010",
+        "OR" | "XOR" => "Z000",
+        "INC" | "DEC" if wide => "----",
+        "INC" => "Z0H-",
+        "DEC" => "Z1H-",
+        "LD" if is_sp_offset(operands[1]) => "00HC",
+        "RLCA" | "RRCA" | "RLA" | "RRA" => "000C",
+        "RLC" | "RRC" | "RL" | "RR" | "SLA" | "SRA" | "SRL" => "Z00C",
+        "SWAP" => "Z000",
+        "BIT" => "Z01-",
+        "DAA" => "Z-0C",
+        "CPL" => "-11-",
+        "SCF" => "-001",
+        "CCF" => "-00C",
+        "POP" if operands[0] == Some(Operand::Reg(Register::AF)) => "ZNHC",
+        _ => "----",
+    })
+}
+
+fn is_sp_offset(operand: Option<Operand>) -> bool {
+    matches!(operand, Some(Operand::SpOffset(_)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::CPU;
+    use super::*;
+
+    #[test]
+    fn operands_are_decoded() {
+        let ld = decode(&[0xEA, 0x34, 0x12]);
+        assert_eq!(ld.mnemonic, "LD (a16),A");
+        assert_eq!(ld.size, 3);
+        assert_eq!(
+            ld.operands,
+            [
+                Some(Operand::Direct(0x1234)),
+                Some(Operand::Reg(Register::A))
+            ]
+        );
+
+        let jr = decode(&[0x38, 0xFE]);
+        assert_eq!(
+            jr.operands,
+            [
+                Some(Operand::Cond(Condition::C)),
+                Some(Operand::Relative(-2))
+            ]
+        );
+        assert_eq!((jr.cycles, jr.cycles_not_taken), (12, 8));
+        assert!(jr.is_branch() && jr.is_conditional());
+
+        let bit = decode(&[0xCB, 0x7E]);
+        assert_eq!(bit.mnemonic, "BIT 7,(HL)");
+        assert_eq!(
+            bit.operands,
+            [Some(Operand::Bit(7)), Some(Operand::Indirect(Register::HL))]
+        );
+        assert_eq!(bit.cycles, 12);
+        assert!(!bit.is_branch());
+        assert!(decode(&[0xC9]).is_branch() && !decode(&[0xC9]).is_conditional());
+
+        assert_eq!(decode(&[0xFF]).operands[0], Some(Operand::Vector(0x38)));
+        assert_eq!(decode(&[0xE2]).operands[0], Some(Operand::HighC));
+        assert_eq!(
+            decode(&[0xF8, 0x80]).operands[1],
+            Some(Operand::SpOffset(-128))
+        );
+        assert!(decode(&[0xD3]).is_illegal());
+
+        // Missing bytes read as open bus
+        assert_eq!(decode(&[0xC3]).imm, Some(Immediate::Imm16(0xFFFF)));
+    }
+
+    #[test]
+    fn flag_effects_are_decoded() {
+        use FlagEffect::*;
+
+        let effects = |bytes: &[u8]| {
+            let f = decode(bytes).flags;
+            [f.z, f.n, f.h, f.c]
+        };
+
+        assert_eq!(effects(&[0x80]), [Affected, Reset, Affected, Affected]);
+        assert_eq!(effects(&[0x09]), [Unaffected, Reset, Affected, Affected]);
+        assert_eq!(effects(&[0x03]), [Unaffected; 4]);
+        assert_eq!(effects(&[0x34]), [Affected, Reset, Affected, Unaffected]);
+        assert_eq!(effects(&[0xE8, 0x01]), [Reset, Reset, Affected, Affected]);
+        assert_eq!(effects(&[0x37]), [Unaffected, Reset, Reset, Set]);
+        assert_eq!(effects(&[0xF1]), [Affected; 4]);
+        assert_eq!(effects(&[0xCB, 0x37]), [Affected, Reset, Reset, Reset]);
+        assert_eq!(effects(&[0xCB, 0xC7]), [Unaffected; 4]);
+    }
+
+    /// Executes the instruction at the beginning of `mem` with flags `f`, returning
+    /// the flags afterwards.
+    fn run_with_flags(mem: &mut [u8], f: u8) -> u8 {
+        let mut cpu = CPU::new();
+        cpu.af = u16::from(f);
+        cpu.sp = 0x8000;
+        cpu.hl = 0x8000;
+        cpu.pc = 0;
+
+        cpu.tick(&mut &mut mem[..]).unwrap();
+        while cpu.executing {
+            cpu.tick(&mut &mut mem[..]).unwrap();
+        }
+        cpu.f()
+    }
+
+    #[test]
+    fn flag_effects_match_execution() {
+        let check = |bytes: &[u8]| {
+            let instr = decode(bytes);
+
+            if instr.is_illegal() || instr.mnemonic == "HALT" || instr.mnemonic == "STOP 0" {
+                return;
+            }
+
+            let masks = [0x80, 0x40, 0x20, 0x10];
+            let flags = [instr.flags.z, instr.flags.n, instr.flags.h, instr.flags.c];
+
+            for f in [0x00, 0xF0].iter() {
+                let mut mem = vec![0; 0x10000];
+                mem[..bytes.len()].copy_from_slice(bytes);
+
+                let after = run_with_flags(&mut mem, *f);
+
+                for (mask, effect) in masks.iter().zip(flags.iter()) {
+                    let expected = match effect {
+                        FlagEffect::Unaffected => f & mask,
+                        FlagEffect::Reset => 0,
+                        FlagEffect::Set => *mask,
+                        FlagEffect::Affected => continue,
+                    };
+                    assert_eq!(after & mask, expected, "{} F={:02X}", instr.mnemonic, f);
+                }
+            }
+        };
+
+        for op in 0_u8..=255 {
+            if op != 0xCB {
+                check(&[op, 0x01, 0x01]);
+            }
+            check(&[0xCB, op]);
+        }
+    }
+}
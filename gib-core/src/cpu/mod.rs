@@ -1,5 +1,6 @@
 mod core;
 mod debug;
+mod decode;
 mod opcodes;
 
 use super::dbg;
@@ -8,4 +9,5 @@ use super::mem;
 
 pub use self::core::*;
 pub use self::debug::*;
+pub use self::decode::*;
 pub use self::opcodes::*;
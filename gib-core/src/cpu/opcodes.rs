@@ -23,10 +23,10 @@ macro_rules! jr {
 macro_rules! call {
     ($cpu:ident, $cond:expr, $to:expr) => {{
         if $cond {
+            $cpu.push_frame($to, false);
             $cpu.write_op = Some(WritebackOp::Push($cpu.pc));
             $cpu.pc = $to;
             $cpu.branch_taken = true;
-            $cpu.call_stack.push($cpu.pc);
         }
     }};
 }
@@ -34,7 +34,7 @@ macro_rules! call {
 macro_rules! ret {
     ($cpu:ident, $cond:expr) => {{
         if $cond {
-            $cpu.call_stack.pop();
+            $cpu.pop_frame();
             $cpu.write_op = Some(WritebackOp::Return);
             $cpu.branch_taken = true;
         }
@@ -58,62 +58,106 @@ macro_rules! or  { ($cpu:ident, $rhs:expr) => { logical!($cpu, |, $rhs, 0, 0, 0)
 
 macro_rules! inc {
     ($cpu:ident, $v:expr) => {{
-        $cpu.set_zf(($v + 1) == 0);
+        let v: u8 = $v;
+        let r = v.wrapping_add(1);
+
+        $cpu.set_zf(r == 0);
         $cpu.set_sf(false);
-        $cpu.set_hc(($v & 0xF) == 0xF);
-        $v + 1
+        $cpu.set_hc((v & 0xF) == 0xF);
+        r
     }};
 }
 
 macro_rules! dec {
     ($cpu:ident, $v:expr) => {{
-        $cpu.set_zf(($v - 1) == 0);
+        let v: u8 = $v;
+        let r = v.wrapping_sub(1);
+
+        $cpu.set_zf(r == 0);
         $cpu.set_sf(true);
-        $cpu.set_hc($v.trailing_zeros() >= 4);
-        $v - 1
+        $cpu.set_hc((v & 0xF) == 0x0);
+        r
     }};
 }
 
 macro_rules! add {
-    ($cpu:ident, $v:expr, $cy:expr) => {{
-        let x = u16::from($cpu.a());
-        let y = u16::from($v);
-        let c = u16::from($cy);
+    ($cpu:ident, $v:expr) => {{
+        let x: u8 = $cpu.a();
+        let y: u8 = $v;
 
-        let r = x + y + c;
-        $cpu.set_a(r as u8);
+        let (r, c) = x.overflowing_add(y);
+        $cpu.set_a(r);
 
-        $cpu.set_zf($cpu.a() == 0);
+        $cpu.set_zf(r == 0);
+        $cpu.set_sf(false);
+        $cpu.set_hc((x & 0xF) + (y & 0xF) > 0xF);
+        $cpu.set_cy(c);
+    }};
+}
+
+macro_rules! adc {
+    ($cpu:ident, $v:expr) => {{
+        let x: u8 = $cpu.a();
+        let y: u8 = $v;
+        let c: u8 = $cpu.cy() as u8;
+
+        // The carry is added together with the operand, so that an overflow
+        // of either the first or the second addition sets the flags.
+        let (r, c0) = x.overflowing_add(y);
+        let (r, c1) = r.overflowing_add(c);
+        $cpu.set_a(r);
+
+        $cpu.set_zf(r == 0);
         $cpu.set_sf(false);
-        $cpu.set_hc((x & 0xF) + (y & 0xF) + c >= 0x10);
-        $cpu.set_cy(r >= 0x100);
+        $cpu.set_hc((x & 0xF) + (y & 0xF) + c > 0xF);
+        $cpu.set_cy(c0 || c1);
     }};
 }
 
 macro_rules! sub {
-    ($cpu:ident, $v:expr, $cy:expr) => {{
-        let x = u16::from($cpu.a());
-        let y = u16::from($v);
-        let c = u16::from($cy);
+    ($cpu:ident, $v:expr) => {{
+        let x: u8 = $cpu.a();
+        let y: u8 = $v;
 
-        let r = x - y - c;
-        $cpu.set_a(r as u8);
+        let (r, b) = x.overflowing_sub(y);
+        $cpu.set_a(r);
 
-        $cpu.set_zf($cpu.a() == 0);
+        $cpu.set_zf(r == 0);
+        $cpu.set_sf(true);
+        $cpu.set_hc((y & 0xF) > (x & 0xF));
+        $cpu.set_cy(b);
+    }};
+}
+
+macro_rules! sbc {
+    ($cpu:ident, $v:expr) => {{
+        let x: u8 = $cpu.a();
+        let y: u8 = $v;
+        let c: u8 = $cpu.cy() as u8;
+
+        // Same as ADC: a borrow out of either subtraction sets the flags.
+        let (r, b0) = x.overflowing_sub(y);
+        let (r, b1) = r.overflowing_sub(c);
+        $cpu.set_a(r);
+
+        $cpu.set_zf(r == 0);
         $cpu.set_sf(true);
         $cpu.set_hc((y & 0xF) + c > (x & 0xF));
-        $cpu.set_cy(r >= 0x100);
+        $cpu.set_cy(b0 || b1);
     }};
 }
 
 macro_rules! add16 {
     ($cpu:ident, $dst: expr, $v:expr) => {{
-        let old = $dst;
-        $dst += $v;
+        let x: u16 = $dst;
+        let y: u16 = $v;
+
+        let (r, c) = x.overflowing_add(y);
+        $dst = r;
 
         $cpu.set_sf(false);
-        $cpu.set_hc((old & 0x0FFF) + ($v & 0x0FFF) >= 0x1000);
-        $cpu.set_cy($dst < old);
+        $cpu.set_hc((x & 0x0FFF) + (y & 0x0FFF) > 0x0FFF);
+        $cpu.set_cy(c);
     }};
 }
 
@@ -232,16 +276,19 @@ macro_rules! set {
 }
 
 impl CPU {
+    /// Executes `opcode`. Called through `OP_HANDLERS`, each with a constant opcode.
     #[rustfmt::skip]
+    #[inline(always)]
     #[allow(clippy::cyclomatic_complexity)]
-    pub fn op(&mut self) -> Result<(), dbg::TraceEvent> {
-        match self.opcode {
+    pub fn op(&mut self, opcode: u8) -> Result<(), dbg::TraceEvent> {
+        match opcode {
             /*
              * Misc/control instructions
              */
             0x00 => (),
 
-            0x10 | 0x76 => self.halted.load(true),
+            0x10 => self.stop(),
+            0x76 => self.halted.load(true),
 
             0xF3 => self.intr_enabled.reset(false),
             0xFB => self.intr_enabled.load(true),
@@ -291,13 +338,13 @@ impl CPU {
              */
             0x02 => self.write_op = Some(WritebackOp::Write8(self.bc, self.a())),
             0x12 => self.write_op = Some(WritebackOp::Write8(self.de, self.a())),
-            0x22 => { self.write_op = Some(WritebackOp::Write8(self.hl, self.a())); self.hl += 1; }
-            0x32 => { self.write_op = Some(WritebackOp::Write8(self.hl, self.a())); self.hl -= 1; }
+            0x22 => { self.write_op = Some(WritebackOp::Write8(self.hl, self.a())); self.hl = self.hl.wrapping_add(1); }
+            0x32 => { self.write_op = Some(WritebackOp::Write8(self.hl, self.a())); self.hl = self.hl.wrapping_sub(1); }
 
             0x0A => self.set_a(self.operand as u8),
             0x1A => self.set_a(self.operand as u8),
-            0x2A => { self.set_a(self.operand as u8); self.hl += 1; }
-            0x3A => { self.set_a(self.operand as u8); self.hl -= 1; }
+            0x2A => { self.set_a(self.operand as u8); self.hl = self.hl.wrapping_add(1); }
+            0x3A => { self.set_a(self.operand as u8); self.hl = self.hl.wrapping_sub(1); }
 
             0x06 => self.set_b(self.operand as u8),
             0x16 => self.set_d(self.operand as u8),
@@ -429,41 +476,41 @@ impl CPU {
                 self.write_op = Some(WritebackOp::Write8(self.hl, v));
             }
 
-            0x80 => add!(self, self.b(), 0u8),
-            0x81 => add!(self, self.c(), 0u8),
-            0x82 => add!(self, self.d(), 0u8),
-            0x83 => add!(self, self.e(), 0u8),
-            0x84 => add!(self, self.h(), 0u8),
-            0x85 => add!(self, self.l(), 0u8),
-            0x87 => add!(self, self.a(), 0u8),
-            0x86 | 0xC6 => add!(self, self.operand as u8, 0u8),
-
-            0x88 => add!(self, self.b(), self.cy() as u8),
-            0x89 => add!(self, self.c(), self.cy() as u8),
-            0x8A => add!(self, self.d(), self.cy() as u8),
-            0x8B => add!(self, self.e(), self.cy() as u8),
-            0x8C => add!(self, self.h(), self.cy() as u8),
-            0x8D => add!(self, self.l(), self.cy() as u8),
-            0x8F => add!(self, self.a(), self.cy() as u8),
-            0x8E | 0xCE => add!(self, self.operand as u8, self.cy() as u8),
-
-            0x90 => sub!(self, self.b(), 0u8),
-            0x91 => sub!(self, self.c(), 0u8),
-            0x92 => sub!(self, self.d(), 0u8),
-            0x93 => sub!(self, self.e(), 0u8),
-            0x94 => sub!(self, self.h(), 0u8),
-            0x95 => sub!(self, self.l(), 0u8),
-            0x97 => sub!(self, self.a(), 0u8),
-            0x96 | 0xD6 => sub!(self, self.operand as u8, 0u8),
-
-            0x98 => sub!(self, self.b(), self.cy() as u8),
-            0x99 => sub!(self, self.c(), self.cy() as u8),
-            0x9A => sub!(self, self.d(), self.cy() as u8),
-            0x9B => sub!(self, self.e(), self.cy() as u8),
-            0x9C => sub!(self, self.h(), self.cy() as u8),
-            0x9D => sub!(self, self.l(), self.cy() as u8),
-            0x9F => sub!(self, self.a(), self.cy() as u8),
-            0x9E | 0xDE => sub!(self, self.operand as u8, self.cy() as u8),
+            0x80 => add!(self, self.b()),
+            0x81 => add!(self, self.c()),
+            0x82 => add!(self, self.d()),
+            0x83 => add!(self, self.e()),
+            0x84 => add!(self, self.h()),
+            0x85 => add!(self, self.l()),
+            0x87 => add!(self, self.a()),
+            0x86 | 0xC6 => add!(self, self.operand as u8),
+
+            0x88 => adc!(self, self.b()),
+            0x89 => adc!(self, self.c()),
+            0x8A => adc!(self, self.d()),
+            0x8B => adc!(self, self.e()),
+            0x8C => adc!(self, self.h()),
+            0x8D => adc!(self, self.l()),
+            0x8F => adc!(self, self.a()),
+            0x8E | 0xCE => adc!(self, self.operand as u8),
+
+            0x90 => sub!(self, self.b()),
+            0x91 => sub!(self, self.c()),
+            0x92 => sub!(self, self.d()),
+            0x93 => sub!(self, self.e()),
+            0x94 => sub!(self, self.h()),
+            0x95 => sub!(self, self.l()),
+            0x97 => sub!(self, self.a()),
+            0x96 | 0xD6 => sub!(self, self.operand as u8),
+
+            0x98 => sbc!(self, self.b()),
+            0x99 => sbc!(self, self.c()),
+            0x9A => sbc!(self, self.d()),
+            0x9B => sbc!(self, self.e()),
+            0x9C => sbc!(self, self.h()),
+            0x9D => sbc!(self, self.l()),
+            0x9F => sbc!(self, self.a()),
+            0x9E | 0xDE => sbc!(self, self.operand as u8),
 
             0xA0 => and!(self, self.b()),
             0xA1 => and!(self, self.c()),
@@ -508,18 +555,18 @@ impl CPU {
             0x27 => {
                 if !self.sf() {
                     if self.cy() || self.a() > 0x99 {
-                        self.set_a(self.a() + 0x60);
+                        self.set_a(self.a().wrapping_add(0x60));
                         self.set_cy(true);
                     }
                     if self.hc() || (self.a() & 0x0f) > 0x09 {
-                        self.set_a(self.a() + 0x06);
+                        self.set_a(self.a().wrapping_add(0x06));
                     }
                 } else {
                     if self.cy() {
-                        self.set_a(self.a() - 0x60);
+                        self.set_a(self.a().wrapping_sub(0x60));
                     }
                     if self.hc() {
-                        self.set_a(self.a() - 0x06);
+                        self.set_a(self.a().wrapping_sub(0x06));
                     }
                 }
 
@@ -530,15 +577,15 @@ impl CPU {
             /*
              * 	16bit arithmetic/logical instructions
              */
-            0x03 => self.bc += 1,
-            0x13 => self.de += 1,
-            0x23 => self.hl += 1,
-            0x33 => self.sp += 1,
+            0x03 => self.bc = self.bc.wrapping_add(1),
+            0x13 => self.de = self.de.wrapping_add(1),
+            0x23 => self.hl = self.hl.wrapping_add(1),
+            0x33 => self.sp = self.sp.wrapping_add(1),
 
-            0x0B => self.bc -= 1,
-            0x1B => self.de -= 1,
-            0x2B => self.hl -= 1,
-            0x3B => self.sp -= 1,
+            0x0B => self.bc = self.bc.wrapping_sub(1),
+            0x1B => self.de = self.de.wrapping_sub(1),
+            0x2B => self.hl = self.hl.wrapping_sub(1),
+            0x3B => self.sp = self.sp.wrapping_sub(1),
 
             0x09 => add16!(self, self.hl, self.bc),
             0x19 => add16!(self, self.hl, self.de),
@@ -555,20 +602,23 @@ impl CPU {
             0x1F => { let v = rr!(self, false, self.a()); self.set_a(v); self.set_zf(false); }
 
             /*
-             * Invalid opcodes
+             * Invalid opcodes: on real hardware, these lock up the CPU until the next reset.
              */
             0xCB | 0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
-                return Err(dbg::TraceEvent::IllegalInstructionFault(self.opcode));
+                let addr = self.pc.wrapping_sub(1);
+                return Err(dbg::TraceEvent::IllegalInstructionFault(addr, opcode));
             }
         };
 
         Ok(())
     }
 
+    /// Executes the CB-prefixed `opcode`. Called through `CB_HANDLERS`, each with a constant opcode.
     #[rustfmt::skip]
+    #[inline(always)]
     #[allow(clippy::cyclomatic_complexity)]
-    pub fn op_cb(&mut self) -> Result<(), dbg::TraceEvent> {
-        match self.opcode {
+    pub fn op_cb(&mut self, opcode: u8) -> Result<(), dbg::TraceEvent> {
+        match opcode {
             0x00 => { let v = rl!(self, true, self.b()); self.set_b(v); }
             0x01 => { let v = rl!(self, true, self.c()); self.set_c(v); }
             0x02 => { let v = rl!(self, true, self.d()); self.set_d(v); }
@@ -952,7 +1002,7 @@ pub const OPCODES: [OpcodeInfo; 256] = [
     OpcodeInfo("DEC C",       Register,    Register,     1, 4,  4),
     OpcodeInfo("LD C,d8",     Register,    Immediate,    2, 8,  8),
     OpcodeInfo("RRCA",        Register,    Register,     1, 4,  4),
-    OpcodeInfo("STOP 0",      Register,    Register,     1, 4,  4),
+    OpcodeInfo("STOP 0",      Register,    Immediate,    2, 8,  8),
     OpcodeInfo("LD DE,d16",   Register,    Immediate,    3, 12, 12),
     OpcodeInfo("LD (DE),A",   Memory(DE),  Register,     1, 8,  8),
     OpcodeInfo("INC DE",      Register,    Register,     1, 8,  8),
@@ -1194,6 +1244,11 @@ pub const OPCODES: [OpcodeInfo; 256] = [
     OpcodeInfo("RST 38H",     Register,    Register,     1, 16, 16),
 ];
 
+/// Signature of the generated opcode handlers.
+pub type OpHandler = fn(&mut CPU) -> Result<(), dbg::TraceEvent>;
+
+include!(concat!(env!("OUT_DIR"), "/dispatch.rs"));
+
 #[cfg(test)]
 mod test {
     use super::super::dbg;
@@ -1296,42 +1351,283 @@ mod test {
 
     #[test]
     fn misc_opcodes_work() {
-        // STOP/HALT
-        CpuTest::new(1, vec![0x10])
+        // HALT
+        CpuTest::new(1, vec![0x76])
             .match_states(vec![FetchOpcode])
             .run(|cpu, _| {
-                assert_eq!(*cpu.halted.loaded(), true);
+                assert!(*cpu.halted.loaded());
             });
 
-        CpuTest::new(2, vec![0x10, 0x00])
+        CpuTest::new(2, vec![0x76, 0x00])
             .match_states(vec![FetchOpcode, FetchOpcode])
             .run(|cpu, _| {
-                assert_eq!(*cpu.halted.value(), true);
+                assert!(*cpu.halted.value());
+            });
+
+        // STOP
+        CpuTest::new(3, vec![0x10, 0x00, 0x00])
+            .match_states(vec![FetchByte0, FetchOpcode, FetchOpcode])
+            .run(|cpu, _| {
+                assert!(cpu.stopped);
+                assert!(!*cpu.halted.value());
+                assert_eq!(cpu.pc, 2);
             });
 
         // EI
         CpuTest::new(1, vec![0xFB])
             .match_states(vec![FetchOpcode])
             .run(|cpu, _| {
-                assert_eq!(*cpu.intr_enabled.value(), false);
+                assert!(!*cpu.intr_enabled.value());
             });
 
         CpuTest::new(2, vec![0xFB, 0x00])
             .match_states(vec![FetchOpcode, FetchOpcode])
             .run(|cpu, _| {
-                assert_eq!(*cpu.intr_enabled.value(), true);
+                assert!(*cpu.intr_enabled.value());
             });
 
         // DI
         CpuTest::new(2, vec![0xFB, 0xF3])
             .match_states(vec![FetchOpcode, FetchOpcode])
             .run(|cpu, _| {
-                assert_eq!(*cpu.intr_enabled.value(), false);
+                assert!(!*cpu.intr_enabled.value());
+            });
+    }
+
+    #[test]
+    fn interrupts_are_serviced() {
+        let mut mem = vec![0x00; 0x10000];
+        let mut cpu = CPU::new();
+
+        cpu.pc = 0x1234;
+        cpu.sp = 0xD000;
+        cpu.intr_enabled.reset(true);
+        cpu.halted.reset(true);
+
+        // Both Timer and Serial are pending, Timer has the highest priority
+        mem[0xFFFF] = 0x0C;
+        mem[0xFF0F] = 0x0C;
+
+        assert!(cpu.service_irqs(&mut &mut mem[..]).unwrap());
+        assert_eq!(cpu.pc, 0x0050);
+        assert_eq!(mem[0xFF0F], 0x08);
+        assert!(!*cpu.halted.value());
+        assert!(!*cpu.intr_enabled.value());
+
+        // The jump to the ISR takes 5 M-cycles, PC is pushed in the 3rd and 4th
+        for _ in 0..5 {
+            assert!(cpu.executing);
+            cpu.tick(&mut &mut mem[..]).unwrap();
+        }
+        assert!(!cpu.executing);
+        assert_eq!(cpu.sp, 0xCFFE);
+        assert_eq!(mem[0xCFFE..0xD000], [0x34, 0x12]);
+
+        // With IME = 0, the CPU only leaves HALT mode
+        cpu.halted.reset(true);
+
+        assert!(!cpu.service_irqs(&mut &mut mem[..]).unwrap());
+        assert_eq!(cpu.pc, 0x0050);
+        assert_eq!(mem[0xFF0F], 0x08);
+        assert!(!*cpu.halted.value());
+    }
+
+    #[test]
+    fn ei_is_delayed_by_one_instruction() {
+        let mut mem = vec![0x00; 0x10000];
+        let mut cpu = CPU::new();
+
+        // EI, LD BC,d16
+        mem[0x0000..0x0004].copy_from_slice(&[0xFB, 0x01, 0xAA, 0x55]);
+
+        // VBlank interrupt pending
+        mem[0xFFFF] = 0x01;
+        mem[0xFF0F] = 0x01;
+
+        cpu.pc = 0x0000;
+        cpu.sp = 0xD000;
+
+        // No interrupt is serviced right after EI
+        cpu.tick(&mut &mut mem[..]).unwrap();
+        assert!(cpu.ime_pending());
+        assert!(!cpu.service_irqs(&mut &mut mem[..]).unwrap());
+
+        // IME takes effect after the following instruction
+        for _ in 0..3 {
+            cpu.tick(&mut &mut mem[..]).unwrap();
+        }
+        assert!(!cpu.ime_pending());
+        assert!(cpu.service_irqs(&mut &mut mem[..]).unwrap());
+        assert_eq!(cpu.pc, 0x0040);
+
+        for _ in 0..5 {
+            cpu.tick(&mut &mut mem[..]).unwrap();
+        }
+        assert_eq!(mem[0xCFFE..0xD000], [0x04, 0x00]);
+    }
+
+    #[test]
+    fn di_cancels_pending_ei() {
+        let mut mem = vec![0x00; 0x10000];
+        let mut cpu = CPU::new();
+
+        // EI, DI, NOP
+        mem[0x0000..0x0003].copy_from_slice(&[0xFB, 0xF3, 0x00]);
+
+        mem[0xFFFF] = 0x01;
+        mem[0xFF0F] = 0x01;
+
+        cpu.pc = 0x0000;
+
+        // No interrupt can be serviced between EI and DI, nor after DI
+        for _ in 0..3 {
+            cpu.tick(&mut &mut mem[..]).unwrap();
+            assert!(!cpu.service_irqs(&mut &mut mem[..]).unwrap());
+        }
+        assert!(!cpu.ime_pending());
+        assert!(!*cpu.intr_enabled.value());
+        assert_eq!(cpu.pc, 0x0003);
+    }
+
+    #[test]
+    fn illegal_opcodes_lock_up() {
+        for op in [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD].iter() {
+            let mut mem = [0x00, *op, 0x00];
+            let mut cpu = CPU::new();
+
+            cpu.pc = 0x0001;
+
+            match cpu.tick(&mut &mut mem[..]) {
+                Err(dbg::TraceEvent::IllegalInstructionFault(addr, opcode)) => {
+                    assert_eq!(addr, 0x0001);
+                    assert_eq!(opcode, *op);
+                }
+                _ => panic!("no lock-up for opcode {:02X}", op),
+            }
+
+            // The CPU state is left untouched, so that it keeps locking up
+            assert_eq!(cpu.pc, 0x0001);
+            assert_eq!(cpu.state, FetchOpcode);
+            assert!(cpu.tick(&mut &mut mem[..]).is_err());
+        }
+    }
+
+    #[test]
+    fn branch_opcodes_work() {
+        // CALL a16, PC is pushed in the last two cycles
+        CpuTest::new(6, vec![0xCD, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .match_states(vec![
+                FetchByte0,
+                FetchByte1,
+                Delay(0),
+                Writeback,
+                Writeback1,
+                FetchOpcode,
+            ])
+            .match_memory(vec![0xCD, 0x00, 0x10, 0x00, 0x00, 0x00, 0x03, 0x00])
+            .setup(|cpu| {
+                cpu.sp = 0x0008;
+            })
+            .run(|cpu, _| {
+                assert_eq!(cpu.pc, 0x1000);
+                assert_eq!(cpu.sp, 0x0006);
+            });
+
+        // RET, PC is popped right after the opcode fetch
+        CpuTest::new(4, vec![0xC9, 0x00, 0x00, 0x00, 0x34, 0x12])
+            .match_states(vec![Writeback, Writeback1, Delay(0), FetchOpcode])
+            .setup(|cpu| {
+                cpu.sp = 0x0004;
+            })
+            .run(|cpu, _| {
+                assert_eq!(cpu.pc, 0x1234);
+                assert_eq!(cpu.sp, 0x0006);
+            });
+
+        // RET NZ, taken
+        CpuTest::new(5, vec![0xC0, 0x00, 0x00, 0x00, 0x34, 0x12])
+            .match_states(vec![
+                Delay(0),
+                Writeback,
+                Writeback1,
+                Delay(0),
+                FetchOpcode,
+            ])
+            .setup(|cpu| {
+                cpu.sp = 0x0004;
+            })
+            .run(|cpu, _| {
+                assert_eq!(cpu.pc, 0x1234);
+                assert_eq!(cpu.sp, 0x0006);
+            });
+
+        // RET Z, not taken
+        CpuTest::new(2, vec![0xC8, 0x00, 0x00, 0x00, 0x34, 0x12])
+            .match_states(vec![Delay(0), FetchOpcode])
+            .setup(|cpu| {
+                cpu.sp = 0x0004;
+            })
+            .run(|cpu, _| {
+                assert_eq!(cpu.pc, 0x0001);
+                assert_eq!(cpu.sp, 0x0004);
             });
     }
 
     #[test]
-    fn branch_opcodes_work() {}
+    fn call_stack_tracks_calls_and_returns() {
+        // CALL $0010; ...; $0010: RST 18H; ...; $0018: RET
+        let mut mem = vec![0x00; 0x100];
+        mem[0x00..0x03].copy_from_slice(&[0xCD, 0x10, 0x00]);
+        mem[0x10] = 0xDF;
+        mem[0x18] = 0xC9;
+
+        CpuTest::new(6 + 4 + 4, mem)
+            .setup(|cpu| {
+                cpu.sp = 0x0100;
+            })
+            .run(|cpu, _| {
+                let entries: Vec<_> = cpu.call_stack.iter().map(|f| f.entry).collect();
+
+                assert_eq!(cpu.pc, 0x0011);
+                assert_eq!(entries, vec![0x0100, 0x0010]);
+                assert_eq!(cpu.call_stack[1].return_addr, 0x0003);
+                assert_eq!(cpu.call_stack[1].sp, 0x00FE);
+            });
+    }
+
+    #[test]
+    fn call_stack_survives_manipulated_returns() {
+        let mut cpu = CPU::new();
+
+        cpu.sp = 0xD000;
+        cpu.push_frame(0x1000, false);
+        cpu.sp = 0xCFFE;
+        cpu.push_frame(0x2000, false);
+        cpu.sp = 0xCFFC;
+
+        // PUSH HL; RET does not return from the current function
+        cpu.sp = 0xCFFA;
+        cpu.pop_frame();
+        assert_eq!(cpu.current_function(), Some(0x2000));
+
+        // POP HL; RET returns from both functions
+        cpu.sp = 0xCFFE;
+        cpu.pop_frame();
+        assert_eq!(cpu.current_function(), Some(0x0100));
+
+        // The bottom frame is never popped
+        cpu.pop_frame();
+        assert_eq!(cpu.call_stack.len(), 1);
+
+        // Resetting SP discards stale frames on the next call
+        cpu.sp = 0xC000;
+        cpu.push_frame(0x3000, false);
+        cpu.sp = 0xD000;
+        cpu.push_frame(0x4000, true);
+
+        let entries: Vec<_> = cpu.call_stack.iter().map(|f| f.entry).collect();
+        assert_eq!(entries, vec![0x0100, 0x4000]);
+    }
 
     #[test]
     fn ld16_opcodes_work() {
@@ -1348,7 +1644,7 @@ mod test {
                 FetchByte0,
                 FetchByte1,
                 Writeback,
-                Delay(0),
+                Writeback1,
                 FetchOpcode,
             ])
             .match_memory(vec![0x08, 0x03, 0x00, 0xC0, 0xBE])
@@ -1361,7 +1657,7 @@ mod test {
 
         // PUSH rr
         CpuTest::new(4, vec![0xD5, 0x00, 0x00, 0x22, 0x11])
-            .match_states(vec![Writeback, Delay(1), Delay(0), FetchOpcode])
+            .match_states(vec![Delay(0), Writeback, Writeback1, FetchOpcode])
             .match_memory(vec![0xD5, 0x00, 0x00, 0xBB, 0xAA])
             .setup(|cpu| {
                 cpu.sp = 0x0005;
@@ -1374,7 +1670,7 @@ mod test {
 
         // POP rr
         CpuTest::new(3, vec![0xE1, 0x00, 0x00, 0x22, 0x11])
-            .match_states(vec![FetchMemory, Delay(0), FetchOpcode])
+            .match_states(vec![FetchMemory, FetchMemory1, FetchOpcode])
             .match_memory(vec![0xE1, 0x00, 0x00, 0x22, 0x11])
             .setup(|cpu| {
                 cpu.sp = 0x0003;
@@ -1692,6 +1988,183 @@ mod test {
             .run(|_, _| {});
     }
 
+    /// Reference flags computed with wide integer arithmetic, as (Z, N, H, C).
+    fn alu_reference(op: u8, a: u8, v: u8, cy: bool) -> (u8, (bool, bool, bool, bool)) {
+        let (a, v, c) = (i32::from(a), i32::from(v), i32::from(cy));
+
+        match op {
+            0x80 | 0x88 => {
+                let c = if op == 0x88 { c } else { 0 };
+                let r = a + v + c;
+                let h = (a & 0xF) + (v & 0xF) + c > 0xF;
+                ((r & 0xFF) as u8, (r & 0xFF == 0, false, h, r > 0xFF))
+            }
+            0x90 | 0x98 => {
+                let c = if op == 0x98 { c } else { 0 };
+                let r = a - v - c;
+                let h = (a & 0xF) - (v & 0xF) - c < 0;
+                ((r & 0xFF) as u8, (r & 0xFF == 0, true, h, r < 0))
+            }
+            0x04 => {
+                let r = v + 1;
+                let h = (v & 0xF) + 1 > 0xF;
+                ((r & 0xFF) as u8, (r & 0xFF == 0, false, h, cy))
+            }
+            0x05 => {
+                let r = v - 1;
+                let h = (v & 0xF) - 1 < 0;
+                ((r & 0xFF) as u8, (r & 0xFF == 0, true, h, cy))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn alu_flags_match_reference() {
+        for op in [0x80, 0x88, 0x90, 0x98, 0x04, 0x05].iter() {
+            for a in 0_u8..=255 {
+                for v in 0_u8..=255 {
+                    for cy in [false, true].iter() {
+                        let mut cpu = CPU::new();
+
+                        cpu.set_a(a);
+                        cpu.set_b(v);
+                        cpu.set_f(0);
+                        cpu.set_cy(*cy);
+                        cpu.opcode = *op;
+                        OP_HANDLERS[*op as usize](&mut cpu).unwrap();
+
+                        let res = if *op == 0x04 || *op == 0x05 {
+                            cpu.b()
+                        } else {
+                            cpu.a()
+                        };
+                        let flags = (cpu.zf(), cpu.sf(), cpu.hc(), cpu.cy());
+
+                        assert_eq!(
+                            (res, flags),
+                            alu_reference(*op, a, v, *cy),
+                            "opcode {:02X}, A={:02X}, v={:02X}, CY={}",
+                            op,
+                            a,
+                            v,
+                            cy
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn adc_sbc_match_known_values() {
+        // (opcode, A, B, CY) => (A, F)
+        let table: &[(u8, u8, u8, bool, u8, u8)] = &[
+            (0x88, 0xFF, 0x00, true, 0x00, 0xB0),
+            (0x88, 0x0F, 0x00, true, 0x10, 0x20),
+            (0x88, 0x00, 0xFF, true, 0x00, 0xB0),
+            (0x88, 0x80, 0x7F, true, 0x00, 0xB0),
+            (0x88, 0x12, 0x34, true, 0x47, 0x00),
+            (0x88, 0xE1, 0x1E, false, 0xFF, 0x00),
+            (0x8F, 0x88, 0x00, true, 0x11, 0x30),
+            (0x8F, 0x08, 0x00, false, 0x10, 0x20),
+            (0x98, 0x00, 0x00, true, 0xFF, 0x70),
+            (0x98, 0x10, 0x0F, true, 0x00, 0xE0),
+            (0x98, 0x00, 0xFF, true, 0x00, 0xF0),
+            (0x98, 0x3B, 0x2A, true, 0x10, 0x40),
+            (0x98, 0x3B, 0x4F, true, 0xEB, 0x70),
+            (0x9F, 0x42, 0x00, false, 0x00, 0xC0),
+            (0x9F, 0x42, 0x00, true, 0xFF, 0x70),
+        ];
+
+        for &(op, a, b, cy, r, f) in table.iter() {
+            let mut cpu = CPU::new();
+
+            cpu.set_a(a);
+            cpu.set_b(b);
+            cpu.set_f(0);
+            cpu.set_cy(cy);
+            cpu.opcode = op;
+            OP_HANDLERS[op as usize](&mut cpu).unwrap();
+
+            assert_eq!(
+                (cpu.a(), cpu.f()),
+                (r, f),
+                "opcode {:02X}, A={:02X}, B={:02X}, CY={}",
+                op,
+                a,
+                b,
+                cy
+            );
+        }
+    }
+
+    #[test]
+    fn add16_flags_match_reference() {
+        for hl in (0_u32..=0xFFFF).step_by(0x0111) {
+            for rr in (0_u32..=0xFFFF).step_by(0x0077) {
+                for op in [0x09, 0x29].iter() {
+                    let mut cpu = CPU::new();
+                    let v = if *op == 0x29 { hl } else { rr };
+
+                    cpu.hl = hl as u16;
+                    cpu.bc = rr as u16;
+                    cpu.set_f(0x80);
+                    cpu.opcode = *op;
+                    OP_HANDLERS[*op as usize](&mut cpu).unwrap();
+
+                    assert_eq!(u32::from(cpu.hl), (hl + v) & 0xFFFF);
+                    assert!(cpu.zf());
+                    assert!(!cpu.sf());
+                    assert_eq!(cpu.hc(), (hl & 0xFFF) + (v & 0xFFF) > 0xFFF);
+                    assert_eq!(cpu.cy(), hl + v > 0xFFFF);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn inc16_dec16_wrap_around() {
+        // INC BC; DEC SP
+        CpuTest::new(4, vec![0x03, 0x3B])
+            .setup(|cpu| {
+                cpu.bc = 0xFFFF;
+                cpu.sp = 0x0000;
+            })
+            .run(|cpu, _| {
+                assert_eq!(cpu.bc, 0x0000);
+                assert_eq!(cpu.sp, 0xFFFF);
+            });
+
+        // LD (HL-),A
+        CpuTest::new(2, vec![0x32, 0x00])
+            .setup(|cpu| {
+                cpu.hl = 0x0000;
+                cpu.set_a(0x32);
+            })
+            .run(|cpu, _| {
+                assert_eq!(cpu.hl, 0xFFFF);
+            });
+    }
+
+    #[test]
+    fn cb_opcode_timings_are_correct() {
+        for op in 0_u8..=255 {
+            let mut mem = vec![0; 0x10000];
+            mem[0] = 0xCB;
+            mem[1] = op;
+
+            CpuTest::new(CB_OPCODES[op as usize].4 as usize / 4, mem)
+                .setup(|cpu| {
+                    cpu.hl = 0x8000;
+                })
+                .run(move |cpu, _| {
+                    assert_eq!(cpu.state, FetchOpcode, "wrong timing for opcode CB {:02X}", op);
+                    assert_eq!(cpu.pc, 0x0002);
+                });
+        }
+    }
+
     #[test]
     fn opcode_timings_are_correct() {
         for op in 0_u8..=255 {
@@ -0,0 +1,188 @@
+use super::CodeAddr;
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+/// Map of the bytes known to be code, since the CPU has executed them. This allows
+/// the disassembler to tell code apart from data, eg. tables interleaved with code.
+///
+/// ROM bytes are tracked separately for each bank, the rest of the memory by address.
+pub struct CodeMap {
+    rom: Vec<bool>,
+    other: Vec<bool>,
+}
+
+impl CodeMap {
+    pub fn new() -> CodeMap {
+        CodeMap {
+            rom: vec![],
+            other: vec![false; 0x8000],
+        }
+    }
+
+    /// Returns the index of `at` in the ROM map, or `None` if it's outside ROM.
+    fn rom_index(at: CodeAddr) -> Option<usize> {
+        match at.addr {
+            0x0000..=0x3FFF => Some(usize::from(at.addr)),
+            0x4000..=0x7FFF => Some(at.bank * 0x4000 + usize::from(at.addr - 0x4000)),
+            _ => None,
+        }
+    }
+
+    /// Marks the byte at `at` as code.
+    pub fn mark(&mut self, at: CodeAddr) {
+        match CodeMap::rom_index(at) {
+            Some(i) => {
+                if i >= self.rom.len() {
+                    self.rom.resize(i + 1, false);
+                }
+                self.rom[i] = true;
+            }
+            None => self.other[usize::from(at.addr - 0x8000)] = true,
+        }
+    }
+
+    /// Returns true if the byte at `at` has been executed as code.
+    pub fn is_code(&self, at: CodeAddr) -> bool {
+        match CodeMap::rom_index(at) {
+            Some(i) => self.rom.get(i).cloned().unwrap_or(false),
+            None => self.other[usize::from(at.addr - 0x8000)],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = CodeMap::new();
+    }
+
+    /// Returns the ranges of addresses of ROM bank `bank` which have been executed.
+    pub fn executed_ranges(&self, bank: usize) -> Vec<RangeInclusive<u16>> {
+        let base: u16 = if bank == 0 { 0x0000 } else { 0x4000 };
+        let mut ranges: Vec<RangeInclusive<u16>> = vec![];
+
+        for addr in (base..=base + 0x3FFF).filter(|a| self.is_code(CodeAddr::new(bank, *a))) {
+            match ranges.last_mut() {
+                Some(r) if *r.end() == addr - 1 => *r = *r.start()..=addr,
+                _ => ranges.push(addr..=addr),
+            }
+        }
+        ranges
+    }
+
+    /// Writes a report of the bytes executed in each of the `rom_banks` ROM banks,
+    /// followed by the ranges of executed addresses.
+    pub fn dump(&self, w: &mut impl Write, rom_banks: usize) -> io::Result<()> {
+        for bank in 0..rom_banks {
+            let ranges = self.executed_ranges(bank);
+            let executed = executed_bytes(&ranges);
+
+            writeln!(
+                w,
+                "Bank {:02X}: {}/16384 bytes executed ({:.2}%)",
+                bank,
+                executed,
+                executed as f64 * 100.0 / 16384.0
+            )?;
+
+            for r in ranges {
+                writeln!(w, "  {:04X}-{:04X}", r.start(), r.end())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as `dump`, in JSON format.
+    pub fn dump_json(&self, w: &mut impl Write, rom_banks: usize) -> io::Result<()> {
+        writeln!(w, "{{\"banks\": [")?;
+
+        for bank in 0..rom_banks {
+            let ranges = self.executed_ranges(bank);
+            let list: Vec<_> = ranges
+                .iter()
+                .map(|r| format!("[{}, {}]", r.start(), r.end()))
+                .collect();
+
+            writeln!(
+                w,
+                "  {{\"bank\": {}, \"executed\": {}, \"size\": 16384, \"ranges\": [{}]}}{}",
+                bank,
+                executed_bytes(&ranges),
+                list.join(", "),
+                if bank + 1 < rom_banks { "," } else { "" }
+            )?;
+        }
+
+        writeln!(w, "]}}")
+    }
+}
+
+fn executed_bytes(ranges: &[RangeInclusive<u16>]) -> usize {
+    ranges
+        .iter()
+        .map(|r| usize::from(r.end() - r.start()) + 1)
+        .sum()
+}
+
+impl Default for CodeMap {
+    fn default() -> CodeMap {
+        CodeMap::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rom_banks_are_tracked_separately() {
+        let mut map = CodeMap::new();
+
+        map.mark(CodeAddr::new(3, 0x4100));
+        map.mark(CodeAddr::new(3, 0xC000));
+
+        assert!(map.is_code(CodeAddr::new(3, 0x4100)));
+        assert!(!map.is_code(CodeAddr::new(2, 0x4100)));
+        assert!(!map.is_code(CodeAddr::new(3, 0x0100)));
+        assert!(!map.is_code(CodeAddr::new(9, 0x7FFF)));
+
+        // Outside ROM, banks are not relevant
+        assert!(map.is_code(CodeAddr::new(1, 0xC000)));
+
+        map.clear();
+        assert!(!map.is_code(CodeAddr::new(3, 0x4100)));
+    }
+
+    #[test]
+    fn coverage_is_reported_per_bank() {
+        let mut map = CodeMap::new();
+
+        for addr in (0x0100..=0x0103).chain(0x0150..=0x0150) {
+            map.mark(CodeAddr::new(0, addr));
+        }
+        map.mark(CodeAddr::new(1, 0x7FFF));
+
+        assert_eq!(
+            map.executed_ranges(0),
+            vec![0x0100..=0x0103, 0x0150..=0x0150]
+        );
+        assert_eq!(map.executed_ranges(1), vec![0x7FFF..=0x7FFF]);
+        assert!(map.executed_ranges(2).is_empty());
+
+        let mut out = vec![];
+        map.dump(&mut out, 2).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Bank 00: 5/16384 bytes executed (0.03%)\n  0100-0103\n  0150-0150\n\
+             Bank 01: 1/16384 bytes executed (0.01%)\n  7FFF-7FFF\n"
+        );
+
+        let mut out = vec![];
+        map.dump_json(&mut out, 2).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"banks\": [\n  \
+             {\"bank\": 0, \"executed\": 5, \"size\": 16384, \"ranges\": [[256, 259], [336, 336]]},\n  \
+             {\"bank\": 1, \"executed\": 1, \"size\": 16384, \"ranges\": [[32767, 32767]]}\n\
+             ]}\n"
+        );
+    }
+}
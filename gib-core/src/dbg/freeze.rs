@@ -0,0 +1,156 @@
+use super::super::mem::MemW;
+
+use std::fmt;
+
+/// Location rewritten with a fixed value every frame, eg. to keep the number of lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Freeze {
+    pub addr: u16,
+    pub val: u8,
+}
+
+impl fmt::Display for Freeze {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X} = {:02X}", self.addr, self.val)
+    }
+}
+
+/// Frozen memory locations, kept sorted by address.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FreezeList(Vec<Freeze>);
+
+impl FreezeList {
+    pub fn new() -> FreezeList {
+        FreezeList::default()
+    }
+
+    /// Parses a list of `addr = val` lines in hexadecimal, as written by `Display`.
+    /// Empty lines, comments starting with `#` and invalid lines are ignored.
+    pub fn parse(text: &str) -> FreezeList {
+        let mut list = FreezeList::new();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=').map(str::trim);
+
+            if let (Some(addr), Some(val)) = (parts.next(), parts.next()) {
+                if let (Ok(addr), Ok(val)) =
+                    (u16::from_str_radix(addr, 16), u8::from_str_radix(val, 16))
+                {
+                    list.freeze(addr, val);
+                }
+            }
+        }
+
+        list
+    }
+
+    /// Freezes `addr` to `val`, replacing the value it was frozen to if any.
+    pub fn freeze(&mut self, addr: u16, val: u8) {
+        match self.0.binary_search_by_key(&addr, |f| f.addr) {
+            Ok(i) => self.0[i].val = val,
+            Err(i) => self.0.insert(i, Freeze { addr, val }),
+        }
+    }
+
+    /// Stops rewriting `addr`, returning whether it was frozen.
+    pub fn unfreeze(&mut self, addr: u16) -> bool {
+        match self.0.binary_search_by_key(&addr, |f| f.addr) {
+            Ok(i) => {
+                self.0.remove(i);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the value `addr` is frozen to, if any.
+    pub fn get(&self, addr: u16) -> Option<u8> {
+        self.0
+            .binary_search_by_key(&addr, |f| f.addr)
+            .ok()
+            .map(|i| self.0[i].val)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Freeze> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Writes the frozen values to `mem`, without any side effect.
+    pub fn apply(&self, mem: &mut impl MemW) {
+        for f in &self.0 {
+            mem.poke(f.addr, f.val);
+        }
+    }
+}
+
+impl fmt::Display for FreezeList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for freeze in &self.0 {
+            writeln!(f, "{}", freeze)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::TraceEvent;
+    use super::*;
+
+    struct Ram(Vec<u8>);
+
+    impl MemW for Ram {
+        fn write(&mut self, addr: u16, val: u8) -> Result<(), TraceEvent> {
+            self.0[usize::from(addr)] = val;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn frozen_locations_are_rewritten() {
+        let mut ram = Ram(vec![0; 0x10000]);
+        let mut list = FreezeList::new();
+
+        list.freeze(0xFF90, 0x01);
+        list.freeze(0xC010, 0x03);
+        list.freeze(0xFF90, 0x09);
+        assert_eq!(list.get(0xFF90), Some(0x09));
+        assert_eq!(list.get(0xC000), None);
+
+        list.apply(&mut ram);
+        assert_eq!(ram.0[0xC010], 0x03);
+        assert_eq!(ram.0[0xFF90], 0x09);
+
+        assert!(list.unfreeze(0xFF90));
+        assert!(!list.unfreeze(0xFF90));
+        assert_eq!(list.iter().count(), 1);
+    }
+
+    #[test]
+    fn lists_are_parsed_back() {
+        let mut list = FreezeList::new();
+        list.freeze(0xD000, 0xFF);
+        list.freeze(0xC010, 0x03);
+
+        let text = list.to_string();
+        assert_eq!(text, "C010 = 03\nD000 = FF\n");
+        assert_eq!(FreezeList::parse(&text), list);
+
+        let list = FreezeList::parse("# lives\nC010=3\n\nnope\nD000 = 100\n");
+        assert_eq!(
+            list.iter().cloned().collect::<Vec<_>>(),
+            vec![Freeze {
+                addr: 0xC010,
+                val: 0x03
+            }]
+        );
+    }
+}
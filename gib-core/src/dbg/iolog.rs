@@ -0,0 +1,239 @@
+use super::MemAccess;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+
+/// Peripheral owning an IO register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Peripheral {
+    Joypad,
+    Serial,
+    Timer,
+    Interrupts,
+    Apu,
+    Ppu,
+    Cgb,
+}
+
+impl Peripheral {
+    pub fn all() -> &'static [Peripheral] {
+        use Peripheral::*;
+
+        &[Joypad, Serial, Timer, Interrupts, Apu, Ppu, Cgb]
+    }
+
+    /// Returns the peripheral which owns the IO register at `addr`.
+    pub fn at(addr: u16) -> Option<Peripheral> {
+        use Peripheral::*;
+
+        match addr {
+            0xFF00 => Some(Joypad),
+            0xFF01..=0xFF02 => Some(Serial),
+            0xFF04..=0xFF07 => Some(Timer),
+            0xFF0F | 0xFFFF => Some(Interrupts),
+            0xFF10..=0xFF3F => Some(Apu),
+            0xFF40..=0xFF4B => Some(Ppu),
+            0xFF4C..=0xFF7F => Some(Cgb),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Peripheral {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Peripheral::*;
+
+        match self {
+            Joypad => write!(f, "JOY"),
+            Serial => write!(f, "SER"),
+            Timer => write!(f, "TIM"),
+            Interrupts => write!(f, "IRQ"),
+            Apu => write!(f, "APU"),
+            Ppu => write!(f, "PPU"),
+            Cgb => write!(f, "CGB"),
+        }
+    }
+}
+
+/// Single CPU access to an IO register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoAccess {
+    pub cycles: u64,
+    pub pc: u16,
+    pub access: MemAccess,
+    pub addr: u16,
+    pub val: u8,
+}
+
+impl IoAccess {
+    pub fn peripheral(&self) -> Option<Peripheral> {
+        Peripheral::at(self.addr)
+    }
+}
+
+impl fmt::Display for IoAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:12} PC:{:04X} {} {} {:04X} {} {:02X}",
+            self.cycles,
+            self.pc,
+            self.peripheral()
+                .map_or("---".to_string(), |p| p.to_string()),
+            match self.access {
+                MemAccess::Read => 'R',
+                MemAccess::Write => 'W',
+            },
+            self.addr,
+            match self.access {
+                MemAccess::Read => "->",
+                MemAccess::Write => "<-",
+            },
+            self.val,
+        )
+    }
+}
+
+/// Records the most recent CPU accesses to IO registers into a ring buffer.
+///
+/// Only accesses performed while a CPU cycle is in progress (see [`IoLog::begin`]) are
+/// recorded, so that the debugger and the emulator itself don't show up in the log.
+pub struct IoLog {
+    entries: RefCell<VecDeque<IoAccess>>,
+    capacity: usize,
+
+    pc: u16,
+    cycles: Option<u64>,
+}
+
+impl IoLog {
+    /// Creates a log holding up to `capacity` accesses.
+    pub fn new(capacity: usize) -> IoLog {
+        IoLog {
+            entries: RefCell::new(VecDeque::with_capacity(capacity)),
+            capacity,
+
+            pc: 0,
+            cycles: None,
+        }
+    }
+
+    /// Sets the address of the instruction being executed.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Starts recording the accesses of the CPU cycle happening at `cycles`.
+    pub fn begin(&mut self, cycles: u64) {
+        self.cycles = Some(cycles);
+    }
+
+    /// Stops recording accesses until the next CPU cycle.
+    pub fn end(&mut self) {
+        self.cycles = None;
+    }
+
+    /// Records an access, discarding the oldest one if the buffer is full.
+    pub fn record(&self, access: MemAccess, addr: u16, val: u8) {
+        let cycles = match self.cycles {
+            Some(cycles) => cycles,
+            None => return,
+        };
+
+        let mut entries = self.entries.borrow_mut();
+
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(IoAccess {
+            cycles,
+            pc: self.pc,
+            access,
+            addr,
+            val,
+        });
+    }
+
+    /// Returns up to `n` of the most recent accesses satisfying `filter`, from the oldest
+    /// to the newest.
+    pub fn tail<F>(&self, n: usize, filter: F) -> Vec<IoAccess>
+    where
+        F: Fn(&IoAccess) -> bool,
+    {
+        let mut tail = self
+            .entries
+            .borrow()
+            .iter()
+            .rev()
+            .filter(|e| filter(e))
+            .take(n)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        tail.reverse();
+        tail
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    /// Removes and returns all the recorded accesses, from the oldest to the newest.
+    pub fn drain(&mut self) -> Vec<IoAccess> {
+        self.entries.borrow_mut().drain(..).collect()
+    }
+
+    /// Writes the recorded accesses, one per line.
+    pub fn dump(&self, w: &mut impl Write) -> io::Result<()> {
+        for entry in self.entries.borrow().iter() {
+            writeln!(w, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_accesses_within_cpu_cycles_are_recorded() {
+        let mut log = IoLog::new(2);
+
+        log.record(MemAccess::Read, 0xFF44, 0x90);
+        assert!(log.is_empty());
+
+        log.set_pc(0x0150);
+        for (i, addr) in [0xFF40, 0xFF05, 0xFF26].iter().enumerate() {
+            log.begin(4 * i as u64);
+            log.record(MemAccess::Write, *addr, 0x80);
+            log.end();
+        }
+
+        let all = log.tail(10, |_| true);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].addr, 0xFF05);
+        assert_eq!(all[1].addr, 0xFF26);
+        assert_eq!(all[1].cycles, 8);
+
+        let apu = log.tail(10, |e| e.peripheral() == Some(Peripheral::Apu));
+        assert_eq!(apu.len(), 1);
+
+        let mut out = vec![];
+        log.dump(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap().lines().last().unwrap(),
+            "           8 PC:0150 APU W FF26 <- 80"
+        );
+    }
+}
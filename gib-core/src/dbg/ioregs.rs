@@ -0,0 +1,330 @@
+use super::Peripheral;
+
+/// Group of bits of an IO register with a meaning of their own, from `msb` down to `lsb`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitField {
+    pub msb: u8,
+    pub lsb: u8,
+    pub name: &'static str,
+}
+
+impl BitField {
+    /// Returns the mask of the bits in the field.
+    pub fn mask(&self) -> u8 {
+        (0xFF >> (7 - self.msb + self.lsb)) << self.lsb
+    }
+
+    /// Extracts the value of the field from the register value `val`.
+    pub fn extract(&self, val: u8) -> u8 {
+        (val & self.mask()) >> self.lsb
+    }
+
+    /// Returns true if the field is made of a single bit.
+    pub fn is_flag(&self) -> bool {
+        self.msb == self.lsb
+    }
+}
+
+/// Description of an IO register, with the meaning of its bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoRegister {
+    pub addr: u16,
+    pub name: &'static str,
+    pub fields: &'static [BitField],
+}
+
+impl IoRegister {
+    pub fn peripheral(&self) -> Option<Peripheral> {
+        Peripheral::at(self.addr)
+    }
+}
+
+const fn bit(n: u8, name: &'static str) -> BitField {
+    BitField {
+        msb: n,
+        lsb: n,
+        name,
+    }
+}
+
+const fn bits(msb: u8, lsb: u8, name: &'static str) -> BitField {
+    BitField { msb, lsb, name }
+}
+
+const fn reg(addr: u16, name: &'static str, fields: &'static [BitField]) -> IoRegister {
+    IoRegister { addr, name, fields }
+}
+
+const IRQ_FIELDS: &[BitField] = &[
+    bit(4, "Joypad"),
+    bit(3, "Serial"),
+    bit(2, "Timer"),
+    bit(1, "STAT"),
+    bit(0, "VBlank"),
+];
+
+const DUTY_LENGTH_FIELDS: &[BitField] = &[bits(7, 6, "Duty cycle"), bits(5, 0, "Length")];
+
+const ENVELOPE_FIELDS: &[BitField] = &[
+    bits(7, 4, "Initial volume"),
+    bit(3, "Envelope increase"),
+    bits(2, 0, "Envelope pace"),
+];
+
+const PERIOD_HI_FIELDS: &[BitField] = &[
+    bit(7, "Trigger"),
+    bit(6, "Length enable"),
+    bits(2, 0, "Period high"),
+];
+
+const PALETTE_FIELDS: &[BitField] = &[
+    bits(7, 6, "Color 3"),
+    bits(5, 4, "Color 2"),
+    bits(3, 2, "Color 1"),
+    bits(1, 0, "Color 0"),
+];
+
+const PALETTE_SPEC_FIELDS: &[BitField] = &[bit(7, "Auto increment"), bits(5, 0, "Address")];
+
+const WAVE_FIELDS: &[BitField] = &[bits(7, 4, "First sample"), bits(3, 0, "Second sample")];
+
+/// All the documented IO registers, sorted by address.
+pub const IO_REGISTERS: &[IoRegister] = &[
+    reg(
+        0xFF00,
+        "P1",
+        &[
+            bit(5, "Select buttons"),
+            bit(4, "Select d-pad"),
+            bit(3, "Down/Start"),
+            bit(2, "Up/Select"),
+            bit(1, "Left/B"),
+            bit(0, "Right/A"),
+        ],
+    ),
+    reg(0xFF01, "SB", &[]),
+    reg(
+        0xFF02,
+        "SC",
+        &[
+            bit(7, "Transfer start"),
+            bit(1, "Fast clock (CGB)"),
+            bit(0, "Internal clock"),
+        ],
+    ),
+    reg(0xFF04, "DIV", &[]),
+    reg(0xFF05, "TIMA", &[]),
+    reg(0xFF06, "TMA", &[]),
+    reg(
+        0xFF07,
+        "TAC",
+        &[bit(2, "Timer enable"), bits(1, 0, "Input clock")],
+    ),
+    reg(0xFF0F, "IF", IRQ_FIELDS),
+    reg(
+        0xFF10,
+        "NR10",
+        &[
+            bits(6, 4, "Sweep pace"),
+            bit(3, "Sweep decrease"),
+            bits(2, 0, "Sweep step"),
+        ],
+    ),
+    reg(0xFF11, "NR11", DUTY_LENGTH_FIELDS),
+    reg(0xFF12, "NR12", ENVELOPE_FIELDS),
+    reg(0xFF13, "NR13", &[]),
+    reg(0xFF14, "NR14", PERIOD_HI_FIELDS),
+    reg(0xFF16, "NR21", DUTY_LENGTH_FIELDS),
+    reg(0xFF17, "NR22", ENVELOPE_FIELDS),
+    reg(0xFF18, "NR23", &[]),
+    reg(0xFF19, "NR24", PERIOD_HI_FIELDS),
+    reg(0xFF1A, "NR30", &[bit(7, "DAC enable")]),
+    reg(0xFF1B, "NR31", &[]),
+    reg(0xFF1C, "NR32", &[bits(6, 5, "Output level")]),
+    reg(0xFF1D, "NR33", &[]),
+    reg(0xFF1E, "NR34", PERIOD_HI_FIELDS),
+    reg(0xFF20, "NR41", &[bits(5, 0, "Length")]),
+    reg(0xFF21, "NR42", ENVELOPE_FIELDS),
+    reg(
+        0xFF22,
+        "NR43",
+        &[
+            bits(7, 4, "Clock shift"),
+            bit(3, "7-bit LFSR"),
+            bits(2, 0, "Clock divider"),
+        ],
+    ),
+    reg(
+        0xFF23,
+        "NR44",
+        &[bit(7, "Trigger"), bit(6, "Length enable")],
+    ),
+    reg(
+        0xFF24,
+        "NR50",
+        &[
+            bit(7, "VIN left"),
+            bits(6, 4, "Left volume"),
+            bit(3, "VIN right"),
+            bits(2, 0, "Right volume"),
+        ],
+    ),
+    reg(
+        0xFF25,
+        "NR51",
+        &[
+            bit(7, "CH4 left"),
+            bit(6, "CH3 left"),
+            bit(5, "CH2 left"),
+            bit(4, "CH1 left"),
+            bit(3, "CH4 right"),
+            bit(2, "CH3 right"),
+            bit(1, "CH2 right"),
+            bit(0, "CH1 right"),
+        ],
+    ),
+    reg(
+        0xFF26,
+        "NR52",
+        &[
+            bit(7, "Audio enable"),
+            bit(3, "CH4 on"),
+            bit(2, "CH3 on"),
+            bit(1, "CH2 on"),
+            bit(0, "CH1 on"),
+        ],
+    ),
+    reg(0xFF30, "WAVE0", WAVE_FIELDS),
+    reg(0xFF31, "WAVE1", WAVE_FIELDS),
+    reg(0xFF32, "WAVE2", WAVE_FIELDS),
+    reg(0xFF33, "WAVE3", WAVE_FIELDS),
+    reg(0xFF34, "WAVE4", WAVE_FIELDS),
+    reg(0xFF35, "WAVE5", WAVE_FIELDS),
+    reg(0xFF36, "WAVE6", WAVE_FIELDS),
+    reg(0xFF37, "WAVE7", WAVE_FIELDS),
+    reg(0xFF38, "WAVE8", WAVE_FIELDS),
+    reg(0xFF39, "WAVE9", WAVE_FIELDS),
+    reg(0xFF3A, "WAVEA", WAVE_FIELDS),
+    reg(0xFF3B, "WAVEB", WAVE_FIELDS),
+    reg(0xFF3C, "WAVEC", WAVE_FIELDS),
+    reg(0xFF3D, "WAVED", WAVE_FIELDS),
+    reg(0xFF3E, "WAVEE", WAVE_FIELDS),
+    reg(0xFF3F, "WAVEF", WAVE_FIELDS),
+    reg(
+        0xFF40,
+        "LCDC",
+        &[
+            bit(7, "LCD enable"),
+            bit(6, "Window tile map"),
+            bit(5, "Window enable"),
+            bit(4, "BG/window tile data"),
+            bit(3, "BG tile map"),
+            bit(2, "OBJ size"),
+            bit(1, "OBJ enable"),
+            bit(0, "BG/window enable"),
+        ],
+    ),
+    reg(
+        0xFF41,
+        "STAT",
+        &[
+            bit(6, "LYC interrupt"),
+            bit(5, "Mode 2 interrupt"),
+            bit(4, "Mode 1 interrupt"),
+            bit(3, "Mode 0 interrupt"),
+            bit(2, "LY=LYC"),
+            bits(1, 0, "Mode"),
+        ],
+    ),
+    reg(0xFF42, "SCY", &[]),
+    reg(0xFF43, "SCX", &[]),
+    reg(0xFF44, "LY", &[]),
+    reg(0xFF45, "LYC", &[]),
+    reg(0xFF46, "DMA", &[]),
+    reg(0xFF47, "BGP", PALETTE_FIELDS),
+    reg(0xFF48, "OBP0", PALETTE_FIELDS),
+    reg(0xFF49, "OBP1", PALETTE_FIELDS),
+    reg(0xFF4A, "WY", &[]),
+    reg(0xFF4B, "WX", &[]),
+    reg(
+        0xFF4D,
+        "KEY1",
+        &[bit(7, "Double speed"), bit(0, "Switch armed")],
+    ),
+    reg(0xFF4F, "VBK", &[bit(0, "VRAM bank")]),
+    reg(0xFF50, "BANK", &[bit(0, "Boot ROM disabled")]),
+    reg(0xFF51, "HDMA1", &[]),
+    reg(0xFF52, "HDMA2", &[]),
+    reg(0xFF53, "HDMA3", &[]),
+    reg(0xFF54, "HDMA4", &[]),
+    reg(
+        0xFF55,
+        "HDMA5",
+        &[bit(7, "H-Blank mode"), bits(6, 0, "Length")],
+    ),
+    reg(
+        0xFF56,
+        "RP",
+        &[
+            bits(7, 6, "Read enable"),
+            bit(1, "Receiving"),
+            bit(0, "LED on"),
+        ],
+    ),
+    reg(0xFF68, "BCPS", PALETTE_SPEC_FIELDS),
+    reg(0xFF69, "BCPD", &[]),
+    reg(0xFF6A, "OCPS", PALETTE_SPEC_FIELDS),
+    reg(0xFF6B, "OCPD", &[]),
+    reg(0xFF6C, "OPRI", &[bit(0, "DMG priority")]),
+    reg(0xFF70, "SVBK", &[bits(2, 0, "WRAM bank")]),
+    reg(0xFF76, "PCM12", &[bits(7, 4, "CH2"), bits(3, 0, "CH1")]),
+    reg(0xFF77, "PCM34", &[bits(7, 4, "CH4"), bits(3, 0, "CH3")]),
+    reg(0xFFFF, "IE", IRQ_FIELDS),
+];
+
+/// Returns the description of the IO register at `addr`, if it's documented.
+pub fn io_register(addr: u16) -> Option<&'static IoRegister> {
+    IO_REGISTERS
+        .binary_search_by_key(&addr, |r| r.addr)
+        .ok()
+        .map(|i| &IO_REGISTERS[i])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registers_are_sorted_and_fields_disjoint() {
+        for pair in IO_REGISTERS.windows(2) {
+            assert!(pair[0].addr < pair[1].addr, "{} out of order", pair[1].name);
+        }
+
+        for r in IO_REGISTERS.iter() {
+            let mut used = 0;
+
+            for f in r.fields.iter() {
+                assert!(f.msb >= f.lsb && f.msb < 8, "{} {}", r.name, f.name);
+                assert_eq!(used & f.mask(), 0, "{} {}", r.name, f.name);
+                used |= f.mask();
+            }
+        }
+    }
+
+    #[test]
+    fn fields_are_extracted_from_values() {
+        let stat = io_register(0xFF41).unwrap();
+        let mode = stat.fields.iter().find(|f| f.name == "Mode").unwrap();
+
+        assert_eq!(stat.name, "STAT");
+        assert_eq!(mode.mask(), 0x03);
+        assert_eq!(mode.extract(0x87), 3);
+        assert!(!mode.is_flag());
+
+        let lcdc = io_register(0xFF40).unwrap();
+        assert_eq!(lcdc.fields[0].mask(), 0x80);
+        assert_eq!(lcdc.fields[0].extract(0x91), 1);
+
+        assert!(io_register(0xFF03).is_none());
+    }
+}
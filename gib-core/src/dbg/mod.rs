@@ -1,3 +1,33 @@
+mod codemap;
+mod freeze;
+mod iolog;
+mod ioregs;
+mod profile;
+mod search;
+mod snapshot;
+mod stopwatch;
+mod stream;
+mod symbols;
+mod timeline;
+mod trace;
+mod watch;
+
+use super::cpu;
+
+pub use codemap::*;
+pub use freeze::*;
+pub use iolog::*;
+pub use ioregs::*;
+pub use profile::*;
+pub use search::*;
+pub use snapshot::*;
+pub use stopwatch::*;
+pub use stream::*;
+pub use symbols::*;
+pub use timeline::*;
+pub use trace::*;
+pub use watch::*;
+
 use failure::Fail;
 
 use std::fmt;
@@ -131,23 +161,71 @@ impl fmt::Display for McbOp {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemAccess {
+    Read,
+    Write,
+}
+
+impl fmt::Display for MemAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemAccess::Read => write!(f, "read"),
+            MemAccess::Write => write!(f, "write"),
+        }
+    }
+}
+
 #[derive(Debug, Fail, Clone, Copy)]
 #[allow(unused)]
 pub enum TraceEvent {
     #[fail(display = "Breakpoint reached: 0x{:04X}", _0)]
     Breakpoint(u16),
-    #[fail(display = "Illegal opcode: {:02X}", _0)]
-    IllegalInstructionFault(u8),
+    #[fail(display = "Watchpoint hit: {} 0x{:04X} = {:02X} at PC 0x{:04X}", _0, _1, _2, _3)]
+    Watchpoint(MemAccess, u16, u8, u16),
+    #[fail(display = "Line reached: LY {}", _0)]
+    LineReached(u8),
+    #[fail(display = "Interrupt dispatched to 0x{:04X}", _0)]
+    Interrupt(u16),
+    #[fail(display = "CPU locked up at ${:04X}: illegal opcode {:02X}", _0, _1)]
+    IllegalInstructionFault(u16, u8),
     #[fail(display = "Bus fault accessing 0x{:04X}", _0)]
     BusFault(u16),
     #[fail(display = "Memory fault accessing 0x{:04X}", _0)]
     MemFault(u16),
+    #[fail(display = "Bus conflict accessing 0x{:04X} during OAM DMA", _0)]
+    DmaBusConflict(u16),
+    #[fail(display = "Bus conflict accessing 0x{:04X} while locked by the PPU", _0)]
+    PpuBusConflict(u16),
     #[fail(display = "Unsupported MBC: {:02X}", _0)]
     UnsupportedMbcType(u8),
+    #[fail(display = "Unsupported ROM size code: {:02X}", _0)]
+    UnsupportedRomSize(u8),
+    #[fail(display = "Unsupported RAM size code: {:02X}", _0)]
+    UnsupportedRamSize(u8),
+    #[fail(display = "ROM image is {1} bytes, but its header declares {0}", _0, _1)]
+    RomSizeMismatch(usize, usize),
+    #[fail(display = "Invalid boot ROM size: {} bytes", _0)]
+    InvalidBootRomSize(usize),
     #[fail(display = "Invalid MBC operation: {}@{:02X}", _0, _1)]
     InvalidMbcOp(McbOp, u8),
-    #[fail(display = "CGB speed switch request")]
-    CgbSpeedSwitchReq,
     #[fail(display = "Unsupported CGB operation: {:04X}", _0)]
     UnsupportedCgbOp(u16),
+    #[fail(display = "Invalid savestate: {}", _0)]
+    InvalidSavestate(&'static str),
+    #[fail(display = "Invalid movie: {}", _0)]
+    InvalidMovie(&'static str),
+}
+
+impl TraceEvent {
+    /// Returns true if the machine cannot go on after the event, eg. once the CPU
+    /// locked up, as opposed to debugging events which merely pause it.
+    pub fn is_fault(&self) -> bool {
+        matches!(
+            self,
+            TraceEvent::IllegalInstructionFault(..)
+                | TraceEvent::BusFault(_)
+                | TraceEvent::MemFault(_)
+        )
+    }
 }
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Bank-aware code address. The bank is only relevant in the switchable ROM area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CodeAddr {
+    pub bank: usize,
+    pub addr: u16,
+}
+
+impl CodeAddr {
+    pub fn new(bank: usize, addr: u16) -> CodeAddr {
+        match addr {
+            0x4000..=0x7FFF => CodeAddr { bank, addr },
+            _ => CodeAddr { bank: 0, addr },
+        }
+    }
+}
+
+impl fmt::Display for CodeAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02X}:{:04X}", self.bank, self.addr)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileStats {
+    pub cycles: u64,
+    pub hits: u64,
+}
+
+/// Accumulates the cycles spent executing each instruction and function.
+#[derive(Default)]
+pub struct Profiler {
+    by_addr: HashMap<CodeAddr, ProfileStats>,
+    by_function: HashMap<CodeAddr, ProfileStats>,
+    total_cycles: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    /// Accounts `cycles` to the instruction at `addr`, part of the function at `function`.
+    pub fn record(&mut self, addr: CodeAddr, function: CodeAddr, cycles: u64) {
+        Profiler::account(&mut self.by_addr, addr, cycles);
+        Profiler::account(&mut self.by_function, function, cycles);
+        self.total_cycles += cycles;
+    }
+
+    /// Returns the `n` instructions which took the most cycles, hottest first.
+    pub fn hottest_addrs(&self, n: usize) -> Vec<(CodeAddr, ProfileStats)> {
+        Profiler::hottest(&self.by_addr, n)
+    }
+
+    /// Returns the `n` functions which took the most cycles, hottest first.
+    pub fn hottest_functions(&self, n: usize) -> Vec<(CodeAddr, ProfileStats)> {
+        Profiler::hottest(&self.by_function, n)
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    pub fn reset(&mut self) {
+        *self = Profiler::default();
+    }
+
+    fn account(map: &mut HashMap<CodeAddr, ProfileStats>, key: CodeAddr, cycles: u64) {
+        let stats = map.entry(key).or_default();
+        stats.cycles += cycles;
+        stats.hits += 1;
+    }
+
+    fn hottest(map: &HashMap<CodeAddr, ProfileStats>, n: usize) -> Vec<(CodeAddr, ProfileStats)> {
+        let mut v: Vec<_> = map.iter().map(|(k, v)| (*k, *v)).collect();
+
+        v.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        v.truncate(n);
+        v
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn banks_are_only_tracked_in_switchable_rom() {
+        assert_eq!(CodeAddr::new(5, 0x0150).bank, 0);
+        assert_eq!(CodeAddr::new(5, 0x4150).bank, 5);
+        assert_eq!(CodeAddr::new(5, 0xC000).bank, 0);
+    }
+
+    #[test]
+    fn hottest_locations_come_first() {
+        let mut p = Profiler::new();
+        let func = CodeAddr::new(1, 0x4000);
+
+        p.record(CodeAddr::new(1, 0x4000), func, 4);
+        p.record(CodeAddr::new(1, 0x4001), func, 12);
+        p.record(CodeAddr::new(2, 0x4000), CodeAddr::new(2, 0x4000), 8);
+
+        let hot: Vec<_> = p.hottest_addrs(2).iter().map(|(a, s)| (*a, s.cycles)).collect();
+        assert_eq!(hot, vec![(CodeAddr::new(1, 0x4001), 12), (CodeAddr::new(2, 0x4000), 8)]);
+
+        let funcs = p.hottest_functions(10);
+        assert_eq!(funcs[0], (func, ProfileStats { cycles: 16, hits: 2 }));
+        assert_eq!(p.total_cycles(), 24);
+    }
+}
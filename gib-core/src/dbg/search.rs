@@ -0,0 +1,165 @@
+use super::super::mem::MemR;
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Memory regions covered by searches: WRAM and HRAM.
+pub const SEARCH_RANGES: [RangeInclusive<u16>; 2] = [0xC000..=0xDFFF, 0xFF80..=0xFFFE];
+
+/// Comparison between the value of a location at the previous scan and the current one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    EqualTo(u8),
+}
+
+impl Comparison {
+    fn matches(self, old: u8, new: u8) -> bool {
+        match self {
+            Comparison::Unchanged => new == old,
+            Comparison::Changed => new != old,
+            Comparison::Increased => new > old,
+            Comparison::Decreased => new < old,
+            Comparison::EqualTo(val) => new == val,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Comparison::Unchanged => write!(f, "Unchanged"),
+            Comparison::Changed => write!(f, "Changed"),
+            Comparison::Increased => write!(f, "Increased"),
+            Comparison::Decreased => write!(f, "Decreased"),
+            Comparison::EqualTo(val) => write!(f, "Equal to {:02X}", val),
+        }
+    }
+}
+
+/// Location still matching a search, with its value at the last scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+    pub addr: u16,
+    pub val: u8,
+}
+
+/// Narrows down the RAM locations holding a value of interest (eg. lives or health),
+/// by repeatedly comparing them against their values at the previous scan.
+#[derive(Debug, Default)]
+pub struct RamSearch {
+    results: Vec<SearchResult>,
+    scans: usize,
+}
+
+impl RamSearch {
+    pub fn new() -> RamSearch {
+        RamSearch::default()
+    }
+
+    /// Starts a new search from every location of the searched regions,
+    /// or only the ones holding `val` if given.
+    pub fn first_scan(&mut self, mem: &impl MemR, val: Option<u8>) {
+        self.results = SEARCH_RANGES
+            .iter()
+            .cloned()
+            .flatten()
+            .map(|addr| SearchResult {
+                addr,
+                val: mem.peek(addr),
+            })
+            .filter(|r| val.is_none_or(|val| r.val == val))
+            .collect();
+
+        self.scans = 1;
+    }
+
+    /// Keeps only the locations whose value compares to the previous scan as `cmp`.
+    pub fn refine(&mut self, mem: &impl MemR, cmp: Comparison) {
+        self.results = self
+            .results
+            .iter()
+            .filter_map(|r| {
+                let val = mem.peek(r.addr);
+
+                if cmp.matches(r.val, val) {
+                    Some(SearchResult { addr: r.addr, val })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        self.scans += 1;
+    }
+
+    /// Returns the locations still matching, with their values at the last scan.
+    pub fn results(&self) -> &[SearchResult] {
+        &self.results
+    }
+
+    /// Returns the number of scans performed since the search was started.
+    pub fn scans(&self) -> usize {
+        self.scans
+    }
+
+    pub fn clear(&mut self) {
+        *self = RamSearch::new();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::TraceEvent;
+    use super::*;
+
+    struct Ram(Vec<u8>);
+
+    impl MemR for Ram {
+        fn read(&self, addr: u16) -> Result<u8, TraceEvent> {
+            Ok(self.0[usize::from(addr)])
+        }
+    }
+
+    #[test]
+    fn scans_narrow_down_the_results() {
+        let mut ram = Ram(vec![0; 0x10000]);
+        ram.0[0xC010] = 3;
+        ram.0[0xD000] = 3;
+        ram.0[0xFF90] = 3;
+        ram.0[0x8000] = 3;
+
+        let mut search = RamSearch::new();
+        search.first_scan(&ram, Some(3));
+
+        let addrs = |s: &RamSearch| s.results().iter().map(|r| r.addr).collect::<Vec<_>>();
+        assert_eq!(addrs(&search), vec![0xC010, 0xD000, 0xFF90]);
+
+        // One life lost
+        ram.0[0xC010] = 2;
+        ram.0[0xD000] = 4;
+        search.refine(&ram, Comparison::Decreased);
+        assert_eq!(addrs(&search), vec![0xC010]);
+        assert_eq!(search.results()[0].val, 2);
+
+        search.refine(&ram, Comparison::Unchanged);
+        search.refine(&ram, Comparison::EqualTo(1));
+        assert!(search.results().is_empty());
+        assert_eq!(search.scans(), 4);
+    }
+
+    #[test]
+    fn first_scan_covers_wram_and_hram() {
+        let ram = Ram(vec![0; 0x10000]);
+        let mut search = RamSearch::new();
+
+        search.first_scan(&ram, None);
+        assert_eq!(search.results().len(), 0x2000 + 0x7F);
+
+        search.refine(&ram, Comparison::Changed);
+        assert!(search.results().is_empty());
+    }
+}
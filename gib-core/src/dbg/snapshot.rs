@@ -0,0 +1,107 @@
+use super::super::mem::MemR;
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Byte which differs between a snapshot and the memory it is compared to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteChange {
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl fmt::Display for ByteChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X}: {:02X} -> {:02X}", self.addr, self.old, self.new)
+    }
+}
+
+/// Named copy of a memory region, to be compared later against live memory.
+#[derive(Debug, Clone)]
+pub struct MemSnapshot {
+    pub name: String,
+    start: u16,
+    data: Vec<u8>,
+}
+
+impl MemSnapshot {
+    /// Copies the bytes in `range` of `mem`, which must not be empty.
+    pub fn take<S: Into<String>>(
+        name: S,
+        mem: &impl MemR,
+        range: RangeInclusive<u16>,
+    ) -> MemSnapshot {
+        MemSnapshot {
+            name: name.into(),
+            start: *range.start(),
+            data: range.map(|addr| mem.peek(addr)).collect(),
+        }
+    }
+
+    /// Returns the addresses covered by the snapshot.
+    pub fn range(&self) -> RangeInclusive<u16> {
+        self.start..=self.start + (self.data.len() - 1) as u16
+    }
+
+    /// Returns the bytes which differ in `mem`, in address order.
+    pub fn diff(&self, mem: &impl MemR) -> Vec<ByteChange> {
+        self.range()
+            .zip(self.data.iter())
+            .filter_map(|(addr, &old)| {
+                let new = mem.peek(addr);
+
+                if new != old {
+                    Some(ByteChange { addr, old, new })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::TraceEvent;
+    use super::*;
+
+    struct Ram(Vec<u8>);
+
+    impl MemR for Ram {
+        fn read(&self, addr: u16) -> Result<u8, TraceEvent> {
+            Ok(self.0[usize::from(addr)])
+        }
+    }
+
+    #[test]
+    fn changes_since_the_snapshot_are_reported() {
+        let mut ram = Ram(vec![0; 0x10000]);
+        let snapshot = MemSnapshot::take("start", &ram, 0xFF80..=0xFFFE);
+
+        assert_eq!(snapshot.range(), 0xFF80..=0xFFFE);
+        assert!(snapshot.diff(&ram).is_empty());
+
+        ram.0[0xFF80] = 3;
+        ram.0[0xFFFE] = 0x99;
+        ram.0[0xC000] = 1;
+
+        let diff = snapshot.diff(&ram);
+        assert_eq!(
+            diff,
+            vec![
+                ByteChange {
+                    addr: 0xFF80,
+                    old: 0,
+                    new: 3
+                },
+                ByteChange {
+                    addr: 0xFFFE,
+                    old: 0,
+                    new: 0x99
+                },
+            ]
+        );
+        assert_eq!(diff[1].to_string(), "FFFE: 00 -> 99");
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+const CYCLES_PER_LINE: u64 = 456;
+const CYCLES_PER_FRAME: u64 = CYCLES_PER_LINE * 154;
+
+/// Time elapsed between two instants of the emulated clock, in clock cycles.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(pub u64);
+
+impl Elapsed {
+    pub fn cycles(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the elapsed machine cycles, at normal speed.
+    pub fn machine_cycles(self) -> u64 {
+        self.0 / 4
+    }
+
+    /// Returns the number of scanlines drawn in the meantime, including the fraction
+    /// of the current one.
+    pub fn lines(self) -> f64 {
+        self.0 as f64 / CYCLES_PER_LINE as f64
+    }
+
+    /// Returns the number of frames drawn in the meantime, including the fraction
+    /// of the current one.
+    pub fn frames(self) -> f64 {
+        self.0 as f64 / CYCLES_PER_FRAME as f64
+    }
+}
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} cycles ({} M), {:.2} lines, {:.3} frames",
+            self.cycles(),
+            self.machine_cycles(),
+            self.lines(),
+            self.frames()
+        )
+    }
+}
+
+/// Measures the time elapsed since it's been reset, and between the last two
+/// breakpoint hits since then. Times are given by the emulated clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stopwatch {
+    start: u64,
+    last_hit: Option<u64>,
+    prev_hit: Option<u64>,
+}
+
+impl Stopwatch {
+    pub fn new(now: u64) -> Stopwatch {
+        Stopwatch {
+            start: now,
+            ..Stopwatch::default()
+        }
+    }
+
+    /// Restarts the stopwatch at `now`, discarding the recorded hits.
+    pub fn reset(&mut self, now: u64) {
+        *self = Stopwatch::new(now);
+    }
+
+    /// Records a breakpoint hit at `now`.
+    pub fn hit(&mut self, now: u64) {
+        self.prev_hit = self.last_hit;
+        self.last_hit = Some(now);
+    }
+
+    /// Returns the time elapsed between the last reset and `now`.
+    pub fn elapsed(&self, now: u64) -> Elapsed {
+        Elapsed(now.saturating_sub(self.start))
+    }
+
+    /// Returns the time elapsed between the last two breakpoint hits, if any.
+    pub fn between_hits(&self) -> Option<Elapsed> {
+        match (self.prev_hit, self.last_hit) {
+            (Some(prev), Some(last)) => Some(Elapsed(last.saturating_sub(prev))),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn elapsed_time_is_measured_from_reset_and_hits() {
+        let mut sw = Stopwatch::new(1000);
+
+        sw.hit(1500);
+        assert_eq!(sw.between_hits(), None);
+
+        sw.hit(1500 + CYCLES_PER_FRAME);
+        assert_eq!(sw.between_hits(), Some(Elapsed(CYCLES_PER_FRAME)));
+        assert_eq!(sw.elapsed(1912).cycles(), 912);
+        assert_eq!(sw.elapsed(1912).machine_cycles(), 228);
+        assert!((sw.elapsed(1912).lines() - 2.0).abs() < 1e-9);
+
+        sw.reset(5000);
+        assert_eq!(sw.between_hits(), None);
+        assert!((sw.elapsed(5000 + CYCLES_PER_FRAME / 2).frames() - 0.5).abs() < 1e-9);
+
+        // The clock might go back in time, eg. when loading a savestate
+        assert_eq!(sw.elapsed(0), Elapsed(0));
+    }
+}
@@ -0,0 +1,263 @@
+use super::{IoAccess, IoLog, MemAccess, TraceEntry};
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Maximum number of IO accesses performed by a single instruction.
+const IO_PER_INSTRUCTION: usize = 16;
+
+/// Selects the instructions and IO accesses which are streamed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Stream the executed instructions
+    pub instructions: bool,
+
+    /// Only stream branches which have been taken
+    pub taken_branches: bool,
+
+    /// Stream the CPU accesses to IO registers
+    pub io: bool,
+
+    /// IO registers whose accesses are streamed, or all of them if empty
+    pub io_ranges: Vec<RangeInclusive<u16>>,
+
+    /// Only stream the instructions within these addresses, and the IO accesses they
+    /// perform, or everything if empty
+    pub pc_ranges: Vec<RangeInclusive<u16>>,
+}
+
+impl Default for TraceFilter {
+    fn default() -> TraceFilter {
+        TraceFilter {
+            instructions: true,
+            taken_branches: false,
+            io: false,
+            io_ranges: vec![],
+            pc_ranges: vec![],
+        }
+    }
+}
+
+impl TraceFilter {
+    fn pc_matches(&self, pc: u16) -> bool {
+        in_ranges(&self.pc_ranges, pc)
+    }
+
+    fn io_matches(&self, access: &IoAccess) -> bool {
+        self.io && self.pc_matches(access.pc) && in_ranges(&self.io_ranges, access.addr)
+    }
+}
+
+/// Returns true if `addr` is in one of `ranges`, or if there are none.
+fn in_ranges(ranges: &[RangeInclusive<u16>], addr: u16) -> bool {
+    ranges.is_empty() || ranges.iter().any(|r| r.contains(&addr))
+}
+
+/// Parses a comma-separated list of hex addresses or ranges, eg. `FF40-FF4B,FF0F`.
+/// Returns `None` if any of them is invalid.
+pub fn parse_ranges(text: &str) -> Option<Vec<RangeInclusive<u16>>> {
+    let hex = |s: &str| {
+        let s = s.trim().trim_start_matches('$').trim_start_matches("0x");
+        u16::from_str_radix(s, 16).ok()
+    };
+
+    text.split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| {
+            let mut bounds = s.splitn(2, '-');
+            let start = hex(bounds.next()?)?;
+            let end = bounds.next().map_or(Some(start), hex)?;
+
+            if start <= end {
+                Some(start..=end)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Single line of a streamed trace.
+#[derive(Debug, Clone, Copy)]
+pub enum TraceRecord {
+    Instruction(TraceEntry),
+    Io(IoAccess),
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceRecord::Instruction(entry) => write!(f, "{}", entry),
+            TraceRecord::Io(access) => write!(f, "{}", access),
+        }
+    }
+}
+
+/// Collects the executed instructions and the IO accesses satisfying a filter,
+/// until they are taken to be written out, eg. to a file.
+///
+/// Unlike the [`Tracer`](super::Tracer), nothing is discarded: records pile up
+/// until [`TraceStream::take_records`] is called.
+pub struct TraceStream {
+    filter: TraceFilter,
+
+    // Instruction being executed, and the IO accesses it performed so far
+    entry: Option<TraceEntry>,
+    io: IoLog,
+
+    records: Vec<TraceRecord>,
+}
+
+impl TraceStream {
+    pub fn new(filter: TraceFilter) -> TraceStream {
+        TraceStream {
+            filter,
+            entry: None,
+            io: IoLog::new(IO_PER_INSTRUCTION),
+            records: vec![],
+        }
+    }
+
+    pub fn filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: TraceFilter) {
+        self.filter = filter;
+    }
+
+    /// Starts collecting the IO accesses of the instruction at `pc`, described by `entry`
+    /// unless the CPU is halted.
+    pub fn begin_instruction(&mut self, pc: u16, entry: Option<TraceEntry>) {
+        self.entry = entry;
+        self.io.drain();
+        self.io.set_pc(pc);
+    }
+
+    /// Starts recording the accesses of the CPU cycle happening at `cycles`.
+    pub fn begin(&mut self, cycles: u64) {
+        self.io.begin(cycles);
+    }
+
+    /// Stops recording accesses until the next CPU cycle.
+    pub fn end(&mut self) {
+        self.io.end();
+    }
+
+    /// Records an IO access performed by the current instruction.
+    pub fn record_io(&self, access: MemAccess, addr: u16, val: u8) {
+        self.io.record(access, addr, val);
+    }
+
+    /// Completes the current instruction, which left the CPU at `pc`, and keeps
+    /// the records satisfying the filter.
+    pub fn end_instruction(&mut self, pc: u16) {
+        if let Some(entry) = self.entry.take() {
+            let instr = entry.instr;
+            let next = entry.pc.wrapping_add(u16::from(instr.size));
+
+            // Conditional branches are taken if they didn't fall through
+            let taken = instr.is_branch() && (!instr.is_conditional() || pc != next);
+
+            if self.filter.instructions
+                && self.filter.pc_matches(entry.pc)
+                && (!self.filter.taken_branches || taken)
+            {
+                self.records.push(TraceRecord::Instruction(entry));
+            }
+        }
+
+        for access in self.io.drain() {
+            if self.filter.io_matches(&access) {
+                self.records.push(TraceRecord::Io(access));
+            }
+        }
+    }
+
+    /// Removes and returns the records collected so far, from the oldest to the newest.
+    pub fn take_records(&mut self) -> Vec<TraceRecord> {
+        std::mem::take(&mut self.records)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::cpu::{self, CPU};
+    use super::*;
+
+    fn execute(stream: &mut TraceStream, bytes: &[u8], pc: u16, next_pc: u16, io: &[u16]) {
+        let mut cpu = CPU::new();
+        cpu.pc = pc;
+
+        let entry = TraceEntry::new(&cpu, cpu::decode(bytes), 0);
+
+        stream.begin_instruction(pc, Some(entry));
+        for addr in io {
+            stream.begin(0);
+            stream.record_io(MemAccess::Write, *addr, 0x00);
+            stream.end();
+        }
+        stream.end_instruction(next_pc);
+    }
+
+    fn addresses(stream: &mut TraceStream) -> Vec<u16> {
+        stream
+            .take_records()
+            .iter()
+            .map(|r| match r {
+                TraceRecord::Instruction(e) => e.pc,
+                TraceRecord::Io(a) => a.addr,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ranges_are_parsed() {
+        assert_eq!(
+            parse_ranges("FF40-FF4B, $FF0F"),
+            Some(vec![0xFF40..=0xFF4B, 0xFF0F..=0xFF0F])
+        );
+        assert_eq!(parse_ranges(""), Some(vec![]));
+        assert_eq!(parse_ranges("FF4B-FF40"), None);
+        assert_eq!(parse_ranges("FF40-"), None);
+    }
+
+    #[test]
+    fn only_taken_branches_are_streamed() {
+        let mut stream = TraceStream::new(TraceFilter {
+            taken_branches: true,
+            ..TraceFilter::default()
+        });
+
+        // NOP, JR NZ not taken, JR NZ taken, JP
+        execute(&mut stream, &[0x00], 0x0100, 0x0101, &[]);
+        execute(&mut stream, &[0x20, 0x10], 0x0101, 0x0103, &[]);
+        execute(&mut stream, &[0x20, 0x10], 0x0103, 0x0115, &[]);
+        execute(&mut stream, &[0xC3, 0x00, 0x02], 0x0115, 0x0200, &[]);
+
+        assert_eq!(addresses(&mut stream), vec![0x0103, 0x0115]);
+        assert!(stream.take_records().is_empty());
+    }
+
+    #[test]
+    fn io_accesses_are_filtered_by_address_and_pc() {
+        let mut stream = TraceStream::new(TraceFilter {
+            instructions: false,
+            io: true,
+            io_ranges: parse_ranges("FF40-FF4B").unwrap(),
+            pc_ranges: parse_ranges("0150-015F").unwrap(),
+            ..TraceFilter::default()
+        });
+
+        execute(
+            &mut stream,
+            &[0xE0, 0x40],
+            0x0150,
+            0x0152,
+            &[0xFF40, 0xFF26],
+        );
+        execute(&mut stream, &[0xE0, 0x41], 0x0200, 0x0202, &[0xFF41]);
+
+        assert_eq!(addresses(&mut stream), vec![0xFF40]);
+    }
+}
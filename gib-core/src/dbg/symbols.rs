@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+/// Table of symbols naming memory locations, as listed in the `.sym` files produced by
+/// RGBDS: one `BB:AAAA Name` line per symbol, where `BB` is the bank of address `AAAA`.
+#[derive(Default)]
+pub struct Symbols {
+    labels: HashMap<(usize, u16), String>,
+    addrs: HashMap<String, (usize, u16)>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    /// Parses the contents of a `.sym` file. Comments (starting with `;`) and malformed
+    /// lines are skipped.
+    pub fn parse(text: &str) -> Symbols {
+        let mut symbols = Symbols::new();
+
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+
+            let (loc, name) = match (words.next(), words.next()) {
+                (Some(loc), Some(name)) => (loc, name),
+                _ => continue,
+            };
+
+            let mut loc = loc.splitn(2, ':');
+
+            let bank = loc.next().and_then(|b| usize::from_str_radix(b, 16).ok());
+            let addr = loc.next().and_then(|a| u16::from_str_radix(a, 16).ok());
+
+            if let (Some(bank), Some(addr)) = (bank, addr) {
+                symbols.insert(bank, addr, name);
+            }
+        }
+
+        symbols
+    }
+
+    /// Names the location `addr` of `bank`. If it has more than one name, global
+    /// labels take precedence over local ones (eg. `Main.loop`).
+    pub fn insert(&mut self, bank: usize, addr: u16, name: &str) {
+        let local = |n: &str| n.contains('.');
+
+        let replace = match self.labels.get(&(bank, addr)) {
+            Some(old) => local(old) && !local(name),
+            None => true,
+        };
+
+        if replace {
+            self.labels.insert((bank, addr), name.to_string());
+        }
+        self.addrs.insert(name.to_string(), (bank, addr));
+    }
+
+    /// Returns the name of location `addr` of `bank`, if any.
+    pub fn label(&self, bank: usize, addr: u16) -> Option<&str> {
+        self.labels.get(&(bank, addr)).map(|s| s.as_str())
+    }
+
+    /// Returns the bank and address of the location called `name`, if any.
+    pub fn lookup(&self, name: &str) -> Option<(usize, u16)> {
+        self.addrs.get(name).cloned()
+    }
+
+    /// Returns the number of named locations.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sym_files_are_parsed() {
+        let symbols = Symbols::parse(
+            "; File generated by rgblink\n\
+             00:0150 Main\n\
+             00:0150 Main.loop\n\
+             01:4000 BankedFunc ; trailing comment\n\
+             00:C000 wBuffer\n\
+             garbage line\n\
+             zz:1234 Bad\n",
+        );
+
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols.label(0, 0x0150), Some("Main"));
+        assert_eq!(symbols.label(1, 0x4000), Some("BankedFunc"));
+        assert_eq!(symbols.label(2, 0x4000), None);
+
+        assert_eq!(symbols.lookup("Main.loop"), Some((0, 0x0150)));
+        assert_eq!(symbols.lookup("wBuffer"), Some((0, 0xC000)));
+        assert_eq!(symbols.lookup("Bad"), None);
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Kind of activity recorded in the PPU timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpuEventKind {
+    /// The LCD controller entered a new mode (0-3)
+    Mode(u8),
+    /// LY became equal to LYC
+    LycMatch,
+    /// A V-Blank interrupt has been requested
+    VBlankIrq,
+    /// A STAT interrupt has been requested
+    StatIrq,
+}
+
+impl fmt::Display for PpuEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PpuEventKind::Mode(mode) => write!(f, "MODE {}", mode),
+            PpuEventKind::LycMatch => write!(f, "LY=LYC"),
+            PpuEventKind::VBlankIrq => write!(f, "VBLANK IRQ"),
+            PpuEventKind::StatIrq => write!(f, "STAT IRQ"),
+        }
+    }
+}
+
+/// Single event in the PPU timeline, along with the position of the LCD controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PpuEvent {
+    pub frame: u32,
+    pub line: u8,
+    pub dot: u16,
+    pub kind: PpuEventKind,
+}
+
+impl fmt::Display for PpuEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:8} LY:{:3} DOT:{:3} {}",
+            self.frame, self.line, self.dot, self.kind
+        )
+    }
+}
+
+/// Records the activity of the PPU over the last few frames, like a logic analyzer would.
+pub struct PpuTimeline {
+    events: VecDeque<PpuEvent>,
+    frames: u32,
+    frame: u32,
+}
+
+impl PpuTimeline {
+    /// Creates a timeline holding the events of the last `frames` frames.
+    pub fn new(frames: u32) -> PpuTimeline {
+        PpuTimeline {
+            events: VecDeque::new(),
+            frames: frames.max(1),
+            frame: 0,
+        }
+    }
+
+    /// Starts recording a new frame, discarding the events of the oldest one if needed.
+    pub fn begin_frame(&mut self) {
+        self.frame = self.frame.wrapping_add(1);
+
+        while let Some(e) = self.events.front() {
+            if self.frame.wrapping_sub(e.frame) < self.frames {
+                break;
+            }
+            self.events.pop_front();
+        }
+    }
+
+    /// Records an event happening at `dot` on `line` of the current frame.
+    pub fn record(&mut self, line: u8, dot: u16, kind: PpuEventKind) {
+        self.events.push_back(PpuEvent {
+            frame: self.frame,
+            line,
+            dot,
+            kind,
+        });
+    }
+
+    /// Returns the range of frames held in the timeline, the last one being in progress.
+    pub fn frames(&self) -> RangeInclusive<u32> {
+        let oldest = self.events.front().map_or(self.frame, |e| e.frame);
+
+        oldest..=self.frame
+    }
+
+    /// Returns the events recorded during `frame`, in chronological order.
+    pub fn events(&self, frame: u32) -> impl Iterator<Item = &PpuEvent> {
+        self.events.iter().filter(move |e| e.frame == frame)
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_the_last_frames_are_kept() {
+        let mut tl = PpuTimeline::new(2);
+
+        for _ in 0..3 {
+            tl.begin_frame();
+            tl.record(0, 0, PpuEventKind::Mode(2));
+            tl.record(144, 0, PpuEventKind::VBlankIrq);
+        }
+
+        assert_eq!(tl.frames(), 2..=3);
+        assert_eq!(tl.events(1).count(), 0);
+        assert_eq!(tl.events(3).count(), 2);
+        assert_eq!(
+            tl.events(2).last().unwrap().to_string(),
+            "       2 LY:144 DOT:  0 VBLANK IRQ"
+        );
+    }
+}
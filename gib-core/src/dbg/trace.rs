@@ -0,0 +1,148 @@
+use super::cpu::{Immediate, Instruction, CPU};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+
+/// CPU state right before the execution of an instruction.
+#[derive(Debug, Clone, Copy)]
+pub struct TraceEntry {
+    pub cycles: u64,
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+    pub instr: Instruction,
+}
+
+impl TraceEntry {
+    pub fn new(cpu: &CPU, instr: Instruction, cycles: u64) -> TraceEntry {
+        TraceEntry {
+            cycles,
+            af: cpu.af,
+            bc: cpu.bc,
+            de: cpu.de,
+            hl: cpu.hl,
+            sp: cpu.sp,
+            pc: cpu.pc,
+            instr,
+        }
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    /// Formats the entry like BGB's trace logs, followed by the disassembled instruction.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |mask, c| if self.af & mask != 0 { c } else { '-' };
+
+        write!(
+            f,
+            "A:{:02X} F:{}{}{}{} BC:{:04X} DE:{:04X} HL:{:04X} SP:{:04X} PC:{:04X} (cy: {}) |{:04X}: {:02X} ",
+            self.af >> 8,
+            flag(0x80, 'Z'),
+            flag(0x40, 'N'),
+            flag(0x20, 'H'),
+            flag(0x10, 'C'),
+            self.bc,
+            self.de,
+            self.hl,
+            self.sp,
+            self.pc,
+            self.cycles,
+            self.pc,
+            self.instr.opcode,
+        )?;
+
+        match self.instr.imm {
+            Some(Immediate::Imm8(d8)) => write!(f, "{:02X}    ", d8)?,
+            Some(Immediate::Imm16(d16)) => write!(f, "{:02X} {:02X} ", d16 as u8, d16 >> 8)?,
+            None => write!(f, "      ")?,
+        }
+
+        write!(f, " {}", self.instr.mnemonic)
+    }
+}
+
+/// Records the most recently executed instructions into a ring buffer.
+pub struct Tracer {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl Tracer {
+    /// Creates a tracer holding up to `capacity` instructions.
+    pub fn new(capacity: usize) -> Tracer {
+        Tracer {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Records an entry, discarding the oldest one if the buffer is full.
+    pub fn record(&mut self, entry: TraceEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Returns the recorded entries, from the oldest to the newest.
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Writes the recorded entries, one per line.
+    pub fn dump(&self, w: &mut impl Write) -> io::Result<()> {
+        for entry in self.entries.iter() {
+            writeln!(w, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::cpu;
+    use super::*;
+
+    fn entry(pc: u16) -> TraceEntry {
+        let instr = cpu::decode(&[0xC3, 0x50, 0x01]);
+
+        let mut cpu = CPU::new();
+        cpu.pc = pc;
+
+        TraceEntry::new(&cpu, instr, 1234)
+    }
+
+    #[test]
+    fn ring_buffer_discards_oldest_entries() {
+        let mut tracer = Tracer::new(2);
+
+        for pc in 0..3 {
+            tracer.record(entry(pc));
+        }
+
+        let pcs: Vec<_> = tracer.entries().map(|e| e.pc).collect();
+        assert_eq!(pcs, vec![1, 2]);
+    }
+
+    #[test]
+    fn entries_are_formatted_like_bgb() {
+        let mut out = vec![];
+        let mut tracer = Tracer::new(1);
+
+        tracer.record(entry(0x0100));
+        tracer.dump(&mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "A:01 F:Z-HC BC:0013 DE:00D8 HL:014D SP:FFFE PC:0100 (cy: 1234) |0100: C3 50 01  JP a16\n"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use super::MemAccess;
+
+use std::cell::Cell;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Memory range to be monitored for reads, writes and/or changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub on_read: bool,
+    pub on_write: bool,
+
+    /// Only trip on writes of a value different from the previous one
+    pub on_change: bool,
+}
+
+impl Watchpoint {
+    pub fn new(range: RangeInclusive<u16>, on_read: bool, on_write: bool) -> Watchpoint {
+        Watchpoint {
+            range,
+            on_read,
+            on_write,
+            on_change: false,
+        }
+    }
+
+    /// Creates a watchpoint tripping whenever a value in `range` changes.
+    pub fn changes(range: RangeInclusive<u16>) -> Watchpoint {
+        Watchpoint {
+            on_change: true,
+            ..Watchpoint::new(range, false, false)
+        }
+    }
+
+    fn matches(&self, addr: u16, access: MemAccess, changed: bool) -> bool {
+        let enabled = match access {
+            MemAccess::Read => self.on_read,
+            MemAccess::Write => self.on_write || (self.on_change && changed),
+        };
+
+        enabled && self.range.contains(&addr)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.range.start() == self.range.end() {
+            write!(f, "0x{:04X}", self.range.start())?;
+        } else {
+            write!(f, "0x{:04X}-0x{:04X}", self.range.start(), self.range.end())?;
+        }
+
+        write!(
+            f,
+            " {}{}{}",
+            if self.on_read { 'R' } else { '-' },
+            if self.on_write { 'W' } else { '-' },
+            if self.on_change { 'C' } else { '-' },
+        )
+    }
+}
+
+/// Memory access which tripped a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub access: MemAccess,
+    pub addr: u16,
+    pub val: u8,
+}
+
+/// Table of watchpoints consulted on every memory access performed through the bus.
+///
+/// Hits are not reported immediately, since aborting an access midway through an
+/// instruction would cause it to be repeated once execution is resumed. Instead, the
+/// first one is latched and can be collected once the instruction is complete.
+#[derive(Default)]
+pub struct Watchpoints {
+    table: Vec<Watchpoint>,
+    hit: Cell<Option<WatchHit>>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Watchpoints {
+        Watchpoints::default()
+    }
+
+    pub fn add(&mut self, wp: Watchpoint) {
+        if !self.table.contains(&wp) {
+            self.table.push(wp);
+        }
+    }
+
+    pub fn remove(&mut self, wp: &Watchpoint) {
+        self.table.retain(|w| w != wp);
+    }
+
+    pub fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.table.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Checks an access against the table, latching it if it trips a watchpoint.
+    /// `old` is the value held by `addr` before the access.
+    pub fn check(&self, access: MemAccess, addr: u16, old: u8, val: u8) {
+        if self.hit.get().is_some() {
+            return;
+        }
+
+        let changed = old != val;
+
+        if self.table.iter().any(|w| w.matches(addr, access, changed)) {
+            self.hit.set(Some(WatchHit { access, addr, val }));
+        }
+    }
+
+    /// Returns the first access which tripped a watchpoint since the last call, if any.
+    pub fn take_hit(&self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_the_first_matching_access_is_latched() {
+        let mut wps = Watchpoints::new();
+
+        wps.add(Watchpoint::new(0xC000..=0xC0FF, false, true));
+        wps.add(Watchpoint::new(0xFF40..=0xFF40, true, false));
+
+        wps.check(MemAccess::Read, 0xC000, 0x12, 0x12);
+        wps.check(MemAccess::Write, 0xFF40, 0x00, 0x91);
+        assert_eq!(wps.take_hit(), None);
+
+        wps.check(MemAccess::Write, 0xC0FF, 0x00, 0x34);
+        wps.check(MemAccess::Read, 0xFF40, 0x91, 0x91);
+        assert_eq!(
+            wps.take_hit(),
+            Some(WatchHit {
+                access: MemAccess::Write,
+                addr: 0xC0FF,
+                val: 0x34,
+            })
+        );
+        assert_eq!(wps.take_hit(), None);
+
+        wps.remove(&Watchpoint::new(0xC000..=0xC0FF, false, true));
+        wps.check(MemAccess::Write, 0xC000, 0x00, 0x56);
+        assert_eq!(wps.take_hit(), None);
+    }
+
+    #[test]
+    fn change_watchpoints_ignore_identical_writes() {
+        let mut wps = Watchpoints::new();
+
+        wps.add(Watchpoint::changes(0xC000..=0xC000));
+
+        wps.check(MemAccess::Read, 0xC000, 0x12, 0x12);
+        wps.check(MemAccess::Write, 0xC000, 0x12, 0x12);
+        assert_eq!(wps.take_hit(), None);
+
+        wps.check(MemAccess::Write, 0xC000, 0x12, 0x34);
+        assert_eq!(
+            wps.take_hit(),
+            Some(WatchHit {
+                access: MemAccess::Write,
+                addr: 0xC000,
+                val: 0x34,
+            })
+        );
+    }
+}
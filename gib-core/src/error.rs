@@ -0,0 +1,132 @@
+//! Errors reported across the boundary of the library, eg. when loading a ROM,
+//! detailed enough for frontends to explain them and offer a way to recover.
+
+use super::dbg::TraceEvent;
+
+use std::{error, fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The ROM image is malformed, eg. shorter than its header declares
+    InvalidRom(String),
+    /// The cartridge has a memory bank controller which is not emulated
+    UnsupportedMapper { code: u8 },
+    /// The header declares a ROM size which doesn't exist
+    UnsupportedRomSize { code: u8 },
+    /// The header declares an external RAM size which doesn't exist
+    UnsupportedRamSize { code: u8 },
+    /// The boot ROM doesn't have the size of any model's
+    InvalidBootRom { size: usize },
+    /// An access to a location which cannot be accessed, with the PC if known
+    BusFault { addr: u16, pc: Option<u16> },
+    /// The CPU executed an illegal opcode, which locks it up
+    CpuLocked { opcode: u8, pc: u16 },
+    /// The savestate is corrupted, or was saved from another ROM or model
+    InvalidSavestate(&'static str),
+    /// The movie is corrupted
+    InvalidMovie(&'static str),
+    /// Execution stopped because of a debugging event, eg. a breakpoint
+    Stopped(TraceEvent),
+    /// A file could not be read, eg. by frontends loading a ROM
+    Io(io::Error),
+}
+
+impl Error {
+    /// Converts an event raised while executing at `pc`, recording it in faults.
+    pub fn from_event(evt: TraceEvent, pc: u16) -> Error {
+        match Error::from(evt) {
+            Error::BusFault { addr, .. } => Error::BusFault { addr, pc: Some(pc) },
+            err => err,
+        }
+    }
+}
+
+impl From<TraceEvent> for Error {
+    fn from(evt: TraceEvent) -> Error {
+        match evt {
+            TraceEvent::RomSizeMismatch(declared, actual) => Error::InvalidRom(format!(
+                "the image is {} bytes, but its header declares {}",
+                actual, declared
+            )),
+            TraceEvent::UnsupportedMbcType(code) => Error::UnsupportedMapper { code },
+            TraceEvent::UnsupportedRomSize(code) => Error::UnsupportedRomSize { code },
+            TraceEvent::UnsupportedRamSize(code) => Error::UnsupportedRamSize { code },
+            TraceEvent::InvalidBootRomSize(size) => Error::InvalidBootRom { size },
+            TraceEvent::BusFault(addr) | TraceEvent::MemFault(addr) => {
+                Error::BusFault { addr, pc: None }
+            }
+            TraceEvent::IllegalInstructionFault(pc, opcode) => Error::CpuLocked { opcode, pc },
+            TraceEvent::InvalidSavestate(reason) => Error::InvalidSavestate(reason),
+            TraceEvent::InvalidMovie(reason) => Error::InvalidMovie(reason),
+            evt => Error::Stopped(evt),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidRom(reason) => write!(f, "Invalid ROM: {}", reason),
+            Error::UnsupportedMapper { code } => write!(f, "Unsupported MBC: {:02X}", code),
+            Error::UnsupportedRomSize { code } => {
+                write!(f, "Unsupported ROM size code: {:02X}", code)
+            }
+            Error::UnsupportedRamSize { code } => {
+                write!(f, "Unsupported RAM size code: {:02X}", code)
+            }
+            Error::InvalidBootRom { size } => write!(f, "Invalid boot ROM size: {} bytes", size),
+            Error::BusFault { addr, pc: Some(pc) } => {
+                write!(f, "Bus fault accessing 0x{:04X} at PC 0x{:04X}", addr, pc)
+            }
+            Error::BusFault { addr, pc: None } => write!(f, "Bus fault accessing 0x{:04X}", addr),
+            Error::CpuLocked { opcode, pc } => write!(
+                f,
+                "CPU locked up at ${:04X}: illegal opcode {:02X}",
+                pc, opcode
+            ),
+            Error::InvalidSavestate(reason) => write!(f, "Invalid savestate: {}", reason),
+            Error::InvalidMovie(reason) => write!(f, "Invalid movie: {}", reason),
+            Error::Stopped(evt) => write!(f, "{}", evt),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn events_are_converted() {
+        match Error::from(TraceEvent::UnsupportedMbcType(0x1B)) {
+            Error::UnsupportedMapper { code: 0x1B } => (),
+            err => panic!("unexpected error: {:?}", err),
+        }
+        match Error::from_event(TraceEvent::MemFault(0xFEA0), 0x0150) {
+            Error::BusFault {
+                addr: 0xFEA0,
+                pc: Some(0x0150),
+            } => (),
+            err => panic!("unexpected error: {:?}", err),
+        }
+        match Error::from(TraceEvent::Breakpoint(0x0100)) {
+            Error::Stopped(TraceEvent::Breakpoint(0x0100)) => (),
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Structured events raised while the emulator runs, which frontends can subscribe to
+//! rather than polling the state of the machine after every step.
+
+use super::dbg;
+
+use bitflags::bitflags;
+
+/// Something which happened during the last instruction.
+#[derive(Debug, Clone, Copy)]
+pub enum Event {
+    /// The PPU has completed a frame, which can be displayed
+    FrameCompleted,
+    /// The PPU has entered V-Blank
+    VBlank,
+    /// A byte has been sent over the link port
+    SerialOut(u8),
+    /// Execution stopped at a breakpoint, at the given address
+    Breakpoint(u16),
+    /// Execution stopped because of a trace event, including breakpoints
+    Trace(dbg::TraceEvent),
+    /// Samples queued in the audio sink and its capacity, reported once per frame
+    AudioLevel { queued: usize, capacity: usize },
+}
+
+bitflags! {
+    /// Kinds of events a subscriber is interested in.
+    #[derive(Default)]
+    pub struct EventKinds: u8 {
+        const FRAME      = 0b_0000_0001;
+        const VBLANK     = 0b_0000_0010;
+        const SERIAL     = 0b_0000_0100;
+        const BREAKPOINT = 0b_0000_1000;
+        const TRACE      = 0b_0001_0000;
+        const AUDIO      = 0b_0010_0000;
+    }
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKinds {
+        match self {
+            Event::FrameCompleted => EventKinds::FRAME,
+            Event::VBlank => EventKinds::VBLANK,
+            Event::SerialOut(_) => EventKinds::SERIAL,
+            Event::Breakpoint(_) => EventKinds::BREAKPOINT,
+            Event::Trace(_) => EventKinds::TRACE,
+            Event::AudioLevel { .. } => EventKinds::AUDIO,
+        }
+    }
+}
+
+/// Handle returned when subscribing, to unsubscribe later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(usize);
+
+type Callback = Box<dyn FnMut(&Event) + Send>;
+
+/// Callbacks subscribed to events, each with the kinds of events it receives.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    next_id: usize,
+    subs: Vec<(SubscriptionId, EventKinds, Callback)>,
+}
+
+impl Subscribers {
+    pub fn subscribe(&mut self, kinds: EventKinds, f: Callback) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+
+        self.next_id += 1;
+        self.subs.push((id, kinds, f));
+        id
+    }
+
+    /// Removes a subscription, returning whether it existed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subs.len();
+        self.subs.retain(|(sub, _, _)| *sub != id);
+        self.subs.len() != len
+    }
+
+    /// Returns true if some subscriber receives any of `kinds`, to skip
+    /// the work of detecting events nobody is interested in.
+    pub fn wants(&self, kinds: EventKinds) -> bool {
+        self.subs.iter().any(|(_, k, _)| k.intersects(kinds))
+    }
+
+    /// Invokes the callbacks subscribed to the kind of `evt`.
+    pub fn emit(&mut self, evt: Event) {
+        let kind = evt.kind();
+
+        for (_, kinds, f) in &mut self.subs {
+            if kinds.contains(kind) {
+                f(&evt);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    #[test]
+    fn events_reach_their_subscribers() {
+        let mut subs = Subscribers::default();
+        let (tx, rx) = mpsc::channel();
+
+        assert!(!subs.wants(EventKinds::all()));
+
+        let id = subs.subscribe(
+            EventKinds::SERIAL | EventKinds::TRACE,
+            Box::new(move |evt| tx.send(*evt).unwrap()),
+        );
+        assert!(subs.wants(EventKinds::SERIAL));
+        assert!(!subs.wants(EventKinds::FRAME | EventKinds::VBLANK));
+
+        subs.emit(Event::SerialOut(0x42));
+        subs.emit(Event::FrameCompleted);
+        subs.emit(Event::Trace(dbg::TraceEvent::Breakpoint(0x0150)));
+
+        let events = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+
+        match events[..] {
+            [Event::SerialOut(0x42), Event::Trace(dbg::TraceEvent::Breakpoint(0x0150))] => (),
+            _ => panic!("unexpected events: {:?}", events),
+        }
+
+        assert!(subs.unsubscribe(id));
+        assert!(!subs.unsubscribe(id));
+        assert!(!subs.wants(EventKinds::all()));
+    }
+}
@@ -1,22 +1,69 @@
 use crossbeam::queue::ArrayQueue;
 
-use super::bus::Bus;
+use super::bus::{Bus, BusPolicy};
+use super::cheat::Cheats;
+use super::config::{DmgPalette, HardwareConfig, Model};
 use super::cpu::CPU;
-use super::dbg;
+use super::dbg::{
+    self, CodeAddr, CodeMap, FreezeList, IoLog, PpuTimeline, Profiler, TraceEntry, TraceFilter,
+    TraceStream, Tracer, WatchHit, Watchpoint,
+};
+use super::error::Error;
+use super::events::{Event, EventKinds, Subscribers, SubscriptionId};
 use super::io::JoypadState;
+use super::movie::{ActiveMovie, Movie, MovieMode, MOVIE_FRAME_CYCLES};
+use super::savestate::{self, Savestate, StateReader, StateWriter};
 
 use std::sync::Arc;
 
 pub const CPU_CLOCK: u64 = 4_194_304; // Hz
 pub const HSYNC_CLOCK: u64 = 9_198; // Hz
 
+/// Size of the LCD, in pixels.
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
 const CYCLES_PER_HSYNC: u64 = CPU_CLOCK / HSYNC_CLOCK;
+/// Number of CPU cycles in a video frame.
+pub const CYCLES_PER_FRAME: u64 = CYCLES_PER_HSYNC * 154;
+
+/// Signature at the beginning of every savestate.
+const SAVESTATE_MAGIC: &[u8; 4] = b"GIBS";
+
+/// Returns the FNV-1a hash of a ROM image, used to match savestates with their ROM.
+fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
 
 pub struct GameBoy {
     cpu: CPU,
     bus: Bus,
+    model: Model,
+    rom_hash: u64,
 
     cycles: u64,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    code_map: CodeMap,
+    code_tracking: bool,
+    watch_hit: Option<WatchHit>,
+    movie: Option<ActiveMovie>,
+
+    /// Locations rewritten every frame, and when they are next due
+    freezes: FreezeList,
+    next_freeze: u64,
+
+    /// Whether the PPU was in V-Blank after the last instruction, to apply GameShark codes
+    in_vblank: bool,
+
+    /// Callbacks notified of events, and the frames completed when they last were
+    subscribers: Subscribers,
+    frames: u64,
+
+    /// Interrupts breaking execution when dispatched, with the same layout as IE/IF
+    irq_breaks: u8,
 }
 
 impl Default for GameBoy {
@@ -24,23 +71,127 @@ impl Default for GameBoy {
         GameBoy {
             cpu: CPU::new(),
             bus: Bus::new(),
+            model: Model::Dmg,
+            rom_hash: 0,
 
             cycles: 0x18FCC,
+            tracer: None,
+            profiler: None,
+            code_map: CodeMap::new(),
+            code_tracking: false,
+            watch_hit: None,
+            movie: None,
+
+            freezes: FreezeList::new(),
+            next_freeze: 0,
+
+            in_vblank: false,
+
+            subscribers: Subscribers::default(),
+            frames: 0,
+
+            irq_breaks: 0,
         }
     }
 }
 
 impl GameBoy {
-    /// Create a new Game Boy instance.
+    /// Create a new DMG instance.
+    ///
+    /// The boot ROM is skipped: CPU and IO registers are directly initialized
+    /// with the values it would leave behind.
     pub fn new() -> GameBoy {
         GameBoy::default()
     }
 
-    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), dbg::TraceEvent> {
-        self.bus.load_rom(rom)
+    /// Creates a new Game Boy instance with the given hardware configuration.
+    pub fn with_config(config: HardwareConfig) -> Result<GameBoy, Error> {
+        let mut gb = GameBoy {
+            model: config.model,
+            ..GameBoy::default()
+        };
+
+        if let Some(ref boot_rom) = config.boot_rom {
+            gb.load_boot_rom(boot_rom)?;
+        } else {
+            let (af, bc, de, hl) = config.model.boot_registers();
+
+            gb.cpu.af = af;
+            gb.cpu.bc = bc;
+            gb.cpu.de = de;
+            gb.cpu.hl = hl;
+        }
+
+        gb.bus.fill_wram(config.wram_fill);
+        gb.bus.ppu.set_dmg_palette(config.dmg_palette);
+        gb.bus
+            .apu
+            .set_hpf_charge_factor(config.model.hpf_charge_factor());
+
+        Ok(gb)
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        self.bus.load_rom(rom)?;
+        self.rom_hash = rom_hash(rom);
+
+        // CGB functions are only available on CGB hardware
+        if self.model != Model::Cgb {
+            self.bus.set_cgb_mode(false);
+        }
+        Ok(())
+    }
+
+    /// Returns the emulated hardware model.
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Loads a boot ROM and powers the system on, so that the boot ROM runs
+    /// before handing control over to the cartridge.
+    pub fn load_boot_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        self.bus.load_boot_rom(rom)?;
+
+        self.cpu = CPU::power_on();
+        self.cycles = 0;
+
+        Ok(())
     }
 
+    /// Executes a single instruction, along with any interrupt dispatch following it,
+    /// and notifies the subscribers of the events raised meanwhile.
     pub fn step(&mut self) -> Result<(), dbg::TraceEvent> {
+        let serial_sent = self.bus.sdt.bytes_sent();
+
+        let res = self.execute();
+
+        // GameShark codes are applied as V-Blank begins
+        let in_vblank = self.bus.ppu.mode() == 1;
+        if in_vblank && !self.in_vblank {
+            self.bus.apply_ram_cheats();
+            self.subscribers.emit(Event::VBlank);
+        }
+        self.in_vblank = in_vblank;
+
+        self.emit_events(serial_sent, &res);
+        res
+    }
+
+    fn execute(&mut self) -> Result<(), dbg::TraceEvent> {
+        self.sync_movie();
+        self.apply_freezes();
+        self.trace_instruction();
+        self.mark_code();
+
+        // The instruction may switch banks, so the bank it runs from is sampled beforehand
+        let (start_pc, start_bank, start_cycles) =
+            (self.cpu.pc, self.bus.cart.rom_bank(), self.cycles);
+        self.watch_hit = None;
+
+        if let Some(ref mut log) = self.bus.io_log {
+            log.set_pc(start_pc);
+        }
+
         // The first tick fetches the opcode
         self.tick()?;
 
@@ -49,14 +200,219 @@ impl GameBoy {
             self.tick()?;
         }
 
+        // Before any interrupt dispatch, so that branches can be told apart
+        if let Some(ref mut stream) = self.bus.trace_stream {
+            stream.end_instruction(self.cpu.pc);
+        }
+
+        if let Some(ref mut profiler) = self.profiler {
+            let function = self.cpu.current_function().unwrap_or(start_pc);
+
+            profiler.record(
+                CodeAddr::new(start_bank, start_pc),
+                CodeAddr::new(start_bank, function),
+                self.cycles - start_cycles,
+            );
+        }
+
         // Finally, handle any interrupts that arised
-        self.handle_irqs()?;
+        let vector = self.handle_irqs()?;
 
-        Ok(())
+        // Watchpoints break after the instruction which tripped them has completed
+        if let Some(hit) = self.watch_hit.take() {
+            return Err(dbg::TraceEvent::Watchpoint(
+                hit.access, hit.addr, hit.val, start_pc,
+            ));
+        }
+
+        // And so do line breaks
+        if let Some(ly) = self.bus.ppu.take_line_hit() {
+            return Err(dbg::TraceEvent::LineReached(ly));
+        }
+
+        // Interrupt breaks happen once the jump to the ISR has been performed
+        match vector {
+            Some(addr) if self.irq_breaks & (1 << ((addr - 0x40) / 0x08)) != 0 => {
+                Err(dbg::TraceEvent::Interrupt(addr))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Notifies the subscribers of the frames completed, the bytes sent over the link port
+    /// and the trace event raised by the last step, given the bytes sent over serial before it.
+    fn emit_events(&mut self, serial_sent: u64, res: &Result<(), dbg::TraceEvent>) {
+        let frames = self.bus.ppu.frame_count();
+
+        if frames != self.frames {
+            self.frames = frames;
+            self.subscribers.emit(Event::FrameCompleted);
+
+            if let Some((queued, capacity)) = self.bus.apu.audio_level() {
+                self.subscribers
+                    .emit(Event::AudioLevel { queued, capacity });
+            }
+        }
+
+        if self.subscribers.wants(EventKinds::SERIAL) {
+            let output = self.bus.sdt.output();
+            let sent = (self.bus.sdt.bytes_sent() - serial_sent) as usize;
+
+            for &b in &output[output.len().saturating_sub(sent)..] {
+                self.subscribers.emit(Event::SerialOut(b));
+            }
+        }
+
+        if let Err(evt) = *res {
+            if let dbg::TraceEvent::Breakpoint(addr) = evt {
+                self.subscribers.emit(Event::Breakpoint(addr));
+            }
+            self.subscribers.emit(Event::Trace(evt));
+        }
+    }
+
+    /// Invokes `f` with every event of the given `kinds` raised from now on,
+    /// until `unsubscribe` is called with the returned id.
+    pub fn subscribe<F: FnMut(&Event) + Send + 'static>(
+        &mut self,
+        kinds: EventKinds,
+        f: F,
+    ) -> SubscriptionId {
+        self.subscribers.subscribe(kinds, Box::new(f))
+    }
+
+    /// Removes a subscription, returning whether it existed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.subscribers.unsubscribe(id)
+    }
+
+    /// Records or replays the joypad state of each frame started since the last step,
+    /// if a movie is active.
+    fn sync_movie(&mut self) {
+        let movie = match self.movie {
+            Some(ref mut movie) => movie,
+            None => return,
+        };
+
+        let mut finished = false;
+
+        while self.cycles >= movie.next_frame {
+            let input = match movie.mode {
+                MovieMode::Recording => {
+                    let input = movie.host_input(self.bus.joy.autofire_period());
+                    movie.movie.push_input(input);
+                    input
+                }
+                MovieMode::Playing => match movie.movie.input(movie.frame) {
+                    Some(input) => input,
+                    None => {
+                        finished = true;
+                        break;
+                    }
+                },
+            };
+
+            self.bus.joy.set_state(input);
+
+            movie.frame += 1;
+            movie.next_frame += MOVIE_FRAME_CYCLES;
+        }
+
+        // Playback ends once the last recorded frame is over
+        if finished {
+            self.movie = None;
+            self.bus.joy.set_state(JoypadState::DEFAULT);
+        }
+    }
+
+    /// Records the instruction about to be executed, if tracing or streaming the trace.
+    fn trace_instruction(&mut self) {
+        if self.tracer.is_none() && self.bus.trace_stream.is_none() {
+            return;
+        }
+
+        let entry = if *self.cpu.halted.value() || self.cpu.stopped {
+            None
+        } else {
+            let instr = self.cpu.disasm(&self.bus, self.cpu.pc);
+            Some(TraceEntry::new(&self.cpu, instr, self.cycles))
+        };
+
+        if let (Some(tracer), Some(entry)) = (self.tracer.as_mut(), entry) {
+            tracer.record(entry);
+        }
+        if let Some(ref mut stream) = self.bus.trace_stream {
+            stream.begin_instruction(self.cpu.pc, entry);
+        }
+    }
+
+    /// Marks the bytes of the instruction about to be executed as code, in the ROM bank
+    /// they're currently mapped from, if code tracking is enabled.
+    fn mark_code(&mut self) {
+        if !self.code_tracking {
+            return;
+        }
+
+        // The boot ROM shadows the cartridge, whose bytes aren't executed
+        if *self.cpu.halted.value() || self.cpu.stopped || self.bus.boot_rom_mapped() {
+            return;
+        }
+
+        let pc = self.cpu.pc;
+        let size = self.cpu.disasm(&self.bus, pc).size;
+
+        for addr in (0..u16::from(size)).map(|i| pc.wrapping_add(i)) {
+            self.code_map
+                .mark(CodeAddr::new(self.bus.bank_at(addr), addr));
+        }
     }
 
     fn tick(&mut self) -> Result<(), dbg::TraceEvent> {
-        self.cpu.tick(&mut self.bus)?;
+        // In STOP mode the system clock is halted, until a joypad key is pressed.
+        // Cycles are still counted, so that frame-based runners can keep making progress.
+        if self.cpu.stopped {
+            if self.bus.joy.selected_keys_pressed() {
+                self.cpu.stopped = false;
+            } else {
+                self.cycles += self.cycles_per_tick();
+                return Ok(());
+            }
+        }
+
+        // Only accesses performed by the CPU itself can trip watchpoints, not the ones
+        // made in between instructions (eg. by the disassembler or the debugger).
+        self.bus.watchpoints.take_hit();
+
+        if let Some(ref mut log) = self.bus.io_log {
+            log.begin(self.cycles);
+        }
+        if let Some(ref mut stream) = self.bus.trace_stream {
+            stream.begin(self.cycles);
+        }
+
+        let res = self.cpu.tick(&mut self.bus);
+
+        if let Some(ref mut log) = self.bus.io_log {
+            log.end();
+        }
+        if let Some(ref mut stream) = self.bus.trace_stream {
+            stream.end();
+        }
+        res?;
+
+        if let Some(addr) = self.cpu.idu_addr.take() {
+            self.bus.trigger_oam_bug(addr);
+        }
+
+        // A STOP following a KEY1 write switches the CPU speed instead of stopping it
+        if self.cpu.stopped && self.bus.speed_switch_prepared() {
+            self.bus.switch_speed();
+            self.cpu.stopped = false;
+        }
+
+        if self.watch_hit.is_none() {
+            self.watch_hit = self.bus.watchpoints.take_hit();
+        }
 
         // Section 4.10 of "The Cycle-Accurate GameBoy Docs"
         // =================================================
@@ -72,34 +428,70 @@ impl GameBoy {
 
         self.bus.tick()?;
 
-        self.cycles += 4;
+        self.cycles += self.cycles_per_tick();
 
         Ok(())
     }
 
-    fn handle_irqs(&mut self) -> Result<(), dbg::TraceEvent> {
-        if let Some(id) = self.bus.itr.get_pending_irq() {
-            let addr = (0x40 + 0x08 * id) as u16;
-
-            self.cpu.halted.reset(false);
+    /// Returns the number of clock cycles elapsed in a CPU M-cycle, which is halved
+    /// in CGB double speed mode.
+    fn cycles_per_tick(&self) -> u64 {
+        if self.bus.double_speed {
+            2
+        } else {
+            4
+        }
+    }
 
-            // If IME = 1, disable HALT mode (if in it), set IME = 0,
-            // clear IF and run the corresponding ISR.
-            // If IME = 0, simply leave HALT mode.
-            if *self.cpu.intr_enabled.value() {
-                self.cpu.intr_enabled.reset(false);
-                self.bus.itr.clear_irq(id);
+    /// Services pending interrupts, returning the vector jumped to if one was dispatched.
+    fn handle_irqs(&mut self) -> Result<Option<u16>, dbg::TraceEvent> {
+        // Interrupts cannot wake the CPU up from STOP mode
+        if self.cpu.stopped {
+            return Ok(None);
+        }
 
-                // Jump to interrupt service routing and wait 5 cycles until
-                // the jump has been performed.
-                self.cpu.jump_to_isr(&mut self.bus, addr)?;
+        // If an interrupt is being serviced, wait 5 cycles until the jump has been performed.
+        if self.cpu.service_irqs(&mut self.bus)? {
+            let vector = self.cpu.pc;
 
-                while self.cpu.executing {
-                    self.tick()?;
-                }
+            while self.cpu.executing {
+                self.tick()?;
             }
+            return Ok(Some(vector));
         }
-        Ok(())
+        Ok(None)
+    }
+
+    /// Rewrites the frozen locations, once per frame.
+    fn apply_freezes(&mut self) {
+        if self.freezes.is_empty() {
+            return;
+        }
+
+        // The clock may also have gone back, eg. after loading a savestate
+        if self.cycles >= self.next_freeze || self.next_freeze > self.cycles + CYCLES_PER_FRAME {
+            self.freezes.apply(&mut self.bus);
+            self.next_freeze = self.cycles + CYCLES_PER_FRAME;
+        }
+    }
+
+    /// Replaces the locations rewritten every frame, starting from the next instruction.
+    pub fn set_freezes(&mut self, freezes: FreezeList) {
+        self.freezes = freezes;
+        self.next_freeze = self.cycles;
+    }
+
+    pub fn freezes(&self) -> &FreezeList {
+        &self.freezes
+    }
+
+    /// Replaces the Game Genie and GameShark codes in effect.
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        self.bus.cheats = cheats;
+    }
+
+    pub fn cheats(&self) -> &Cheats {
+        &self.bus.cheats
     }
 
     pub fn run_for_vblank(&mut self) -> Result<(), dbg::TraceEvent> {
@@ -115,24 +507,213 @@ impl GameBoy {
     /// The emulation speed will be limited by the specified sample rate.
     /// This is very useful for "sync-by-audio"-style emulator.
     pub fn set_audio_sink(&mut self, sink: Arc<ArrayQueue<i16>>, sample_rate: f32) {
+        self.bus.sync_apu();
         self.bus.apu.set_sample_rate(sample_rate);
         self.bus.apu.set_audio_sink(sink);
     }
 
+    /// Slightly speeds up (`ratio` > 1.0) or slows down the production of audio samples,
+    /// eg. to keep the audio sink from running dry or overflowing.
+    pub fn set_audio_rate_adjust(&mut self, ratio: f32) {
+        self.bus.sync_apu();
+        self.bus.apu.set_rate_adjust(ratio);
+    }
+
     /// Marks the given key as pressed.
+    ///
+    /// While a movie is active, keys only take effect at the next frame boundary.
     pub fn press_key(&mut self, key: JoypadState) {
-        self.bus.joy.set_pressed_keys(key);
+        match self.movie {
+            Some(ref mut movie) => movie.held |= key,
+            None => self.bus.joy.button_pressed(key),
+        }
     }
 
     /// Marks the given key as not pressed.
     pub fn release_key(&mut self, key: JoypadState) {
-        self.bus.joy.set_release_keys(key);
+        match self.movie {
+            Some(ref mut movie) => movie.held &= !key,
+            None => self.bus.joy.button_released(key),
+        }
+    }
+
+    /// Holds the given key with autofire, repeatedly pressing and releasing it.
+    pub fn press_autofire(&mut self, key: JoypadState) {
+        match self.movie {
+            Some(ref mut movie) => movie.autofire |= key,
+            None => self.bus.joy.autofire_pressed(key),
+        }
+    }
+
+    /// Stops the autofire of the given key.
+    pub fn release_autofire(&mut self, key: JoypadState) {
+        match self.movie {
+            Some(ref mut movie) => movie.autofire &= !key,
+            None => self.bus.joy.autofire_released(key),
+        }
+    }
+
+    /// Returns the keys currently held with autofire.
+    pub fn autofire_keys(&self) -> JoypadState {
+        match self.movie {
+            Some(ref movie) => movie.autofire,
+            None => self.bus.joy.autofire(),
+        }
+    }
+
+    /// Starts recording a movie from the current state. Joypad inputs are
+    /// recorded once per frame until `stop_movie` is called.
+    pub fn start_recording(&mut self) {
+        let movie = Movie::new(self.save_state());
+
+        self.bus.joy.set_state(JoypadState::DEFAULT);
+        self.movie = Some(ActiveMovie::new(movie, MovieMode::Recording, self.cycles));
+    }
+
+    /// Restores the initial state of `movie` and starts replaying its inputs,
+    /// ignoring the keys pressed in the meantime.
+    pub fn start_playback(&mut self, movie: Movie) -> Result<(), Error> {
+        self.movie = None;
+        self.load_state(movie.initial_state())?;
+
+        self.bus.joy.set_state(JoypadState::DEFAULT);
+        self.movie = Some(ActiveMovie::new(movie, MovieMode::Playing, self.cycles));
+        Ok(())
+    }
+
+    /// Stops the active movie, returning it along with the frames recorded so far.
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        let movie = self.movie.take()?;
+
+        self.bus.joy.set_state(JoypadState::DEFAULT);
+        Some(movie.movie)
+    }
+
+    /// Returns whether a movie is being recorded or played back, along with
+    /// the current frame and the number of frames in the movie.
+    pub fn movie_status(&self) -> Option<(MovieMode, usize, usize)> {
+        self.movie
+            .as_ref()
+            .map(|movie| (movie.mode, movie.frame, movie.movie.frames()))
+    }
+
+    /// Sets for how many emulated frames autofire keys stay pressed, and then released.
+    pub fn set_autofire_period(&mut self, frames: u8) {
+        self.bus.joy.set_autofire_period(frames);
+    }
+
+    /// Returns the last bytes sent over the link port (up to `io::SERIAL_OUTPUT_CAPACITY`),
+    /// where test ROMs usually print their results.
+    pub fn serial_output(&self) -> &[u8] {
+        self.bus.sdt.output()
+    }
+
+    /// Discards the bytes sent over the link port so far.
+    pub fn clear_serial_output(&mut self) {
+        self.bus.sdt.clear_output();
     }
 
+    /// Serializes the state of the whole machine, which can be restored with `load_state`
+    /// as long as the same ROM is loaded on the same hardware model.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        let model = Model::all().iter().position(|m| *m == self.model);
+
+        w.write_bytes(SAVESTATE_MAGIC);
+        savestate::FORMAT_VERSION.save_state(&mut w);
+        (model.unwrap_or(0) as u8).save_state(&mut w);
+        self.rom_hash.save_state(&mut w);
+
+        self.cycles.save_state(&mut w);
+        self.cpu.save_state(&mut w);
+        self.bus.save_state(&mut w);
+
+        w.into_inner()
+    }
+
+    /// Restores a state produced by `save_state`. Debugging facilities (eg. breakpoints
+    /// or the tracer) and host settings (eg. the audio sink) are left untouched.
+    ///
+    /// States made for a different ROM, model or format version are rejected, and so
+    /// are corrupted ones, in which case the machine is left as it was.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        // Components are loaded in place, so the current state is kept to go back to
+        let backup = self.save_state();
+
+        self.restore_state(state).inspect_err(|_| {
+            self.restore_state(&backup)
+                .expect("the current state cannot be restored");
+        })
+    }
+
+    /// Loads `state` in place, leaving the machine partly loaded if it is corrupted.
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let mut r = StateReader::new(state);
+
+        if r.read_bytes(SAVESTATE_MAGIC.len())? != SAVESTATE_MAGIC {
+            return Err(Error::InvalidSavestate("not a savestate"));
+        }
+
+        let (mut version, mut model, mut hash) = (0u16, 0u8, 0u64);
+        version.load_state(&mut r)?;
+        model.load_state(&mut r)?;
+        hash.load_state(&mut r)?;
+
+        if version != savestate::FORMAT_VERSION {
+            return Err(Error::InvalidSavestate("unsupported version"));
+        }
+        if Model::all().get(usize::from(model)) != Some(&self.model) {
+            return Err(Error::InvalidSavestate("different model"));
+        }
+        if hash != self.rom_hash {
+            return Err(Error::InvalidSavestate("different ROM"));
+        }
+
+        self.cycles.load_state(&mut r)?;
+        self.cpu.load_state(&mut r)?;
+        self.bus.load_state(&mut r)?;
+        self.watch_hit = None;
+
+        if r.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidSavestate("trailing data"))
+        }
+    }
+
+    /// Copies the last frame rendered by the PPU to `vbuf`, in U8U8U8U8 RGBA format.
     pub fn rasterize(&self, vbuf: &mut [u8]) {
         self.bus.ppu.rasterize(vbuf);
     }
 
+    /// Returns the frame being displayed, as 160x144 pixels in U8U8U8U8 RGBA format,
+    /// with the palette and frame blending applied.
+    pub fn screenshot(&self) -> Vec<u8> {
+        let mut vbuf = vec![0xFF; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        self.rasterize(&mut vbuf);
+        vbuf
+    }
+
+    /// Returns the last frame completed by the PPU, if it hasn't been taken yet.
+    pub fn take_frame(&mut self) -> Option<&[u8]> {
+        self.bus.ppu.take_frame()
+    }
+
+    /// Sets a callback invoked with every frame completed by the PPU.
+    pub fn on_frame<F: FnMut(&[u8]) + Send + 'static>(&mut self, f: F) {
+        self.bus.ppu.on_frame(f);
+    }
+
+    /// Blends each frame with the previous ones, with the given `persistence` (0.0 to disable).
+    pub fn set_frame_blending(&mut self, persistence: f32) {
+        self.bus.ppu.set_frame_blending(persistence);
+    }
+
+    /// Sets the colors used to display the four shades on monochrome models.
+    pub fn set_dmg_palette(&mut self, pal: DmgPalette) {
+        self.bus.ppu.set_dmg_palette(pal);
+    }
+
     pub fn clock_cycles(&self) -> u64 {
         self.cycles
     }
@@ -148,4 +729,149 @@ impl GameBoy {
     pub fn bus(&self) -> &Bus {
         &self.bus
     }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    /// Sets how accesses to unmapped memory are handled.
+    pub fn set_bus_policy(&mut self, policy: BusPolicy) {
+        self.bus.policy = policy;
+    }
+
+    /// Breaks execution whenever the CPU accesses memory monitored by `wp`.
+    pub fn add_watchpoint(&mut self, wp: Watchpoint) {
+        self.bus.watchpoints.add(wp);
+    }
+
+    pub fn remove_watchpoint(&mut self, wp: &Watchpoint) {
+        self.bus.watchpoints.remove(wp);
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.bus.watchpoints.iter()
+    }
+
+    /// Breaks execution once the LCD controller starts line `ly` (eg. 144 for V-Blank),
+    /// or never if `None`. The break happens after the current instruction has completed.
+    pub fn break_at_line(&mut self, ly: Option<u8>) {
+        self.bus.ppu.set_break_line(ly);
+    }
+
+    /// Breaks execution whenever one of the interrupts in `mask` (with the same layout
+    /// as the IE and IF registers) is dispatched.
+    pub fn set_irq_breaks(&mut self, mask: u8) {
+        self.irq_breaks = mask & 0x1F;
+    }
+
+    /// Returns the mask of interrupts breaking execution when dispatched.
+    pub fn irq_breaks(&self) -> u8 {
+        self.irq_breaks
+    }
+
+    /// Starts recording CPU accesses to IO registers, keeping up to `capacity` of them.
+    pub fn enable_io_log(&mut self, capacity: usize) {
+        self.bus.io_log = Some(IoLog::new(capacity));
+    }
+
+    /// Stops recording IO register accesses and discards the log.
+    pub fn disable_io_log(&mut self) {
+        self.bus.io_log = None;
+    }
+
+    pub fn io_log(&self) -> Option<&IoLog> {
+        self.bus.io_log.as_ref()
+    }
+
+    pub fn io_log_mut(&mut self) -> Option<&mut IoLog> {
+        self.bus.io_log.as_mut()
+    }
+
+    /// Starts recording the activity of the LCD controller over the last `frames` frames.
+    pub fn enable_ppu_timeline(&mut self, frames: u32) {
+        self.bus.ppu.timeline = Some(PpuTimeline::new(frames));
+    }
+
+    /// Stops recording the activity of the LCD controller and discards the timeline.
+    pub fn disable_ppu_timeline(&mut self) {
+        self.bus.ppu.timeline = None;
+    }
+
+    pub fn ppu_timeline(&self) -> Option<&PpuTimeline> {
+        self.bus.ppu.timeline.as_ref()
+    }
+
+    /// Starts recording executed instructions, keeping up to `capacity` of them.
+    pub fn enable_tracing(&mut self, capacity: usize) {
+        self.tracer = Some(Tracer::new(capacity));
+    }
+
+    /// Stops recording executed instructions and discards the trace.
+    pub fn disable_tracing(&mut self) {
+        self.tracer = None;
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    /// Starts streaming the executed instructions and IO accesses selected by `filter`.
+    /// Records pile up until taken with `take_trace_records`.
+    pub fn enable_trace_stream(&mut self, filter: TraceFilter) {
+        self.bus.trace_stream = Some(TraceStream::new(filter));
+    }
+
+    /// Stops streaming the trace and discards the records which haven't been taken.
+    pub fn disable_trace_stream(&mut self) {
+        self.bus.trace_stream = None;
+    }
+
+    pub fn trace_stream(&self) -> Option<&TraceStream> {
+        self.bus.trace_stream.as_ref()
+    }
+
+    pub fn trace_stream_mut(&mut self) -> Option<&mut TraceStream> {
+        self.bus.trace_stream.as_mut()
+    }
+
+    /// Removes and returns the records streamed so far, if streaming the trace.
+    pub fn take_trace_records(&mut self) -> Vec<dbg::TraceRecord> {
+        self.bus
+            .trace_stream
+            .as_mut()
+            .map_or(vec![], |stream| stream.take_records())
+    }
+
+    /// Starts accumulating the cycles spent at each address and function.
+    pub fn enable_profiling(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    /// Stops profiling and discards the collected data.
+    pub fn disable_profiling(&mut self) {
+        self.profiler = None;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn profiler_mut(&mut self) -> Option<&mut Profiler> {
+        self.profiler.as_mut()
+    }
+
+    /// Enables or disables marking the bytes executed as code. It costs a disassembly
+    /// per instruction, so it is disabled by default.
+    pub fn set_code_tracking(&mut self, enable: bool) {
+        self.code_tracking = enable;
+    }
+
+    pub fn code_tracking(&self) -> bool {
+        self.code_tracking
+    }
+
+    /// Returns the map of the bytes executed as code while code tracking was enabled.
+    pub fn code_map(&self) -> &CodeMap {
+        &self.code_map
+    }
 }
@@ -82,3 +82,5 @@ impl MemW for IrqController {
 }
 
 impl MemRW for IrqController {}
+
+savestate_fields!(IrqController, ien, ifg);
@@ -1,6 +1,7 @@
 use bitflags::bitflags;
 
 use super::dbg;
+use super::{InterruptSource, IrqSource};
 use super::{MemR, MemRW, MemW};
 
 bitflags! {
@@ -18,6 +19,8 @@ bitflags! {
     }
 }
 
+savestate_bits!(JoypadState);
+
 bitflags! {
     struct JoyP: u8 {
         const SEL_BTNS = 0b_0010_0000;
@@ -29,11 +32,24 @@ bitflags! {
 }
 
 mem_rw!(JoyP, 0xC0);
+savestate_bits!(JoyP);
+
+/// Clock cycles in a video frame, the unit of the autofire rate.
+const FRAME_CYCLES: u32 = 70_224;
 
 pub struct Joypad {
     joyp: JoyP,
 
     state: JoypadState,
+    irq_pending: bool,
+
+    // Buttons held with autofire, which are repeatedly pressed and released
+    // every `autofire_period` frames.
+    autofire: JoypadState,
+    autofire_period: u8,
+    autofire_frames: u8,
+    autofire_clk: u32,
+    autofire_down: bool,
 }
 
 impl Default for Joypad {
@@ -41,6 +57,13 @@ impl Default for Joypad {
         Joypad {
             joyp: JoyP::DEFAULT,
             state: JoypadState::DEFAULT,
+            irq_pending: false,
+
+            autofire: JoypadState::empty(),
+            autofire_period: 2,
+            autofire_frames: 0,
+            autofire_clk: 0,
+            autofire_down: false,
         }
     }
 }
@@ -50,27 +73,136 @@ impl Joypad {
         Joypad::default()
     }
 
-    pub fn set_pressed_keys(&mut self, pressed: JoypadState) {
+    /// Marks the given buttons as pressed.
+    pub fn button_pressed(&mut self, pressed: JoypadState) {
+        let old = self.lines();
         self.state &= !pressed;
+        self.detect_falling_edge(old);
     }
 
-    pub fn set_release_keys(&mut self, released: JoypadState) {
+    /// Marks the given buttons as released.
+    pub fn button_released(&mut self, released: JoypadState) {
         self.state |= released;
     }
+
+    /// Replaces the state of all the buttons at once, stopping any autofire.
+    pub fn set_state(&mut self, state: JoypadState) {
+        let old = self.lines();
+        self.state = state;
+        self.autofire = JoypadState::empty();
+        self.detect_falling_edge(old);
+    }
+
+    /// Holds the given buttons with autofire. The first press happens immediately.
+    pub fn autofire_pressed(&mut self, pressed: JoypadState) {
+        let old = self.lines();
+
+        if self.autofire.is_empty() {
+            self.autofire_frames = 0;
+            self.autofire_clk = 0;
+            self.autofire_down = true;
+        }
+        self.autofire |= pressed;
+
+        self.detect_falling_edge(old);
+    }
+
+    /// Stops the autofire of the given buttons.
+    pub fn autofire_released(&mut self, released: JoypadState) {
+        self.autofire &= !released;
+    }
+
+    /// Returns the buttons currently held with autofire.
+    pub fn autofire(&self) -> JoypadState {
+        self.autofire
+    }
+
+    /// Sets the number of frames autofire buttons stay pressed, and then released, for.
+    pub fn set_autofire_period(&mut self, frames: u8) {
+        self.autofire_period = frames.max(1);
+    }
+
+    pub fn autofire_period(&self) -> u8 {
+        self.autofire_period
+    }
+
+    /// Advances the autofire timer by a single M-cycle at normal speed,
+    /// so that the rate is tied to emulated frames.
+    pub fn tick(&mut self) {
+        if self.autofire.is_empty() {
+            return;
+        }
+
+        self.autofire_clk += 4;
+        if self.autofire_clk < FRAME_CYCLES {
+            return;
+        }
+        self.autofire_clk = 0;
+
+        self.autofire_frames += 1;
+        if self.autofire_frames < self.autofire_period {
+            return;
+        }
+        self.autofire_frames = 0;
+
+        let old = self.lines();
+        self.autofire_down = !self.autofire_down;
+        self.detect_falling_edge(old);
+    }
+
+    /// Returns the state of the buttons, including the ones being autofired.
+    fn effective_state(&self) -> JoypadState {
+        if self.autofire_down {
+            self.state & !self.autofire
+        } else {
+            self.state
+        }
+    }
+
+    /// Returns true if any of the keys in the currently selected group is pressed.
+    pub fn selected_keys_pressed(&self) -> bool {
+        self.lines() != 0x0F
+    }
+
+    /// Returns the state of the P10-P13 input lines, which are pulled low by the
+    /// pressed buttons of the selected groups. When both groups are selected, a line
+    /// is low if either of the buttons connected to it is pressed.
+    fn lines(&self) -> u8 {
+        let mut res = 0x0F;
+
+        let state = self.effective_state().bits();
+
+        if !self.joyp.contains(JoyP::SEL_BTNS) {
+            res &= state;
+        }
+        if !self.joyp.contains(JoyP::SEL_DIRS) {
+            res &= state >> 4;
+        }
+        res & 0x0F
+    }
+
+    /// Requests an interrupt if any of the input lines went from high to low.
+    fn detect_falling_edge(&mut self, old: u8) {
+        if old & !self.lines() != 0 {
+            self.irq_pending = true;
+        }
+    }
 }
 
-impl MemR for Joypad {
-    fn read(&self, _addr: u16) -> Result<u8, dbg::TraceEvent> {
-        // Assign upper, lower or no half of state depending on the selection bits
-        let res = if !self.joyp.contains(JoyP::SEL_BTNS) {
-            self.state.bits()
-        } else if !self.joyp.contains(JoyP::SEL_DIRS) {
-            self.state.bits() >> 4
+impl InterruptSource for Joypad {
+    fn get_and_clear_irq(&mut self) -> Option<IrqSource> {
+        if self.irq_pending {
+            self.irq_pending = false;
+            Some(IrqSource::Joypad)
         } else {
-            0x0F
-        };
+            None
+        }
+    }
+}
 
-        let joyp = (self.joyp | JoyP::BTN_MASK) & JoyP::from_bits_truncate(res | 0xF0);
+impl MemR for Joypad {
+    fn read(&self, _addr: u16) -> Result<u8, dbg::TraceEvent> {
+        let joyp = (self.joyp | JoyP::BTN_MASK) & JoyP::from_bits_truncate(self.lines() | 0xF0);
 
         (&joyp).read(0)
     }
@@ -78,8 +210,99 @@ impl MemR for Joypad {
 
 impl MemW for Joypad {
     fn write(&mut self, _addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
-        (&mut self.joyp).write(0, val)
+        // Selecting a group with buttons held down pulls the lines low too
+        let old = self.lines();
+        (&mut self.joyp).write(0, val)?;
+        self.detect_falling_edge(old);
+
+        Ok(())
     }
 }
 
 impl MemRW for Joypad {}
+
+savestate_fields!(
+    Joypad,
+    joyp,
+    state,
+    irq_pending,
+    autofire,
+    autofire_frames,
+    autofire_clk,
+    autofire_down,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn button_matrix() {
+        let mut joy = Joypad::new();
+
+        joy.button_pressed(JoypadState::A | JoypadState::DOWN);
+
+        // Nothing selected
+        joy.write(0xFF00, 0x30).unwrap();
+        assert_eq!(joy.read(0xFF00).unwrap(), 0xFF);
+
+        joy.write(0xFF00, 0x10).unwrap();
+        assert_eq!(joy.read(0xFF00).unwrap(), 0xDE);
+
+        joy.write(0xFF00, 0x20).unwrap();
+        assert_eq!(joy.read(0xFF00).unwrap(), 0xE7);
+
+        // Both groups selected
+        joy.write(0xFF00, 0x00).unwrap();
+        assert_eq!(joy.read(0xFF00).unwrap(), 0xC6);
+
+        joy.button_released(JoypadState::A | JoypadState::DOWN);
+        assert_eq!(joy.read(0xFF00).unwrap(), 0xCF);
+    }
+
+    #[test]
+    fn interrupt_on_falling_edge() {
+        let mut joy = Joypad::new();
+
+        // Pressing a button of a group that is not selected does not raise an IRQ
+        joy.write(0xFF00, 0x20).unwrap();
+        joy.button_pressed(JoypadState::A);
+        assert!(joy.get_and_clear_irq().is_none());
+
+        // ...but selecting it does
+        joy.write(0xFF00, 0x10).unwrap();
+        assert!(joy.get_and_clear_irq().is_some());
+
+        // Releasing a button does not
+        joy.button_released(JoypadState::A);
+        assert!(joy.get_and_clear_irq().is_none());
+
+        joy.button_pressed(JoypadState::START);
+        assert!(joy.get_and_clear_irq().is_some());
+    }
+
+    #[test]
+    fn autofire_toggles_every_period() {
+        let mut joy = Joypad::new();
+        joy.write(0xFF00, 0x10).unwrap();
+        joy.set_autofire_period(2);
+
+        joy.autofire_pressed(JoypadState::A);
+        assert_eq!(joy.read(0xFF00).unwrap(), 0xDE);
+        assert!(joy.get_and_clear_irq().is_some());
+
+        for frame in 1..=8 {
+            for _ in 0..FRAME_CYCLES / 4 {
+                joy.tick();
+            }
+
+            // Each press raises an interrupt again
+            let pressed = (frame / 2) % 2 == 0;
+            assert_eq!(joy.read(0xFF00).unwrap() & 0x01 == 0, pressed);
+            assert_eq!(joy.get_and_clear_irq().is_some(), pressed && frame % 2 == 0);
+        }
+
+        joy.autofire_released(JoypadState::A);
+        assert_eq!(joy.read(0xFF00).unwrap(), 0xDF);
+    }
+}
@@ -1,3 +1,6 @@
+use crate::dbg;
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
 use std::ops::{BitAnd, BitAndAssign, BitOrAssign, Not, Shl};
 
 /// Blanket implementation of MemR/MemW/MemRW for a bitflags!-generated struct
@@ -45,6 +48,16 @@ impl Zero for u16 {
 #[derive(Default, Copy, Clone, Debug, PartialEq)]
 pub struct IoReg<T>(pub T);
 
+impl<T: Savestate> Savestate for IoReg<T> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.0.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        self.0.load_state(r)
+    }
+}
+
 impl<T> IoReg<T>
 where
     T: PartialEq
@@ -123,6 +136,18 @@ impl<T: Copy + Clone> Latch<T> {
     }
 }
 
+impl<T: Copy + Clone + Savestate> Savestate for Latch<T> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.0.save_state(w);
+        self.1.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        self.0.load_state(r)?;
+        self.1.load_state(r)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
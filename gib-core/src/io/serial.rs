@@ -3,9 +3,24 @@ use super::IoReg;
 use super::{InterruptSource, IrqSource};
 use super::{MemR, MemRW, MemW};
 
+/// Clock cycles needed to shift a single bit using the internal clock (8192Hz).
+const BIT_PERIOD: u16 = 512;
+
+/// Number of bytes kept from the link port output, older ones are dropped.
+pub const SERIAL_OUTPUT_CAPACITY: usize = 0x1000;
+
 pub struct Serial {
     sb: IoReg<u8>,
     sc: IoReg<u8>,
+
+    // State of the ongoing transfer
+    bits_left: u8,
+    bit_clk: u16,
+    irq_pending: bool,
+
+    // Last bytes sent over the link port, and how many were sent in total
+    output: Vec<u8>,
+    sent: u64,
 }
 
 impl Default for Serial {
@@ -13,6 +28,13 @@ impl Default for Serial {
         Serial {
             sb: IoReg(0x00),
             sc: IoReg(0x00),
+
+            bits_left: 0,
+            bit_clk: 0,
+            irq_pending: false,
+
+            output: vec![],
+            sent: 0,
         }
     }
 }
@@ -21,17 +43,85 @@ impl Serial {
     pub fn new() -> Serial {
         Serial::default()
     }
+
+    /// Returns the last `SERIAL_OUTPUT_CAPACITY` bytes transferred using the internal clock.
+    /// Test ROMs usually print their results this way.
+    pub fn output(&self) -> &[u8] {
+        &self.output[..]
+    }
+
+    /// Returns the number of bytes transferred using the internal clock since power on,
+    /// including the ones dropped from the output.
+    pub fn bytes_sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Discards the bytes transferred so far.
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+
+    /// Advances an ongoing transfer by a single M-cycle.
+    ///
+    /// No device is ever connected to the link port, so 1s are shifted in (0xFF is
+    /// received), and transfers started with the external clock never complete.
+    pub fn tick(&mut self) {
+        if self.bits_left == 0 {
+            return;
+        }
+
+        self.bit_clk -= 4;
+        if self.bit_clk > 0 {
+            return;
+        }
+
+        self.sb.0 = (self.sb.0 << 1) | 1;
+        self.bits_left -= 1;
+        self.bit_clk = BIT_PERIOD;
+
+        // Once all 8 bits have been shifted, the transfer flag is cleared and an IRQ is raised
+        if self.bits_left == 0 {
+            self.sc.0 &= 0x7F;
+            self.irq_pending = true;
+        }
+    }
+
+    fn write_to_sc(&mut self, val: u8) {
+        // A transfer is started with the internal clock selected
+        if val & 0x81 == 0x81 {
+            if self.output.len() == SERIAL_OUTPUT_CAPACITY {
+                self.output.remove(0);
+            }
+            self.output.push(self.sb.0);
+            self.sent += 1;
+
+            self.bits_left = 8;
+            self.bit_clk = BIT_PERIOD;
+        } else {
+            self.bits_left = 0;
+        }
+        self.sc.0 = val;
+    }
+
+    /// Returns true if the output fits in its capacity, eg. after loading a state.
+    fn output_in_range(&self) -> bool {
+        self.output.len() <= SERIAL_OUTPUT_CAPACITY
+    }
 }
 
 impl InterruptSource for Serial {
     fn get_and_clear_irq(&mut self) -> Option<IrqSource> {
-        None
+        if self.irq_pending {
+            self.irq_pending = false;
+            Some(IrqSource::Serial)
+        } else {
+            None
+        }
     }
 }
 
 impl MemR for Serial {
     fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
-        // TODO: it's gonna be a while before serial link is implemented :)
         Ok(match addr {
             0xFF01 => self.sb.0,
             0xFF02 => self.sc.0 | 0x7E,
@@ -41,15 +131,95 @@ impl MemR for Serial {
 }
 
 impl MemW for Serial {
-    fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
-        // TODO: it's gonna be a while before serial link is implemented :)
+    fn poke(&mut self, addr: u16, val: u8) {
+        // Don't start a transfer
         match addr {
             0xFF01 => self.sb.0 = val,
             0xFF02 => self.sc.0 = val,
             _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
+        match addr {
+            0xFF01 => self.sb.0 = val,
+            0xFF02 => self.write_to_sc(val),
+            _ => unreachable!(),
         };
         Ok(())
     }
 }
 
 impl MemRW for Serial {}
+
+savestate_fields!(
+    Serial where Serial::output_in_range,
+    sb,
+    sc,
+    bits_left,
+    bit_clk,
+    irq_pending,
+    output,
+    sent,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_clock_transfer() {
+        let mut serial = Serial::new();
+
+        serial.write(0xFF01, 0x42).unwrap();
+        serial.write(0xFF02, 0x81).unwrap();
+        assert_eq!(serial.output(), &[0x42]);
+
+        // Each bit takes 512 clock cycles to be shifted
+        for _ in 0..(7 * 512 / 4) {
+            serial.tick();
+        }
+        assert_eq!(serial.read(0xFF01).unwrap(), 0x7F);
+        assert_eq!(serial.read(0xFF02).unwrap(), 0xFF);
+        assert!(serial.get_and_clear_irq().is_none());
+
+        for _ in 0..(512 / 4) {
+            serial.tick();
+        }
+        assert_eq!(serial.read(0xFF01).unwrap(), 0xFF);
+        assert_eq!(serial.read(0xFF02).unwrap(), 0x7F);
+        assert!(serial.get_and_clear_irq().is_some());
+
+        serial.clear_output();
+        assert!(serial.output().is_empty());
+    }
+
+    #[test]
+    fn output_keeps_the_last_bytes() {
+        let mut serial = Serial::new();
+
+        for i in 0..SERIAL_OUTPUT_CAPACITY + 2 {
+            serial.write(0xFF01, i as u8).unwrap();
+            serial.write(0xFF02, 0x81).unwrap();
+            serial.write(0xFF02, 0x00).unwrap();
+        }
+        assert_eq!(serial.output().len(), SERIAL_OUTPUT_CAPACITY);
+        assert_eq!(serial.output()[0], 2);
+        assert_eq!(serial.bytes_sent(), SERIAL_OUTPUT_CAPACITY as u64 + 2);
+    }
+
+    #[test]
+    fn external_clock_transfer_never_completes() {
+        let mut serial = Serial::new();
+
+        serial.write(0xFF01, 0x42).unwrap();
+        serial.write(0xFF02, 0x80).unwrap();
+
+        for _ in 0..(16 * 512 / 4) {
+            serial.tick();
+        }
+        assert_eq!(serial.read(0xFF01).unwrap(), 0x42);
+        assert_eq!(serial.read(0xFF02).unwrap(), 0xFE);
+        assert!(serial.get_and_clear_irq().is_none());
+    }
+}
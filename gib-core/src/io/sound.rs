@@ -8,9 +8,11 @@ use super::{MemR, MemW};
 
 use std::sync::Arc;
 
-const CLK_64_RELOAD: u32 = 4_194_304 / 64;
-const CLK_128_RELOAD: u32 = 4_194_304 / 128;
-const CLK_256_RELOAD: u32 = 4_194_304 / 256;
+/// Number of clock cycles between two steps of the 512Hz frame sequencer.
+const FRAME_SEQ_RELOAD: u32 = 4_194_304 / 512;
+
+/// Number of mixed samples kept for inspection.
+const SCOPE_SIZE: usize = 512;
 
 bitflags! {
     // NRx0 - Channel x Sweep register (R/W)
@@ -90,6 +92,54 @@ bitflags! {
     }
 }
 
+savestate_bits!(NRx0, NRx1, NRx2, NRx4, NR50, NR51, NR52);
+
+/// Controls applied by the mixer on top of the emulated hardware,
+/// eg. to listen to a single channel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MixerControls {
+    /// Channels left out of the mix.
+    pub muted: [bool; 3],
+    /// If set, the only channel in the mix, regardless of it being muted.
+    pub solo: Option<usize>,
+    /// Gain applied to the mixed output.
+    pub master_volume: f32,
+}
+
+impl Default for MixerControls {
+    fn default() -> MixerControls {
+        MixerControls {
+            muted: [false; 3],
+            solo: None,
+            master_volume: 1.0,
+        }
+    }
+}
+
+savestate_fields!(MixerControls, muted, solo, master_volume);
+
+impl MixerControls {
+    /// Returns true if channel `ch` (starting from 0) is part of the mix.
+    pub fn audible(&self, ch: usize) -> bool {
+        match self.solo {
+            Some(solo) => solo == ch,
+            None => !self.muted[ch],
+        }
+    }
+}
+
+/// Snapshot of the internal state of a sound channel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChannelStatus {
+    pub enabled: bool,
+    pub dac_on: bool,
+    pub frequency: u16,
+    /// Current volume, on a 0-15 scale.
+    pub volume: u8,
+    pub length: u16,
+    pub length_enabled: bool,
+}
+
 /// A sound channel able to produce quadrangular wave patterns
 /// with optional sweep and envelope functions.
 struct ToneChannel {
@@ -100,23 +150,22 @@ struct ToneChannel {
     nrx3: IoReg<u8>,
     nrx4: NRx4,
 
-    // Internal state and timer counter
+    // Internal state, timer and length counters
     enabled: bool,
     timer_counter: u32,
+    length: u16,
 
     // Frequency sweep unit
     sweep_support: bool,
     sweep_enabled: bool,
     sweep_freq_shadow: u32,
     sweep_timer: u8,
+    sweep_negated: bool,
 
     // Volume control
     volume: i16,
     vol_ctr: u8,
     vol_env_enabled: bool,
-
-    // Channel output fed as input to the mixer
-    waveform_level: i16,
 }
 
 impl ToneChannel {
@@ -138,47 +187,79 @@ impl ToneChannel {
 
             enabled: false,
             timer_counter: 0,
+            length: 0,
 
             sweep_support,
             sweep_enabled: false,
             sweep_freq_shadow: 0,
             sweep_timer: 0,
+            sweep_negated: false,
 
             volume: 0,
             vol_ctr: 0,
             vol_env_enabled: false,
-
-            waveform_level: 1,
         }
     }
 
-    /// Advances the internal timer state by one M-cycle.
-    fn tick(&mut self) {
+    /// Advances the internal timer state by `cycles` clock cycles (a multiple of 4),
+    /// returning the sum of the channel output over each of them.
+    fn advance(&mut self, cycles: u32) -> i32 {
         let period = self.get_period();
+        let threshold = self.duty_threshold();
+        let mut steps = cycles / 4;
+        let mut out = 0;
 
         // The timer generates an output clock every N input clocks,
         // where N is the timer's period.
-        if self.timer_counter < 4 {
-            self.timer_counter = period - self.timer_counter;
-        } else {
-            self.timer_counter -= 4;
+        while steps > 0 {
+            let level_out = i32::from(self.get_channel_out());
+
+            if self.timer_counter < 4 {
+                self.timer_counter = period - self.timer_counter;
+                out += level_out * 4;
+                steps -= 1;
+            } else {
+                // Skip to the next reload or duty edge, whichever comes first
+                let mut n = steps.min(self.timer_counter / 4);
+                if self.timer_counter >= threshold {
+                    n = n.min((self.timer_counter - threshold) / 4 + 1);
+                }
+
+                self.timer_counter -= 4 * n;
+                out += level_out * 4 * n as i32;
+                steps -= n;
+            }
         }
 
+        out
+    }
+
+    /// Returns the timer value below which the duty waveform is high.
+    fn duty_threshold(&self) -> u32 {
+        let period = self.get_period();
+
         // Duty   Waveform    Ratio
         // -------------------------
         // 0      00000001    12.5%
         // 1      10000001    25%
         // 2      10000111    50%
         // 3      01111110    75%
-        let threshold = match (self.nrx1 & NRx1::WAVE_DUTY).bits() >> 6 {
+        match (self.nrx1 & NRx1::WAVE_DUTY).bits() >> 6 {
             0 => period / 8,
             1 => period / 4,
             2 => period / 2,
             3 => period * 3 / 4,
             _ => unreachable!(),
-        };
+        }
+    }
 
-        self.waveform_level = if self.timer_counter < threshold { 1 } else { 0 };
+    /// Returns the current level of the duty waveform.
+    fn waveform_level(&self) -> i16 {
+        if self.timer_counter < self.duty_threshold() {
+            1
+        } else {
+            0
+        }
     }
 
     /// Advances the frequency sweep unit by 1/128th of a second.
@@ -186,16 +267,20 @@ impl ToneChannel {
         let shift = (self.nrx0 & NRx0::SWEEP_SHIFT).bits();
         let period = (self.nrx0 & NRx0::SWEEP_TIME).bits() >> 4;
 
-        if !self.sweep_support || !self.sweep_enabled || period == 0 {
+        if !self.sweep_support {
             return;
         }
 
-        self.sweep_timer -= 1;
+        self.sweep_timer = self.sweep_timer.saturating_sub(1);
 
         // Sweep timer expired -> do sweep
         if self.sweep_timer == 0 {
-            // Reload internal timer
-            self.sweep_timer = period;
+            // Reload internal timer, which treats a period of 0 as 8
+            self.sweep_timer = if period == 0 { 8 } else { period };
+
+            if !self.sweep_enabled || period == 0 {
+                return;
+            }
 
             // Compute new frequency
             let new_freq = self.do_sweep_calc();
@@ -216,11 +301,18 @@ impl ToneChannel {
     fn tick_vol_env(&mut self) {
         let period = (self.nrx2 & NRx2::ENV_PERIOD).bits();
 
+        self.vol_ctr = self.vol_ctr.saturating_sub(1);
+
+        if self.vol_ctr != 0 {
+            return;
+        }
+
+        // Reload internal timer, which treats a period of 0 as 8
+        self.vol_ctr = if period == 0 { 8 } else { period };
+
         // When the timer generates a clock and the envelope period is not zero,
         // a new volume is calculated by adding or subtracting 1 from the current volume.
         if self.vol_env_enabled && period > 0 {
-            self.nrx2 = (self.nrx2 & !NRx2::ENV_PERIOD) | NRx2::from_bits_truncate(period - 1);
-
             let new_volume = if self.nrx2.contains(NRx2::ENV_DIR) {
                 self.volume + 1
             } else {
@@ -230,26 +322,44 @@ impl ToneChannel {
             // If this new volume within the 0 to 15 range, the volume is updated,
             // otherwise it is left unchanged and no further automatic increments/decrements
             // are made to the volume until the channel is triggered again.
-            if new_volume <= 15 {
-                self.volume = new_volume;
-            } else {
-                self.vol_env_enabled = false;
+            match new_volume {
+                0..=15 => self.volume = new_volume,
+                _ => self.vol_env_enabled = false,
             }
         }
     }
 
+    /// Updates the volume after a write to NRx2 while the channel is on ("zombie" mode).
+    /// `old` is the value of NRx2 before the write.
+    fn zombie_vol_update(&mut self, old: NRx2) {
+        let mut volume = self.volume;
+
+        // If the old envelope period was zero and the envelope is still doing automatic
+        // updates, volume is incremented by 1, otherwise if the envelope was in subtract
+        // mode, volume is incremented by 2.
+        if (old & NRx2::ENV_PERIOD).bits() == 0 && self.vol_env_enabled {
+            volume += 1;
+        } else if !old.contains(NRx2::ENV_DIR) {
+            volume += 2;
+        }
+
+        // If the mode was changed, volume is set to 16 - volume
+        if old.contains(NRx2::ENV_DIR) != self.nrx2.contains(NRx2::ENV_DIR) {
+            volume = 16 - volume;
+        }
+
+        // Only the low 4 bits of volume are kept after the above operations
+        self.volume = volume & 0x0F;
+    }
+
     /// Advances the length counter unit by 1/256th of a second.
     fn tick_len_ctr(&mut self) {
-        let len = (self.nrx1 & NRx1::SOUND_LEN).bits();
-
         // When clocked while enabled by NRx4 and the counter is not zero, length is decremented
-        if self.nrx4.contains(NRx4::LEN_EN) && len != 0 {
-            let len = len - 1;
-
-            self.nrx1 = (self.nrx1 & !NRx1::SOUND_LEN) | NRx1::from_bits_truncate(len);
+        if self.nrx4.contains(NRx4::LEN_EN) && self.length != 0 {
+            self.length -= 1;
 
             // If it becomes zero, the channel is disabled
-            if len == 0 {
+            if self.length == 0 {
                 self.enabled = false;
             }
         }
@@ -265,6 +375,7 @@ impl ToneChannel {
         let mut new_freq = self.sweep_freq_shadow >> shift;
 
         if neg {
+            self.sweep_negated = true;
             new_freq = self.sweep_freq_shadow - new_freq;
         } else {
             new_freq += self.sweep_freq_shadow;
@@ -304,7 +415,7 @@ impl ToneChannel {
     /// Returns the channel's current output level, ready to be fed to the mixer.
     fn get_channel_out(&self) -> i16 {
         if self.dac_on() {
-            (self.waveform_level * 2 * self.get_volume() as i16) - 15
+            (self.waveform_level() * 2 * self.get_volume()) - 15
         } else {
             0
         }
@@ -315,18 +426,53 @@ impl ToneChannel {
         (self.nrx2 & NRx2::DAC_ON).bits() != 0
     }
 
+    /// Returns a snapshot of the channel state.
+    fn status(&self) -> ChannelStatus {
+        ChannelStatus {
+            enabled: self.enabled,
+            dac_on: self.dac_on(),
+            frequency: self.get_frequency(),
+            volume: self.volume as u8,
+            length: self.length,
+            length_enabled: self.nrx4.contains(NRx4::LEN_EN),
+        }
+    }
+
     /// Handles a write to the NRx4 register.
-    fn write_to_nr4(&mut self, val: u8) {
+    ///
+    /// `len_clk_skipped` tells whether the next frame sequencer step won't clock the length
+    /// counter, in which case enabling it causes an extra length clock.
+    fn write_to_nr4(&mut self, val: u8, len_clk_skipped: bool) {
+        let len_was_enabled = self.nrx4.contains(NRx4::LEN_EN);
+
         self.nrx4 = NRx4::from_bits_truncate(val);
 
+        let len_enabled = self.nrx4.contains(NRx4::LEN_EN);
+        let trigger = self.nrx4.contains(NRx4::TRIGGER);
+
+        // Enabling the length counter in the first half of a length period clocks it once.
+        // If this makes it zero and the channel is not being triggered, it gets disabled.
+        if len_clk_skipped && !len_was_enabled && len_enabled && self.length != 0 {
+            self.length -= 1;
+
+            if self.length == 0 && !trigger {
+                self.enabled = false;
+            }
+        }
+
         // When a TRIGGER occurs, a number of things happen
-        if self.nrx4.contains(NRx4::TRIGGER) {
+        if trigger {
             // Channel is enabled
             self.enabled = true;
 
-            // If length counter is zero, it is set to 64 (256 for wave channel)
-            if (self.nrx1 & NRx1::SOUND_LEN).bits() == 0 {
-                self.nrx1 |= NRx1::SOUND_LEN;
+            // If length counter is zero, it is set to 64 (256 for wave channel),
+            // minus the extra clock if length is enabled in the first half of a length period.
+            if self.length == 0 {
+                self.length = 64;
+
+                if len_clk_skipped && len_enabled {
+                    self.length -= 1;
+                }
             }
 
             // Frequency timer is reloaded with period
@@ -335,7 +481,10 @@ impl ToneChannel {
             // Volume envelope timer is reloaded with period and
             // channel volume is reloaded from NRx2.
             self.volume = i16::from((self.nrx2 & NRx2::START_VOL).bits() >> 4);
-            self.vol_ctr = (self.nrx2 & NRx2::ENV_PERIOD).bits();
+            self.vol_ctr = match (self.nrx2 & NRx2::ENV_PERIOD).bits() {
+                0 => 8,
+                period => period,
+            };
             self.vol_env_enabled = true;
 
             // Square 1's frequency is copied to the shadow register, the sweep timer is reloaded,
@@ -344,7 +493,8 @@ impl ToneChannel {
             let sweep_period = (self.nrx0 & NRx0::SWEEP_TIME).bits() >> 4;
 
             self.sweep_freq_shadow = u32::from(self.get_frequency());
-            self.sweep_timer = sweep_period;
+            self.sweep_timer = if sweep_period == 0 { 8 } else { sweep_period };
+            self.sweep_negated = false;
             self.sweep_enabled = sweep_shift != 0 || sweep_period != 0;
             if sweep_shift != 0 {
                 self.do_sweep_calc();
@@ -381,17 +531,33 @@ impl MemR for ToneChannel {
 impl MemW for ToneChannel {
     fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
         match addr {
-            0 => self.nrx0 = NRx0::from_bits_truncate(val),
-            1 => self.nrx1 = NRx1::from_bits_truncate(val),
+            0 => {
+                self.nrx0 = NRx0::from_bits_truncate(val);
+
+                // Clearing negate mode after a sweep calculation has been made
+                // using it disables the channel.
+                if self.sweep_negated && !self.nrx0.contains(NRx0::SWEEP_NEG) {
+                    self.enabled = false;
+                }
+            }
+            1 => {
+                self.nrx1 = NRx1::from_bits_truncate(val);
+                self.length = 64 - u16::from((self.nrx1 & NRx1::SOUND_LEN).bits());
+            }
             2 => {
+                let old = self.nrx2;
                 self.nrx2 = NRx2::from_bits_truncate(val);
 
+                if self.enabled {
+                    self.zombie_vol_update(old);
+                }
+
                 if !self.dac_on() {
                     self.enabled = false;
                 }
             }
             3 => self.nrx3.0 = val,
-            4 => self.write_to_nr4(val),
+            // NRx4 writes depend on the frame sequencer, see `APU::write`
             _ => unreachable!(),
         };
 
@@ -399,6 +565,25 @@ impl MemW for ToneChannel {
     }
 }
 
+savestate_fields!(
+    ToneChannel,
+    nrx0,
+    nrx1,
+    nrx2,
+    nrx3,
+    nrx4,
+    enabled,
+    timer_counter,
+    length,
+    sweep_enabled,
+    sweep_freq_shadow,
+    sweep_timer,
+    sweep_negated,
+    volume,
+    vol_ctr,
+    vol_env_enabled,
+);
+
 struct WaveChannel {
     // Channel registers
     nrx0: NRx0,
@@ -407,14 +592,16 @@ struct WaveChannel {
     nrx3: IoReg<u8>,
     nrx4: NRx4,
 
-    // Internal state and timer counter
+    // Internal state, timer and length counters
     enabled: bool,
     timer_counter: u32,
+    length: u16,
 
     // Wave functions
     wave_ram: [u8; 16],
     sample_buffer: u8,
     position_counter: usize,
+    just_fetched: bool,
 }
 
 impl Default for WaveChannel {
@@ -428,10 +615,12 @@ impl Default for WaveChannel {
 
             enabled: false,
             timer_counter: 0,
+            length: 0,
 
             wave_ram: [0; 16],
             sample_buffer: 0,
             position_counter: 0,
+            just_fetched: false,
         }
     }
 }
@@ -439,38 +628,67 @@ impl Default for WaveChannel {
 // TODO there is a lot of code shared between WaveChannel and ToneChannel.
 // It should be aggregated without impacting too much on performance.
 impl WaveChannel {
-    /// Advances the internal timer state by one M-cycle.
-    fn tick(&mut self) {
-        // Every N input clocks, advance the position counter and latch the new sample.
-        if self.timer_counter < 4 {
-            self.timer_counter = self.get_period() - self.timer_counter;
-
-            self.position_counter = (self.position_counter + 1) % 32;
-            self.sample_buffer = self.wave_ram[self.position_counter >> 1];
-
-            // Select the correct nibble
-            if self.position_counter & 0x1 == 0 {
-                self.sample_buffer >>= 4;
+    /// Advances the internal timer state by `cycles` clock cycles (a multiple of 4).
+    ///
+    /// The timer counts the clock cycles until the next sample is played, which
+    /// at the highest frequencies happens more than once per M-cycle.
+    /// Returns the sum of the channel output over each of the elapsed clock cycles.
+    fn advance(&mut self, cycles: u32) -> i32 {
+        let mut steps = cycles / 4;
+        let mut out = 0;
+
+        while steps > 0 {
+            if self.timer_counter <= 4 {
+                // Every N input clocks, advance the position counter and latch the new sample
+                while self.timer_counter <= 4 {
+                    self.timer_counter += self.get_period();
+
+                    if self.enabled {
+                        self.next_sample();
+                    }
+                }
+                self.timer_counter -= 4;
+                self.just_fetched = true;
+                out += i32::from(self.get_channel_out()) * 4;
+                steps -= 1;
             } else {
-                self.sample_buffer &= 0x0F;
+                let n = steps.min((self.timer_counter - 1) / 4);
+                self.timer_counter -= 4 * n;
+                self.just_fetched = false;
+                out += i32::from(self.get_channel_out()) * 4 * n as i32;
+                steps -= n;
             }
+        }
+
+        out
+    }
+
+    /// Returns true if the sample being played is in wave RAM, eg. after loading a state.
+    fn position_in_range(&self) -> bool {
+        self.position_counter < 32
+    }
+
+    /// Moves to the next 4 bit sample in wave RAM, and latches it in the sample buffer.
+    fn next_sample(&mut self) {
+        self.position_counter = (self.position_counter + 1) % 32;
+        self.sample_buffer = self.wave_ram[self.position_counter >> 1];
+
+        // Samples are played starting from the upper nibble
+        if self.position_counter & 0x1 == 0 {
+            self.sample_buffer >>= 4;
         } else {
-            self.timer_counter -= 4;
+            self.sample_buffer &= 0x0F;
         }
     }
 
     /// Advances the length counter unit by 1/256th of a second.
     fn tick_len_ctr(&mut self) {
-        let len = (self.nrx1 & NRx1::WAVE_SOUND_LEN).bits();
-
         // When clocked while enabled by NRx4 and the counter is not zero, length is decremented
-        if self.nrx4.contains(NRx4::LEN_EN) && len != 0 {
-            let len = len - 1;
-
-            self.nrx1 = (self.nrx1 & !NRx1::WAVE_SOUND_LEN) | NRx1::from_bits_truncate(len);
+        if self.nrx4.contains(NRx4::LEN_EN) && self.length != 0 {
+            self.length -= 1;
 
             // If it becomes zero, the channel is disabled
-            if len == 0 {
+            if self.length == 0 {
                 self.enabled = false;
             }
         }
@@ -488,15 +706,20 @@ impl WaveChannel {
         (hi << 8) | lo
     }
 
-    /// Returns the channel's current volume.
-    fn get_volume(&self) -> u8 {
-        u8::from(self.enabled) * ((self.nrx2 & NRx2::WAVE_VOLUME).bits() >> 5)
+    /// Returns the right shift applied to samples by the output level in NR32.
+    fn get_volume_shift(&self) -> u8 {
+        match (self.nrx2 & NRx2::WAVE_VOLUME).bits() >> 5 {
+            0 => 4,
+            code => code - 1,
+        }
     }
 
     /// Returns the channel's current output level, ready to be fed to the mixer.
     fn get_channel_out(&self) -> i16 {
         if self.dac_on() {
-            i16::from(self.sample_buffer >> (self.get_volume() - 1))
+            let sample = u8::from(self.enabled) * (self.sample_buffer >> self.get_volume_shift());
+
+            i16::from(sample) * 2 - 15
         } else {
             0
         }
@@ -507,18 +730,51 @@ impl WaveChannel {
         self.nrx0.contains(NRx0::WAVE_DAC_ON)
     }
 
+    /// Returns a snapshot of the channel state.
+    fn status(&self) -> ChannelStatus {
+        ChannelStatus {
+            enabled: self.enabled,
+            dac_on: self.dac_on(),
+            frequency: self.get_frequency(),
+            volume: 15 >> self.get_volume_shift(),
+            length: self.length,
+            length_enabled: self.nrx4.contains(NRx4::LEN_EN),
+        }
+    }
+
     /// Handles a write to the NRx4 register.
-    fn write_to_nr4(&mut self, val: u8) {
+    ///
+    /// `len_clk_skipped` tells whether the next frame sequencer step won't clock the length
+    /// counter, in which case enabling it causes an extra length clock.
+    fn write_to_nr4(&mut self, val: u8, len_clk_skipped: bool) {
+        let len_was_enabled = self.nrx4.contains(NRx4::LEN_EN);
+
         self.nrx4 = NRx4::from_bits_truncate(val);
 
+        let len_enabled = self.nrx4.contains(NRx4::LEN_EN);
+        let trigger = self.nrx4.contains(NRx4::TRIGGER);
+
+        // Same extra length clock as the tone channels
+        if len_clk_skipped && !len_was_enabled && len_enabled && self.length != 0 {
+            self.length -= 1;
+
+            if self.length == 0 && !trigger {
+                self.enabled = false;
+            }
+        }
+
         // When a TRIGGER occurs, a number of things happen
-        if self.nrx4.contains(NRx4::TRIGGER) {
+        if trigger {
             // Channel is enabled
             self.enabled = true;
 
-            // If length counter is zero, it is set to 64 (256 for wave channel)
-            if (self.nrx1 & NRx1::WAVE_SOUND_LEN).bits() == 0 {
-                self.nrx1 |= NRx1::WAVE_SOUND_LEN;
+            // If length counter is zero, it is set to 256 (255 with the extra clock)
+            if self.length == 0 {
+                self.length = 256;
+
+                if len_clk_skipped && len_enabled {
+                    self.length -= 1;
+                }
             }
 
             // Frequency timer is reloaded with period
@@ -559,10 +815,13 @@ impl MemW for WaveChannel {
                     self.enabled = false;
                 }
             }
-            1 => self.nrx1 = NRx1::from_bits_truncate(val),
+            1 => {
+                self.nrx1 = NRx1::from_bits_truncate(val);
+                self.length = 256 - u16::from(val);
+            }
             2 => self.nrx2 = NRx2::from_bits_truncate(val),
             3 => self.nrx3.0 = val,
-            4 => self.write_to_nr4(val),
+            // NRx4 writes depend on the frame sequencer, see `APU::write`
             _ => unreachable!(),
         };
 
@@ -570,6 +829,22 @@ impl MemW for WaveChannel {
     }
 }
 
+savestate_fields!(
+    WaveChannel where WaveChannel::position_in_range,
+    nrx0,
+    nrx1,
+    nrx2,
+    nrx3,
+    nrx4,
+    enabled,
+    timer_counter,
+    length,
+    wave_ram,
+    sample_buffer,
+    position_counter,
+    just_fetched,
+);
+
 pub struct APU {
     // Channels
     ch1: ToneChannel,
@@ -587,20 +862,33 @@ pub struct APU {
     nr51: NR51,
     nr52: NR52,
 
-    // Audio sample channel
+    // Audio sample channel, along with the channel outputs summed since the last sample
     sample_rate_counter: f32,
+    sample_acc: [i32; 3],
+    sample_acc_cycles: u32,
     sample_channel: Option<Arc<ArrayQueue<i16>>>,
+    sample_rate: f32,
     sample_period: f32,
+    mixer: MixerControls,
+    scope: Vec<i16>,
+    scope_pos: usize,
+
+    // Wave RAM accesses hit the byte being played while channel 3 is on
+    pub wave_ram_quirks: bool,
+    cgb: bool,
 
-    // Frame sequencer clocks
-    clk_64: u32,
-    clk_128: u32,
-    clk_256: u32,
+    // High-pass filter state, for the left and right terminals
+    hpf_charge: f32,
+    hpf_capacitors: [f32; 2],
+
+    // Frame sequencer clock and next step
+    frame_seq_clk: u32,
+    frame_seq_step: u8,
 }
 
 impl Default for APU {
     fn default() -> APU {
-        APU {
+        let mut apu = APU {
             ch1: ToneChannel::new(
                 NRx0::from_bits_truncate(0x80),
                 NRx1::from_bits_truncate(0x8F),
@@ -628,19 +916,32 @@ impl Default for APU {
 
             nr50: NR50::from_bits_truncate(0x77),
             nr51: NR51::from_bits_truncate(0xF3),
-            nr52: NR52::from_bits_truncate(0xF1),
+            nr52: NR52::PWR_CTRL,
 
             sample_rate_counter: 0f32,
+            sample_acc: [0; 3],
+            sample_acc_cycles: 0,
             sample_channel: None,
+            sample_rate: 0f32,
             sample_period: std::f32::INFINITY,
+            mixer: MixerControls::default(),
+            scope: vec![0; SCOPE_SIZE],
+            scope_pos: 0,
 
-            // TODO according to [1] these clocks are slightly out of phase,
-            // initialization and ticking should be fixed accordingly.
-            // [1] http://gbdev.gg8.se/wiki/articles/Gameboy_sound_hardware#Frame_Sequencer
-            clk_64: CLK_64_RELOAD,
-            clk_128: CLK_128_RELOAD,
-            clk_256: CLK_256_RELOAD,
-        }
+            wave_ram_quirks: false,
+            cgb: false,
+
+            hpf_charge: 0.999_958,
+            hpf_capacitors: [0.0; 2],
+
+            frame_seq_clk: FRAME_SEQ_RELOAD,
+            frame_seq_step: 0,
+        };
+
+        // The boot ROM leaves channel 1 on after the startup sound, so that NR52 reads F1.
+        // Only the power bit is kept in NR52, the others always reflect the channels.
+        apu.ch1.enabled = true;
+        apu
     }
 }
 
@@ -652,63 +953,143 @@ impl APU {
         apu
     }
 
-    /// Advances the sound controller state machine by a single M-cycle.
-    pub fn tick(&mut self) {
-        self.clk_64 -= 4;
-        self.clk_128 -= 4;
-        self.clk_256 -= 4;
+    /// Returns the number of clock cycles until the next event, ie. a frame sequencer clock
+    /// (the state visible through the registers may change on its own) or an output sample.
+    pub fn next_event(&self) -> u32 {
+        // Accesses to wave RAM depend on what channel 3 is doing in that very M-cycle
+        if self.wave_ram_quirks && self.ch3.enabled {
+            return 4;
+        }
+
+        self.next_frame_seq_clock().min(self.cycles_to_next_sample())
+    }
+
+    /// Returns the number of clock cycles until the next frame sequencer clock.
+    fn next_frame_seq_clock(&self) -> u32 {
+        self.frame_seq_clk
+    }
 
-        // Internal timer clock tick
-        self.ch1.tick();
-        self.ch2.tick();
-        self.ch3.tick();
+    /// Runs the next step of the frame sequencer, which clocks the other units as follows [1]:
+    ///
+    /// ```text
+    /// Step   Length Ctr  Vol Env     Sweep
+    /// ---------------------------------------
+    /// 0      Clock       -           -
+    /// 1      -           -           -
+    /// 2      Clock       -           Clock
+    /// 3      -           -           -
+    /// 4      Clock       -           -
+    /// 5      -           -           -
+    /// 6      Clock       -           Clock
+    /// 7      -           Clock       -
+    /// ```
+    ///
+    /// [1] http://gbdev.gg8.se/wiki/articles/Gameboy_sound_hardware#Frame_Sequencer
+    fn tick_frame_seq(&mut self) {
+        let step = self.frame_seq_step;
+
+        if step.is_multiple_of(2) {
+            self.ch1.tick_len_ctr();
+            self.ch2.tick_len_ctr();
+            self.ch3.tick_len_ctr();
+        }
 
-        // Volume envelope clock tick
-        if self.clk_64 == 0 {
-            self.clk_64 = CLK_64_RELOAD;
+        if step == 2 || step == 6 {
+            self.ch1.tick_freq_sweep();
+        }
 
+        if step == 7 {
             self.ch1.tick_vol_env();
             self.ch2.tick_vol_env();
         }
 
-        // Sweep clock tick
-        if self.clk_128 == 0 {
-            self.clk_128 = CLK_128_RELOAD;
+        self.frame_seq_step = (step + 1) % 8;
+    }
 
-            self.ch1.tick_freq_sweep();
-        }
+    /// Returns true if the frame sequencer and the sample clock are in range,
+    /// eg. after loading a state. Otherwise, `advance` could stop making progress.
+    fn clocks_in_range(&self) -> bool {
+        (1..=FRAME_SEQ_RELOAD).contains(&self.frame_seq_clk)
+            && self.sample_rate_counter.is_finite()
+            && self.sample_rate_counter >= 0.0
+    }
 
-        // Lenght counter clock tick
-        if self.clk_256 == 0 {
-            self.clk_256 = CLK_256_RELOAD;
+    /// Returns true if the next frame sequencer step doesn't clock the length counters.
+    fn len_clk_skipped(&self) -> bool {
+        self.frame_seq_step % 2 == 1
+    }
 
-            self.ch1.tick_len_ctr();
-            self.ch2.tick_len_ctr();
-            self.ch3.tick_len_ctr();
+    /// Catches the sound controller up by `cycles` clock cycles (a multiple of 4).
+    ///
+    /// Time is advanced in spans between events,
+    /// the only points where the channel state has to be looked at.
+    pub fn advance(&mut self, mut cycles: u32) {
+        while cycles > 0 {
+            let span = cycles.min(self.next_event());
+
+            self.frame_seq_clk -= span;
+
+            // Internal timer clock tick
+            let out = [
+                self.ch1.advance(span),
+                self.ch2.advance(span),
+                self.ch3.advance(span),
+            ];
+
+            if self.sample_channel.is_some() {
+                for (acc, out) in self.sample_acc.iter_mut().zip(out.iter()) {
+                    *acc += out;
+                }
+                self.sample_acc_cycles += span;
+            }
+
+            // Frame sequencer clock tick
+            if self.frame_seq_clk == 0 {
+                self.frame_seq_clk = FRAME_SEQ_RELOAD;
+                self.tick_frame_seq();
+            }
+
+            self.sample_rate_counter += span as f32;
+            self.tick_mixer();
+
+            cycles -= span;
         }
+    }
 
-        self.tick_mixer();
+    /// Returns the number of clock cycles (a multiple of 4) until the next output sample.
+    fn cycles_to_next_sample(&self) -> u32 {
+        if !self.sample_period.is_finite() {
+            return u32::MAX;
+        }
+
+        let steps = ((self.sample_period - self.sample_rate_counter) / 4.0).floor() as u32 + 1;
+        steps * 4
     }
 
     /// Update mixer output
+    ///
+    /// Rather than picking the channel outputs at the time of the sample, which aliases
+    /// high-pitched waves, their average since the previous sample is used (a box filter).
     fn tick_mixer(&mut self) {
-        self.sample_rate_counter += 4.0;
-
         // Update the audio channel
         if self.sample_rate_counter > self.sample_period {
             self.sample_rate_counter -= self.sample_period;
 
             if let Some(ref mut sink) = self.sample_channel {
-                let ch1 = self.ch1.get_channel_out();
-                let ch2 = self.ch2.get_channel_out();
-                let ch3 = self.ch3.get_channel_out();
+                let mut acc = self.sample_acc;
+                for (ch, acc) in acc.iter_mut().enumerate() {
+                    if !self.mixer.audible(ch) {
+                        *acc = 0;
+                    }
+                }
+                let [ch1, ch2, ch3] = acc;
 
                 let mut so2 = 0;
                 let mut so1 = 0;
 
                 // If the peripheral is disabled, no sound is emitted.
-                if !self.nr52.contains(NR52::PWR_CTRL) {
-                    sink.push(0).unwrap_or(());
+                let sample = if !self.nr52.contains(NR52::PWR_CTRL) {
+                    0
                 } else {
                     // Update LEFT speaker
                     if self.nr51.contains(NR51::OUT1_L) {
@@ -733,14 +1114,41 @@ impl APU {
                     }
 
                     // Adjust master volumes
-                    so2 *= 1 + i16::from((self.nr50 & NR50::LEFT_VOL).bits() >> 4);
-                    so1 *= 1 + i16::from((self.nr50 & NR50::RIGHT_VOL).bits());
+                    so2 *= 1 + i32::from((self.nr50 & NR50::LEFT_VOL).bits() >> 4);
+                    so1 *= 1 + i32::from((self.nr50 & NR50::RIGHT_VOL).bits());
+
+                    // Remove the DC offset from both terminals, as the capacitors
+                    // on the output do, unless all DACs are off.
+                    let cycles = self.sample_acc_cycles.max(1) as i32;
+                    let charge = self.hpf_charge.powi(cycles);
+                    let dacs_on = self.ch1.dac_on() || self.ch2.dac_on() || self.ch3.dac_on();
+
+                    let left = high_pass(
+                        &mut self.hpf_capacitors[0],
+                        so2 as f32 / cycles as f32,
+                        charge,
+                        dacs_on,
+                    );
+                    let right = high_pass(
+                        &mut self.hpf_capacitors[1],
+                        so1 as f32 / cycles as f32,
+                        charge,
+                        dacs_on,
+                    );
 
                     // Produce a sample which is an average of the two channels.
                     // TODO implement true stero sound.
-                    sink.push((so1 + so2) / 2).unwrap_or(());
-                }
+                    ((left + right) / 2.0 * self.mixer.master_volume) as i16
+                };
+
+                sink.push(sample).unwrap_or(());
+
+                self.scope[self.scope_pos] = sample;
+                self.scope_pos = (self.scope_pos + 1) % SCOPE_SIZE;
             }
+
+            self.sample_acc = [0; 3];
+            self.sample_acc_cycles = 0;
         }
     }
 
@@ -767,7 +1175,7 @@ impl APU {
                 NR52::empty()
             };
 
-            (self.nr52 | ch1_en | ch2_en | ch3_en).bits()
+            ((self.nr52 & NR52::PWR_CTRL) | ch1_en | ch2_en | ch3_en).bits()
         }
     }
 
@@ -780,6 +1188,9 @@ impl APU {
             for addr in 0xFF10..=0xFF25 {
                 self.write(addr, 0)?;
             }
+        } else if !self.nr52.contains(NR52::PWR_CTRL) {
+            // When powered on, the frame sequencer is reset so that the next step will be 0
+            self.frame_seq_step = 0;
         }
 
         self.nr52 = new_nr52;
@@ -789,14 +1200,95 @@ impl APU {
 
     /// Changes the current sample rate.
     pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
         self.sample_period = (crate::CPU_CLOCK as f32) / sample_rate;
         self.sample_rate_counter = 0f32;
+        self.sample_acc = [0; 3];
+        self.sample_acc_cycles = 0;
+    }
+
+    /// Produces `ratio` times the samples expected at the current sample rate,
+    /// without resetting the sampling phase. Used for dynamic rate control.
+    pub fn set_rate_adjust(&mut self, ratio: f32) {
+        self.sample_period = (crate::CPU_CLOCK as f32) / (self.sample_rate * ratio);
+    }
+
+    /// Selects whether the APU behaves as in CGB mode.
+    pub fn set_cgb_mode(&mut self, cgb: bool) {
+        self.cgb = cgb;
+    }
+
+    /// Returns the index of the wave RAM byte accessed by the CPU at `addr`, if any.
+    ///
+    /// While channel 3 is playing, accesses end up on the byte being played instead. On DMG,
+    /// this only works when the channel is reading it too, otherwise the access is dropped.
+    fn wave_ram_index(&self, addr: u16) -> Option<usize> {
+        if !self.wave_ram_quirks || !self.ch3.enabled {
+            Some(usize::from(addr) - 0xFF30)
+        } else if self.cgb || self.ch3.just_fetched {
+            Some(self.ch3.position_counter >> 1)
+        } else {
+            None
+        }
+    }
+
+    /// Sets the fraction of charge kept by the high-pass filter capacitors on each clock cycle,
+    /// which depends on the hardware model. A factor of 1.0 disables the filter.
+    pub fn set_hpf_charge_factor(&mut self, charge: f32) {
+        self.hpf_charge = charge;
+    }
+
+    /// Sets the controls applied by the mixer on top of the emulated hardware.
+    pub fn set_mixer_controls(&mut self, controls: MixerControls) {
+        self.mixer = controls;
+    }
+
+    /// Returns the controls applied by the mixer on top of the emulated hardware.
+    pub fn mixer_controls(&self) -> MixerControls {
+        self.mixer
+    }
+
+    /// Returns a snapshot of the state of channels 1 to 3.
+    pub fn channel_status(&self) -> [ChannelStatus; 3] {
+        [self.ch1.status(), self.ch2.status(), self.ch3.status()]
+    }
+
+    /// Returns the frame sequencer step to be run next.
+    pub fn frame_seq_step(&self) -> u8 {
+        self.frame_seq_step
+    }
+
+    /// Returns the last samples produced by the mixer, oldest first.
+    pub fn recent_samples<'a>(&'a self) -> impl Iterator<Item = i16> + 'a {
+        self.scope[self.scope_pos..]
+            .iter()
+            .chain(self.scope[..self.scope_pos].iter())
+            .cloned()
     }
 
     /// Sets the current audio sink.
     pub fn set_audio_sink(&mut self, sink: Arc<ArrayQueue<i16>>) {
         self.sample_channel = Some(sink);
     }
+
+    /// Returns the number of samples queued in the audio sink and its capacity, if any.
+    pub fn audio_level(&self) -> Option<(usize, usize)> {
+        self.sample_channel
+            .as_ref()
+            .map(|sink| (sink.len(), sink.capacity()))
+    }
+}
+
+/// Runs a sample through a high-pass filter, whose capacitor keeps a `charge` fraction of
+/// its charge between samples. When the DACs are off, the capacitor is left untouched.
+fn high_pass(capacitor: &mut f32, input: f32, charge: f32, dacs_on: bool) -> f32 {
+    if dacs_on {
+        let out = input - *capacitor;
+        *capacitor = input - out * charge;
+        out
+    } else {
+        0.0
+    }
 }
 
 impl InterruptSource for APU {
@@ -821,25 +1313,51 @@ impl MemR for APU {
             0xFF25 => self.nr51.bits(),
             0xFF26 => self.read_pwr_reg() | 0x70,
 
-            0xFF30..=0xFF3F => self.ch3.wave_ram[usize::from(addr) - 0xFF30],
+            0xFF30..=0xFF3F => self
+                .wave_ram_index(addr)
+                .map_or(0xFF, |i| self.ch3.wave_ram[i]),
 
             // Unused regs in this range: 0xFF15, 0xFF1F, 0xFF27..=0xFF2F
             _ => 0xFF,
         })
     }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF30..=0xFF3F => self.ch3.wave_ram[usize::from(addr) - 0xFF30],
+            _ => self.read(addr).unwrap_or(0xFF),
+        }
+    }
 }
 
 impl MemW for APU {
+    fn poke(&mut self, addr: u16, val: u8) {
+        match addr {
+            // Don't trigger the channels
+            0xFF14 | 0xFF19 | 0xFF1E => self.write(addr, val & 0x7F).unwrap_or(()),
+            // Don't clear the sound registers when powering off
+            0xFF26 => self.nr52 = NR52::from_bits_truncate(val) & NR52::PWR_CTRL,
+            0xFF30..=0xFF3F => self.ch3.wave_ram[usize::from(addr) - 0xFF30] = val,
+            _ => self.write(addr, val).unwrap_or(()),
+        }
+    }
+
     fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
         // Writes to any register in range NR10-NR51 are ignored if the peripheral is off
         if addr < 0xFF26 && !self.nr52.contains(NR52::PWR_CTRL) {
             return Ok(());
         }
 
+        let len_clk_skipped = self.len_clk_skipped();
+
         match addr {
-            0xFF10..=0xFF14 => self.ch1.write(addr - 0xFF10, val)?,
-            0xFF15..=0xFF19 => self.ch2.write(addr - 0xFF15, val)?,
-            0xFF1A..=0xFF1E => self.ch3.write(addr - 0xFF1A, val)?,
+            0xFF14 => self.ch1.write_to_nr4(val, len_clk_skipped),
+            0xFF19 => self.ch2.write_to_nr4(val, len_clk_skipped),
+            0xFF1E => self.ch3.write_to_nr4(val, len_clk_skipped),
+
+            0xFF10..=0xFF13 => self.ch1.write(addr - 0xFF10, val)?,
+            0xFF15..=0xFF18 => self.ch2.write(addr - 0xFF15, val)?,
+            0xFF1A..=0xFF1D => self.ch3.write(addr - 0xFF1A, val)?,
 
             0xFF20 => self.ch4_len_reg.0 = val,
             0xFF21 => self.ch4_vol_reg.0 = val,
@@ -850,7 +1368,11 @@ impl MemW for APU {
             0xFF25 => self.nr51 = NR51::from_bits_truncate(val),
             0xFF26 => self.write_to_pwr_reg(val)?,
 
-            0xFF30..=0xFF3F => self.ch3.wave_ram[usize::from(addr) - 0xFF30] = val,
+            0xFF30..=0xFF3F => {
+                if let Some(i) = self.wave_ram_index(addr) {
+                    self.ch3.wave_ram[i] = val;
+                }
+            }
 
             // Unused regs in this range: 0xFF15, 0xFF1F, 0xFF27..=0xFF2F
             _ => (),
@@ -859,3 +1381,402 @@ impl MemW for APU {
         Ok(())
     }
 }
+
+savestate_fields!(
+    APU where APU::clocks_in_range,
+    ch1,
+    ch2,
+    ch3,
+    ch4_len_reg,
+    ch4_vol_reg,
+    ch4_cnt_reg,
+    ch4_ini_reg,
+    nr50,
+    nr51,
+    nr52,
+    cgb,
+    hpf_capacitors,
+    frame_seq_clk,
+    frame_seq_step,
+    sample_rate_counter,
+    sample_acc,
+    sample_acc_cycles,
+    mixer,
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn playing_apu(sink: Arc<ArrayQueue<i16>>) -> APU {
+        let mut apu = APU::new(44_100.0);
+        apu.set_audio_sink(sink);
+
+        // Square 1 with sweep and envelope, square 2 and a wave pattern
+        for (addr, val) in [
+            (0xFF10, 0x16),
+            (0xFF11, 0x80),
+            (0xFF12, 0xF3),
+            (0xFF13, 0x73),
+            (0xFF14, 0xC6),
+            (0xFF16, 0x40),
+            (0xFF17, 0x87),
+            (0xFF18, 0x20),
+            (0xFF19, 0x87),
+            (0xFF30, 0x01),
+            (0xFF31, 0x23),
+            (0xFF32, 0x45),
+            (0xFF33, 0x67),
+            (0xFF1A, 0x80),
+            (0xFF1C, 0x20),
+            (0xFF1D, 0x11),
+            (0xFF1E, 0x87),
+        ]
+        .iter()
+        {
+            apu.write(*addr, *val).unwrap();
+        }
+        apu
+    }
+
+    #[test]
+    fn wave_channel_plays_wave_ram() {
+        let mut apu = APU::default();
+
+        // Samples alternate between 15 and 0, each lasting 64 clock cycles
+        for addr in 0xFF30..=0xFF3F {
+            apu.write(addr, 0xF0).unwrap();
+        }
+        for (addr, val) in [
+            (0xFF1A, 0x80),
+            (0xFF1C, 0x20),
+            (0xFF1D, 0xE0),
+            (0xFF1E, 0x87),
+        ]
+        .iter()
+        {
+            apu.write(*addr, *val).unwrap();
+        }
+        assert_eq!(apu.read(0xFF26).unwrap() & 0x04, 0x04);
+
+        // Playback starts from the second sample
+        apu.advance(64);
+        assert_eq!(apu.ch3.get_channel_out(), -15);
+        apu.advance(64);
+        assert_eq!(apu.ch3.get_channel_out(), 15);
+
+        // Output level 50%, then muted
+        apu.write(0xFF1C, 0x40).unwrap();
+        assert_eq!(apu.ch3.get_channel_out(), -1);
+        apu.write(0xFF1C, 0x00).unwrap();
+        assert_eq!(apu.ch3.get_channel_out(), -15);
+
+        // The length counter stops the channel
+        apu.write(0xFF1B, 0xFF).unwrap();
+        apu.write(0xFF1E, 0xC7).unwrap();
+        apu.advance(FRAME_SEQ_RELOAD);
+        assert_eq!(apu.read(0xFF26).unwrap() & 0x04, 0x00);
+
+        // Turning the DAC off silences the channel
+        apu.write(0xFF1A, 0x00).unwrap();
+        assert_eq!(apu.ch3.get_channel_out(), 0);
+    }
+
+    #[test]
+    fn sweep_updates_frequency_until_overflow() {
+        let mut apu = APU::default();
+
+        // Period 1, addition, shift 1, starting from 0x400
+        for (addr, val) in [
+            (0xFF10, 0x11),
+            (0xFF12, 0xF0),
+            (0xFF13, 0x00),
+            (0xFF14, 0x84),
+        ]
+        .iter()
+        {
+            apu.write(*addr, *val).unwrap();
+        }
+        assert_eq!(apu.read(0xFF26).unwrap() & 0x01, 0x01);
+
+        // 0x400 becomes 0x600 on step 2, then the second check (0x900) overflows
+        apu.advance(3 * FRAME_SEQ_RELOAD);
+        assert_eq!(apu.ch1.get_frequency(), 0x600);
+        assert_eq!(apu.read(0xFF26).unwrap() & 0x01, 0x00);
+
+        // The overflow check is done right away on trigger
+        apu.write(0xFF13, 0x00).unwrap();
+        apu.write(0xFF14, 0x87).unwrap();
+        assert_eq!(apu.read(0xFF26).unwrap() & 0x01, 0x00);
+
+        // Leaving negate mode after it was used disables the channel
+        apu.write(0xFF10, 0x19).unwrap();
+        apu.write(0xFF14, 0x84).unwrap();
+        assert_eq!(apu.read(0xFF26).unwrap() & 0x01, 0x01);
+        apu.write(0xFF10, 0x11).unwrap();
+        assert_eq!(apu.read(0xFF26).unwrap() & 0x01, 0x00);
+    }
+
+    #[test]
+    fn length_is_clocked_when_enabled_in_first_half() {
+        let mut apu = APU::default();
+
+        // Square 2 with a length of 1, but length disabled
+        for (addr, val) in [(0xFF16, 0x3F), (0xFF17, 0xF0), (0xFF19, 0x80)].iter() {
+            apu.write(*addr, *val).unwrap();
+        }
+
+        // After step 0, enabling length clocks it and disables the channel
+        apu.advance(FRAME_SEQ_RELOAD);
+        assert_eq!(apu.read(0xFF26).unwrap() & 0x02, 0x02);
+        apu.write(0xFF19, 0x40).unwrap();
+        assert_eq!(apu.read(0xFF26).unwrap() & 0x02, 0x00);
+
+        // Triggering with a zero length reloads it, minus the extra clock
+        apu.write(0xFF19, 0x00).unwrap();
+        apu.write(0xFF19, 0xC0).unwrap();
+        assert_eq!(apu.ch2.length, 63);
+
+        // Enabling length before a step which clocks it has no side effects
+        apu.advance(FRAME_SEQ_RELOAD);
+        apu.write(0xFF19, 0x00).unwrap();
+        apu.write(0xFF16, 0x3F).unwrap();
+        apu.write(0xFF19, 0x40).unwrap();
+        assert_eq!(apu.ch2.length, 1);
+        assert_eq!(apu.read(0xFF26).unwrap() & 0x02, 0x02);
+    }
+
+    #[test]
+    fn rate_adjust_changes_sample_count() {
+        let samples = |ratio: f32| {
+            let sink = Arc::new(ArrayQueue::new(8192));
+            let mut apu = APU::new(44_100.0);
+
+            apu.set_audio_sink(sink.clone());
+            apu.set_rate_adjust(ratio);
+            apu.advance(crate::CPU_CLOCK as u32 / 10);
+            sink.len()
+        };
+
+        // About 4410 samples are produced in 100ms, give or take one due to rounding
+        let nominal = samples(1.0) as i32;
+        assert!((nominal - 4410).abs() <= 1);
+        assert!((samples(1.01) as i32 - nominal - 44).abs() <= 1);
+        assert!((nominal - samples(0.99) as i32 - 44).abs() <= 1);
+    }
+
+    #[test]
+    fn samples_average_high_pitched_waves() {
+        let sink = Arc::new(ArrayQueue::new(8192));
+        let mut apu = APU::new(44_100.0);
+        apu.set_audio_sink(sink.clone());
+
+        // Square 2 at full volume and highest frequency (a 32 cycles period) with 50% duty,
+        // sent to both terminals with unit master volume
+        for (addr, val) in [
+            (0xFF24, 0x00),
+            (0xFF25, 0x22),
+            (0xFF16, 0x80),
+            (0xFF17, 0xF0),
+            (0xFF18, 0xFF),
+            (0xFF19, 0x87),
+        ]
+        .iter()
+        {
+            apu.write(*addr, *val).unwrap();
+        }
+
+        apu.advance(crate::CPU_CLOCK as u32 / 100);
+        assert!(sink.len() > 400);
+
+        // Picking single values would give either -15 or 15, averaging stays close to 0
+        while let Ok(s) = sink.pop() {
+            assert!(s.abs() <= 5, "sample {} is not averaged", s);
+        }
+    }
+
+    #[test]
+    fn mixer_controls_are_applied() {
+        let sink = Arc::new(ArrayQueue::new(8192));
+        let mut apu = APU::new(44_100.0);
+        apu.set_audio_sink(sink.clone());
+
+        // Keep the DC offset, to compare the raw channel levels
+        apu.set_hpf_charge_factor(1.0);
+
+        // Square 2 at full volume and the lowest frequency, staying low for the whole test
+        for (addr, val) in [
+            (0xFF24, 0x00),
+            (0xFF25, 0x22),
+            (0xFF16, 0x00),
+            (0xFF17, 0xF0),
+            (0xFF18, 0x00),
+            (0xFF19, 0x80),
+        ]
+        .iter()
+        {
+            apu.write(*addr, *val).unwrap();
+        }
+
+        let mix = |apu: &mut APU, controls: MixerControls| {
+            apu.set_mixer_controls(controls);
+            apu.advance(1024);
+
+            let mut samples = Vec::new();
+            while let Ok(s) = sink.pop() {
+                samples.push(s);
+            }
+            assert!(!samples.is_empty());
+            assert!(samples.iter().all(|s| *s == samples[0]));
+            samples[0]
+        };
+
+        let mut controls = MixerControls::default();
+        assert_eq!(mix(&mut apu, controls), -15);
+
+        controls.muted[1] = true;
+        assert_eq!(mix(&mut apu, controls), 0);
+
+        // Solo wins over mute
+        controls.solo = Some(1);
+        assert_eq!(mix(&mut apu, controls), -15);
+        controls.solo = Some(0);
+        assert_eq!(mix(&mut apu, controls), 0);
+
+        let halved = MixerControls {
+            master_volume: 0.5,
+            ..MixerControls::default()
+        };
+        assert_eq!(mix(&mut apu, halved), -7);
+        assert_eq!(apu.recent_samples().last(), Some(-7));
+    }
+
+    #[test]
+    fn high_pass_filter_removes_dc_offset() {
+        let sink = Arc::new(ArrayQueue::new(32_768));
+        let mut apu = APU::new(44_100.0);
+        apu.set_audio_sink(sink.clone());
+
+        // Square 2 with 0% volume, which outputs a constant -15
+        for (addr, val) in [
+            (0xFF24, 0x00),
+            (0xFF25, 0x22),
+            (0xFF17, 0x08),
+            (0xFF19, 0x80),
+        ]
+        .iter()
+        {
+            apu.write(*addr, *val).unwrap();
+        }
+
+        apu.advance(crate::CPU_CLOCK as u32 / 4);
+
+        let first = sink.pop().unwrap();
+        let mut last = first;
+        while let Ok(s) = sink.pop() {
+            last = s;
+        }
+
+        // The offset goes through at first, then the capacitor charges up
+        assert_eq!(first, -15);
+        assert_eq!(last, 0);
+    }
+
+    #[test]
+    fn wave_ram_accesses_hit_the_playing_byte() {
+        let mut apu = APU {
+            wave_ram_quirks: true,
+            ..APU::default()
+        };
+
+        for addr in 0xFF30..=0xFF3F {
+            apu.write(addr, (addr as u8 & 0x0F) * 0x11).unwrap();
+        }
+
+        // Wave RAM is read every 8 clock cycles, the first time after 8 cycles
+        for (addr, val) in [
+            (0xFF1A, 0x80),
+            (0xFF1C, 0x20),
+            (0xFF1D, 0xFC),
+            (0xFF1E, 0x87),
+        ]
+        .iter()
+        {
+            apu.write(*addr, *val).unwrap();
+        }
+        assert_eq!(apu.next_event(), 4);
+
+        // On DMG, accesses only work while the channel is reading too
+        apu.advance(4);
+        assert_eq!(apu.read(0xFF35).unwrap(), 0xFF);
+        apu.write(0xFF35, 0xAB).unwrap();
+        assert_eq!(apu.peek(0xFF35), 0x55);
+
+        apu.advance(4);
+        assert_eq!(apu.read(0xFF35).unwrap(), 0x00);
+        apu.write(0xFF35, 0xAB).unwrap();
+        assert_eq!(apu.peek(0xFF30), 0xAB);
+        assert_eq!(apu.peek(0xFF35), 0x55);
+
+        // On CGB, they always work
+        apu.set_cgb_mode(true);
+        apu.advance(4);
+        assert_eq!(apu.read(0xFF3F).unwrap(), 0xAB);
+
+        // Without the quirks, the CPU can access any byte
+        apu.wave_ram_quirks = false;
+        assert_eq!(apu.read(0xFF3F).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn volume_envelope_and_zombie_mode() {
+        let mut apu = APU::default();
+
+        // Square 2 fading out from 15, one step every 1/64th of a second
+        apu.write(0xFF17, 0xF1).unwrap();
+        apu.write(0xFF19, 0x80).unwrap();
+        assert_eq!(apu.ch2.volume, 15);
+
+        apu.advance(8 * FRAME_SEQ_RELOAD);
+        assert_eq!(apu.ch2.volume, 14);
+        assert_eq!(apu.read(0xFF17).unwrap(), 0xF1);
+
+        // Writes while the channel is on change the volume
+        for &(val, volume) in [
+            (0xF0, 0),  // Subtract mode: +2, wrapping around to 0
+            (0xF8, 15), // Period 0 and updates on: +1, then mode changed: 16 - 1
+            (0xF8, 0),  // Period 0 and updates on: +1, wrapping around to 0
+            (0xF8, 1),
+        ]
+        .iter()
+        {
+            apu.write(0xFF17, val).unwrap();
+            assert_eq!(apu.ch2.volume, volume, "writing {:02X}", val);
+        }
+    }
+
+    #[test]
+    fn bulk_advance_matches_single_steps() {
+        let stepped_sink = Arc::new(ArrayQueue::new(8192));
+        let bulk_sink = Arc::new(ArrayQueue::new(8192));
+
+        let mut stepped = playing_apu(stepped_sink.clone());
+        let mut bulk = playing_apu(bulk_sink.clone());
+
+        for _ in 0..40 {
+            for _ in 0..4096 / 4 {
+                stepped.advance(4);
+            }
+            bulk.advance(4096);
+
+            assert_eq!(stepped.read(0xFF26).unwrap(), bulk.read(0xFF26).unwrap());
+        }
+
+        assert!(stepped_sink.len() > 1000);
+        assert_eq!(stepped_sink.len(), bulk_sink.len());
+
+        while let Ok(s) = stepped_sink.pop() {
+            assert_eq!(Ok(s), bulk_sink.pop());
+        }
+    }
+}
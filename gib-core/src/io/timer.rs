@@ -11,6 +11,10 @@ pub struct Timer {
     irq_pending: bool,
     tima_reload_scheduled: bool,
     tima_is_being_reloaded: bool,
+
+    // Statistics about TIMA overflows, for debugging
+    overflows: u64,
+    cycles_since_overflow: u64,
 }
 
 impl Default for Timer {
@@ -24,6 +28,9 @@ impl Default for Timer {
             irq_pending: false,
             tima_reload_scheduled: false,
             tima_is_being_reloaded: false,
+
+            overflows: 0,
+            cycles_since_overflow: 0,
         }
     }
 }
@@ -33,13 +40,19 @@ impl Timer {
         Timer::default()
     }
 
+    /// Creates a timer in its power-on state, with the system counter cleared.
+    pub fn power_on() -> Timer {
+        Timer {
+            sys_counter: IoReg(0),
+            ..Timer::default()
+        }
+    }
+
     pub fn div(&self) -> IoReg<u8> {
         IoReg((self.sys_counter.0 >> 8) as u8)
     }
 
     pub fn tick(&mut self) {
-        let rb = self.curr_rate();
-
         // TIMA reload lasts one cycle, so it's ok to reset this
         // at the beginning of each tick.
         self.tima_is_being_reloaded = false;
@@ -53,62 +66,88 @@ impl Timer {
             self.tima_is_being_reloaded = true;
             self.irq_pending = true;
             self.tima = self.tma;
-        }
 
-        if !self.running() {
-            self.sys_counter.0 += 4;
-        } else {
-            let old = self.sys_counter;
-            self.sys_counter.0 += 4;
-            let new = self.sys_counter;
-
-            // TIMA is incremented when a falling edge is detected on the rate bit.
-            if old.bit(rb) && !new.bit(rb) {
-                self.inc_timer();
-            }
+            self.overflows += 1;
+            self.cycles_since_overflow = 0;
         }
+        self.cycles_since_overflow += 4;
+
+        let old = self.signal();
+        self.sys_counter.0 = self.sys_counter.0.wrapping_add(4);
+        self.detect_falling_edge(old);
     }
 
     pub fn running(&self) -> bool {
         self.tac.bit(2)
     }
 
+    /// Returns the frequency of the clock incrementing TIMA selected by TAC, in Hz.
+    pub fn input_clock(&self) -> u32 {
+        (crate::CPU_CLOCK >> (self.curr_rate() + 1)) as u32
+    }
+
+    /// Returns the clock cycles left until TIMA is next incremented,
+    /// or `None` if the timer is stopped.
+    pub fn cycles_to_increment(&self) -> Option<u16> {
+        let period = 1 << (self.curr_rate() + 1);
+
+        if self.running() {
+            Some(period - (self.sys_counter.0 & (period - 1)))
+        } else {
+            None
+        }
+    }
+
+    /// Returns how many times TIMA overflowed and got reloaded, raising an IRQ.
+    pub fn overflows(&self) -> u64 {
+        self.overflows
+    }
+
+    /// Returns the clock cycles elapsed since the last TIMA reload.
+    pub fn cycles_since_overflow(&self) -> u64 {
+        self.cycles_since_overflow
+    }
+
     fn inc_timer(&mut self) {
-        self.tima.0 += 1;
+        self.tima.0 = self.tima.0.wrapping_add(1);
 
-        // Wehn TIMA overflows, TMA gets loaded in it and an IRQ request is registered.
-        // This happend with a full cycle delay, so for 4 clock cycles upon overflowing,
-        // TIMA stays 00, so here we just schedule the increment.
+        // When TIMA overflows, TMA gets loaded in it and an IRQ request is registered.
+        // This happens with a full cycle delay, so for 4 clock cycles upon overflowing,
+        // TIMA stays 00, so here we just schedule the reload.
         if self.tima.0 == 0 {
             self.tima_reload_scheduled = true;
         }
     }
 
+    /// Output of the multiplexer selecting the bit of the system counter
+    /// corresponding to the current tick rate, ANDed with the enable bit of TAC.
+    fn signal(&self) -> bool {
+        self.running() && self.rate_bit()
+    }
+
+    /// Increments TIMA if the multiplexer output went from 1 to 0.
+    /// This is the only thing driving TIMA, so anything that changes either the
+    /// system counter or TAC can cause an increment, not only the passing of time.
+    fn detect_falling_edge(&mut self, old: bool) {
+        if old && !self.signal() {
+            self.inc_timer();
+        }
+    }
+
     fn reset_sys_counter(&mut self) {
         // HW BUG: resetting DIV while the multiplexer bit corresponding
         // to the current tick rate is set causes TIMA to increment.
-        if self.running() && self.rate_bit() {
-            self.inc_timer()
-        }
+        let old = self.signal();
         self.sys_counter.0 = 0;
+        self.detect_falling_edge(old);
     }
 
     fn write_to_tac(&mut self, val: u8) {
-        let val = IoReg(val);
-
-        // HW BUG: when changing TAC register value, if the old selected bit
-        // by the multiplexer was 0, the new one is 1, and the new enable bit
-        // of TAC is set to 1, it will increase TIMA.
-        let c1 = val.bit(2) && !self.rate_bit() && self.sys_counter.bit(Timer::rate_of(val));
-
-        // HW BUG: whenever half the clocks of the count are reached,
-        // TIMA will increase when disabling the timer.
-        let c2 = self.running() && !val.bit(2) && self.rate_bit();
-
-        if c1 || c2 {
-            self.inc_timer();
-        }
-        self.tac = val;
+        // HW BUG: switching to a rate whose bit is 0 while the old one was 1,
+        // or disabling the timer while the selected bit is 1, increments TIMA.
+        let old = self.signal();
+        self.tac = IoReg(val);
+        self.detect_falling_edge(old);
     }
 
     fn curr_rate(&self) -> usize {
@@ -154,6 +193,17 @@ impl MemR for Timer {
 }
 
 impl MemW for Timer {
+    fn poke(&mut self, addr: u16, val: u8) {
+        // Set the registers directly, without triggering any of the glitches above
+        match addr {
+            0xFF04 => self.sys_counter.0 = u16::from(val) << 8,
+            0xFF05 => self.tima.0 = val,
+            0xFF06 => self.tma.0 = val,
+            0xFF07 => self.tac.0 = val,
+            _ => unreachable!(),
+        }
+    }
+
     fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
         match addr {
             0xFF04 => self.reset_sys_counter(),
@@ -189,16 +239,24 @@ impl MemW for Timer {
 
 impl MemRW for Timer {}
 
+savestate_fields!(
+    Timer,
+    sys_counter,
+    tima,
+    tma,
+    tac,
+    irq_pending,
+    tima_reload_scheduled,
+    tima_is_being_reloaded,
+);
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // TODO: this tests are failing after 4ad06f9. Fix them.
-
     #[test]
-    #[should_panic]
     fn system_counter_tick() {
-        let mut timer = Timer::default();
+        let mut timer = Timer::power_on();
 
         // Counter starts at 0
         assert_eq!(timer.div().0, 0);
@@ -228,9 +286,8 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn system_counter_reset() {
-        let mut timer = Timer::default();
+        let mut timer = Timer::power_on();
 
         for _ in 0..129 {
             timer.tick();
@@ -245,9 +302,8 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn timer_tick() {
-        let mut timer = Timer::default();
+        let mut timer = Timer::power_on();
 
         // Ticking does not affect a stopped timer
         for _ in 0..512 {
@@ -274,11 +330,10 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn replicate_timer_hw_bugs() {
         // Test 1: when writing to DIV register the TIMA register can be increased
         // if the counter has reached half the clocks it needs to increase.
-        let mut timer = Timer::default();
+        let mut timer = Timer::power_on();
         timer.tac.0 = 0b101;
 
         for _ in 0..3 {
@@ -289,4 +344,89 @@ mod tests {
         timer.reset_sys_counter();
         assert_eq!(timer.tima.0, 1);
     }
+
+    #[test]
+    fn tima_reload_is_delayed() {
+        let mut timer = Timer::power_on();
+        timer.tma.0 = 0x42;
+        timer.tima.0 = 0xFF;
+        timer.write_to_tac(0b101);
+
+        for _ in 0..4 {
+            timer.tick();
+        }
+        // TIMA stays 00 for a cycle after overflowing
+        assert_eq!(timer.tima.0, 0x00);
+        assert!(timer.get_and_clear_irq().is_none());
+
+        timer.tick();
+        assert_eq!(timer.tima.0, 0x42);
+        assert!(timer.get_and_clear_irq().is_some());
+        assert_eq!(timer.overflows(), 1);
+    }
+
+    #[test]
+    fn cycles_to_increment() {
+        let mut timer = Timer::power_on();
+        assert_eq!(timer.cycles_to_increment(), None);
+
+        timer.write_to_tac(0b110);
+        assert_eq!(timer.input_clock(), 65_536);
+        assert_eq!(timer.cycles_to_increment(), Some(64));
+
+        for _ in 0..15 {
+            timer.tick();
+        }
+        assert_eq!(timer.cycles_to_increment(), Some(4));
+
+        timer.tick();
+        assert_eq!(timer.tima.0, 1);
+        assert_eq!(timer.cycles_to_increment(), Some(64));
+    }
+
+    #[test]
+    fn writes_during_tima_reload() {
+        // Writing TIMA in the cycle after the overflow cancels the reload
+        let mut timer = Timer::power_on();
+        timer.tma.0 = 0x42;
+        timer.tima.0 = 0xFF;
+        timer.write_to_tac(0b101);
+
+        for _ in 0..4 {
+            timer.tick();
+        }
+        timer.write(0xFF05, 0x10).unwrap();
+        timer.tick();
+        assert_eq!(timer.tima.0, 0x10);
+        assert!(timer.get_and_clear_irq().is_none());
+
+        // Writing TIMA during the reload is ignored, while writing TMA propagates
+        timer.tima.0 = 0xFF;
+        for _ in 0..4 {
+            timer.tick();
+        }
+        timer.write(0xFF05, 0x10).unwrap();
+        assert_eq!(timer.tima.0, 0x42);
+        timer.write(0xFF06, 0x24).unwrap();
+        assert_eq!(timer.tima.0, 0x24);
+    }
+
+    #[test]
+    fn tac_writes_detect_falling_edges() {
+        let mut timer = Timer::power_on();
+        timer.write_to_tac(0b101);
+
+        for _ in 0..2 {
+            timer.tick();
+        }
+        assert_eq!(timer.tima.0, 0);
+
+        // Bit 3 is set, bit 9 is not: switching rate causes an increment
+        timer.write_to_tac(0b100);
+        assert_eq!(timer.tima.0, 1);
+
+        // Disabling the timer with the selected bit clear does not
+        timer.write_to_tac(0b000);
+        assert_eq!(timer.tima.0, 1);
+    }
 }
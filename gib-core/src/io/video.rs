@@ -3,6 +3,19 @@ use bitflags::bitflags;
 use super::dbg;
 use super::{InterruptSource, IrqSource};
 use super::{IoReg, MemR, MemRW, MemW};
+use crate::config::DmgPalette;
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
+use std::collections::VecDeque;
+
+/// Width and height of the BG and Window maps, in pixels.
+pub const BG_MAP_SIZE: usize = 256;
+
+/// Number of sprites in OAM.
+pub const OAM_ENTRIES: usize = 40;
+
+/// Maximum number of sprites displayed on a single line.
+const MAX_SPRITES_PER_LINE: usize = 10;
 
 /// A Tile is the bit representation of an 8x8 sprite or BG tile,
 /// with a color depth of 4 colors/gray shades.
@@ -34,6 +47,8 @@ impl Tile {
     }
 }
 
+savestate_fields!(Tile, 0);
+
 /// A Sprite is an entry in the Sprite Attribute Table (or OAM - Object Attribute Memory).
 ///
 /// Each Sprite consists of 4 bytes representing the sprite's position, associated tile and attributes.
@@ -52,12 +67,28 @@ bitflags! {
         const FLIP_X  = 0b_0010_0000;
         const PAL_NUM = 0b_0001_0000;
 
+        // CGB only
+        const VRAM_BANK = 0b_0000_1000;
+        const CGB_PAL   = 0b_0000_0111;
+
         const DEFAULT = 0b_0000_0000;
     }
 }
 
+impl Sprite {
+    /// Returns the position in the line at which the sprite is fetched,
+    /// or `None` if it is horizontally off-screen.
+    fn fetch_x(&self) -> Option<u8> {
+        match self.x {
+            0 | 168..=255 => None,
+            x => Some(x.saturating_sub(8)),
+        }
+    }
+}
+
 // On DMG the sprite flags have unused bits, but they are still writable and readable normally.
 mem_rw!(SpriteAttributes, 0x00);
+savestate_bits!(SpriteAttributes);
 
 impl Default for SpriteAttributes {
     fn default() -> SpriteAttributes {
@@ -65,7 +96,7 @@ impl Default for SpriteAttributes {
     }
 }
 
-impl<'a> MemR for &'a [Sprite] {
+impl MemR for &[Sprite] {
     fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
         let s = &self[usize::from(addr >> 2)];
 
@@ -79,13 +110,13 @@ impl<'a> MemR for &'a [Sprite] {
     }
 }
 
-impl<'a> MemR for &'a mut [Sprite] {
+impl MemR for &mut [Sprite] {
     fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
-        (&*self as &[Sprite]).read(addr)
+        (self as &[Sprite]).read(addr)
     }
 }
 
-impl<'a> MemW for &'a mut [Sprite] {
+impl MemW for &mut [Sprite] {
     fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
         let s = &mut self[usize::from(addr >> 2)];
 
@@ -100,7 +131,254 @@ impl<'a> MemW for &'a mut [Sprite] {
     }
 }
 
-impl<'a> MemRW for &'a mut [Sprite] {}
+impl MemRW for &mut [Sprite] {}
+
+savestate_fields!(Sprite, y, x, tid, attributes);
+
+bitflags! {
+    /// CGB BG map attributes, stored in VRAM bank 1 alongside the tile IDs in bank 0.
+    struct BgAttributes: u8 {
+        const BG_PRIO   = 0b_1000_0000;
+        const FLIP_Y    = 0b_0100_0000;
+        const FLIP_X    = 0b_0010_0000;
+        const VRAM_BANK = 0b_0000_1000;
+        const PAL_NUM   = 0b_0000_0111;
+    }
+}
+
+/// BG/Window pixel in the pixel FIFO.
+#[derive(Default, Copy, Clone)]
+struct BgPixel {
+    color: u8,
+    palette: u8,
+    prio: bool,
+}
+
+savestate_fields!(BgPixel, color, palette, prio);
+
+/// Sprite pixel in the pixel FIFO.
+#[derive(Default, Copy, Clone)]
+struct ObjPixel {
+    color: u8,
+    attr: SpriteAttributes,
+    oam_index: usize,
+}
+
+savestate_fields!(ObjPixel, color, attr, oam_index);
+
+/// Steps of the pixel fetcher. Each step takes 2 dots, except for Push
+/// which is retried every dot until the BG FIFO is empty.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FetcherStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+impl Savestate for FetcherStep {
+    fn save_state(&self, w: &mut StateWriter) {
+        (*self as u8).save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        let mut step = 0u8;
+        step.load_state(r)?;
+
+        *self = match step {
+            0 => FetcherStep::Tile,
+            1 => FetcherStep::DataLow,
+            2 => FetcherStep::DataHigh,
+            3 => FetcherStep::Push,
+            _ => return Err(dbg::TraceEvent::InvalidSavestate("fetcher step")),
+        };
+        Ok(())
+    }
+}
+
+/// Pixel FIFOs and fetcher, which draw the current line during mode 3.
+///
+/// The fetcher reads a row of 8 BG or Window pixels at a time, which are pushed to
+/// the BG FIFO once it runs empty. Every dot, a pixel is shifted out to the LCD,
+/// mixed with the sprite pixel at the same position. Mode 3 lasts until all of the
+/// 160 pixels have been shifted out, which takes longer when pixels are discarded
+/// (SCX fine scrolling), the fetcher is restarted (Window) or sprites are fetched.
+struct PixelFifo {
+    bg: VecDeque<BgPixel>,
+    obj: VecDeque<ObjPixel>,
+
+    // Fetcher state
+    step: FetcherStep,
+    step_dots: u8,
+    fetch_x: u8,
+    row: [BgPixel; 8],
+    window: bool,
+
+    // Line state
+    lx: u8,
+    discard: u8,
+    stall: u8,
+    sprites: Vec<usize>,
+    pending_sprite: Option<usize>,
+    done: bool,
+}
+
+impl PixelFifo {
+    /// Starts a new line, discarding the first `discard` pixels. `sprites` are the
+    /// indices of the OAM entries found on the line.
+    fn new(discard: u8, sprites: Vec<usize>) -> PixelFifo {
+        PixelFifo {
+            bg: VecDeque::with_capacity(16),
+            obj: VecDeque::with_capacity(8),
+
+            step: FetcherStep::Tile,
+            step_dots: 0,
+            fetch_x: 0,
+            row: [BgPixel::default(); 8],
+            window: false,
+
+            lx: 0,
+            discard,
+            // The first tile fetched in a line is thrown away
+            stall: 6,
+            sprites,
+            pending_sprite: None,
+            done: false,
+        }
+    }
+
+    /// Restarts fetching from the first tile, eg. when the Window is reached.
+    fn restart_fetch(&mut self) {
+        self.step = FetcherStep::Tile;
+        self.step_dots = 0;
+        self.fetch_x = 0;
+    }
+}
+
+impl Default for PixelFifo {
+    fn default() -> PixelFifo {
+        PixelFifo {
+            done: true,
+            ..PixelFifo::new(0, vec![])
+        }
+    }
+}
+
+savestate_fields!(
+    PixelFifo,
+    bg,
+    obj,
+    step,
+    step_dots,
+    fetch_x,
+    row,
+    window,
+    lx,
+    discard,
+    stall,
+    sprites,
+    pending_sprite,
+    done,
+);
+
+/// CGB color palette memory, accessed through a pair of index (BCPS/OCPS)
+/// and data (BCPD/OCPD) registers.
+///
+/// Each of the 8 palettes holds 4 colors, stored as little endian RGB555 values.
+struct CgbPalettes {
+    spec: u8,
+    data: [u8; 64],
+}
+
+impl CgbPalettes {
+    fn new() -> CgbPalettes {
+        CgbPalettes {
+            spec: 0x00,
+            data: [0xFF; 64],
+        }
+    }
+
+    fn read_spec(&self) -> u8 {
+        self.spec | 0x40
+    }
+
+    fn write_spec(&mut self, val: u8) {
+        self.spec = val & 0xBF;
+    }
+
+    fn read_data(&self) -> u8 {
+        self.data[usize::from(self.spec & 0x3F)]
+    }
+
+    /// Writes to the palette memory at the current index, which is then incremented
+    /// if auto-increment (bit 7 of the index register) is enabled.
+    fn write_data(&mut self, val: u8) {
+        self.data[usize::from(self.spec & 0x3F)] = val;
+
+        if self.spec & 0x80 != 0 {
+            self.spec = 0x80 | (self.spec.wrapping_add(1) & 0x3F);
+        }
+    }
+
+    /// Returns the RGB555 value associated with a pixel value in a palette.
+    fn entry(&self, palette: u8, pixel: u8) -> u16 {
+        let i = usize::from(palette) * 8 + usize::from(pixel) * 2;
+
+        u16::from(self.data[i]) | (u16::from(self.data[i + 1]) << 8)
+    }
+
+    fn set_entry(&mut self, palette: u8, pixel: u8, rgb: u16) {
+        let i = usize::from(palette) * 8 + usize::from(pixel) * 2;
+
+        self.data[i] = rgb as u8;
+        self.data[i + 1] = (rgb >> 8) as u8 & 0x7F;
+    }
+
+    /// Returns the RGB color associated with a pixel value in a palette.
+    fn color(&self, palette: u8, pixel: u8) -> [u8; 3] {
+        let rgb = self.entry(palette, pixel);
+
+        // Scale each 5 bit component to 8 bits
+        let component = |shift: u16| {
+            let c = ((rgb >> shift) & 0x1F) as u8;
+            (c << 3) | (c >> 2)
+        };
+
+        [component(0), component(5), component(10)]
+    }
+}
+
+savestate_fields!(CgbPalettes, spec, data);
+
+/// Palette mapping the pixel values of BG/Window tiles or sprites to colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    /// FF47 - BGP - BG Palette Data
+    Bgp,
+    /// FF48 - OBP0 - Object Palette 0 Data
+    Obp0,
+    /// FF49 - OBP1 - Object Palette 1 Data
+    Obp1,
+    /// One of the 8 CGB BG palettes
+    CgbBg(u8),
+    /// One of the 8 CGB sprite palettes
+    CgbObj(u8),
+}
+
+/// Scrolling and palette registers, as latched at the beginning of a scanline.
+///
+/// Games often change them during H-Blank for raster effects (eg. parallax or
+/// screen shake), so the values seen by each line are kept for the debugger.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LineRegisters {
+    pub scx: u8,
+    pub scy: u8,
+    pub wx: u8,
+    pub wy: u8,
+    pub bgp: u8,
+}
+
+savestate_fields!(LineRegisters, scx, scy, wx, wy, bgp);
 
 bitflags! {
     /// FF40 - LCDC - LCD Control (R/W)
@@ -119,6 +397,7 @@ bitflags! {
 }
 
 mem_rw!(LCDC, 0x00);
+savestate_bits!(LCDC);
 
 bitflags! {
     /// FF41 - STAT - LCDC Status (R/W)
@@ -140,6 +419,7 @@ bitflags! {
 }
 
 mem_rw!(STAT, 0x80);
+savestate_bits!(STAT);
 
 bitflags! {
     /// Used to keep track of which STAT IRQs are currently active.
@@ -153,7 +433,10 @@ bitflags! {
     }
 }
 
+savestate_bits!(STATIRQ);
+
 /// A DMA transfer from ROM/RAM to OAM.
+#[derive(Default)]
 struct DMATransfer {
     src: u16,
     dst: u16,
@@ -187,13 +470,31 @@ impl DMATransfer {
 
         xfer
     }
+
+    /// Returns true if the rest of the transfer lies in OAM, eg. after loading a state.
+    fn in_oam(&self) -> bool {
+        self.dst >= 0xFE00 && u64::from(self.dst) + self.remaining <= 0xFEA0
+    }
 }
 
+savestate_fields!(DMATransfer where DMATransfer::in_oam, src, dst, remaining);
+
+/// Callback invoked with each frame presented at V-Blank.
+type FrameCallback = Box<dyn FnMut(&[u8]) + Send>;
+
 pub struct PPU {
-    tdt: [Tile; 384],  // Tile Data Table
-    oam: [Sprite; 40], // Object Attribute Memory
-    bgtm0: [u8; 1024], // Background Tile Map #0
-    bgtm1: [u8; 1024], // Background Tile Map #1
+    // VRAM banks. In bank 1, the tile maps hold the CGB BG map attributes.
+    tdt: [[Tile; 384]; 2],  // Tile Data Table
+    oam: [Sprite; 40],      // Object Attribute Memory
+    bgtm0: [[u8; 1024]; 2], // Background Tile Map #0
+    bgtm1: [[u8; 1024]; 2], // Background Tile Map #1
+    vbk_reg: IoReg<u8>,     // VRAM bank select (CGB only)
+
+    // CGB mode: color palettes and object priority mode
+    cgb: bool,
+    bg_pal: CgbPalettes,
+    obj_pal: CgbPalettes,
+    opri_reg: IoReg<u8>,
 
     // Ctrl/status IO registes
     lcdc_reg: LCDC,
@@ -208,30 +509,65 @@ pub struct PPU {
     wy_reg: IoReg<u8>,
     wx_reg: IoReg<u8>,
 
-    // Monochorome palette registers
+    // Monochorome palette registers, and the colors of their shades
     obp0_reg: IoReg<u8>,
     obp1_reg: IoReg<u8>,
     bgp_reg: IoReg<u8>,
+    dmg_palette: DmgPalette,
 
     // DMA register & counter
     dma_reg: IoReg<u8>,
     dma_xfer: Option<DMATransfer>,
     dma_xfer_queue: [Option<DMATransfer>; 2],
 
+    // Frame being drawn, and the last complete one, in U8U8U8U8 RGBA format.
+    // They are flipped at V-Blank. The first frame after turning the LCD on is not displayed.
+    framebuffer: Vec<u8>,
+    frontbuffer: Vec<u8>,
+    frame_ready: bool,
+    on_frame: Option<FrameCallback>,
+    blank_frame: bool,
+    frames: u64,
+
+    // Frames blended with the previous ones, to mimic the slow response of the DMG LCD
+    persistence: f32,
+    blended: Vec<u8>,
+    fifo: PixelFifo,
+
+    // The Window keeps its own line counter, and is only shown once LY has matched WY
+    win_line: u8,
+    win_y_hit: bool,
+
+    // Registers latched at the beginning of each visible line
+    line_regs: [LineRegisters; 144],
+
     // Timings
     tstate: u64,
 
     // IRQ handling
     vblank_irq_pending: bool,
+
+    // Debugging: log of the recent activity of the LCD controller
+    pub timeline: Option<dbg::PpuTimeline>,
+
+    // Debugging: line whose start breaks execution, latched until collected
+    break_line: Option<u8>,
+    line_hit: bool,
 }
 
 impl Default for PPU {
     fn default() -> PPU {
         PPU {
-            tdt: [Tile::default(); 384],
+            tdt: [[Tile::default(); 384]; 2],
             oam: [Sprite::default(); 40],
-            bgtm0: [0; 1024],
-            bgtm1: [0; 1024],
+            bgtm0: [[0; 1024]; 2],
+            bgtm1: [[0; 1024]; 2],
+            vbk_reg: IoReg(0x00),
+
+            cgb: false,
+            bg_pal: CgbPalettes::new(),
+            obj_pal: CgbPalettes::new(),
+            opri_reg: IoReg(0x00),
 
             lcdc_reg: LCDC::DEFAULT,
             stat_reg: STAT::DEFAULT,
@@ -247,14 +583,35 @@ impl Default for PPU {
             bgp_reg: IoReg(0xFC),
             obp0_reg: IoReg(0xFF),
             obp1_reg: IoReg(0xFF),
+            dmg_palette: DmgPalette::default(),
 
             dma_reg: IoReg(0x00),
             dma_xfer: None,
             dma_xfer_queue: [None, None],
 
+            framebuffer: vec![0xFF; 160 * 144 * 4],
+            frontbuffer: vec![0xFF; 160 * 144 * 4],
+            frame_ready: false,
+            on_frame: None,
+            blank_frame: false,
+            frames: 0,
+
+            persistence: 0.0,
+            blended: vec![0xFF; 160 * 144 * 4],
+            fifo: PixelFifo::default(),
+            win_line: 0,
+            win_y_hit: false,
+
+            line_regs: [LineRegisters::default(); 144],
+
             tstate: 70164,
 
             vblank_irq_pending: true,
+
+            timeline: None,
+
+            break_line: None,
+            line_hit: false,
         }
     }
 }
@@ -264,8 +621,48 @@ impl PPU {
         PPU::default()
     }
 
+    /// Creates a PPU in its power-on state, with the LCD turned off.
+    pub fn power_on() -> PPU {
+        PPU {
+            lcdc_reg: LCDC::empty(),
+            ly_reg: IoReg(0x00),
+            bgp_reg: IoReg(0x00),
+            tstate: 0,
+            vblank_irq_pending: false,
+            ..PPU::default()
+        }
+    }
+
+    /// Enables the CGB rendering features: color palettes, BG map attributes
+    /// and sprites in VRAM bank 1.
+    pub fn set_cgb_mode(&mut self, cgb: bool) {
+        self.cgb = cgb;
+    }
+
+    /// Sets the colors used to display the shades of the monochrome palettes.
+    pub fn set_dmg_palette(&mut self, pal: DmgPalette) {
+        self.dmg_palette = pal;
+    }
+
+    /// Returns true if the frame buffers and the line being drawn are in range,
+    /// eg. after loading a state.
+    fn state_in_range(&self) -> bool {
+        let fifo = &self.fifo;
+        let mut sprites = fifo.sprites.iter().chain(fifo.pending_sprite.iter());
+
+        self.framebuffer.len() == 160 * 144 * 4
+            && self.frontbuffer.len() == 160 * 144 * 4
+            && (fifo.done || fifo.lx < 160)
+            && sprites.all(|&i| i < self.oam.len())
+    }
+
     /// Advances the LCD controller state machine by a single M-cycle.
     pub fn tick(&mut self) {
+        // The LCD controller is stopped while the display is off
+        if !self.lcdc_reg.contains(LCDC::DISP_EN) {
+            return;
+        }
+
         // Update ticks
         self.tstate = (self.tstate + 4) % 70224;
         let tstate = self.tstate % 456;
@@ -273,15 +670,83 @@ impl PPU {
 
         self.ly_reg.0 = v_line as u8;
 
-        // V-Blank IRQ happens at the beginning of the 144th line
+        if tstate == 0 && self.break_line == Some(v_line as u8) {
+            self.line_hit = true;
+        }
+
+        // Mode 3 starts after the OAM scan, and draws the line one dot at a time
+        if v_line < 144 {
+            if tstate == 80 {
+                self.start_line(v_line as u8);
+            } else if tstate > 80 {
+                for _ in 0..4 {
+                    self.fifo_dot(v_line as u8);
+                }
+            }
+        }
+
+        // The Window keeps track of its own line, restarting from the top every frame
+        if v_line == 0 && tstate == 0 {
+            self.win_line = 0;
+            self.win_y_hit = false;
+
+            if let Some(ref mut tl) = self.timeline {
+                tl.begin_frame();
+            }
+        }
+
+        // V-Blank IRQ happens at the beginning of the 144th line. By then,
+        // the first frame after turning the LCD on is complete and can be shown.
         if v_line == 144 && tstate == 0 {
             self.vblank_irq_pending = true;
+            self.blank_frame = false;
+            self.record_event(v_line, tstate, dbg::PpuEventKind::VBlankIrq);
+
+            self.present_frame();
         }
 
         // This should be called last, after every other counter has been updated!
         self.tick_stat(tstate, v_line);
     }
 
+    /// Flips the frame which has just been completed to the front, so that it can be
+    /// displayed, blending it with the previous ones if enabled.
+    fn present_frame(&mut self) {
+        std::mem::swap(&mut self.framebuffer, &mut self.frontbuffer);
+
+        if self.persistence > 0.0 {
+            self.blend_frame();
+        }
+        self.frame_ready = true;
+        self.frames += 1;
+
+        if let Some(ref mut on_frame) = self.on_frame {
+            let frame = if self.persistence > 0.0 {
+                &self.blended
+            } else {
+                &self.frontbuffer
+            };
+
+            on_frame(&frame[..]);
+        }
+    }
+
+    /// Stops the LCD controller, which is reset to the beginning of line 0 in H-Blank.
+    fn turn_lcd_off(&mut self) {
+        self.tstate = 0;
+        self.ly_reg.0 = 0;
+        self.stat_reg &= !STAT::MOD_FLAG;
+        self.fifo = PixelFifo::default();
+    }
+
+    /// Restarts the LCD controller from line 0. The first line has no OAM scan,
+    /// and the first frame is not displayed.
+    fn turn_lcd_on(&mut self) {
+        // Line 0 starts on the next tick
+        self.tstate = 70224 - 4;
+        self.blank_frame = true;
+    }
+
     /// Returns a pair of source and destination addresses for DMA transfer
     /// if one is currently in progress, otherwise `None`.
     pub fn advance_dma_xfer(&mut self) -> Option<(u16, u16)> {
@@ -307,6 +772,51 @@ impl PPU {
         ret
     }
 
+    /// Returns the source address of the OAM DMA transfer in progress, if any.
+    pub fn dma_source(&self) -> Option<u16> {
+        self.dma_xfer.as_ref().map(|xfer| xfer.src)
+    }
+
+    /// Breaks execution at the beginning of line `ly`, or never if `None`.
+    pub fn set_break_line(&mut self, ly: Option<u8>) {
+        self.break_line = ly;
+        self.line_hit = false;
+    }
+
+    /// Returns the line breaking execution if it has started since the last call.
+    pub fn take_line_hit(&mut self) -> Option<u8> {
+        if std::mem::replace(&mut self.line_hit, false) {
+            self.break_line
+        } else {
+            None
+        }
+    }
+
+    /// Returns the current LCD mode (0-3).
+    pub fn mode(&self) -> u8 {
+        (self.stat_reg & STAT::MOD_FLAG).bits()
+    }
+
+    /// Returns the registers latched at the beginning of each of the 144 visible lines.
+    pub fn line_registers(&self) -> &[LineRegisters] {
+        &self.line_regs[..]
+    }
+
+    /// Returns true if the CPU can access VRAM, which is locked while mode 3 draws the screen.
+    pub fn vram_accessible(&self) -> bool {
+        !self.lcdc_reg.contains(LCDC::DISP_EN) || self.mode() != 3
+    }
+
+    /// Returns true if the CPU can access OAM, which is locked during modes 2 and 3.
+    pub fn oam_accessible(&self) -> bool {
+        !self.lcdc_reg.contains(LCDC::DISP_EN) || self.mode() < 2
+    }
+
+    /// Returns the VRAM bank currently accessible by the CPU.
+    pub fn vram_bank(&self) -> usize {
+        usize::from(self.vbk_reg.0 & 0x01)
+    }
+
     /// Writes `val` to OAM. `addr` should be in range 0xFE00..=0xFE9F.
     ///
     /// This is a utility function that bypassed the OAM DMA access checks
@@ -315,204 +825,600 @@ impl PPU {
         (&mut self.oam[..]).write(addr - 0xFE00, val)
     }
 
-    /// Rasterizes the current contents of the Video RAM to the provided video buffer.
+    /// Corrupts the row of OAM being read by the OAM scan, if any, as it happens on DMG
+    /// when the CPU puts an OAM address on the bus during mode 2.
+    ///
+    /// The first word of the row is mixed with the first and third ones of the preceding
+    /// row, from which the other three words are then copied. The first row is never hit.
+    pub fn corrupt_oam(&mut self) {
+        let row = ((self.tstate % 456) / 4) as u16;
+
+        if self.mode() != 2 || row == 0 || row >= 20 {
+            return;
+        }
+
+        let (cur, prev) = (row * 8, (row - 1) * 8);
+        let word = |oam: &[Sprite], addr: u16| {
+            u16::from(oam.peek(addr)) | (u16::from(oam.peek(addr + 1)) << 8)
+        };
+
+        let (a, b, c) = (
+            word(&self.oam[..], cur),
+            word(&self.oam[..], prev),
+            word(&self.oam[..], prev + 4),
+        );
+        let first = ((a ^ c) & (b ^ c)) ^ c;
+
+        let mut bytes = [0; 8];
+        bytes[0] = first as u8;
+        bytes[1] = (first >> 8) as u8;
+        for (i, b) in bytes.iter_mut().enumerate().skip(2) {
+            *b = (&self.oam[..]).peek(prev + i as u16);
+        }
+
+        for (i, b) in bytes.iter().enumerate() {
+            (&mut self.oam[..]).poke(cur + i as u16, *b);
+        }
+    }
+
+    /// Copies the last rendered frame to the provided video buffer.
     ///
     /// NOTE: the buffer is assumed to be in U8U8U8U8 RGBA format.
     pub fn rasterize(&self, vbuf: &mut [u8]) {
-        // When the LCD display is disabled, show a white screen
-        if !self.lcdc_reg.contains(LCDC::DISP_EN) {
-            for b in vbuf.iter_mut() {
-                *b = 0xFF;
+        // When the LCD display is disabled, show a blank (white) screen
+        if !self.lcdc_reg.contains(LCDC::DISP_EN) || self.blank_frame {
+            let [r, g, b] = if self.cgb {
+                [0xFF; 3]
+            } else {
+                self.dmg_palette.0[0]
+            };
+
+            for pixel in vbuf.chunks_mut(4) {
+                pixel.copy_from_slice(&[r, g, b, 0xFF]);
             }
             return;
         }
 
-        // Draw BG, Window and sprites
-        self.rasterize_bg(vbuf);
-        self.rasterize_window(vbuf);
-        self.rasterize_sprites(vbuf);
+        let frame = self.displayed_frame();
+
+        vbuf[..frame.len()].copy_from_slice(frame);
     }
 
-    /// Rasterizes the current background map to the video buffer.
-    fn rasterize_bg(&self, vbuf: &mut [u8]) {
-        if !self.lcdc_reg.contains(LCDC::BG_DISP) {
-            // When BG displaying is disabled, show a white background
-            for b in vbuf.iter_mut() {
-                *b = 0xFF;
-            }
-            return;
+    /// Returns the last complete frame, if it hasn't been taken yet. No frames are
+    /// produced while the LCD is off.
+    ///
+    /// NOTE: the frame is in U8U8U8U8 RGBA format.
+    pub fn take_frame(&mut self) -> Option<&[u8]> {
+        if self.frame_ready {
+            self.frame_ready = false;
+            Some(self.displayed_frame())
+        } else {
+            None
         }
+    }
 
-        // The active area is displayed from coordinates (SCX, SCY) in the BG area
-        let scy = u16::from(self.scy_reg.0);
-        let scx = u16::from(self.scx_reg.0);
-
-        // Iterate over each pixel in the screen
-        for py in 0u16..144 {
-            for px in 0u16..160 {
-                // Compute the corresponding logical pixel.
-                // Wrap to the top-left in case the scroll registers cause any overflows.
-                let ly = usize::from(py + scy) % 256;
-                let lx = usize::from(px + scx) % 256;
-
-                self.rasterize_tile(
-                    self.get_bg_tile(lx, ly),
-                    (lx, ly),
-                    (px as usize, py as usize),
-                    vbuf,
-                );
-            }
+    /// Returns the number of frames completed since the PPU was created.
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /// Sets a callback invoked with every frame as soon as it is complete, at V-Blank.
+    pub fn on_frame<F: FnMut(&[u8]) + Send + 'static>(&mut self, f: F) {
+        self.on_frame = Some(Box::new(f));
+    }
+
+    /// Returns the last complete frame, blended with the previous ones if enabled.
+    fn displayed_frame(&self) -> &[u8] {
+        if self.persistence > 0.0 {
+            &self.blended[..]
+        } else {
+            &self.frontbuffer[..]
         }
     }
 
-    /// Rasterizes the current window map to the video buffer, if enabled.
-    fn rasterize_window(&self, vbuf: &mut [u8]) {
-        if !self.lcdc_reg.contains(LCDC::WIN_DISP_EN) {
-            return;
+    /// Enables frame blending, which mixes each frame with the previous ones to mimic
+    /// the ghosting of the DMG LCD. `persistence` is the weight of the previous frames,
+    /// from 0.0 (disabled) to 1.0 (exclusive).
+    pub fn set_frame_blending(&mut self, persistence: f32) {
+        let persistence = persistence.clamp(0.0, 0.95);
+
+        // Start blending from the last complete frame
+        if self.persistence <= 0.0 {
+            self.blended.copy_from_slice(&self.frontbuffer[..]);
+        }
+
+        self.persistence = persistence;
+    }
+
+    /// Mixes the frame which has just been rendered into the blended one.
+    fn blend_frame(&mut self) {
+        let p = self.persistence;
+
+        for (b, f) in self.blended.iter_mut().zip(self.frontbuffer.iter()) {
+            *b = (f32::from(*b) * p + f32::from(*f) * (1.0 - p)).round() as u8;
+        }
+    }
+
+    /// Returns the RGB colors associated with the four pixel values in `pal`.
+    pub fn palette_colors(&self, pal: Palette) -> [[u8; 3]; 4] {
+        let mut colors = [[0; 3]; 4];
+
+        for (pixel, color) in colors.iter_mut().enumerate() {
+            let pixel = pixel as u8;
+
+            *color = match pal {
+                Palette::Bgp => self.get_shade(self.bgp_reg.0, pixel),
+                Palette::Obp0 => self.get_shade(self.obp0_reg.0, pixel),
+                Palette::Obp1 => self.get_shade(self.obp1_reg.0, pixel),
+                Palette::CgbBg(n) => self.bg_pal.color(n, pixel),
+                Palette::CgbObj(n) => self.obj_pal.color(n, pixel),
+            };
+        }
+
+        colors
+    }
+
+    /// Returns the raw value associated with a pixel value in `pal`: the shade number
+    /// (0-3) for monochrome palettes, or the RGB555 color for CGB ones.
+    pub fn palette_entry(&self, pal: Palette, pixel: u8) -> u16 {
+        let shade = |reg: u8| u16::from((reg >> (pixel * 2)) & 0x03);
+
+        match pal {
+            Palette::Bgp => shade(self.bgp_reg.0),
+            Palette::Obp0 => shade(self.obp0_reg.0),
+            Palette::Obp1 => shade(self.obp1_reg.0),
+            Palette::CgbBg(n) => self.bg_pal.entry(n, pixel),
+            Palette::CgbObj(n) => self.obj_pal.entry(n, pixel),
+        }
+    }
+
+    /// Sets the raw value associated with a pixel value in `pal`,
+    /// bypassing the CGB palette index registers.
+    pub fn set_palette_entry(&mut self, pal: Palette, pixel: u8, val: u16) {
+        let shift = pixel * 2;
+        let set_shade = |reg: &mut IoReg<u8>| {
+            reg.0 = (reg.0 & !(0x03 << shift)) | ((val as u8 & 0x03) << shift);
+        };
+
+        match pal {
+            Palette::Bgp => set_shade(&mut self.bgp_reg),
+            Palette::Obp0 => set_shade(&mut self.obp0_reg),
+            Palette::Obp1 => set_shade(&mut self.obp1_reg),
+            Palette::CgbBg(n) => self.bg_pal.set_entry(n, pixel, val),
+            Palette::CgbObj(n) => self.obj_pal.set_entry(n, pixel, val),
+        }
+    }
+
+    /// Draws the whole 256x256 BG map at 0x9C00 if `high` is set, or at 0x9800 otherwise,
+    /// using the tile data and palettes currently selected.
+    ///
+    /// NOTE: the buffer is assumed to be in U8U8U8U8 RGBA format.
+    pub fn rasterize_bg_map(&self, high: bool, vbuf: &mut [u8]) {
+        for y in 0..BG_MAP_SIZE {
+            for x in 0..BG_MAP_SIZE {
+                let (tile, attr) = self.get_bg_win_tile(((y >> 3) << 5) + (x >> 3), high);
+
+                let row = if attr.contains(BgAttributes::FLIP_Y) {
+                    7 - (y & 0x07)
+                } else {
+                    y & 0x07
+                };
+                let col = if attr.contains(BgAttributes::FLIP_X) {
+                    7 - (x & 0x07)
+                } else {
+                    x & 0x07
+                };
+
+                let pixel = tile.pixel(col as u8, row as u8);
+                let color = if self.cgb {
+                    self.bg_pal
+                        .color((attr & BgAttributes::PAL_NUM).bits(), pixel)
+                } else {
+                    self.get_shade(self.bgp_reg.0, pixel)
+                };
+
+                put_pixel(vbuf, BG_MAP_SIZE, x, y, color);
+            }
         }
+    }
 
-        // The window is displayed from coordinates (WX-7, WY) in the active area
-        let wy = i16::from(self.wy_reg.0);
-        let wx = i16::from(self.wx_reg.0) - 7;
+    /// Draws all the sprites in OAM side by side, as they would appear on screen,
+    /// each one in an 8x16 cell. Transparent pixels have an alpha of zero.
+    ///
+    /// NOTE: the buffer is assumed to be in U8U8U8U8 RGBA format,
+    /// and to be `OAM_ENTRIES * 8` pixels wide.
+    pub fn rasterize_sprites(&self, vbuf: &mut [u8]) {
+        let width = OAM_ENTRIES * 8;
 
-        // Iterate over each physical pixel in the window area
-        for py in wy.max(0)..(wy + 144).min(144) {
-            for px in wx.max(0)..(wx + 160).min(160) {
-                // Compute the corresponding logical pixel in the BG map
-                let ly = (py - wy) as usize % 256;
-                let lx = (px - wx) as usize % 256;
+        let height = if self.lcdc_reg.contains(LCDC::OBJ_SIZE) {
+            16
+        } else {
+            8
+        };
+
+        for (i, sprite) in self.oam.iter().enumerate() {
+            let attr = sprite.attributes;
 
-                self.rasterize_tile(
-                    self.get_win_tile(lx, ly),
-                    (lx, ly),
-                    (px as usize, py as usize),
-                    vbuf,
-                );
+            for row in 0..16u8 {
+                for col in 0..8u8 {
+                    let (px, py) = (i * 8 + usize::from(col), usize::from(row));
+                    let alpha = (py * width + px) * 4 + 3;
+
+                    if row >= height {
+                        vbuf[alpha] = 0x00;
+                        continue;
+                    }
+
+                    let row = if attr.contains(SpriteAttributes::FLIP_Y) {
+                        height - 1 - row
+                    } else {
+                        row
+                    };
+                    let col = if attr.contains(SpriteAttributes::FLIP_X) {
+                        7 - col
+                    } else {
+                        col
+                    };
+
+                    let tid = if height == 16 {
+                        (sprite.tid & 0xFE) | (row >> 3)
+                    } else {
+                        sprite.tid
+                    };
+                    let pixel = self
+                        .get_sprite_tile(tid.into(), attr)
+                        .pixel(col, row & 0x07);
+
+                    put_pixel(vbuf, width, px, py, self.sprite_color(attr, pixel));
+
+                    if pixel == 0 {
+                        vbuf[alpha] = 0x00;
+                    }
+                }
             }
         }
     }
 
-    /// Rasterizes the `tile` located at logical coordinates `(lx, ly)` to the video buffer
-    /// at physical coordinates `(px, py)`.
-    fn rasterize_tile(
-        &self,
-        tile: &Tile,
-        (lx, ly): (usize, usize),
-        (px, py): (usize, usize),
-        vbuf: &mut [u8],
-    ) {
-        // Obtain the color of the tile's pixel corresponding to (lx, ly)
-        let pixel = tile.pixel((lx & 0x07) as u8, (ly & 0x7) as u8);
-        let shade = self.get_shade(self.bgp_reg.0, pixel);
+    /// Starts drawing line `ly`, selecting the sprites which appear on it.
+    fn start_line(&mut self, ly: u8) {
+        let height = if self.lcdc_reg.contains(LCDC::OBJ_SIZE) {
+            16
+        } else {
+            8
+        };
+
+        // The OAM scan selects the first 10 sprites (in OAM order) overlapping the line,
+        // regardless of their X coordinate, so even off-screen ones count to the limit.
+        let mut sprites: Vec<usize> = (0..self.oam.len())
+            .filter(|&i| {
+                let row = i16::from(ly) - (i16::from(self.oam[i].y) - 16);
+                row >= 0 && row < height
+            })
+            .take(MAX_SPRITES_PER_LINE)
+            .collect();
+
+        // On DMG, the sprite with the lowest X coordinate is drawn on top, so make sure
+        // it is fetched first. Ties are won by the one which comes first in OAM.
+        if !self.sprite_prio_by_oam_index() {
+            let oam = &self.oam;
+            sprites.sort_by_key(|&i| oam[i].x);
+        }
+
+        // WY is only compared at the beginning of each line: once it matches, the Window
+        // can be shown for the rest of the frame, even if WY is changed afterwards.
+        if ly == self.wy_reg.0 {
+            self.win_y_hit = true;
+        }
+
+        self.line_regs[usize::from(ly)] = LineRegisters {
+            scx: self.scx_reg.0,
+            scy: self.scy_reg.0,
+            wx: self.wx_reg.0,
+            wy: self.wy_reg.0,
+            bgp: self.bgp_reg.0,
+        };
 
-        // Compute the index in the video buffer
-        let pid = (py as usize) * 160 * 4 + (px as usize) * 4;
+        self.fifo = PixelFifo::new(self.scx_reg.0 & 0x07, sprites);
+    }
 
-        vbuf[pid] = shade;
-        vbuf[pid + 1] = shade;
-        vbuf[pid + 2] = shade;
+    /// Returns true if the Window is displayed on the current line.
+    fn window_visible(&self) -> bool {
+        self.lcdc_reg.contains(LCDC::WIN_DISP_EN) && self.win_y_hit && self.wx_reg.0 <= 166
     }
 
-    /// Rasterizes any visible sprite to the video buffer.
-    fn rasterize_sprites(&self, vbuf: &mut [u8]) {
-        // Do nothing if sprite displaying is disabled
-        if !self.lcdc_reg.contains(LCDC::OBJ_DISP_EN) {
+    /// Advances the pixel FIFO and fetcher by a single dot.
+    fn fifo_dot(&mut self, ly: u8) {
+        if self.fifo.done {
             return;
         }
 
-        let is_8x16 = self.lcdc_reg.contains(LCDC::OBJ_SIZE);
+        // Nothing is shifted out while the first tile or a sprite are being fetched
+        if self.fifo.stall > 0 {
+            self.fifo.stall -= 1;
+            return;
+        }
 
-        for sprite in self.oam.iter() {
-            let y = i16::from(sprite.y) - 16;
-            let x = i16::from(sprite.x) - 8;
-            let attr = sprite.attributes;
+        // Switch to the Window as soon as its first pixel is reached
+        if !self.fifo.window && self.window_visible() && self.fifo.lx + 7 >= self.wx_reg.0 {
+            self.fifo.window = true;
+            self.fifo.bg.clear();
+            self.fifo.discard = 7u8.saturating_sub(self.wx_reg.0);
+            self.fifo.restart_fetch();
+        }
 
-            // In 8x16 mode, the upper 8x8 tile is "tid & 0xFE",
-            // and the lower 8x8 tile is "tid | 0x01".
-            let tile = if is_8x16 {
-                self.get_sprite_tile((sprite.tid & 0xFE).into())
+        // Sprites are fetched when their first pixel is reached
+        if self.fifo.pending_sprite.is_none() && self.lcdc_reg.contains(LCDC::OBJ_DISP_EN) {
+            let (lx, oam) = (self.fifo.lx, &self.oam);
+
+            if let Some(pos) = self
+                .fifo
+                .sprites
+                .iter()
+                .position(|&i| oam[i].fetch_x() == Some(lx))
+            {
+                self.fifo.pending_sprite = Some(self.fifo.sprites.remove(pos));
+            }
+        }
+
+        // A sprite fetch waits for the fetcher to have a BG tile ready, then takes 6 dots
+        if let Some(i) = self.fifo.pending_sprite {
+            if self.fifo.step == FetcherStep::Push {
+                self.fifo.pending_sprite = None;
+                self.fifo.stall = 5;
+                self.merge_sprite(i, ly);
             } else {
-                self.get_sprite_tile(sprite.tid.into())
-            };
+                self.fetcher_dot(ly);
+            }
+            return;
+        }
+
+        self.fetcher_dot(ly);
+
+        // Shift a pixel out to the LCD
+        let bg = match self.fifo.bg.pop_front() {
+            Some(bg) => bg,
+            None => return,
+        };
+
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return;
+        }
+
+        let obj = self.fifo.obj.pop_front();
+        let color = self.mix_pixel(bg, obj);
 
-            self.rasterize_sprite(tile, x, y, attr, vbuf);
+        put_pixel(
+            &mut self.framebuffer,
+            160,
+            usize::from(self.fifo.lx),
+            usize::from(ly),
+            color,
+        );
 
-            // In 8x16 mode, rasterize the lower sprite too
-            if is_8x16 {
-                let tile = self.get_sprite_tile((sprite.tid | 0x01).into());
+        self.fifo.lx += 1;
+        if self.fifo.lx == 160 {
+            self.fifo.done = true;
 
-                self.rasterize_sprite(tile, x, y + 8, attr, vbuf);
+            if self.fifo.window {
+                self.win_line += 1;
             }
         }
     }
 
-    /// Rasterizes a single sprite to screen at coordinates `(x,y)`.
-    fn rasterize_sprite(
-        &self,
-        tile: &Tile,
-        x: i16,
-        y: i16,
-        attr: SpriteAttributes,
-        vbuf: &mut [u8],
-    ) {
-        // The palette used in rasterizing the srpite depends on its attributes
-        let palette = if attr.contains(SpriteAttributes::PAL_NUM) {
-            self.obp1_reg.0
+    /// Advances the pixel fetcher by a single dot.
+    fn fetcher_dot(&mut self, ly: u8) {
+        let step = self.fifo.step;
+
+        if step == FetcherStep::Push {
+            if self.fifo.bg.is_empty() {
+                let row = self.fifo.row;
+
+                self.fifo.bg.extend(row.iter().cloned());
+                self.fifo.fetch_x = self.fifo.fetch_x.wrapping_add(1);
+                self.fifo.step = FetcherStep::Tile;
+            }
+            return;
+        }
+
+        self.fifo.step_dots += 1;
+        if self.fifo.step_dots < 2 {
+            return;
+        }
+        self.fifo.step_dots = 0;
+
+        self.fifo.step = match step {
+            FetcherStep::Tile => FetcherStep::DataLow,
+            FetcherStep::DataLow => FetcherStep::DataHigh,
+            FetcherStep::DataHigh => {
+                self.fifo.row = self.fetch_tile_row(ly);
+                FetcherStep::Push
+            }
+            FetcherStep::Push => unreachable!(),
+        };
+    }
+
+    /// Returns the row of BG or Window pixels the fetcher is currently reading.
+    fn fetch_tile_row(&self, ly: u8) -> [BgPixel; 8] {
+        let fetch_x = usize::from(self.fifo.fetch_x);
+
+        let ((tile, attr), y) = if self.fifo.window {
+            let y = usize::from(self.win_line);
+
+            (self.get_win_tile((fetch_x * 8) % 256, y), y)
+        } else {
+            // Wrap to the top-left in case the scroll registers cause any overflows
+            let y = (usize::from(ly) + usize::from(self.scy_reg.0)) % 256;
+            let x = ((usize::from(self.scx_reg.0 >> 3) + fetch_x) % 32) * 8;
+
+            (self.get_bg_tile(x, y), y)
+        };
+
+        // Flip tile (CGB only)
+        let row = if attr.contains(BgAttributes::FLIP_Y) {
+            7 - (y & 0x07)
+        } else {
+            y & 0x07
+        };
+
+        let mut pixels = [BgPixel::default(); 8];
+
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let col = if attr.contains(BgAttributes::FLIP_X) {
+                7 - i
+            } else {
+                i
+            };
+
+            *pixel = BgPixel {
+                color: tile.pixel(col as u8, row as u8),
+                palette: (attr & BgAttributes::PAL_NUM).bits(),
+                prio: attr.contains(BgAttributes::BG_PRIO),
+            };
+        }
+
+        pixels
+    }
+
+    /// Fetches the row of sprite `i` on line `ly`, and merges it into the sprite FIFO.
+    fn merge_sprite(&mut self, i: usize, ly: u8) {
+        let sprite = self.oam[i];
+        let attr = sprite.attributes;
+
+        let height = if self.lcdc_reg.contains(LCDC::OBJ_SIZE) {
+            16
         } else {
-            self.obp0_reg.0
+            8
         };
 
-        // Flip sprite horizontally
-        let off_x = if attr.contains(SpriteAttributes::FLIP_X) {
-            7
+        // Flip sprite vertically. The sprite size may have been changed since the OAM scan.
+        let row = (i16::from(ly) - (i16::from(sprite.y) - 16)) as u8 & (height - 1);
+        let row = if attr.contains(SpriteAttributes::FLIP_Y) {
+            height - 1 - row
         } else {
-            0
+            row
         };
 
-        // Flip sprite vertically
-        let off_y = if attr.contains(SpriteAttributes::FLIP_Y) {
-            7
+        // In 8x16 mode, the upper 8x8 tile is "tid & 0xFE",
+        // and the lower 8x8 tile is "tid | 0x01".
+        let tid = if height == 16 {
+            (sprite.tid & 0xFE) | (row >> 3)
         } else {
-            0
+            sprite.tid
         };
+        let tile = *self.get_sprite_tile(tid.into(), attr);
 
-        // TODO put the sprite behind BG colors 1-3
-        let _behind_bg = attr.contains(SpriteAttributes::BG_PRIO);
+        // In CGB priority mode, sprites which come first in OAM are drawn on top
+        // of the others. Otherwise, the sprite which has been fetched first wins.
+        let by_oam_index = self.sprite_prio_by_oam_index();
 
-        // Clip to currently visible area
-        for py in y.max(0)..(y + 8).min(144) {
-            for px in x.max(0)..(x + 8).min(160) {
-                let x = (off_x - (px - x) as i16).abs() as u8;
-                let y = (off_y - (py - y) as i16).abs() as u8;
+        // Sprites which are partially off-screen to the left are clipped
+        let skip = 8u8.saturating_sub(sprite.x);
 
-                let pixel = tile.pixel(x, y);
-                let shade = self.get_shade(palette, pixel);
+        for (slot, col) in (skip..8).enumerate() {
+            // Flip sprite horizontally
+            let col = if attr.contains(SpriteAttributes::FLIP_X) {
+                7 - col
+            } else {
+                col
+            };
+
+            let pixel = ObjPixel {
+                color: tile.pixel(col, row & 0x07),
+                attr,
+                oam_index: i,
+            };
 
-                let pid = (py as usize) * 160 * 4 + (px as usize) * 4;
+            match self.fifo.obj.get_mut(slot) {
+                Some(cur) => {
+                    let wins = cur.color == 0 || (by_oam_index && i < cur.oam_index);
 
-                if pixel != 0 {
-                    vbuf[pid] = shade;
-                    vbuf[pid + 1] = shade;
-                    vbuf[pid + 2] = shade;
+                    if pixel.color != 0 && wins {
+                        *cur = pixel;
+                    }
                 }
+                None => self.fifo.obj.push_back(pixel),
+            }
+        }
+    }
+
+    /// Returns true if overlapping sprites are prioritized by their OAM index
+    /// rather than by their X coordinate.
+    fn sprite_prio_by_oam_index(&self) -> bool {
+        self.cgb && self.opri_reg.0 & 0x01 == 0
+    }
+
+    /// Returns the color of a BG/Window pixel mixed with the sprite pixel at the same position.
+    fn mix_pixel(&self, bg: BgPixel, obj: Option<ObjPixel>) -> [u8; 3] {
+        // On DMG, clearing LCDC bit 0 blanks both BG and Window
+        let bg_enabled = self.cgb || self.lcdc_reg.contains(LCDC::BG_DISP);
+        let bg = if bg_enabled { bg } else { BgPixel::default() };
+
+        if let Some(obj) = obj {
+            if obj.color != 0
+                && self.lcdc_reg.contains(LCDC::OBJ_DISP_EN)
+                && self.sprite_over_bg(obj.attr, bg)
+            {
+                return self.sprite_color(obj.attr, obj.color);
             }
         }
+
+        if self.cgb {
+            self.bg_pal.color(bg.palette, bg.color)
+        } else if bg_enabled {
+            self.get_shade(self.bgp_reg.0, bg.color)
+        } else {
+            self.dmg_palette.0[0]
+        }
+    }
+
+    /// Returns the color of a sprite pixel, using the palette selected by its attributes.
+    fn sprite_color(&self, attr: SpriteAttributes, pixel: u8) -> [u8; 3] {
+        if self.cgb {
+            self.obj_pal
+                .color((attr & SpriteAttributes::CGB_PAL).bits(), pixel)
+        } else if attr.contains(SpriteAttributes::PAL_NUM) {
+            self.get_shade(self.obp1_reg.0, pixel)
+        } else {
+            self.get_shade(self.obp0_reg.0, pixel)
+        }
+    }
+
+    /// Returns true if a sprite pixel with attributes `attr` is drawn over
+    /// the BG/Window pixel `bg`.
+    fn sprite_over_bg(&self, attr: SpriteAttributes, bg: BgPixel) -> bool {
+        // BG color 0 is always behind sprites, and so is the whole BG
+        // when LCDC bit 0 is cleared in CGB mode.
+        if bg.color == 0 || (self.cgb && !self.lcdc_reg.contains(LCDC::BG_DISP)) {
+            return true;
+        }
+
+        !attr.contains(SpriteAttributes::BG_PRIO) && !bg.prio
     }
 
     /// Update the STAT register and set any relevant interrupts.
     fn tick_stat(&mut self, tstate: u64, v_line: u64) {
-        // Compute current LCD mode
-        let mode = if v_line < 144 {
-            match tstate {
-                0..=79 => STAT::MOD_2,
-                80..=253 => STAT::MOD_3,
-                _ => STAT::MOD_0,
+        // Compute current LCD mode. The length of mode 3 depends on the pixel FIFO.
+        let mode = if v_line >= 144 {
+            STAT::MOD_1
+        } else if tstate < 80 {
+            // The first line after turning the LCD on has no OAM scan
+            if self.blank_frame && v_line == 0 {
+                STAT::MOD_0
+            } else {
+                STAT::MOD_2
             }
+        } else if !self.fifo.done {
+            STAT::MOD_3
         } else {
-            STAT::MOD_1
+            STAT::MOD_0
         };
 
+        // H-Blank starts as soon as mode 3 is over
+        let hblank_start = mode == STAT::MOD_0 && self.stat_reg & STAT::MOD_FLAG == STAT::MOD_3;
+
         let lyc_coinc = self.ly_reg == self.lyc_reg;
+        let stat_irq = self.stat_irq;
 
         // Set STAT interrupt flags depending on the enable bits in STAT
         if self.stat_reg.contains(STAT::LYC_INTR) && lyc_coinc && tstate == 0 {
@@ -524,10 +1430,22 @@ impl PPU {
         if self.stat_reg.contains(STAT::VBK_INTR) && v_line == 144 && tstate == 0 {
             self.stat_irq |= STATIRQ::VBK;
         }
-        if self.stat_reg.contains(STAT::HBK_INTR) && mode == STAT::MOD_0 && tstate == 256 {
+        if self.stat_reg.contains(STAT::HBK_INTR) && hblank_start {
             self.stat_irq |= STATIRQ::HBK;
         }
 
+        if self.timeline.is_some() {
+            if mode != self.stat_reg & STAT::MOD_FLAG {
+                self.record_event(v_line, tstate, dbg::PpuEventKind::Mode(mode.bits()));
+            }
+            if lyc_coinc && !self.stat_reg.contains(STAT::LYC_FLAG) {
+                self.record_event(v_line, tstate, dbg::PpuEventKind::LycMatch);
+            }
+            if self.stat_irq != stat_irq {
+                self.record_event(v_line, tstate, dbg::PpuEventKind::StatIrq);
+            }
+        }
+
         // Update coincidence flag
         if lyc_coinc {
             self.stat_reg |= STAT::LYC_FLAG;
@@ -539,6 +1457,13 @@ impl PPU {
         self.stat_reg = (self.stat_reg & !STAT::MOD_FLAG) | mode;
     }
 
+    /// Records an event in the timeline, if enabled.
+    fn record_event(&mut self, v_line: u64, tstate: u64, kind: dbg::PpuEventKind) {
+        if let Some(ref mut tl) = self.timeline {
+            tl.record(v_line as u8, tstate as u16, kind);
+        }
+    }
+
     /// Queues a new DMA transfer from RAM or ROM to OAM.
     ///
     /// A DMA transfer lasts 160 cycles, during which the CPU can only access HRAM.
@@ -556,27 +1481,21 @@ impl PPU {
         self.dma_xfer_queue[1] = Some(DMATransfer::new(u16::from(val) << 8));
     }
 
-    /// Returns the actual gray shade associated with a pixel value in a palette.
-    fn get_shade(&self, palette: u8, pixel: u8) -> u8 {
-        match (palette >> (pixel * 2)) & 0x3 {
-            0b00 => 0xFF, // White
-            0b01 => 0xAA, // Light gray
-            0b10 => 0x55, // Dark gray
-            0b11 => 0x00, // Black
-            _ => unreachable!(),
-        }
+    /// Returns the color of the shade associated with a pixel value in a monochrome palette.
+    fn get_shade(&self, palette: u8, pixel: u8) -> [u8; 3] {
+        self.dmg_palette.0[usize::from((palette >> (pixel * 2)) & 0x3)]
     }
 
-    /// Returns the BG tile corresponding to the given ID.
-    fn get_bg_tile(&self, x: usize, y: usize) -> &Tile {
+    /// Returns the BG tile corresponding to the given ID, along with its attributes.
+    fn get_bg_tile(&self, x: usize, y: usize) -> (&Tile, BgAttributes) {
         self.get_bg_win_tile(
             ((y >> 3) << 5) + (x >> 3), // coords to 8x8 tile ID
             self.lcdc_reg.contains(LCDC::BG_DISP_SEL),
         )
     }
 
-    /// Returns the Window tile corresponding to the given ID.
-    fn get_win_tile(&self, x: usize, y: usize) -> &Tile {
+    /// Returns the Window tile corresponding to the given ID, along with its attributes.
+    fn get_win_tile(&self, x: usize, y: usize) -> (&Tile, BgAttributes) {
         self.get_bg_win_tile(
             ((y >> 3) << 5) + (x >> 3), // coords to 8x8 tile ID
             self.lcdc_reg.contains(LCDC::WIN_DISP_SEL),
@@ -586,25 +1505,33 @@ impl PPU {
     /// Returns the BG or Window tile corresponding to the given ID.
     ///
     /// The resulting Tile depends on the selected BG/Window Tile Map
-    /// and addressing mode in LCDC register.
-    fn get_bg_win_tile(&self, id: usize, disp_sel: bool) -> &Tile {
-        let tile_id = if disp_sel {
-            self.bgtm1[id]
+    /// and addressing mode in LCDC register. In CGB mode, the attributes
+    /// in VRAM bank 1 also select the bank holding the tile data.
+    fn get_bg_win_tile(&self, id: usize, disp_sel: bool) -> (&Tile, BgAttributes) {
+        let tile_map = if disp_sel { &self.bgtm1 } else { &self.bgtm0 };
+
+        let tile_id = tile_map[0][id];
+        let attr = if self.cgb {
+            BgAttributes::from_bits_truncate(tile_map[1][id])
         } else {
-            self.bgtm0[id]
+            BgAttributes::empty()
         };
+        let bank = &self.tdt[usize::from(attr.contains(BgAttributes::VRAM_BANK))];
 
-        if self.lcdc_reg.contains(LCDC::BG_WIN_DATA_SEL) {
-            &self.tdt[usize::from(tile_id)]
+        let tile = if self.lcdc_reg.contains(LCDC::BG_WIN_DATA_SEL) {
+            &bank[usize::from(tile_id)]
         } else {
-            &self.tdt[(256 + i32::from(tile_id as i8)) as usize]
-        }
+            &bank[(256 + i32::from(tile_id as i8)) as usize]
+        };
+
+        (tile, attr)
     }
 
     /// Returns the sprite tile corresponding to the given ID.
-    fn get_sprite_tile(&self, id: usize) -> &Tile {
-        // TODO support loading 8x16 sprites
-        &self.tdt[id]
+    fn get_sprite_tile(&self, id: usize, attr: SpriteAttributes) -> &Tile {
+        let bank = self.cgb && attr.contains(SpriteAttributes::VRAM_BANK);
+
+        &self.tdt[usize::from(bank)][id]
     }
 }
 
@@ -624,16 +1551,24 @@ impl InterruptSource for PPU {
 }
 
 impl MemR for PPU {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // OAM is always visible to the debugger, even during DMA transfers
+            0xFE00..=0xFE9F => (&self.oam[..]).peek(addr - 0xFE00),
+            _ => self.read(addr).unwrap_or(0xFF),
+        }
+    }
+
     fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
         Ok(match addr {
             0x8000..=0x97FF => {
                 let addr = addr - 0x8000;
                 let tid = usize::from(addr >> 4);
                 let bid = usize::from(addr & 0xF);
-                self.tdt[tid].data()[bid]
+                self.tdt[self.vram_bank()][tid].data()[bid]
             }
-            0x9800..=0x9BFF => self.bgtm0[usize::from(addr - 0x9800)],
-            0x9C00..=0x9FFF => self.bgtm1[usize::from(addr - 0x9C00)],
+            0x9800..=0x9BFF => self.bgtm0[self.vram_bank()][usize::from(addr - 0x9800)],
+            0x9C00..=0x9FFF => self.bgtm1[self.vram_bank()][usize::from(addr - 0x9C00)],
 
             0xFE00..=0xFE9F => {
                 if self.dma_xfer.is_none() {
@@ -657,6 +1592,12 @@ impl MemR for PPU {
             0xFF49 => self.obp1_reg.0,
             0xFF4A => self.wy_reg.0,
             0xFF4B => self.wx_reg.0,
+            0xFF4F => self.vbk_reg.0 | 0xFE,
+            0xFF68 => self.bg_pal.read_spec(),
+            0xFF69 => self.bg_pal.read_data(),
+            0xFF6A => self.obj_pal.read_spec(),
+            0xFF6B => self.obj_pal.read_data(),
+            0xFF6C => self.opri_reg.0 | 0xFE,
 
             _ => unreachable!(),
         })
@@ -664,16 +1605,26 @@ impl MemR for PPU {
 }
 
 impl MemW for PPU {
+    fn poke(&mut self, addr: u16, val: u8) {
+        match addr {
+            0xFE00..=0xFE9F => self.write_to_oam(addr, val).unwrap_or(()),
+            0xFF44 => self.ly_reg.0 = val,
+            // Don't start a DMA transfer
+            0xFF46 => self.dma_reg.0 = val,
+            _ => self.write(addr, val).unwrap_or(()),
+        }
+    }
+
     fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
         match addr {
             0x8000..=0x97FF => {
                 let addr = addr - 0x8000;
                 let tid = usize::from(addr >> 4);
                 let bid = usize::from(addr & 0xF);
-                self.tdt[tid].data_mut()[bid] = val;
+                self.tdt[self.vram_bank()][tid].data_mut()[bid] = val;
             }
-            0x9800..=0x9BFF => self.bgtm0[usize::from(addr - 0x9800)] = val,
-            0x9C00..=0x9FFF => self.bgtm1[usize::from(addr - 0x9C00)] = val,
+            0x9800..=0x9BFF => self.bgtm0[self.vram_bank()][usize::from(addr - 0x9800)] = val,
+            0x9C00..=0x9FFF => self.bgtm1[self.vram_bank()][usize::from(addr - 0x9C00)] = val,
 
             0xFE00..=0xFE9F => {
                 // OAM is accessible only if no DMA transfer is in progress
@@ -682,7 +1633,17 @@ impl MemW for PPU {
                 }
             }
 
-            0xFF40 => (&mut self.lcdc_reg).write(0, val)?,
+            0xFF40 => {
+                let was_on = self.lcdc_reg.contains(LCDC::DISP_EN);
+
+                (&mut self.lcdc_reg).write(0, val)?;
+
+                match (was_on, self.lcdc_reg.contains(LCDC::DISP_EN)) {
+                    (true, false) => self.turn_lcd_off(),
+                    (false, true) => self.turn_lcd_on(),
+                    _ => (),
+                }
+            }
             0xFF41 => (&mut self.stat_reg).write(0, val)?,
             0xFF42 => self.scy_reg.0 = val,
             0xFF43 => self.scx_reg.0 = val,
@@ -694,6 +1655,12 @@ impl MemW for PPU {
             0xFF49 => self.obp1_reg.0 = val,
             0xFF4A => self.wy_reg.0 = val,
             0xFF4B => self.wx_reg.0 = val,
+            0xFF4F => self.vbk_reg.0 = val & 0x01,
+            0xFF68 => self.bg_pal.write_spec(val),
+            0xFF69 => self.bg_pal.write_data(val),
+            0xFF6A => self.obj_pal.write_spec(val),
+            0xFF6B => self.obj_pal.write_data(val),
+            0xFF6C => self.opri_reg.0 = val & 0x01,
 
             _ => unreachable!(),
         };
@@ -701,3 +1668,52 @@ impl MemW for PPU {
         Ok(())
     }
 }
+
+savestate_fields!(
+    PPU where PPU::state_in_range,
+    tdt,
+    oam,
+    bgtm0,
+    bgtm1,
+    vbk_reg,
+    cgb,
+    bg_pal,
+    obj_pal,
+    opri_reg,
+    lcdc_reg,
+    stat_reg,
+    stat_irq,
+    scx_reg,
+    scy_reg,
+    lyc_reg,
+    ly_reg,
+    wy_reg,
+    wx_reg,
+    obp0_reg,
+    obp1_reg,
+    bgp_reg,
+    dma_reg,
+    dma_xfer,
+    dma_xfer_queue,
+    framebuffer,
+    frontbuffer,
+    frame_ready,
+    blank_frame,
+    fifo,
+    win_line,
+    win_y_hit,
+    line_regs,
+    tstate,
+    vblank_irq_pending,
+);
+
+/// Writes an RGB color to the pixel at `(px, py)` in a U8U8U8U8 video buffer
+/// which is `width` pixels wide.
+fn put_pixel(vbuf: &mut [u8], width: usize, px: usize, py: usize, [r, g, b]: [u8; 3]) {
+    let pid = (py * width + px) * 4;
+
+    vbuf[pid] = r;
+    vbuf[pid + 1] = g;
+    vbuf[pid + 2] = b;
+    vbuf[pid + 3] = 0xFF;
+}
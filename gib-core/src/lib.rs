@@ -1,11 +1,27 @@
 #![feature(try_from)]
 
+#[macro_use]
+pub mod savestate;
+
 pub mod bus;
+pub mod cart;
 pub mod cpu;
 pub mod dbg;
 pub mod io;
 pub mod mem;
 
+mod cheat;
+mod config;
+mod error;
+mod events;
 mod gameboy;
+mod movie;
+mod testrom;
 
+pub use cheat::{Cheat, CheatCode, Cheats};
+pub use config::*;
+pub use error::Error;
+pub use events::{Event, EventKinds, SubscriptionId};
 pub use gameboy::*;
+pub use movie::{Movie, MovieMode, MOVIE_FRAME_CYCLES};
+pub use testrom::{run_test_rom, TestOutcome};
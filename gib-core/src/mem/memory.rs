@@ -1,7 +1,8 @@
 use super::dbg;
 use super::{MemR, MemRW, MemW};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Memory {
     data: Vec<u8>,
 }
@@ -28,3 +29,91 @@ impl MemW for Memory {
 }
 
 impl MemRW for Memory {}
+
+/// The size of memory is fixed by the hardware or the cartridge, so states
+/// of a different size are rejected rather than resizing it.
+impl Savestate for Memory {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.data.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        let mut len = 0u32;
+        len.load_state(r)?;
+
+        if len as usize != self.data.len() {
+            return Err(dbg::TraceEvent::InvalidSavestate("memory size"));
+        }
+
+        let bytes = r.read_bytes(self.data.len())?;
+        self.data.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Memory split into equally-sized banks, only one of which is accessible at a time.
+#[derive(Clone)]
+pub struct BankedMemory {
+    banks: Vec<Memory>,
+    bank: usize,
+}
+
+impl BankedMemory {
+    pub fn new(bank_size: u16, count: usize) -> BankedMemory {
+        BankedMemory {
+            banks: vec![Memory::new(bank_size); count],
+            bank: 0,
+        }
+    }
+
+    /// Selects the bank to be accessed, wrapping around the number of banks.
+    pub fn select(&mut self, bank: usize) {
+        self.bank = bank % self.banks.len();
+    }
+
+    /// Returns the currently selected bank.
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+}
+
+impl MemR for BankedMemory {
+    fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
+        self.banks[self.bank].read(addr)
+    }
+}
+
+impl MemW for BankedMemory {
+    fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
+        self.banks[self.bank].write(addr, val)
+    }
+}
+
+impl MemRW for BankedMemory {}
+
+impl Savestate for BankedMemory {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.banks.save_state(w);
+        self.bank.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        let mut count = 0u32;
+        count.load_state(r)?;
+
+        if count as usize != self.banks.len() {
+            return Err(dbg::TraceEvent::InvalidSavestate("memory banks"));
+        }
+
+        for bank in self.banks.iter_mut() {
+            bank.load_state(r)?;
+        }
+        self.bank.load_state(r)?;
+
+        if self.bank < self.banks.len() {
+            Ok(())
+        } else {
+            Err(dbg::TraceEvent::InvalidSavestate("memory bank"))
+        }
+    }
+}
@@ -6,10 +6,22 @@ pub use memory::*;
 
 pub trait MemR {
     fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent>;
+
+    /// Reads a value for debugging purposes, without any side effect or access restriction.
+    /// Locations which cannot be read yield 0xFF.
+    fn peek(&self, addr: u16) -> u8 {
+        self.read(addr).unwrap_or(0xFF)
+    }
 }
 
 pub trait MemW {
     fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent>;
+
+    /// Writes a value for debugging purposes, without any side effect or access restriction.
+    /// Writes to locations which cannot be written are ignored.
+    fn poke(&mut self, addr: u16, val: u8) {
+        self.write(addr, val).unwrap_or(());
+    }
 }
 
 pub trait MemRW: MemR + MemW {}
@@ -0,0 +1,178 @@
+//! Input movies: the joypad state of every frame, recorded from a savestate
+//! so that the same run can be replayed deterministically.
+//!
+//! While a movie is active, joypad inputs only change at frame boundaries, which
+//! are counted in clock cycles from the initial state rather than following the LCD.
+
+use super::dbg;
+use super::io::JoypadState;
+use super::savestate::{Savestate, StateReader, StateWriter};
+
+/// Signature at the beginning of every movie file.
+const MOVIE_MAGIC: &[u8; 4] = b"GIBM";
+
+/// Version of the movie format, to be bumped whenever its layout changes.
+const MOVIE_VERSION: u16 = 1;
+
+/// Number of clock cycles in a movie frame, the same as a video frame.
+pub const MOVIE_FRAME_CYCLES: u64 = 70_224;
+
+/// A recorded movie: the initial savestate, followed by the joypad state of every frame.
+#[derive(Default, Clone)]
+pub struct Movie {
+    state: Vec<u8>,
+    inputs: Vec<u8>,
+}
+
+impl Movie {
+    /// Creates an empty movie starting from `state`, as returned by `GameBoy::save_state`.
+    pub fn new(state: Vec<u8>) -> Movie {
+        Movie {
+            state,
+            inputs: vec![],
+        }
+    }
+
+    /// Returns the savestate the movie starts from.
+    pub fn initial_state(&self) -> &[u8] {
+        &self.state
+    }
+
+    /// Returns the number of recorded frames.
+    pub fn frames(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Returns the joypad state recorded for `frame`.
+    pub fn input(&self, frame: usize) -> Option<JoypadState> {
+        self.inputs
+            .get(frame)
+            .map(|bits| JoypadState::from_bits_truncate(*bits))
+    }
+
+    /// Appends the joypad state of the next frame.
+    pub fn push_input(&mut self, input: JoypadState) {
+        self.inputs.push(input.bits());
+    }
+
+    /// Serializes the movie, eg. to write it to a file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+
+        w.write_bytes(MOVIE_MAGIC);
+        MOVIE_VERSION.save_state(&mut w);
+        self.state.save_state(&mut w);
+        self.inputs.save_state(&mut w);
+
+        w.into_inner()
+    }
+
+    /// Parses a movie serialized by `to_bytes`. The initial state is only
+    /// validated once the movie is played.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, dbg::TraceEvent> {
+        let mut r = StateReader::new(bytes);
+        let mut movie = Movie::default();
+
+        if r.read_bytes(MOVIE_MAGIC.len())? != MOVIE_MAGIC {
+            return Err(dbg::TraceEvent::InvalidMovie("bad signature"));
+        }
+
+        let mut version = 0u16;
+        version.load_state(&mut r)?;
+
+        if version != MOVIE_VERSION {
+            return Err(dbg::TraceEvent::InvalidMovie("unsupported version"));
+        }
+
+        movie.state.load_state(&mut r)?;
+        movie.inputs.load_state(&mut r)?;
+
+        Ok(movie)
+    }
+}
+
+/// Whether a movie is being recorded or played back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+}
+
+/// A movie being recorded or played back, along with the host inputs
+/// waiting for the next frame boundary.
+pub struct ActiveMovie {
+    pub movie: Movie,
+    pub mode: MovieMode,
+
+    /// Current frame, and the clock cycle at which the next one starts
+    pub frame: usize,
+    pub next_frame: u64,
+
+    /// Buttons held by the host, either normally or with autofire
+    pub held: JoypadState,
+    pub autofire: JoypadState,
+}
+
+impl ActiveMovie {
+    pub fn new(movie: Movie, mode: MovieMode, cycles: u64) -> ActiveMovie {
+        ActiveMovie {
+            movie,
+            mode,
+            frame: 0,
+            next_frame: cycles,
+            held: JoypadState::empty(),
+            autofire: JoypadState::empty(),
+        }
+    }
+
+    /// Returns the joypad state of the current frame while recording, with autofire
+    /// buttons pressed for `autofire_period` frames and then released for as many.
+    pub fn host_input(&self, autofire_period: u8) -> JoypadState {
+        let period = usize::from(autofire_period.max(1));
+        let mut pressed = self.held;
+
+        if (self.frame / period).is_multiple_of(2) {
+            pressed |= self.autofire;
+        }
+
+        // Joypad states are active low
+        !pressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn movies_round_trip() {
+        let mut movie = Movie::new(vec![1, 2, 3]);
+        movie.push_input(JoypadState::DEFAULT);
+        movie.push_input(!JoypadState::A);
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+
+        assert_eq!(movie.initial_state(), &[1, 2, 3]);
+        assert_eq!(movie.frames(), 2);
+        assert_eq!(movie.input(1), Some(!JoypadState::A));
+        assert_eq!(movie.input(2), None);
+        assert!(Movie::from_bytes(b"GIBS").is_err());
+    }
+
+    #[test]
+    fn autofire_alternates_every_period() {
+        let mut movie = ActiveMovie::new(Movie::default(), MovieMode::Recording, 0);
+        movie.held = JoypadState::B;
+        movie.autofire = JoypadState::A;
+
+        let inputs: Vec<_> = (0..6)
+            .map(|frame| {
+                movie.frame = frame;
+                movie.host_input(2)
+            })
+            .collect();
+
+        let (down, up) = (!(JoypadState::A | JoypadState::B), !JoypadState::B);
+        assert_eq!(inputs, vec![down, down, up, up, down, down]);
+    }
+}
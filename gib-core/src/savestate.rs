@@ -0,0 +1,345 @@
+//! Serialization of the machine state, in a simple versioned binary format.
+//!
+//! Each component writes its fields in a fixed order, as little endian values,
+//! and reads them back in the same order. Debugging facilities, host-side
+//! settings and ROM contents are not part of the state.
+
+use super::dbg;
+
+use std::collections::VecDeque;
+
+/// Implements `Savestate` for a struct, by saving the given fields in order.
+///
+/// With `Type where check, ...`, the loaded state is rejected unless `check(&self)`
+/// holds, eg. to make sure that indices read from the state are in range.
+macro_rules! savestate_fields {
+    ($ty:ty where $check:path, $($field:tt),* $(,)*) => {
+        impl $crate::savestate::Savestate for $ty {
+            fn save_state(&self, w: &mut $crate::savestate::StateWriter) {
+                $($crate::savestate::Savestate::save_state(&self.$field, w);)*
+            }
+
+            fn load_state(
+                &mut self,
+                r: &mut $crate::savestate::StateReader,
+            ) -> Result<(), $crate::dbg::TraceEvent> {
+                $($crate::savestate::Savestate::load_state(&mut self.$field, r)?;)*
+
+                if $check(self) {
+                    Ok(())
+                } else {
+                    Err($crate::dbg::TraceEvent::InvalidSavestate(concat!(
+                        "out of range ",
+                        stringify!($ty),
+                        " state"
+                    )))
+                }
+            }
+        }
+    };
+    ($ty:ty, $($field:tt),* $(,)*) => {
+        impl $crate::savestate::Savestate for $ty {
+            fn save_state(&self, w: &mut $crate::savestate::StateWriter) {
+                $($crate::savestate::Savestate::save_state(&self.$field, w);)*
+            }
+
+            fn load_state(
+                &mut self,
+                r: &mut $crate::savestate::StateReader,
+            ) -> Result<(), $crate::dbg::TraceEvent> {
+                $($crate::savestate::Savestate::load_state(&mut self.$field, r)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Implements `Savestate` for bitflags!-generated structs, by saving their raw bits.
+macro_rules! savestate_bits {
+    ($($ty:ident),* $(,)*) => {
+        $(
+            impl $crate::savestate::Savestate for $ty {
+                fn save_state(&self, w: &mut $crate::savestate::StateWriter) {
+                    $crate::savestate::Savestate::save_state(&self.bits, w);
+                }
+
+                fn load_state(
+                    &mut self,
+                    r: &mut $crate::savestate::StateReader,
+                ) -> Result<(), $crate::dbg::TraceEvent> {
+                    $crate::savestate::Savestate::load_state(&mut self.bits, r)
+                }
+            }
+        )*
+    };
+}
+
+/// Version of the format, to be bumped whenever the saved fields change.
+pub const FORMAT_VERSION: u16 = 3;
+
+/// Buffer the state of the machine gets serialized to.
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter::default()
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Returns the serialized state.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Cursor over a serialized state.
+pub struct StateReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(buf: &'a [u8]) -> StateReader<'a> {
+        StateReader { buf }
+    }
+
+    /// Reads the next `n` bytes, failing if the state is truncated.
+    pub fn read_bytes(&mut self, n: usize) -> Result<&'a [u8], dbg::TraceEvent> {
+        if self.buf.len() < n {
+            return Err(dbg::TraceEvent::InvalidSavestate("truncated"));
+        }
+
+        let (bytes, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    /// Returns true if the whole state has been read.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+}
+
+/// A component whose state can be saved and restored.
+///
+/// The state is loaded in place, so that the parts which are not saved
+/// (eg. the ROM, or debugging facilities) are left untouched.
+pub trait Savestate {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent>;
+}
+
+macro_rules! savestate_primitives {
+    ($($ty:ty: $size:expr),* $(,)*) => {
+        $(
+            impl Savestate for $ty {
+                fn save_state(&self, w: &mut StateWriter) {
+                    w.write_bytes(&self.to_le_bytes());
+                }
+
+                fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+                    let mut bytes = [0; $size];
+                    bytes.copy_from_slice(r.read_bytes($size)?);
+                    *self = <$ty>::from_le_bytes(bytes);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+savestate_primitives!(u8: 1, u16: 2, u32: 4, u64: 8, i16: 2, i32: 4);
+
+impl Savestate for bool {
+    fn save_state(&self, w: &mut StateWriter) {
+        u8::from(*self).save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        let mut v = 0u8;
+        v.load_state(r)?;
+        *self = v != 0;
+        Ok(())
+    }
+}
+
+impl Savestate for usize {
+    fn save_state(&self, w: &mut StateWriter) {
+        (*self as u64).save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        let mut v = 0u64;
+        v.load_state(r)?;
+        *self = v as usize;
+        Ok(())
+    }
+}
+
+impl Savestate for f32 {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.to_bits().save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        let mut v = 0u32;
+        v.load_state(r)?;
+        *self = f32::from_bits(v);
+        Ok(())
+    }
+}
+
+macro_rules! savestate_arrays {
+    ($($n:expr),* $(,)*) => {
+        $(
+            impl<T: Savestate> Savestate for [T; $n] {
+                fn save_state(&self, w: &mut StateWriter) {
+                    for v in self.iter() {
+                        v.save_state(w);
+                    }
+                }
+
+                fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+                    for v in self.iter_mut() {
+                        v.load_state(r)?;
+                    }
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+savestate_arrays!(2, 3, 8, 16, 40, 64, 144, 384, 1024);
+
+impl<T: Savestate + Default> Savestate for Option<T> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.is_some().save_state(w);
+
+        if let Some(v) = self {
+            v.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        let mut some = false;
+        some.load_state(r)?;
+
+        *self = if some {
+            let mut v = T::default();
+            v.load_state(r)?;
+            Some(v)
+        } else {
+            None
+        };
+        Ok(())
+    }
+}
+
+impl<T: Savestate + ?Sized> Savestate for Box<T> {
+    fn save_state(&self, w: &mut StateWriter) {
+        (**self).save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        (**self).load_state(r)
+    }
+}
+
+impl<T: Savestate + Default> Savestate for Vec<T> {
+    fn save_state(&self, w: &mut StateWriter) {
+        (self.len() as u32).save_state(w);
+
+        for v in self.iter() {
+            v.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        let mut len = 0u32;
+        len.load_state(r)?;
+
+        self.clear();
+        for _ in 0..len {
+            let mut v = T::default();
+            v.load_state(r)?;
+            self.push(v);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Savestate + Default> Savestate for VecDeque<T> {
+    fn save_state(&self, w: &mut StateWriter) {
+        (self.len() as u32).save_state(w);
+
+        for v in self.iter() {
+            v.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), dbg::TraceEvent> {
+        let mut len = 0u32;
+        len.load_state(r)?;
+
+        self.clear();
+        for _ in 0..len {
+            let mut v = T::default();
+            v.load_state(r)?;
+            self.push_back(v);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip() {
+        let mut w = StateWriter::new();
+
+        0xABu8.save_state(&mut w);
+        0x1234u16.save_state(&mut w);
+        (-2i16).save_state(&mut w);
+        true.save_state(&mut w);
+        1.5f32.save_state(&mut w);
+        Some(7usize).save_state(&mut w);
+        vec![1u8, 2, 3].save_state(&mut w);
+
+        let buf = w.into_inner();
+        let mut r = StateReader::new(&buf[..]);
+
+        let (mut a, mut b, mut c, mut d, mut e) = (0u8, 0u16, 0i16, false, 0f32);
+        let (mut f, mut g) = (None::<usize>, Vec::<u8>::new());
+
+        a.load_state(&mut r).unwrap();
+        b.load_state(&mut r).unwrap();
+        c.load_state(&mut r).unwrap();
+        d.load_state(&mut r).unwrap();
+        e.load_state(&mut r).unwrap();
+        f.load_state(&mut r).unwrap();
+        g.load_state(&mut r).unwrap();
+
+        assert_eq!((a, b, c, d, e), (0xAB, 0x1234, -2, true, 1.5));
+        assert_eq!((f, g), (Some(7), vec![1, 2, 3]));
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn truncated_states_are_rejected() {
+        let mut r = StateReader::new(&[0x34]);
+        let mut v = 0u16;
+
+        match v.load_state(&mut r) {
+            Err(dbg::TraceEvent::InvalidSavestate(_)) => (),
+            _ => panic!("truncated state accepted"),
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Headless runner for the test ROMs of the common suites, detecting how each one
+//! reports its result.
+
+use super::gameboy::{GameBoy, CYCLES_PER_FRAME};
+use super::mem::MemR;
+
+use std::fmt;
+
+/// Result of running a test ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestOutcome {
+    Pass,
+    /// The ROM reported a failure, with its output if any
+    Fail(String),
+    /// The ROM didn't report a result within the cycle budget
+    Timeout,
+    /// The emulator stopped, eg. on an illegal instruction
+    Error(String),
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        *self == TestOutcome::Pass
+    }
+}
+
+impl fmt::Display for TestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TestOutcome::Pass => write!(f, "pass"),
+            TestOutcome::Fail(_) => write!(f, "FAIL"),
+            TestOutcome::Timeout => write!(f, "timeout"),
+            TestOutcome::Error(_) => write!(f, "error"),
+        }
+    }
+}
+
+/// Runs `rom` from power-on until it reports a result, for at most `budget` cycles:
+///
+/// * Blargg's ROMs print `Passed` or `Failed` over the link port, or to cartridge RAM
+/// * mooneye-gb's ROMs execute `LD B,B`, with 3/5/8/13/21/34 in B/C/D/E/H/L on success
+/// * dmg-acid2 executes `LD B,B` too, after which the frame is compared with `reference`,
+///   160x144 RGBA pixels with the default grayscale palette
+pub fn run_test_rom(rom: &[u8], reference: Option<&[u8]>, budget: u64) -> TestOutcome {
+    let mut gb = GameBoy::new();
+
+    if let Err(e) = gb.load_rom(rom) {
+        return TestOutcome::Error(e.to_string());
+    }
+
+    let mut next_check = CYCLES_PER_FRAME;
+
+    while gb.clock_cycles() < budget {
+        if let Err(e) = gb.step() {
+            return TestOutcome::Error(e.to_string());
+        }
+
+        let cpu = gb.cpu();
+
+        if cpu.opcode == 0x40 && !cpu.cb_mode {
+            return match reference {
+                Some(reference) => compare_frame(&mut gb, reference),
+                None => check_registers(&gb),
+            };
+        }
+
+        // Blargg's output is only checked once per frame, as it is slower to look for
+        if gb.clock_cycles() >= next_check {
+            next_check += CYCLES_PER_FRAME;
+
+            if let Some(outcome) = blargg_result(&gb) {
+                return outcome;
+            }
+        }
+    }
+
+    TestOutcome::Timeout
+}
+
+/// Checks the registers set by a mooneye-gb ROM at the `LD B,B` breakpoint.
+fn check_registers(gb: &GameBoy) -> TestOutcome {
+    let cpu = gb.cpu();
+    let regs = [cpu.b(), cpu.c(), cpu.d(), cpu.e(), cpu.h(), cpu.l()];
+
+    if regs == [3, 5, 8, 13, 21, 34] {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail(format!("registers: {:02X?}", regs))
+    }
+}
+
+/// Compares the frame with the reference image, once it has been drawn completely.
+fn compare_frame(gb: &mut GameBoy, reference: &[u8]) -> TestOutcome {
+    let until = gb.clock_cycles() + CYCLES_PER_FRAME;
+
+    while gb.clock_cycles() < until {
+        if let Err(e) = gb.step() {
+            return TestOutcome::Error(e.to_string());
+        }
+    }
+
+    if gb.screenshot()[..] == *reference {
+        TestOutcome::Pass
+    } else {
+        TestOutcome::Fail("the frame differs from the reference".to_string())
+    }
+}
+
+/// Returns the result printed by Blargg's ROMs over the link port or, for newer ROMs,
+/// to cartridge RAM: the signature $DE,$B0,$61 at $A001 marks valid output, $A000 holds
+/// the status ($80 while running) and the text starts at $A004.
+fn blargg_result(gb: &GameBoy) -> Option<TestOutcome> {
+    let bus = gb.bus();
+    let read = |addr| bus.read(addr).unwrap_or(0);
+
+    let output = if [read(0xA001), read(0xA002), read(0xA003)] == [0xDE, 0xB0, 0x61]
+        && read(0xA000) != 0x80
+    {
+        let text: Vec<u8> = (0xA004..0xC000).map(read).take_while(|&b| b != 0).collect();
+
+        String::from_utf8_lossy(&text).into_owned()
+    } else {
+        String::from_utf8_lossy(gb.serial_output()).into_owned()
+    };
+
+    if output.contains("Passed") {
+        Some(TestOutcome::Pass)
+    } else if output.contains("Failed") {
+        Some(TestOutcome::Fail(output.trim().to_string()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns a ROM setting B/C/D/E/H/L to `regs`, then executing `LD B,B`.
+    fn mooneye_rom(regs: [u8; 6]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        let mut pc = 0x100;
+
+        // LD B/C/D/E/H/L,d8
+        for (op, val) in [0x06, 0x0E, 0x16, 0x1E, 0x26, 0x2E].iter().zip(regs.iter()) {
+            rom[pc] = *op;
+            rom[pc + 1] = *val;
+            pc += 2;
+        }
+
+        // LD B,B, then loop forever
+        rom[pc..pc + 3].copy_from_slice(&[0x40, 0x18, 0xFE]);
+        rom
+    }
+
+    #[test]
+    fn outcomes_are_detected() {
+        let budget = CYCLES_PER_FRAME * 10;
+
+        assert_eq!(
+            run_test_rom(&mooneye_rom([3, 5, 8, 13, 21, 34]), None, budget),
+            TestOutcome::Pass
+        );
+        assert!(!run_test_rom(&mooneye_rom([0x42; 6]), None, budget).passed());
+
+        // Only loops forever
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+        assert_eq!(run_test_rom(&rom, None, budget), TestOutcome::Timeout);
+    }
+}
@@ -0,0 +1,142 @@
+//! Browser frontend of the emulator, built with `wasm-pack build --target web`.
+//!
+//! The page in `www` loads ROMs dropped on it, draws the frames on a canvas and
+//! plays the audio with WebAudio. Everything runs on the main thread: JavaScript
+//! paces the emulation with its own clock, calling `Emulator::run_frame` once per
+//! video frame and draining the frame and audio samples it produced.
+//!
+//! The core needs no clock or RNG to be plugged in. It never reads the host time: its
+//! only time base is the count of emulated cycles, and no cartridge has a real-time
+//! clock. It draws no random numbers either, since the power-on state is fixed.
+
+use gib_core::io::JoypadState;
+use gib_core::{cart, GameBoy, HardwareConfig, Model, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use crossbeam::queue::ArrayQueue;
+use wasm_bindgen::prelude::*;
+
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+/// Audio samples buffered between two frames, enough for a few frames at 48 kHz.
+const AUDIO_CAPACITY: usize = 8192;
+
+/// Emulated machine exported to JavaScript.
+#[wasm_bindgen]
+pub struct Emulator {
+    gb: GameBoy,
+    frame: Vec<u8>,
+    audio: Arc<ArrayQueue<i16>>,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Powers on a machine running `rom`, producing audio samples at `sample_rate`.
+    /// `rom` can also be a zip archive, in which case the first `.gb` or `.gbc` file
+    /// inside is run. The model is chosen depending on whether the cartridge supports CGB.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8], sample_rate: f32) -> Result<Emulator, JsValue> {
+        let unzipped;
+        let rom = if rom.starts_with(b"PK\x03\x04") {
+            unzipped = unzip(rom)?;
+            &unzipped[..]
+        } else {
+            rom
+        };
+
+        let model = if cart::Header::parse(rom).map_err(to_js)?.cgb {
+            Model::Cgb
+        } else {
+            Model::Dmg
+        };
+
+        let mut gb = GameBoy::with_config(HardwareConfig::new(model)).map_err(to_js)?;
+        gb.load_rom(rom).map_err(to_js)?;
+
+        let audio = Arc::new(ArrayQueue::new(AUDIO_CAPACITY));
+        gb.set_audio_sink(audio.clone(), sample_rate);
+
+        Ok(Emulator {
+            gb,
+            frame: vec![0xFF; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            audio,
+        })
+    }
+
+    /// Runs the emulator for a video frame, failing if the CPU locks up.
+    pub fn run_frame(&mut self) -> Result<(), JsValue> {
+        self.gb.run_for_vblank().map_err(to_js)?;
+        self.gb.rasterize(&mut self.frame);
+        Ok(())
+    }
+
+    /// Returns the last frame, as 160x144 pixels in RGBA format, ready for `ImageData`.
+    pub fn frame(&self) -> Vec<u8> {
+        self.frame.clone()
+    }
+
+    /// Takes the mono audio samples produced since the last call, in the range of
+    /// WebAudio buffers.
+    pub fn take_audio(&mut self) -> Vec<f32> {
+        let mut samples = Vec::with_capacity(self.audio.len());
+
+        while let Ok(sample) = self.audio.pop() {
+            samples.push(f32::from(sample) * 0.001);
+        }
+        samples
+    }
+
+    /// Presses or releases a button, by name: `up`, `down`, `left`, `right`,
+    /// `a`, `b`, `start` or `select`. Unknown names are ignored.
+    pub fn set_button(&mut self, name: &str, pressed: bool) {
+        let key = match name {
+            "up" => JoypadState::UP,
+            "down" => JoypadState::DOWN,
+            "left" => JoypadState::LEFT,
+            "right" => JoypadState::RIGHT,
+            "a" => JoypadState::A,
+            "b" => JoypadState::B,
+            "start" => JoypadState::START,
+            "select" => JoypadState::SELECT,
+            _ => return,
+        };
+
+        if pressed {
+            self.gb.press_key(key);
+        } else {
+            self.gb.release_key(key);
+        }
+    }
+
+    /// Returns the state of the machine, eg. to keep it in local storage.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.gb.save_state()
+    }
+
+    /// Restores a state returned by `save_state`.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsValue> {
+        self.gb.load_state(state).map_err(to_js)
+    }
+}
+
+/// Returns the first `.gb` or `.gbc` file in a zip archive.
+fn unzip(data: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(to_js)?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(to_js)?;
+        let name = entry.name().to_lowercase();
+
+        if name.ends_with(".gb") || name.ends_with(".gbc") {
+            let mut rom = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut rom).map_err(to_js)?;
+            return Ok(rom);
+        }
+    }
+
+    Err(JsValue::from_str("no .gb or .gbc file in the archive"))
+}
+
+fn to_js<E: std::fmt::Display>(e: E) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
@@ -0,0 +1,123 @@
+import init, { Emulator } from './pkg/gib_web.js';
+
+// Video frames per second of the Game Boy
+const FRAME_RATE = 59.73;
+
+// Joypad buttons bound to each key, as in the desktop frontend
+const KEYS = {
+  ArrowUp: 'up',
+  ArrowDown: 'down',
+  ArrowLeft: 'left',
+  ArrowRight: 'right',
+  KeyX: 'a',
+  KeyZ: 'b',
+  Enter: 'start',
+  Backspace: 'select',
+};
+
+const canvas = document.getElementById('screen');
+const screen = canvas.getContext('2d');
+const status = document.getElementById('status');
+
+let audio = null;
+let emu = null;
+let lastTime = 0;
+let pending = 0;
+let nextAudioTime = 0;
+
+// Runs as many frames as elapsed since the last animation frame, independently
+// of the refresh rate of the monitor
+function tick(time) {
+  requestAnimationFrame(tick);
+
+  if (emu === null) {
+    return;
+  }
+
+  // Skip ahead rather than catching up after the tab was in background
+  pending = Math.min(pending + (time - lastTime) * FRAME_RATE / 1000, 4);
+  lastTime = time;
+
+  if (pending < 1) {
+    return;
+  }
+
+  try {
+    while (pending >= 1) {
+      emu.run_frame();
+      pending -= 1;
+    }
+  } catch (e) {
+    status.textContent = `The emulator stopped: ${e}`;
+    emu = null;
+    return;
+  }
+
+  screen.putImageData(new ImageData(new Uint8ClampedArray(emu.frame()), 160, 144), 0, 0);
+  playAudio(emu.take_audio());
+}
+
+// Schedules the samples right after the ones already queued
+function playAudio(samples) {
+  if (samples.length === 0) {
+    return;
+  }
+
+  const buffer = audio.createBuffer(1, samples.length, audio.sampleRate);
+  buffer.copyToChannel(samples, 0);
+
+  const source = audio.createBufferSource();
+  source.buffer = buffer;
+  source.connect(audio.destination);
+
+  nextAudioTime = Math.max(nextAudioTime, audio.currentTime);
+  source.start(nextAudioTime);
+  nextAudioTime += buffer.duration;
+}
+
+async function loadRom(file) {
+  // Browsers only allow audio to start in response to user actions, like dropping a file
+  if (audio === null) {
+    audio = new AudioContext();
+  }
+
+  try {
+    const rom = new Uint8Array(await file.arrayBuffer());
+
+    emu = new Emulator(rom, audio.sampleRate);
+    lastTime = performance.now();
+    pending = 0;
+    status.textContent = file.name;
+  } catch (e) {
+    status.textContent = `Cannot load ${file.name}: ${e}`;
+  }
+}
+
+document.body.addEventListener('dragover', (e) => {
+  e.preventDefault();
+  document.body.classList.add('dragging');
+});
+
+document.body.addEventListener('dragleave', () => {
+  document.body.classList.remove('dragging');
+});
+
+document.body.addEventListener('drop', (e) => {
+  e.preventDefault();
+  document.body.classList.remove('dragging');
+
+  if (e.dataTransfer.files.length > 0) {
+    loadRom(e.dataTransfer.files[0]);
+  }
+});
+
+for (const [type, pressed] of [['keydown', true], ['keyup', false]]) {
+  document.addEventListener(type, (e) => {
+    if (emu !== null && KEYS[e.code] !== undefined) {
+      e.preventDefault();
+      emu.set_button(KEYS[e.code], pressed);
+    }
+  });
+}
+
+init().then(() => requestAnimationFrame(tick));
@@ -17,16 +17,173 @@ fn main() {
             Arg::with_name("devel")
                 .short("d")
                 .long("devel")
+                .visible_alias("debug")
                 .help("Open development GUI"),
         )
+        .arg(
+            Arg::with_name("boot-rom")
+                .short("b")
+                .long("boot-rom")
+                .visible_alias("bootrom")
+                .value_name("FILE")
+                .help("Boot ROM to run before the cartridge"),
+        )
+        .arg(
+            Arg::with_name("skip-boot")
+                .long("skip-boot")
+                .help("Skip the boot ROM, starting directly from the cartridge"),
+        )
+        .arg(
+            Arg::with_name("model")
+                .short("m")
+                .long("model")
+                .value_name("MODEL")
+                .possible_values(&["dmg0", "dmg", "mgb", "cgb"])
+                .help("Hardware model to emulate, chosen from the cartridge by default"),
+        )
+        .arg(
+            Arg::with_name("palette")
+                .short("p")
+                .long("palette")
+                .value_name("PALETTE")
+                .help("Screen colors on DMG models: grayscale, green or 4 RRGGBB colors"),
+        )
+        .arg(
+            Arg::with_name("speed")
+                .long("speed")
+                .value_name("SPEED")
+                .possible_values(&["25%", "50%", "100%", "200%", "400%", "uncapped"])
+                .help("Emulation speed, relative to the hardware"),
+        )
+        .arg(
+            Arg::with_name("scale")
+                .short("s")
+                .long("scale")
+                .value_name("N")
+                .validator(is_positive)
+                .help("Size of the screen, as a multiple of the native resolution"),
+        )
+        .arg(
+            Arg::with_name("fullscreen")
+                .short("f")
+                .long("fullscreen")
+                .help("Start in fullscreen mode"),
+        )
+        .arg(
+            Arg::with_name("savestate")
+                .long("savestate")
+                .value_name("FILE")
+                .requires("ROM")
+                .help("State to restore once the ROM is loaded"),
+        )
+        .arg(
+            Arg::with_name("headless")
+                .long("headless")
+                .requires("ROM")
+                .conflicts_with_all(&["devel", "fullscreen", "speed"])
+                .help("Run the ROM without a window or audio, then exit"),
+        )
+        .arg(
+            Arg::with_name("frames")
+                .long("frames")
+                .value_name("N")
+                .requires("headless")
+                .validator(is_positive)
+                .help("Number of frames to run in headless mode (60 by default)"),
+        )
+        .arg(
+            Arg::with_name("screenshot")
+                .long("screenshot")
+                .value_name("FILE")
+                .requires("headless")
+                .help("PNG file the last frame is saved to in headless mode"),
+        )
+        .arg(
+            Arg::with_name("run-tests")
+                .long("run-tests")
+                .value_name("DIR")
+                .help("Run the test ROMs in a directory headlessly and report the results"),
+        )
         .arg(Arg::with_name("ROM").help("ROM file to run").index(1))
         .get_matches();
 
+    if let Some(dir) = matches.value_of("run-tests") {
+        let failed = ui::run_suite(dir.as_ref()).expect("error running test ROMs");
+        std::process::exit(if failed == 0 { 0 } else { 1 });
+    }
+
+    // Numbers are checked by their validators
+    let scale = matches
+        .value_of("scale")
+        .map(|s| s.parse::<usize>().unwrap());
+
+    if matches.is_present("headless") {
+        let boot_rom = match matches.value_of("boot-rom") {
+            Some(path) if !matches.is_present("skip-boot") => {
+                Some(std::fs::read(path).expect("error loading boot rom"))
+            }
+            _ => None,
+        };
+
+        let opts = ui::Headless {
+            frames: matches.value_of("frames").unwrap_or("60").parse().unwrap(),
+            savestate: matches.value_of("savestate").map(Into::into),
+            screenshot: matches.value_of("screenshot").map(Into::into),
+            scale: scale.unwrap_or(1),
+        };
+
+        ui::run_headless(
+            matches.value_of("ROM").unwrap().as_ref(),
+            boot_rom,
+            matches.value_of("model").map(|m| m.parse().unwrap()),
+            matches
+                .value_of("palette")
+                .map_or_else(Default::default, |p| p.parse().expect("invalid palette")),
+            &opts,
+        )
+        .expect("error running rom");
+        return;
+    }
+
     let mut emu = ui::EmuUi::new(matches.is_present("devel")).unwrap();
 
+    if let Some(scale) = scale {
+        emu.set_scale(scale);
+    }
+    emu.set_fullscreen(matches.is_present("fullscreen"));
+
+    if let Some(ref boot_rom) = matches.value_of("boot-rom") {
+        emu.load_boot_rom(boot_rom).expect("error loading boot rom");
+    }
+    emu.set_skip_boot(matches.is_present("skip-boot"));
+
+    if let Some(model) = matches.value_of("model") {
+        emu.set_model(Some(model.parse().unwrap()));
+    }
+
+    if let Some(palette) = matches.value_of("palette") {
+        emu.set_dmg_palette(palette.parse().expect("invalid palette"));
+    }
+
+    if let Some(speed) = matches.value_of("speed") {
+        emu.set_speed(speed.parse().expect("invalid speed"));
+    }
+
     if let Some(ref rom) = matches.value_of("ROM") {
         emu.load_rom(rom).expect("error loading rom");
     }
 
+    if let Some(ref savestate) = matches.value_of("savestate") {
+        emu.load_state_file(savestate).expect("error loading savestate");
+    }
+
     emu.run().expect("while running emulator");
 }
+
+/// Validates the numeric arguments, which must be greater than zero.
+fn is_positive(s: String) -> Result<(), String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("expected a positive number, found '{}'", s)),
+    }
+}
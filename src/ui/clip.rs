@@ -0,0 +1,198 @@
+use gib_core::{GameBoy, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use super::screenshot;
+
+use failure::{format_err, Error};
+use gif::SetParameter;
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Seconds of emulation kept in the clip buffer.
+pub const CLIP_SECONDS: u64 = 10;
+
+/// Video frames per second of the Game Boy.
+const FRAME_RATE: f64 = 59.73;
+
+/// Only one frame every `FRAME_STEP` is kept: most GIF decoders slow down
+/// frames shorter than 2/100 s, and the clips are half as large.
+const FRAME_STEP: u64 = 2;
+
+/// Frame of a clip, quantized to a palette of up to 256 colors.
+struct ClipFrame {
+    // Number of the frame since the buffer was created
+    number: u64,
+
+    // Colors as RGB triplets, and the index of each pixel in them
+    palette: Vec<u8>,
+    pixels: Vec<u8>,
+}
+
+/// Rolling buffer of the last frames displayed, which can be saved as an animated GIF.
+/// Consecutive identical frames are stored once.
+#[derive(Default)]
+pub struct ClipBuffer {
+    frames: VecDeque<ClipFrame>,
+    count: u64,
+}
+
+impl ClipBuffer {
+    pub fn new() -> ClipBuffer {
+        ClipBuffer::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Adds the frame `gb` has just completed to the buffer, dropping the frames
+    /// older than `CLIP_SECONDS`.
+    pub fn frame_completed(&mut self, gb: &GameBoy) {
+        let number = self.count;
+        self.count += 1;
+
+        if number % FRAME_STEP != 0 {
+            return;
+        }
+
+        let frame = quantize(number, &gb.screenshot());
+
+        let unchanged = self.frames.back().map_or(false, |f| {
+            f.pixels == frame.pixels && f.palette == frame.palette
+        });
+
+        if !unchanged {
+            self.frames.push_back(frame);
+        }
+
+        // The first frame is kept as long as it is displayed, even if it started earlier
+        let start = self.start();
+        while self.frames.len() > 1 && self.frames[1].number <= start {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Number of the first frame in the clip.
+    fn start(&self) -> u64 {
+        self.count
+            .saturating_sub((CLIP_SECONDS as f64 * FRAME_RATE) as u64)
+    }
+
+    /// Writes the buffered frames as an animated GIF in `dir` named after `name` and
+    /// the current time, eg. `tetris-20190412-183000-250.gif`. Returns the path of the clip.
+    pub fn save(&self, dir: &Path, name: &str) -> Result<PathBuf, Error> {
+        let first = self
+            .frames
+            .front()
+            .ok_or_else(|| format_err!("no frames recorded yet"))?;
+
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}-{}.gif", name, screenshot::timestamp()));
+
+        let mut encoder = gif::Encoder::new(
+            BufWriter::new(File::create(&path)?),
+            SCREEN_WIDTH as u16,
+            SCREEN_HEIGHT as u16,
+            &first.palette,
+        )?;
+        encoder.set(gif::Repeat::Infinite)?;
+
+        let start = self.start();
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            let end = self.frames.get(i + 1).map_or(self.count, |f| f.number);
+            let delay = centis(end) - centis(frame.number.max(start));
+
+            // Frames with the colors of the first one use the global palette
+            let palette = if frame.palette == first.palette {
+                None
+            } else {
+                Some(frame.palette.clone())
+            };
+
+            encoder.write_frame(&gif::Frame {
+                width: SCREEN_WIDTH as u16,
+                height: SCREEN_HEIGHT as u16,
+                delay: delay.min(u64::from(u16::max_value())) as u16,
+                palette,
+                buffer: Cow::Borrowed(&frame.pixels),
+                ..gif::Frame::default()
+            })?;
+        }
+
+        Ok(path)
+    }
+}
+
+/// Converts a frame number to hundredths of a second, the unit of GIF delays.
+fn centis(number: u64) -> u64 {
+    (number as f64 * 100.0 / FRAME_RATE).round() as u64
+}
+
+/// Converts a frame in U8U8U8U8 RGBA format to indexed colors. Frames have only 4
+/// shades on monochrome models, and few more in color, so they rarely lose colors.
+fn quantize(number: u64, frame: &[u8]) -> ClipFrame {
+    let mut palette = Vec::with_capacity(4 * 3);
+    let mut pixels = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT);
+    let mut last = None;
+
+    for px in frame.chunks(4) {
+        let rgb = &px[..3];
+
+        // Runs of pixels of the same color are common, and skip the palette search
+        let index = match last {
+            Some((color, index)) if color == rgb => index,
+            _ => {
+                let index = match palette.chunks(3).position(|c| c == rgb) {
+                    Some(i) => i as u8,
+                    None if palette.len() < 256 * 3 => {
+                        palette.extend_from_slice(rgb);
+                        (palette.len() / 3 - 1) as u8
+                    }
+                    None => return quantize_rgb332(number, frame),
+                };
+
+                last = Some((rgb, index));
+                index
+            }
+        };
+
+        pixels.push(index);
+    }
+
+    ClipFrame {
+        number,
+        palette,
+        pixels,
+    }
+}
+
+/// Converts a frame with more than 256 colors, eg. with frame blending, to a fixed
+/// palette with 3 bits of red and green and 2 of blue.
+fn quantize_rgb332(number: u64, frame: &[u8]) -> ClipFrame {
+    let scale = |bits: u16, max: u16| (bits * 255 / max) as u8;
+
+    let palette = (0..256u16)
+        .flat_map(|i| {
+            vec![
+                scale(i >> 5, 7),
+                scale((i >> 2) & 0x7, 7),
+                scale(i & 0x3, 3),
+            ]
+        })
+        .collect();
+
+    let pixels = frame
+        .chunks(4)
+        .map(|px| (px[0] & 0xE0) | ((px[1] >> 3) & 0x1C) | (px[2] >> 6))
+        .collect();
+
+    ClipFrame {
+        number,
+        palette,
+        pixels,
+    }
+}
@@ -0,0 +1,180 @@
+use super::input::{self, AutofireBindings, KeyBindings, AUTOFIRE_BUTTONS, BUTTONS};
+
+use failure::Error;
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Largest factor screenshots can be scaled up by.
+pub const MAX_SCREENSHOT_SCALE: usize = 4;
+
+/// Settings of the emulator UI which persist across runs, stored as `name = value` lines.
+pub struct Config {
+    pub bindings: KeyBindings,
+    pub autofire: AutofireBindings,
+
+    /// Factor screenshots are scaled up by
+    pub screenshot_scale: usize,
+
+    /// Whether files are picked with the dialog of the OS, rather than the built-in one
+    pub native_file_dialog: bool,
+    /// Directory the last file was picked from
+    pub last_dir: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bindings: KeyBindings::default(),
+            autofire: AutofireBindings::default(),
+            screenshot_scale: 1,
+            native_file_dialog: true,
+            last_dir: None,
+        }
+    }
+}
+
+impl Config {
+    /// Returns the configuration directory, eg. `~/.config/gib`.
+    pub fn dir() -> Option<PathBuf> {
+        use std::env::var_os;
+
+        let dir = var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .or_else(|| var_os("APPDATA").map(PathBuf::from))?;
+
+        Some(dir.join("gib"))
+    }
+
+    /// Returns the path of the configuration file, eg. `~/.config/gib/gib.cfg`.
+    pub fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("gib.cfg"))
+    }
+
+    /// Returns the path of the freeze list of `rom`, eg. `~/.config/gib/freeze/tetris.freeze`.
+    pub fn freeze_path(rom: &Path) -> Option<PathBuf> {
+        Config::rom_path(rom, "freeze")
+    }
+
+    /// Returns the path of the cheats of `rom`, eg. `~/.config/gib/cheats/tetris.cheats`.
+    pub fn cheats_path(rom: &Path) -> Option<PathBuf> {
+        Config::rom_path(rom, "cheats")
+    }
+
+    /// Returns the path of a file of kind `kind` specific to `rom`, in a directory
+    /// named after the kind.
+    fn rom_path(rom: &Path, kind: &str) -> Option<PathBuf> {
+        let name = rom.file_stem()?.to_string_lossy();
+
+        Config::dir().map(|dir| dir.join(kind).join(format!("{}.{}", name, kind)))
+    }
+
+    /// Loads the configuration file, falling back to the defaults
+    /// for any missing or invalid setting.
+    pub fn load() -> Config {
+        let mut config = Config::default();
+
+        let text = match Config::path().map(std::fs::read_to_string) {
+            Some(Ok(text)) => text,
+            _ => return config,
+        };
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=').map(str::trim);
+
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                config.set(name, value);
+            }
+        }
+
+        config
+    }
+
+    /// Writes the configuration file, creating its directory if needed.
+    pub fn save(&self) -> Result<(), Error> {
+        let path = match Config::path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let mut text = String::new();
+
+        for (i, (_, button)) in BUTTONS.iter().enumerate() {
+            if let Some(key) = input::key_name(self.bindings.key(i)) {
+                writeln!(text, "input.{} = {}", button.to_lowercase(), key)?;
+            }
+        }
+        for (i, (_, button)) in AUTOFIRE_BUTTONS.iter().enumerate() {
+            if let Some(key) = self.autofire.keys[i].and_then(input::key_name) {
+                writeln!(text, "input.turbo_{} = {}", button.to_lowercase(), key)?;
+            }
+        }
+        writeln!(text, "input.turbo_period = {}", self.autofire.period)?;
+        writeln!(text, "screenshot.scale = {}", self.screenshot_scale)?;
+        writeln!(text, "file_dialog.native = {}", self.native_file_dialog)?;
+
+        if let Some(ref dir) = self.last_dir {
+            writeln!(text, "file_dialog.last_dir = {}", dir.display())?;
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, text)?;
+
+        Ok(())
+    }
+
+    /// Applies a single setting, ignoring unknown names or values.
+    fn set(&mut self, name: &str, value: &str) {
+        if name == "screenshot.scale" {
+            if let Ok(scale @ 1..=MAX_SCREENSHOT_SCALE) = value.parse::<usize>() {
+                self.screenshot_scale = scale;
+            }
+            return;
+        }
+
+        if name == "file_dialog.native" {
+            if let Ok(native) = value.parse() {
+                self.native_file_dialog = native;
+            }
+            return;
+        }
+
+        if name == "file_dialog.last_dir" {
+            self.last_dir = Some(PathBuf::from(value));
+            return;
+        }
+
+        if !name.starts_with("input.") {
+            return;
+        }
+
+        match &name["input.".len()..] {
+            "turbo_period" => {
+                if let Ok(period @ 1..=8) = value.parse::<u8>() {
+                    self.autofire.period = period;
+                }
+            }
+            button if button.starts_with("turbo_") => {
+                let i = AUTOFIRE_BUTTONS
+                    .iter()
+                    .position(|(_, name)| name.eq_ignore_ascii_case(&button["turbo_".len()..]));
+
+                if let Some(i) = i {
+                    self.autofire.keys[i] = input::parse_key(value);
+                }
+            }
+            button => {
+                if let Some(key) = input::parse_key(value) {
+                    self.bindings.bind_by_name(button, key);
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,17 @@
-use imgui::{FontGlyphRange, ImFontConfig, ImGui, ImVec4, Ui};
+use imgui::{FontGlyphRange, ImFontConfig, ImGui, ImTexture, ImVec4, Ui};
 use imgui_gfx_renderer::{Renderer, Shaders};
 
-use gfx_core::handle::{DepthStencilView, RenderTargetView};
+use gfx::format::{ChannelType, Rgba8, Swizzle, R8_G8_B8_A8};
+use gfx::memory::{Bind, Usage};
+use gfx::texture::{AaMode, FilterMethod, Kind, SamplerInfo, WrapMode};
+use gfx_core::factory::Factory as GfxFactory;
+use gfx_core::handle::{DepthStencilView, RenderTargetView, Texture};
 use gfx_device_gl::{Device, Factory, Resources};
 use glutin::{EventsLoop, GlWindow, VirtualKeyCode as Key};
 
 use std::cell::RefCell;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::rc::Rc;
 
 type ColorFormat = gfx::format::Rgba8;
@@ -19,6 +24,15 @@ struct MouseState {
     wheel: f32,
 }
 
+/// Texture which is created once, and then updated in place whenever its contents change.
+struct DynamicTexture {
+    texture: Texture<Resources, R8_G8_B8_A8>,
+    size: (u16, u16),
+
+    // New contents, written to the texture during the next rendering step
+    pending: Option<Vec<[u8; 4]>>,
+}
+
 pub struct UiContext {
     pub imgui: ImGui,
 
@@ -32,9 +46,12 @@ pub struct UiContext {
     pub events_loop: Rc<RefCell<EventsLoop>>,
     pub hidpi_factor: f64,
 
+    textures: HashMap<ImTexture, DynamicTexture>,
+
     key_state: HashSet<Key>,
     should_quit: bool,
     focused: bool,
+    dropped_file: Option<PathBuf>,
 }
 
 impl UiContext {
@@ -113,9 +130,12 @@ impl UiContext {
             events_loop: Rc::new(RefCell::from(events_loop)),
             hidpi_factor,
 
+            textures: HashMap::new(),
+
             key_state: HashSet::new(),
             should_quit: false,
             focused: true,
+            dropped_file: None,
         }
     }
 
@@ -126,7 +146,7 @@ impl UiContext {
             use glutin::{
                 ElementState::Pressed,
                 Event,
-                WindowEvent::{CloseRequested, Focused, KeyboardInput, Resized},
+                WindowEvent::{CloseRequested, DroppedFile, Focused, KeyboardInput, Resized},
             };
 
             imgui_winit_support::handle_event(
@@ -156,6 +176,9 @@ impl UiContext {
                     CloseRequested => {
                         self.should_quit = true;
                     }
+                    DroppedFile(path) => {
+                        self.dropped_file = Some(path);
+                    }
                     KeyboardInput { input, .. } => {
                         let pressed = input.state == Pressed;
 
@@ -175,10 +198,36 @@ impl UiContext {
         imgui_winit_support::update_mouse_cursor(&self.imgui, &self.window);
     }
 
+    /// Resizes the window to (width, height).
+    pub fn set_size(&mut self, width: f64, height: f64) {
+        self.window
+            .set_inner_size(glutin::dpi::LogicalSize::new(width, height));
+    }
+
+    /// Makes the window cover the monitor it is on, or restores it.
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        let monitor = if fullscreen {
+            Some(self.window.get_current_monitor())
+        } else {
+            None
+        };
+        self.window.set_fullscreen(monitor);
+    }
+
+    /// Returns the file dropped on the window since the last call, if any.
+    pub fn take_dropped_file(&mut self) -> Option<PathBuf> {
+        self.dropped_file.take()
+    }
+
     pub fn should_quit(&self) -> bool {
         self.should_quit
     }
 
+    /// Returns true if the window has the input focus.
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
     pub fn render<F>(&mut self, delta_s: f32, mut f: F)
     where
         F: FnMut(&Ui),
@@ -195,6 +244,18 @@ impl UiContext {
         let mut encoder: gfx::Encoder<_, _> = self.factory.create_command_buffer().into();
 
         encoder.clear(&self.main_color, [0.4, 0.5, 0.6, 1.0]);
+
+        // Update the textures whose contents have changed
+        for tex in self.textures.values_mut() {
+            if let Some(data) = tex.pending.take() {
+                let info = tex.texture.get_info().to_image_info(0);
+
+                encoder
+                    .update_texture::<R8_G8_B8_A8, Rgba8>(&tex.texture, None, info, &data[..])
+                    .expect("Texture update failed");
+            }
+        }
+
         {
             self.renderer
                 .render(ui, &mut self.factory, &mut encoder)
@@ -212,11 +273,86 @@ impl UiContext {
         }
     }
 
+    /// Uploads a `width`x`height` image in U8U8U8U8 RGBA format to the GPU, so that it
+    /// can be displayed during the next rendering step.
+    ///
+    /// If `texture` is given, its contents are updated in place, otherwise a new texture
+    /// is created. A texture is only recreated if its size changes.
+    pub fn upload_texture(
+        &mut self,
+        width: usize,
+        height: usize,
+        data: &[u8],
+        texture: Option<ImTexture>,
+    ) -> ImTexture {
+        let size = (width as u16, height as u16);
+        let pixels = data
+            .chunks(4)
+            .map(|p| [p[0], p[1], p[2], p[3]])
+            .collect::<Vec<_>>();
+
+        if let Some(tex) = texture.and_then(|t| self.textures.get_mut(&t)) {
+            if tex.size == size {
+                tex.pending = Some(pixels);
+                return texture.unwrap();
+            }
+        }
+
+        let tex = self
+            .factory
+            .create_texture::<R8_G8_B8_A8>(
+                Kind::D2(size.0, size.1, AaMode::Single),
+                1,
+                Bind::SHADER_RESOURCE,
+                Usage::Dynamic,
+                Some(ChannelType::Unorm),
+            )
+            .unwrap();
+
+        let view = self
+            .factory
+            .view_texture_as_shader_resource::<Rgba8>(&tex, (0, 0), Swizzle::new())
+            .unwrap();
+
+        let sampler = self
+            .factory
+            .create_sampler(SamplerInfo::new(FilterMethod::Scale, WrapMode::Clamp));
+
+        let id = match texture {
+            Some(texture) => {
+                self.renderer.textures().replace(texture, (view, sampler));
+                texture
+            }
+            None => self.renderer.textures().insert((view, sampler)),
+        };
+
+        self.textures.insert(
+            id,
+            DynamicTexture {
+                texture: tex,
+                size,
+                pending: Some(pixels),
+            },
+        );
+
+        id
+    }
+
     /// Returns the pressed state for the given virtual key.
     pub fn is_key_pressed(&self, key: Key) -> bool {
         self.key_state.contains(&key)
     }
 
+    /// Returns true if a text field has the keyboard focus.
+    pub fn wants_text_input(&self) -> bool {
+        unsafe { (*imgui_sys::igGetIO()).want_text_input }
+    }
+
+    /// Returns all the virtual keys currently pressed.
+    pub fn pressed_keys<'a>(&'a self) -> impl Iterator<Item = Key> + 'a {
+        self.key_state.iter().cloned()
+    }
+
     fn load_fonts(imgui: &mut ImGui, hidpi_factor: f64) {
         let font_size = (13.0 * hidpi_factor) as f32;
 
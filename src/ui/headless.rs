@@ -0,0 +1,52 @@
+//! Runs a ROM without opening a window or playing audio, eg. to check its output
+//! from scripts.
+
+use gib_core::{DmgPalette, Model};
+
+use super::screenshot;
+use super::state::EmuState;
+
+use failure::Error;
+
+use std::path::{Path, PathBuf};
+
+/// What a headless run does after powering on the machine.
+pub struct Headless {
+    /// Number of video frames to run for
+    pub frames: usize,
+    /// State to restore before running, if any
+    pub savestate: Option<PathBuf>,
+    /// Where to write the last frame as a PNG image, if anywhere
+    pub screenshot: Option<PathBuf>,
+    /// Size of the pixels in the screenshot
+    pub scale: usize,
+}
+
+/// Runs `rom` for the frames requested by `opts`, failing if the machine faults.
+/// If `boot_rom` is `None`, the boot ROM is skipped.
+pub fn run_headless(
+    rom: &Path,
+    boot_rom: Option<Vec<u8>>,
+    model: Option<Model>,
+    palette: DmgPalette,
+    opts: &Headless,
+) -> Result<(), Error> {
+    let mut emu = EmuState::new(rom, boot_rom, model)?;
+    emu.set_dmg_palette(palette);
+
+    if let Some(ref path) = opts.savestate {
+        emu.load_state_from(path)?;
+    }
+
+    for _ in 0..opts.frames {
+        if let Err(evt) = emu.gameboy_mut().run_for_vblank() {
+            return Err(gib_core::Error::from_event(evt, emu.cpu().pc).into());
+        }
+    }
+
+    if let Some(ref path) = opts.screenshot {
+        screenshot::write(path, &emu.gameboy().screenshot(), opts.scale)?;
+    }
+
+    Ok(())
+}
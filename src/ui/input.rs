@@ -0,0 +1,278 @@
+use gib_core::io::JoypadState;
+
+use super::config::Config;
+use super::utils;
+
+use glutin::VirtualKeyCode as Key;
+
+use imgui::{im_str, ImGuiCond, Ui};
+
+/// Joypad buttons that can be bound to a key, along with their name.
+pub const BUTTONS: [(JoypadState, &str); 8] = [
+    (JoypadState::UP, "Up"),
+    (JoypadState::DOWN, "Down"),
+    (JoypadState::LEFT, "Left"),
+    (JoypadState::RIGHT, "Right"),
+    (JoypadState::A, "A"),
+    (JoypadState::B, "B"),
+    (JoypadState::START, "Start"),
+    (JoypadState::SELECT, "Select"),
+];
+
+/// Buttons that can be bound to a key with autofire, along with their name.
+pub const AUTOFIRE_BUTTONS: [(JoypadState, &str); 2] =
+    [(JoypadState::A, "A"), (JoypadState::B, "B")];
+
+macro_rules! bindable_keys {
+    ($($key:ident),* $(,)*) => {
+        /// Keys that can be bound to a joypad button, along with their name.
+        const KEYS: &[(Key, &str)] = &[$((Key::$key, stringify!($key))),*];
+    };
+}
+
+bindable_keys! {
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Up, Down, Left, Right,
+    Insert, Delete, Home, End, PageUp, PageDown,
+    Back, Return, Tab, Space,
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+    Comma, Period, Semicolon, Slash, Backslash, Apostrophe, Grave,
+    Minus, Equals, LBracket, RBracket,
+    Add, Subtract, Multiply, Divide, NumpadEnter,
+}
+
+/// Returns the name of a bindable key, or `None` if the key cannot be bound.
+pub fn key_name(key: Key) -> Option<&'static str> {
+    KEYS.iter().find(|(k, _)| *k == key).map(|(_, name)| *name)
+}
+
+/// Returns the bindable key with the given name.
+pub fn parse_key(name: &str) -> Option<Key> {
+    KEYS.iter().find(|(_, n)| *n == name).map(|(key, _)| *key)
+}
+
+/// Keyboard keys bound to each joypad button, in the same order as `BUTTONS`.
+#[derive(Clone, Copy, PartialEq)]
+pub struct KeyBindings([Key; 8]);
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        KeyBindings([
+            Key::Up,
+            Key::Down,
+            Key::Left,
+            Key::Right,
+            Key::X,
+            Key::Z,
+            Key::Return,
+            Key::Back,
+        ])
+    }
+}
+
+impl KeyBindings {
+    /// Returns the key bound to the `i`-th button of `BUTTONS`.
+    pub fn key(&self, i: usize) -> Key {
+        self.0[i]
+    }
+
+    /// Binds `key` to the `i`-th button of `BUTTONS`.
+    pub fn bind(&mut self, i: usize, key: Key) {
+        self.0[i] = key;
+    }
+
+    /// Binds `key` to the button with the given name, returning false if there is none.
+    pub fn bind_by_name(&mut self, button: &str, key: Key) -> bool {
+        match BUTTONS
+            .iter()
+            .position(|(_, name)| name.eq_ignore_ascii_case(button))
+        {
+            Some(i) => {
+                self.bind(i, key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the pairs of keys and the joypad buttons they are bound to.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (Key, JoypadState)> + 'a {
+        self.0
+            .iter()
+            .zip(BUTTONS.iter())
+            .map(|(k, (b, _))| (*k, *b))
+    }
+}
+
+/// Optional keys holding buttons with autofire, in the same order as `AUTOFIRE_BUTTONS`.
+#[derive(Clone, Copy, PartialEq)]
+pub struct AutofireBindings {
+    pub keys: [Option<Key>; 2],
+
+    /// Emulated frames the buttons stay pressed, and then released, for.
+    pub period: u8,
+}
+
+impl Default for AutofireBindings {
+    fn default() -> AutofireBindings {
+        AutofireBindings {
+            keys: [None, None],
+            period: 2,
+        }
+    }
+}
+
+impl AutofireBindings {
+    /// Returns the pairs of keys and the joypad buttons they autofire.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (Key, JoypadState)> + 'a {
+        self.keys
+            .iter()
+            .zip(AUTOFIRE_BUTTONS.iter())
+            .filter_map(|(k, (b, _))| k.map(|k| (k, *b)))
+    }
+}
+
+/// Button waiting for a key to be bound to it, as an index in `BUTTONS`
+/// or `AUTOFIRE_BUTTONS`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Binding {
+    Button(usize),
+    Autofire(usize),
+}
+
+/// Dialog to rebind the joypad buttons. Clicking on a button starts waiting
+/// for a key to be pressed, which is then bound to it and saved to the config file.
+pub struct InputDialog {
+    capturing: Option<Binding>,
+    status: Option<String>,
+}
+
+impl InputDialog {
+    pub fn new() -> InputDialog {
+        InputDialog {
+            capturing: None,
+            status: None,
+        }
+    }
+
+    /// Returns true if the dialog is waiting for a key to be pressed.
+    pub fn capturing(&self) -> bool {
+        self.capturing.is_some()
+    }
+
+    /// Binds the pressed key to the button waiting for one, if any.
+    /// Escape cancels the capture, while keys which cannot be bound are ignored.
+    pub fn capture(&mut self, config: &mut Config, key: Key) {
+        let binding = match self.capturing {
+            Some(binding) => binding,
+            None => return,
+        };
+
+        if key == Key::Escape {
+            self.capturing = None;
+        } else if key_name(key).is_some() {
+            self.capturing = None;
+
+            match binding {
+                Binding::Button(i) => config.bindings.bind(i, key),
+                Binding::Autofire(i) => config.autofire.keys[i] = Some(key),
+            }
+            self.save(config);
+        }
+    }
+
+    fn save(&mut self, config: &Config) {
+        self.status = match config.save() {
+            Ok(()) => None,
+            Err(e) => Some(format!("Error saving config: {}", e)),
+        };
+    }
+
+    /// Draws a button showing the key bound to `binding`, which starts capturing when clicked.
+    fn draw_binding(&mut self, ui: &Ui, binding: Binding, key: Option<Key>) {
+        let label = if self.capturing == Some(binding) {
+            "Press a key..."
+        } else {
+            key.map_or("None", |key| key_name(key).unwrap_or("?"))
+        };
+
+        if ui.button(im_str!("{}##{:?}", label, binding), (110.0, 0.0)) {
+            self.capturing = Some(binding);
+        }
+    }
+
+    /// Draws the dialog, returning false once it has been closed.
+    pub fn build(&mut self, ui: &Ui, config: &mut Config) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("Input"))
+            .size((280.0, 400.0), ImGuiCond::FirstUseEver)
+            .resizable(false)
+            .collapsible(false)
+            .opened(&mut open)
+            .build(|| {
+                ui.columns(2, im_str!("input_bindings"), false);
+
+                for (i, (_, name)) in BUTTONS.iter().enumerate() {
+                    ui.text(name);
+                    ui.next_column();
+
+                    self.draw_binding(ui, Binding::Button(i), Some(config.bindings.key(i)));
+                    ui.next_column();
+                }
+
+                ui.columns(1, im_str!("input_bindings"), false);
+                ui.separator();
+                ui.columns(2, im_str!("input_autofire"), false);
+
+                for (i, (_, name)) in AUTOFIRE_BUTTONS.iter().enumerate() {
+                    ui.text(format!("Turbo {}", name));
+                    ui.next_column();
+
+                    self.draw_binding(ui, Binding::Autofire(i), config.autofire.keys[i]);
+                    ui.same_line(0.0);
+
+                    if ui.small_button(im_str!("x##autofire{}", i)) {
+                        config.autofire.keys[i] = None;
+                        self.save(config);
+                    }
+                    ui.next_column();
+                }
+
+                ui.columns(1, im_str!("input_autofire"), false);
+
+                let mut period = i32::from(config.autofire.period);
+
+                if ui
+                    .slider_int(im_str!("Turbo period"), &mut period, 1, 8)
+                    .display_format(im_str!("%d frames"))
+                    .build()
+                {
+                    config.autofire.period = period as u8;
+                    self.save(config);
+                }
+
+                ui.separator();
+
+                if ui.button(im_str!("Restore defaults"), (0.0, 0.0)) {
+                    self.capturing = None;
+
+                    config.bindings = KeyBindings::default();
+                    config.autofire = AutofireBindings::default();
+                    self.save(config);
+                }
+
+                if self.capturing.is_some() {
+                    ui.text_disabled(im_str!("Press Escape to cancel"));
+                }
+                if let Some(ref status) = self.status {
+                    ui.text_colored(utils::RED, im_str!("{}", status));
+                }
+            });
+
+        open
+    }
+}
@@ -1,58 +1,143 @@
-use gib_core::{self, io::JoypadState};
+use gib_core::{self, DmgPalette, Model, MovieMode};
 
+mod clip;
+mod config;
 mod ctx;
+mod headless;
+mod input;
+mod screenshot;
 mod sound;
 mod state;
+mod testsuite;
+mod tracefile;
 mod utils;
 mod views;
 
+use clip::CLIP_SECONDS;
+use config::{Config, MAX_SCREENSHOT_SCALE};
 use ctx::UiContext;
+use input::InputDialog;
 use sound::SoundEngine;
-use state::EmuState;
+use state::{EmuState, Speed, SyncMode};
+use testsuite::TestSuiteDialog;
 use views::{
-    DebuggerView, DisassemblyView, MemEditView, MemMapView, PeripheralView, View, WindowView,
+    ApuView, BgMapView, CheatsView, CommandView, DebuggerView, DisassemblyView, IoLogView,
+    IoRegView, MemEditView, MemMapView, MixerView, OamView, PaletteView, PeripheralView,
+    ProfilerView, RamSearchView, SerialView, SnapshotView, TimelineView, TimerView, View,
+    WindowView,
 };
 
+pub use headless::{run_headless, Headless};
+pub use testsuite::run_suite;
+
 use crossbeam::queue::ArrayQueue;
 use failure::Error;
 
-use gfx::texture::{FilterMethod, SamplerInfo, WrapMode};
-use gfx_core::factory::Factory;
 use glutin::VirtualKeyCode as Key;
 
 use imgui::{im_str, ImGuiCond, Ui};
 
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 const EMU_X_RES: usize = 160;
 const EMU_Y_RES: usize = 144;
 
+/// Height of the main menu bar
+const MENU_BAR_HEIGHT: f64 = 19.5;
+
 /// Emulator window width (in gaming mode)
 const EMU_WIN_X_RES: f64 = (EMU_X_RES * 2) as f64;
 /// Emulator window height (in gaming mode)
-const EMU_WIN_Y_RES: f64 = (EMU_Y_RES * 2) as f64 + 19.5;
-
-/// Mapping between VirtualKey and joypad button
-const KEYMAP: [(Key, JoypadState); 8] = [
-    (Key::Up, JoypadState::UP),
-    (Key::Down, JoypadState::DOWN),
-    (Key::Left, JoypadState::LEFT),
-    (Key::Right, JoypadState::RIGHT),
-    (Key::Z, JoypadState::B),
-    (Key::X, JoypadState::A),
-    (Key::Back, JoypadState::SELECT),
-    (Key::Return, JoypadState::START),
-];
+const EMU_WIN_Y_RES: f64 = (EMU_Y_RES * 2) as f64 + MENU_BAR_HEIGHT;
+
+/// Window size in debug mode, where the interface is much more cluttered
+const DEBUG_WIN_X_RES: f64 = 1440.0;
+const DEBUG_WIN_Y_RES: f64 = 720.0;
+
+/// What the file picked in the file dialog is loaded as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileAction {
+    LoadRom,
+    LoadSymbols,
+}
+
+impl FileAction {
+    fn title(self) -> &'static str {
+        match self {
+            FileAction::LoadRom => "Load ROM...",
+            FileAction::LoadSymbols => "Load symbols...",
+        }
+    }
+
+    /// Returns the description and the extensions of the files which can be picked.
+    fn filter(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            FileAction::LoadRom => ("Game Boy ROMs", &["gb", "gbc", "zip"]),
+            FileAction::LoadSymbols => ("RGBDS symbol files", &["sym"]),
+        }
+    }
+}
+
+/// Ways to recover from a machine fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FaultAction {
+    Reset,
+    LoadState,
+    Debug,
+    Close,
+}
+
+/// Ways to recover from a ROM which cannot be loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoadErrorAction {
+    SkipBoot,
+    ChooseRom,
+    Close,
+}
+
+/// Explains why a ROM cannot be loaded, and what can be done about it.
+fn load_error_hint(e: &gib_core::Error) -> String {
+    use gib_core::Error::*;
+
+    match e {
+        InvalidRom(_) | UnsupportedRomSize { .. } | UnsupportedRamSize { .. } => {
+            "The file is not a valid ROM, or is a bad dump. Try another copy of the game."
+                .to_string()
+        }
+        UnsupportedMapper { code } => format!(
+            "The cartridge has a memory bank controller (type {:02X}) which is not emulated yet.",
+            code
+        ),
+        InvalidBootRom { size } => format!(
+            "The boot ROM is {} bytes, which doesn't match any model.\n\
+             Skip it, or load a boot ROM of 256 or 2304 bytes.",
+            size
+        ),
+        Io(_) => "The file cannot be read.".to_string(),
+        _ => "The emulator cannot start the ROM.".to_string(),
+    }
+}
 
 pub struct GuiState {
     debug: bool,
     should_quit: bool,
-    file_dialog: Option<utils::FileDialog>,
+    fault_shown: bool,
+    error: Option<String>,
+    error_shown: bool,
+    load_error: Option<(PathBuf, gib_core::Error)>,
+    load_error_shown: bool,
+    held_keys: HashSet<Key>,
+    file_dialog: Option<(utils::FileDialog, FileAction)>,
+    input_dialog: Option<InputDialog>,
+    test_suite: Option<TestSuiteDialog>,
     views: HashMap<View, Box<WindowView>>,
+
+    // Size the window is to be resized to, eg. when switching to debug mode
+    window_size: Option<(f64, f64)>,
 }
 
 impl Default for GuiState {
@@ -60,8 +145,17 @@ impl Default for GuiState {
         GuiState {
             debug: false,
             should_quit: false,
+            fault_shown: false,
+            error: None,
+            error_shown: false,
+            load_error: None,
+            load_error_shown: false,
+            held_keys: HashSet::new(),
             file_dialog: None,
+            input_dialog: None,
+            test_suite: None,
             views: HashMap::new(),
+            window_size: None,
         }
     }
 }
@@ -72,8 +166,20 @@ pub struct EmuUi {
     ctx: Rc<RefCell<UiContext>>,
     snd: SoundEngine,
     gui: GuiState,
+    config: Config,
 
     emu: Option<Arc<Mutex<EmuState>>>,
+    boot_rom: Option<Vec<u8>>,
+    skip_boot: bool,
+    model: Option<Model>,
+    dmg_palette: DmgPalette,
+    frame_blending: f32,
+    oam_bug: bool,
+    wave_ram_quirks: bool,
+    sync_mode: SyncMode,
+    speed: Speed,
+    fast_forward_speed: Speed,
+    pause_unfocused: bool,
     vpu_buffer: Vec<u8>,
     vpu_texture: Option<imgui::ImTexture>,
 
@@ -87,7 +193,7 @@ impl EmuUi {
 
         // In debug mode, the interface is much more cluttered, so default to a bigger size
         let ctx = if debug {
-            UiContext::new(1440.0, 720.0)
+            UiContext::new(DEBUG_WIN_X_RES, DEBUG_WIN_Y_RES)
         } else {
             UiContext::new(EMU_WIN_X_RES, EMU_WIN_Y_RES)
         };
@@ -103,8 +209,20 @@ impl EmuUi {
             ctx: Rc::from(RefCell::from(ctx)),
             snd,
             gui,
+            config: Config::load(),
 
             emu: None,
+            boot_rom: None,
+            skip_boot: false,
+            model: None,
+            dmg_palette: DmgPalette::default(),
+            frame_blending: 0.0,
+            oam_bug: false,
+            wave_ram_quirks: false,
+            sync_mode: SyncMode::Audio,
+            speed: Speed::Normal,
+            fast_forward_speed: Speed::Uncapped,
+            pause_unfocused: false,
             vpu_buffer: vec![0xFFu8; EMU_X_RES * EMU_Y_RES * 4],
             vpu_texture: None,
 
@@ -112,11 +230,156 @@ impl EmuUi {
         })
     }
 
+    /// Loads the boot ROM run before any cartridge, unless skipped.
+    pub fn load_boot_rom<P: AsRef<Path>>(&mut self, boot_rom: P) -> Result<(), Error> {
+        self.boot_rom = Some(std::fs::read(boot_rom)?);
+        Ok(())
+    }
+
+    /// Sets whether the boot ROM should be skipped, starting directly from the cartridge.
+    /// Takes effect on the next ROM load or reset.
+    pub fn set_skip_boot(&mut self, skip: bool) {
+        self.skip_boot = skip;
+
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_boot_rom(self.active_boot_rom());
+        }
+    }
+
+    /// Resizes the window so that the screen is `scale` times the native resolution.
+    /// Only used in gaming mode, where the screen fills the window.
+    pub fn set_scale(&mut self, scale: usize) {
+        if !self.gui.debug {
+            let scale = scale.max(1);
+
+            self.ctx.borrow_mut().set_size(
+                (EMU_X_RES * scale) as f64,
+                (EMU_Y_RES * scale) as f64 + MENU_BAR_HEIGHT,
+            );
+        }
+    }
+
+    /// Makes the window cover the whole monitor, or restores it.
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.ctx.borrow_mut().set_fullscreen(fullscreen);
+    }
+
+    /// Sets the emulated hardware model. If `None`, it is chosen depending on the cartridge.
+    /// Takes effect on the next ROM load or reset.
+    pub fn set_model(&mut self, model: Option<Model>) {
+        self.model = model;
+
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_model(model);
+        }
+    }
+
+    /// Sets the colors used to display the shades of the monochrome models.
+    pub fn set_dmg_palette(&mut self, pal: DmgPalette) {
+        self.dmg_palette = pal;
+
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_dmg_palette(pal);
+        }
+    }
+
+    /// Blends each frame with the previous ones, to mimic the ghosting of the DMG LCD.
+    /// `persistence` is the weight of the previous frames, 0.0 to disable blending.
+    pub fn set_frame_blending(&mut self, persistence: f32) {
+        self.frame_blending = persistence;
+
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_frame_blending(persistence);
+        }
+    }
+
+    /// Enables or disables the emulation of the DMG OAM corruption bug.
+    pub fn set_oam_bug(&mut self, enable: bool) {
+        self.oam_bug = enable;
+
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_oam_bug(enable);
+        }
+    }
+
+    /// Enables or disables the emulation of wave RAM accesses while channel 3 is playing.
+    pub fn set_wave_ram_quirks(&mut self, enable: bool) {
+        self.wave_ram_quirks = enable;
+
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_wave_ram_quirks(enable);
+        }
+    }
+
+    /// Sets how the emulation speed is kept in sync with the host.
+    pub fn set_sync_mode(&mut self, mode: SyncMode) {
+        self.sync_mode = mode;
+
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_sync_mode(mode);
+        }
+    }
+
+    /// Sets the emulation speed, relative to real hardware.
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_speed(speed);
+        }
+    }
+
+    /// Sets the speed the emulator runs at while the fast-forward key is held.
+    pub fn set_fast_forward_speed(&mut self, speed: Speed) {
+        self.fast_forward_speed = speed;
+
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_fast_forward_speed(speed);
+        }
+    }
+
+    /// Returns the boot ROM to run, if any.
+    fn active_boot_rom(&self) -> Option<Vec<u8>> {
+        if self.skip_boot {
+            None
+        } else {
+            self.boot_rom.clone()
+        }
+    }
+
+    /// Loads a ROM chosen by the user, explaining in a popup why it cannot be loaded
+    /// if so. Returns true if the ROM was loaded.
+    fn open_rom(&mut self, rom: &Path) -> bool {
+        match self.load_rom(rom) {
+            Ok(()) => true,
+            Err(e) => {
+                self.gui.load_error = Some((rom.to_path_buf(), e));
+                false
+            }
+        }
+    }
+
+    /// Restores the state saved in the file at `path`, once a ROM is loaded.
+    pub fn load_state_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), gib_core::Error> {
+        match self.emu {
+            Some(ref emu) => emu.lock().unwrap().load_state_from(path.as_ref()),
+            None => Err(gib_core::Error::InvalidSavestate("no ROM loaded")),
+        }
+    }
+
     /// Loads the ROM file and starts the emulation.
-    pub fn load_rom<P: AsRef<Path>>(&mut self, rom: P) -> Result<(), Error> {
+    pub fn load_rom<P: AsRef<Path>>(&mut self, rom: P) -> Result<(), gib_core::Error> {
         let emu = {
-            let mut emu = EmuState::new(rom)?;
+            let mut emu = EmuState::new(rom, self.active_boot_rom(), self.model)?;
             emu.set_audio_sink(self.snd_sink.clone(), self.snd.get_sample_rate());
+            emu.set_dmg_palette(self.dmg_palette);
+            emu.set_frame_blending(self.frame_blending);
+            emu.set_oam_bug(self.oam_bug);
+            emu.set_wave_ram_quirks(self.wave_ram_quirks);
+            emu.set_sync_mode(self.sync_mode);
+            emu.set_speed(self.speed);
+            emu.set_fast_forward_speed(self.fast_forward_speed);
+            emu.set_code_tracking(self.gui.debug);
             emu.set_running();
 
             Arc::new(Mutex::new(emu))
@@ -145,10 +408,10 @@ impl EmuUi {
                     emu.lock().unwrap().do_step();
 
                     // After each step, we can sleep for a fraction of the audio buffer,
-                    // or for much less if not in audio sync mode.
+                    // or for much less if running uncapped.
                     //
                     // TODO this is ugly, find a better paradigm to synchronize everything.
-                    if !emu.lock().unwrap().turbo() {
+                    if !emu.lock().unwrap().uncapped() {
                         std::thread::sleep(Duration::from_millis(5));
                     } else {
                         std::thread::sleep(Duration::from_micros(1));
@@ -187,24 +450,83 @@ impl EmuUi {
                 return Ok(());
             }
 
+            // ROMs, or zip archives containing them, can be dropped on the window
+            if let Some(rom) = ctx.take_dropped_file() {
+                self.open_rom(&rom);
+            }
+
+            // Bind the first key pressed while the input dialog is waiting for one
+            let mut capturing = false;
+
+            if let Some(ref mut dialog) = self.gui.input_dialog {
+                if dialog.capturing() {
+                    capturing = true;
+
+                    if let Some(key) = ctx.pressed_keys().next() {
+                        dialog.capture(&mut self.config, key);
+                    }
+                }
+            }
+
+            // Hotkeys trigger once per key press
+            let pressed: Vec<_> = ctx
+                .pressed_keys()
+                .filter(|key| !self.gui.held_keys.contains(key))
+                .collect();
+
+            self.gui.held_keys = ctx.pressed_keys().collect();
+
+            // Keys typed in a text field are not hotkeys
+            if !capturing && !ctx.wants_text_input() {
+                let shift = ctx.is_key_pressed(Key::LShift) || ctx.is_key_pressed(Key::RShift);
+
+                for key in pressed {
+                    // The built-in file dialog can be navigated from the keyboard
+                    match self.gui.file_dialog {
+                        Some((ref mut fd, _)) => fd.handle_key(key),
+                        None => self.handle_hotkey(key, shift),
+                    }
+                }
+            }
+
             /*
              * Emulator syncing phase
              */
 
+            // Stop stepping while a modal dialog is open, or optionally while in background
+            let suspend = self.gui.file_dialog.is_some()
+                || self.gui.error.is_some()
+                || self.gui.load_error.is_some()
+                || (self.pause_unfocused && !ctx.focused());
+
             if let Some(ref mut emu) = self.emu {
                 let emu = &mut emu.lock().unwrap();
 
+                emu.set_suspended(suspend);
+
                 // Forward keypresses to the emulator
-                for (vk, js) in KEYMAP.iter() {
-                    if ctx.is_key_pressed(*vk) {
-                        emu.gameboy_mut().press_key(*js);
+                for (vk, js) in self.config.bindings.iter() {
+                    if ctx.is_key_pressed(vk) {
+                        emu.gameboy_mut().press_key(js);
                     } else {
-                        emu.gameboy_mut().release_key(*js);
+                        emu.gameboy_mut().release_key(js);
                     }
                 }
 
-                // Enable/disable turbo mode
-                emu.set_turbo(ctx.is_key_pressed(Key::Space));
+                // Autofire is timed by the emulator, so that it follows the emulated frames
+                emu.gameboy_mut()
+                    .set_autofire_period(self.config.autofire.period);
+
+                for (vk, js) in self.config.autofire.iter() {
+                    if ctx.is_key_pressed(vk) {
+                        emu.gameboy_mut().press_autofire(js);
+                    } else {
+                        emu.gameboy_mut().release_autofire(js);
+                    }
+                }
+
+                // Fast-forward while the key is held
+                emu.set_fast_forward(ctx.is_key_pressed(Key::Space));
 
                 // TODO this really needs to be done only if some changes
                 // have happened in the last interval.
@@ -217,6 +539,10 @@ impl EmuUi {
 
             self.prepare_screen_texture(&mut *ctx);
 
+            if self.gui.debug {
+                self.prepare_view_textures(&mut *ctx);
+            }
+
             ctx.render(delta.as_float_secs() as f32, |ui| {
                 if self.gui.debug {
                     self.draw_debug_ui(delta.as_float_secs() as f32, ui)
@@ -224,38 +550,186 @@ impl EmuUi {
                     self.draw_game_ui(delta.as_float_secs() as f32, ui)
                 }
             });
+
+            if let Some((width, height)) = self.gui.window_size.take() {
+                ctx.set_size(width, height);
+            }
+        }
+    }
+
+    /// Handles the keys bound to emulator actions:
+    ///
+    /// * P pauses or resumes the emulator
+    /// * F5 saves the state, F7 loads it back
+    /// * F6 runs a single frame while paused, or pauses the emulator
+    ///
+    /// In debug mode, the debugger keys take precedence (see `handle_debugger_hotkey`).
+    fn handle_hotkey(&mut self, key: Key, shift: bool) {
+        if self.gui.debug && self.handle_debugger_hotkey(key, shift) {
+            return;
+        }
+
+        match key {
+            Key::P => self.toggle_pause(),
+            Key::F5 => self.save_state(),
+            Key::F6 => self.frame_advance(),
+            Key::F7 => self.load_state(),
+            Key::F12 if shift => self.save_clip(),
+            Key::F12 => self.save_screenshot(),
+            _ => (),
+        }
+    }
+
+    /// Handles the keys bound to debugger actions, returning true if `key` was one:
+    ///
+    /// * F1 opens the command palette
+    /// * F5 continues until the next breakpoint (Shift+F5 still saves the state)
+    /// * F9 toggles a breakpoint at PC
+    /// * F10 steps over calls, F11 steps into them
+    fn handle_debugger_hotkey(&mut self, key: Key, shift: bool) -> bool {
+        if key == Key::F1 {
+            self.open_command_palette();
+            return true;
+        }
+
+        let emu = match self.emu {
+            Some(ref emu) => emu,
+            None => return false,
+        };
+        let mut emu = emu.lock().unwrap();
+
+        match key {
+            Key::F5 if !shift => emu.set_running(),
+            Key::F9 => {
+                let pc = emu.cpu().pc;
+                emu.toggle_breakpoint(pc);
+            }
+            Key::F10 => emu.set_step_over(),
+            Key::F11 => emu.set_single_step(),
+            _ => return false,
         }
+        true
     }
 
-    /// Creates a new texture displaying the currently emulated screen,
+    /// Opens the debugger command palette, if a ROM is loaded.
+    fn open_command_palette(&mut self) {
+        if self.emu.is_some() {
+            self.gui
+                .views
+                .entry(View::Commands)
+                .or_insert_with(|| box CommandView::new());
+        }
+    }
+
+    /// Pauses the emulator if running, or resumes it if paused.
+    fn toggle_pause(&mut self) {
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().toggle_pause();
+        }
+    }
+
+    /// Runs a single frame while paused, or pauses the emulator.
+    fn frame_advance(&mut self) {
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_frame_advance();
+        }
+    }
+
+    /// Saves the emulator state next to the ROM file, reporting any error in a popup.
+    fn save_state(&mut self) {
+        if let Some(ref emu) = self.emu {
+            if let Err(e) = emu.lock().unwrap().save_state() {
+                self.gui.error = Some(format!("Error saving state: {}", e));
+            }
+        }
+    }
+
+    /// Saves the current frame as a PNG image next to the ROM file, reporting any error in a popup.
+    fn save_screenshot(&mut self) {
+        if let Some(ref emu) = self.emu {
+            if let Err(e) = emu
+                .lock()
+                .unwrap()
+                .save_screenshot(self.config.screenshot_scale)
+            {
+                self.gui.error = Some(format!("Error saving screenshot: {}", e));
+            }
+        }
+    }
+
+    /// Saves the last seconds of emulation as an animated GIF next to the ROM file,
+    /// reporting any error in a popup.
+    fn save_clip(&mut self) {
+        if let Some(ref emu) = self.emu {
+            if let Err(e) = emu.lock().unwrap().save_clip() {
+                self.gui.error = Some(format!("Error saving clip: {}", e));
+            }
+        }
+    }
+
+    /// Sets the factor screenshots are scaled up by, and saves it in the configuration.
+    fn set_screenshot_scale(&mut self, scale: usize) {
+        self.config.screenshot_scale = scale;
+
+        if let Err(e) = self.config.save() {
+            self.gui.error = Some(format!("Error saving the configuration: {}", e));
+        }
+    }
+
+    /// Restores the emulator state saved next to the ROM file, reporting any error in a popup.
+    /// States cannot be loaded while a movie is active, as it would desync.
+    fn load_state(&mut self) {
+        if let Some(ref emu) = self.emu {
+            let mut emu = emu.lock().unwrap();
+
+            if emu.movie_status().is_some() {
+                self.gui.error = Some("Stop the movie before loading a state".to_string());
+            } else if let Err(e) = emu.load_state() {
+                self.gui.error = Some(format!("Error loading state: {}", e));
+            }
+        }
+    }
+
+    /// Starts recording a movie, or replaying the one recorded next to the ROM file.
+    fn start_movie(&mut self, mode: MovieMode) {
+        if let Some(ref emu) = self.emu {
+            let mut emu = emu.lock().unwrap();
+
+            match mode {
+                MovieMode::Recording => emu.start_recording(),
+                MovieMode::Playing => {
+                    if let Err(e) = emu.start_playback() {
+                        self.gui.error = Some(format!("Error playing movie: {}", e));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stops the active movie, saving it next to the ROM file if it was being recorded.
+    fn stop_movie(&mut self) {
+        if let Some(ref emu) = self.emu {
+            if let Err(e) = emu.lock().unwrap().stop_movie() {
+                self.gui.error = Some(format!("Error saving movie: {}", e));
+            }
+        }
+    }
+
+    /// Updates the texture displaying the currently emulated screen,
     /// ready to be presented during the next rendering step.
     fn prepare_screen_texture(&mut self, ctx: &mut UiContext) {
-        let texture = ctx
-            .factory
-            .create_texture_immutable_u8::<gfx::format::Rgba8>(
-                gfx::texture::Kind::D2(
-                    EMU_X_RES as u16,
-                    EMU_Y_RES as u16,
-                    gfx::texture::AaMode::Single,
-                ),
-                gfx::texture::Mipmap::Provided,
-                &[&self.vpu_buffer[..]],
-            )
-            .unwrap()
-            .1;
-
-        let sampler = ctx
-            .factory
-            .create_sampler(SamplerInfo::new(FilterMethod::Scale, WrapMode::Clamp));
+        self.vpu_texture =
+            Some(ctx.upload_texture(EMU_X_RES, EMU_Y_RES, &self.vpu_buffer[..], self.vpu_texture));
+    }
 
-        let texture = (texture, sampler);
+    /// Lets the open debug views upload the textures they are going to display.
+    fn prepare_view_textures(&mut self, ctx: &mut UiContext) {
+        if let Some(ref emu) = self.emu {
+            let emu = &emu.lock().unwrap();
 
-        // If this is the first time rendering, insert the new texture, otherwise
-        // replace an existing one.
-        if let Some(ref vpu_texture) = self.vpu_texture {
-            ctx.renderer.textures().replace(*vpu_texture, texture);
-        } else {
-            self.vpu_texture = Some(ctx.renderer.textures().insert(texture));
+            for view in self.gui.views.values_mut() {
+                view.prepare_textures(ctx, emu);
+            }
         }
     }
 
@@ -273,13 +747,15 @@ impl EmuUi {
             StyleVar::WindowPadding(ImVec2::new(0.0, 0.0)),
         ];
 
-        let win_x = EMU_WIN_X_RES as f32;
-        let win_y = EMU_WIN_Y_RES as f32 - 18.0; // account for menu bar
+        // Fill the window, which can be resized or fullscreen
+        let (width, height) = ui.frame_size().logical_size;
+        let win_x = width as f32;
+        let win_y = (height - MENU_BAR_HEIGHT) as f32;
 
         ui.with_style_vars(&style_vars, || {
             ui.window(im_str!("Screen"))
-                .size((win_x, win_y), ImGuiCond::FirstUseEver)
-                .position((0.0, 19.5), ImGuiCond::FirstUseEver)
+                .size((win_x, win_y), ImGuiCond::Always)
+                .position((0.0, MENU_BAR_HEIGHT as f32), ImGuiCond::FirstUseEver)
                 .flags(
                     // Disable any window feature
                     ImGuiWindowFlags::NoTitleBar
@@ -298,8 +774,13 @@ impl EmuUi {
                         }
                     }
 
+                    // Keep the aspect ratio of the screen, centering it horizontally
                     if let Some(texture) = self.vpu_texture {
-                        ui.image(texture, (win_x, win_y)).build();
+                        let scale = (win_x / EMU_X_RES as f32).min(win_y / EMU_Y_RES as f32);
+                        let (img_x, img_y) = (EMU_X_RES as f32 * scale, EMU_Y_RES as f32 * scale);
+
+                        ui.set_cursor_pos(((win_x - img_x) / 2.0, ui.get_cursor_pos().1));
+                        ui.image(texture, (img_x, img_y)).build();
                     }
                 });
         });
@@ -316,6 +797,14 @@ impl EmuUi {
         if let Some(ref mut emu) = self.emu {
             let emu = &mut emu.lock().unwrap();
             self.gui.views.retain(|_, view| view.draw(ui, emu));
+
+            // Open the memory editor if another view asked to show something in it
+            if emu.mem_editor_goto().is_some() {
+                self.gui
+                    .views
+                    .entry(View::MemEditor)
+                    .or_insert_with(|| box MemEditView::new());
+            }
         }
     }
 
@@ -323,11 +812,16 @@ impl EmuUi {
         let emu_running = self.emu.is_some();
 
         self.draw_file_dialog(delta_s, ui);
+        self.draw_input_dialog(ui);
+        self.draw_test_suite_dialog(ui);
+        self.draw_fault_popup(ui);
+        self.draw_error_popup(ui);
+        self.draw_load_error_popup(ui);
 
         ui.main_menu_bar(|| {
             ui.menu(im_str!("Emulator")).build(|| {
                 if ui.menu_item(im_str!("Load ROM...")).build() {
-                    self.gui.file_dialog = Some(utils::FileDialog::new("Load ROM..."));
+                    self.open_file_dialog(FileAction::LoadRom);
                 }
 
                 ui.separator();
@@ -336,6 +830,262 @@ impl EmuUi {
                     std::fs::write("screen-dump.bin", &self.vpu_buffer[..]).unwrap();
                 }
 
+                if ui
+                    .menu_item(im_str!("Save screenshot"))
+                    .shortcut(im_str!("F12"))
+                    .enabled(emu_running)
+                    .build()
+                {
+                    self.save_screenshot();
+                }
+
+                if ui
+                    .menu_item(im_str!("Save last {} seconds as GIF", CLIP_SECONDS))
+                    .shortcut(im_str!("Shift+F12"))
+                    .enabled(emu_running)
+                    .build()
+                {
+                    self.save_clip();
+                }
+
+                ui.menu(im_str!("Screenshot scale")).build(|| {
+                    for scale in 1..=MAX_SCREENSHOT_SCALE {
+                        if ui
+                            .menu_item(im_str!("{}x", scale))
+                            .selected(&mut (self.config.screenshot_scale == scale))
+                            .build()
+                        {
+                            self.set_screenshot_scale(scale);
+                        }
+                    }
+                });
+
+                let mut native_file_dialog = self.config.native_file_dialog;
+
+                if ui
+                    .menu_item(im_str!("Native file dialog"))
+                    .selected(&mut native_file_dialog)
+                    .build()
+                {
+                    self.set_native_file_dialog(native_file_dialog);
+                }
+
+                let mut skip_boot = self.skip_boot;
+
+                if ui
+                    .menu_item(im_str!("Skip boot ROM"))
+                    .selected(&mut skip_boot)
+                    .enabled(self.boot_rom.is_some())
+                    .build()
+                {
+                    self.set_skip_boot(skip_boot);
+                }
+
+                ui.menu(im_str!("Model")).build(|| {
+                    if ui
+                        .menu_item(im_str!("Auto"))
+                        .selected(&mut self.model.is_none())
+                        .build()
+                    {
+                        self.set_model(None);
+                    }
+
+                    for model in Model::all() {
+                        if ui
+                            .menu_item(im_str!("{}", model))
+                            .selected(&mut (self.model == Some(*model)))
+                            .build()
+                        {
+                            self.set_model(Some(*model));
+                        }
+                    }
+                });
+
+                ui.menu(im_str!("Palette")).build(|| {
+                    for (name, pal) in DmgPalette::presets() {
+                        if ui
+                            .menu_item(im_str!("{}", name))
+                            .selected(&mut (self.dmg_palette == *pal))
+                            .build()
+                        {
+                            self.set_dmg_palette(*pal);
+                        }
+                    }
+                });
+
+                ui.menu(im_str!("Sync")).build(|| {
+                    for mode in SyncMode::all() {
+                        if ui
+                            .menu_item(im_str!("{}", mode))
+                            .selected(&mut (self.sync_mode == *mode))
+                            .build()
+                        {
+                            self.set_sync_mode(*mode);
+                        }
+                    }
+                });
+
+                ui.menu(im_str!("Speed")).build(|| {
+                    for speed in Speed::all() {
+                        if ui
+                            .menu_item(im_str!("{}", speed))
+                            .selected(&mut (self.speed == *speed))
+                            .build()
+                        {
+                            self.set_speed(*speed);
+                        }
+                    }
+
+                    ui.separator();
+
+                    ui.menu(im_str!("Fast-forward")).build(|| {
+                        for speed in Speed::fast() {
+                            if ui
+                                .menu_item(im_str!("{}", speed))
+                                .selected(&mut (self.fast_forward_speed == *speed))
+                                .build()
+                            {
+                                self.set_fast_forward_speed(*speed);
+                            }
+                        }
+                    });
+
+                    if ui
+                        .menu_item(im_str!("Frame advance"))
+                        .shortcut(im_str!("F6"))
+                        .enabled(emu_running)
+                        .build()
+                    {
+                        self.frame_advance();
+                    }
+                });
+
+                ui.menu(im_str!("LCD ghosting")).build(|| {
+                    let mut persistence = self.frame_blending;
+
+                    if ui
+                        .slider_float(im_str!("Persistence"), &mut persistence, 0.0, 0.9)
+                        .build()
+                    {
+                        self.set_frame_blending(persistence);
+                    }
+                });
+
+                if ui.menu_item(im_str!("Input...")).build() {
+                    self.gui.input_dialog = Some(InputDialog::new());
+                }
+
+                if ui.menu_item(im_str!("Test ROMs...")).build() {
+                    self.gui.test_suite = Some(TestSuiteDialog::new());
+                }
+
+                if ui
+                    .menu_item(im_str!("Cheats..."))
+                    .enabled(emu_running)
+                    .build()
+                {
+                    self.gui
+                        .views
+                        .entry(View::Cheats)
+                        .or_insert_with(|| box CheatsView::new());
+                }
+
+                let mut oam_bug = self.oam_bug;
+
+                if ui
+                    .menu_item(im_str!("OAM corruption bug"))
+                    .selected(&mut oam_bug)
+                    .build()
+                {
+                    self.set_oam_bug(oam_bug);
+                }
+
+                let mut wave_ram_quirks = self.wave_ram_quirks;
+
+                if ui
+                    .menu_item(im_str!("Wave RAM quirks"))
+                    .selected(&mut wave_ram_quirks)
+                    .build()
+                {
+                    self.set_wave_ram_quirks(wave_ram_quirks);
+                }
+
+                ui.separator();
+
+                if ui
+                    .menu_item(im_str!("Save state"))
+                    .shortcut(if self.gui.debug {
+                        im_str!("Shift+F5")
+                    } else {
+                        im_str!("F5")
+                    })
+                    .enabled(emu_running)
+                    .build()
+                {
+                    self.save_state();
+                }
+
+                if ui
+                    .menu_item(im_str!("Load state"))
+                    .shortcut(im_str!("F7"))
+                    .enabled(emu_running)
+                    .build()
+                {
+                    self.load_state();
+                }
+
+                let movie = self
+                    .emu
+                    .as_ref()
+                    .and_then(|emu| emu.lock().unwrap().movie_status());
+
+                ui.menu(im_str!("Movie")).build(|| {
+                    if ui
+                        .menu_item(im_str!("Record"))
+                        .enabled(emu_running && movie.is_none())
+                        .build()
+                    {
+                        self.start_movie(MovieMode::Recording);
+                    }
+
+                    if ui
+                        .menu_item(im_str!("Play"))
+                        .enabled(emu_running && movie.is_none())
+                        .build()
+                    {
+                        self.start_movie(MovieMode::Playing);
+                    }
+
+                    if ui
+                        .menu_item(im_str!("Stop"))
+                        .enabled(movie.is_some())
+                        .build()
+                    {
+                        self.stop_movie();
+                    }
+                });
+
+                ui.separator();
+
+                let mut paused = self
+                    .emu
+                    .as_ref()
+                    .map_or(false, |emu| emu.lock().unwrap().paused());
+
+                if ui
+                    .menu_item(im_str!("Pause"))
+                    .shortcut(im_str!("P"))
+                    .selected(&mut paused)
+                    .enabled(emu_running)
+                    .build()
+                {
+                    self.toggle_pause();
+                }
+
+                ui.menu_item(im_str!("Pause in background"))
+                    .selected(&mut self.pause_unfocused)
+                    .build();
+
                 if ui.menu_item(im_str!("Reset")).enabled(emu_running).build() {
                     if let Some(ref mut emu) = self.emu {
                         emu.lock().unwrap().reset().expect("error during reset");
@@ -369,6 +1119,59 @@ impl EmuUi {
                             .entry(View::Peripherals)
                             .or_insert_with(|| box PeripheralView::new());
                     }
+
+                    if ui.menu_item(im_str!("BG Map")).enabled(emu_running).build() {
+                        self.gui
+                            .views
+                            .entry(View::BgMap)
+                            .or_insert_with(|| box BgMapView::new());
+                    }
+
+                    if ui.menu_item(im_str!("OAM")).enabled(emu_running).build() {
+                        self.gui
+                            .views
+                            .entry(View::Oam)
+                            .or_insert_with(|| box OamView::new());
+                    }
+
+                    if ui
+                        .menu_item(im_str!("Palettes"))
+                        .enabled(emu_running)
+                        .build()
+                    {
+                        self.gui
+                            .views
+                            .entry(View::Palettes)
+                            .or_insert_with(|| box PaletteView::new());
+                    }
+
+                    if ui.menu_item(im_str!("Timer")).enabled(emu_running).build() {
+                        self.gui
+                            .views
+                            .entry(View::Timer)
+                            .or_insert_with(|| box TimerView::new());
+                    }
+
+                    if ui.menu_item(im_str!("APU")).enabled(emu_running).build() {
+                        self.gui
+                            .views
+                            .entry(View::Apu)
+                            .or_insert_with(|| box ApuView::new());
+                    }
+
+                    if ui.menu_item(im_str!("Mixer")).enabled(emu_running).build() {
+                        self.gui
+                            .views
+                            .entry(View::Mixer)
+                            .or_insert_with(|| box MixerView::new());
+                    }
+
+                    if ui.menu_item(im_str!("Serial")).enabled(emu_running).build() {
+                        self.gui
+                            .views
+                            .entry(View::Serial)
+                            .or_insert_with(|| box SerialView::new());
+                    }
                 });
 
                 ui.menu(im_str!("Debugging")).build(|| {
@@ -394,6 +1197,15 @@ impl EmuUi {
                             .or_insert_with(|| box DisassemblyView::new());
                     }
 
+                    if ui
+                        .menu_item(im_str!("Command palette"))
+                        .shortcut(im_str!("F1"))
+                        .enabled(emu_running)
+                        .build()
+                    {
+                        self.open_command_palette();
+                    }
+
                     if ui
                         .menu_item(im_str!("Memory Editor"))
                         .enabled(emu_running)
@@ -404,16 +1216,92 @@ impl EmuUi {
                             .entry(View::MemEditor)
                             .or_insert_with(|| box MemEditView::new());
                     }
+
+                    if ui
+                        .menu_item(im_str!("RAM Search"))
+                        .enabled(emu_running)
+                        .build()
+                    {
+                        self.gui
+                            .views
+                            .entry(View::RamSearch)
+                            .or_insert_with(|| box RamSearchView::new());
+                    }
+
+                    if ui
+                        .menu_item(im_str!("Memory Snapshots"))
+                        .enabled(emu_running)
+                        .build()
+                    {
+                        self.gui
+                            .views
+                            .entry(View::Snapshots)
+                            .or_insert_with(|| box SnapshotView::new());
+                    }
+
+                    if ui
+                        .menu_item(im_str!("Load symbols..."))
+                        .enabled(emu_running)
+                        .build()
+                    {
+                        self.open_file_dialog(FileAction::LoadSymbols);
+                    }
+
+                    if ui
+                        .menu_item(im_str!("Profiler"))
+                        .enabled(emu_running)
+                        .build()
+                    {
+                        self.gui
+                            .views
+                            .entry(View::Profiler)
+                            .or_insert_with(|| box ProfilerView::new());
+                    }
+
+                    if ui
+                        .menu_item(im_str!("IO Registers"))
+                        .enabled(emu_running)
+                        .build()
+                    {
+                        self.gui
+                            .views
+                            .entry(View::IoRegs)
+                            .or_insert_with(|| box IoRegView::new());
+                    }
+
+                    if ui.menu_item(im_str!("IO Log")).enabled(emu_running).build() {
+                        self.gui
+                            .views
+                            .entry(View::IoLog)
+                            .or_insert_with(|| box IoLogView::new());
+                    }
+
+                    if ui
+                        .menu_item(im_str!("PPU Timeline"))
+                        .enabled(emu_running)
+                        .build()
+                    {
+                        self.gui
+                            .views
+                            .entry(View::Timeline)
+                            .or_insert_with(|| box TimelineView::new());
+                    }
                 })
             }
+
+            self.draw_pause_indicator(ui);
+            self.draw_movie_indicator(ui);
+            self.draw_autofire_indicator(ui);
         });
     }
 
     fn draw_file_dialog(&mut self, delta_s: f32, ui: &Ui) {
         let mut fd_closed = false;
         let mut fd_chosen = None;
+        let mut fd_action = FileAction::LoadRom;
 
-        if let Some(ref mut fd) = self.gui.file_dialog {
+        if let Some((ref mut fd, action)) = self.gui.file_dialog {
+            fd_action = action;
             fd.build(delta_s, ui, |res| {
                 fd_closed = true;
                 fd_chosen = res;
@@ -423,16 +1311,341 @@ impl EmuUi {
             self.gui.file_dialog = None;
         }
 
-        if let Some(ref rom_file) = fd_chosen {
-            if let Err(evt) = self.load_rom(rom_file) {
-                ui.popup_modal(im_str!("Error loading ROM")).build(|| {
+        if let Some(file) = fd_chosen {
+            self.file_picked(fd_action, &file);
+        }
+    }
+
+    /// Asks the user for a file to load as `action`, with the dialog of the OS if enabled,
+    /// or with the built-in one otherwise. Either starts from the last directory used.
+    fn open_file_dialog(&mut self, action: FileAction) {
+        let (filter_name, extensions) = action.filter();
+
+        if !self.config.native_file_dialog {
+            let fd = utils::FileDialog::new(
+                action.title(),
+                self.config.last_dir.as_ref().map(PathBuf::as_path),
+                extensions,
+            );
+            self.gui.file_dialog = Some((fd, action));
+            return;
+        }
+
+        // The native dialog blocks until it is closed, so stop stepping meanwhile
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_suspended(true);
+        }
+
+        let mut dialog = rfd::FileDialog::new()
+            .set_title(action.title())
+            .add_filter(filter_name, extensions);
+
+        if let Some(ref dir) = self.config.last_dir {
+            dialog = dialog.set_directory(dir);
+        }
+
+        if let Some(file) = dialog.pick_file() {
+            self.file_picked(action, &file);
+        }
+    }
+
+    /// Loads the file picked in a file dialog, remembering its directory for the next time.
+    fn file_picked(&mut self, action: FileAction, file: &Path) {
+        if let Some(dir) = file.parent() {
+            self.config.last_dir = Some(dir.to_path_buf());
+
+            if let Err(e) = self.config.save() {
+                self.gui.error = Some(format!("Error saving the configuration: {}", e));
+            }
+        }
+
+        match action {
+            FileAction::LoadRom => {
+                self.open_rom(file);
+            }
+            FileAction::LoadSymbols => {
+                if let Some(ref emu) = self.emu {
+                    if let Err(e) = emu.lock().unwrap().load_symbols(file) {
+                        self.gui.error = Some(format!("Error loading symbols: {}", e));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sets whether files are picked with the dialog of the OS, and saves it in the
+    /// configuration.
+    fn set_native_file_dialog(&mut self, native: bool) {
+        self.config.native_file_dialog = native;
+
+        if let Err(e) = self.config.save() {
+            self.gui.error = Some(format!("Error saving the configuration: {}", e));
+        }
+    }
+
+    /// Shows whether the emulator is paused in the menu bar.
+    fn draw_pause_indicator(&self, ui: &Ui) {
+        if let Some(ref emu) = self.emu {
+            let mut emu = emu.lock().unwrap();
+
+            if emu.paused() || emu.suspended() {
+                ui.text_colored(utils::YELLOW, im_str!("PAUSED"));
+            }
+        }
+    }
+
+    /// Shows the active movie and its progress in the menu bar, if any.
+    fn draw_movie_indicator(&self, ui: &Ui) {
+        if let Some(ref emu) = self.emu {
+            match emu.lock().unwrap().movie_status() {
+                Some((MovieMode::Recording, frame, _)) => {
+                    ui.text_colored(utils::RED, im_str!("REC {}", frame));
+                }
+                Some((MovieMode::Playing, frame, frames)) => {
+                    ui.text_colored(utils::GREEN, im_str!("PLAY {}/{}", frame, frames));
+                }
+                None => (),
+            }
+        }
+    }
+
+    /// Shows which buttons are being autofired in the menu bar, if any.
+    fn draw_autofire_indicator(&self, ui: &Ui) {
+        if let Some(ref emu) = self.emu {
+            let keys = emu.lock().unwrap().gameboy().autofire_keys();
+
+            for (js, name) in input::AUTOFIRE_BUTTONS.iter() {
+                if keys.contains(*js) {
+                    ui.text_colored(utils::YELLOW, im_str!("Turbo {}", name));
+                }
+            }
+        }
+    }
+
+    /// Shows the dialog to rebind the joypad buttons, if open.
+    fn draw_input_dialog(&mut self, ui: &Ui) {
+        if let Some(ref mut dialog) = self.gui.input_dialog {
+            if !dialog.build(ui, &mut self.config) {
+                self.gui.input_dialog = None;
+            }
+        }
+    }
+
+    /// Shows the dialog running suites of test ROMs, if open. The ROM chosen to debug
+    /// is loaded paused, so that it can be stepped through from the start.
+    fn draw_test_suite_dialog(&mut self, ui: &Ui) {
+        let mut debug_rom = None;
+
+        if let Some(ref mut dialog) = self.gui.test_suite {
+            let open = dialog.build(ui);
+            debug_rom = dialog.take_debug_rom();
+
+            if !open {
+                self.gui.test_suite = None;
+            }
+        }
+
+        if let Some(rom) = debug_rom {
+            if self.open_rom(&rom) {
+                if let Some(ref emu) = self.emu {
+                    emu.lock().unwrap().pause();
+                }
+            }
+        }
+    }
+
+    /// Shows a modal dialog whenever the machine faults, eg. when the CPU locks up,
+    /// offering to reset it, load the saved state or inspect it in the debugger.
+    /// In debug mode, the debugger is opened as soon as the fault happens.
+    fn draw_fault_popup(&mut self, ui: &Ui) {
+        let fault = match self.emu {
+            Some(ref emu) => emu.lock().unwrap().fault(),
+            None => None,
+        };
+
+        // Open the popup only once per fault, so that it can be dismissed
+        match fault {
+            Some(_) if !self.gui.fault_shown => {
+                ui.open_popup(im_str!("Machine fault"));
+                self.gui.fault_shown = true;
+
+                if self.gui.debug {
+                    self.open_debugger();
+                }
+            }
+            None => self.gui.fault_shown = false,
+            _ => (),
+        }
+
+        let debug = self.gui.debug;
+        let mut action = None;
+
+        ui.popup_modal(im_str!("Machine fault"))
+            .always_auto_resize(true)
+            .build(|| {
+                if let Some((evt, pc)) = fault {
                     ui.text(format!("{}", evt));
-                });
-                ui.open_popup(im_str!("Error loading ROM"));
+                    ui.text(format!("PC: ${:04X}", pc));
+                    ui.spacing();
+                    ui.text_colored(
+                        utils::DARK_GREY,
+                        im_str!("The machine is frozen until it is reset or a state is loaded."),
+                    );
+                }
+
+                ui.separator();
+
+                if ui.button(im_str!("Reset"), (0.0, 0.0)) {
+                    action = Some(FaultAction::Reset);
+                }
+                ui.same_line(0.0);
+
+                if ui.button(im_str!("Load state"), (0.0, 0.0)) {
+                    action = Some(FaultAction::LoadState);
+                }
+                ui.same_line(0.0);
+
+                // The debugger is already open in debug mode
+                if !debug {
+                    if ui.button(im_str!("Debug"), (0.0, 0.0)) {
+                        action = Some(FaultAction::Debug);
+                    }
+                    ui.same_line(0.0);
+                }
+
+                if ui.button(im_str!("Close"), (0.0, 0.0)) {
+                    action = Some(FaultAction::Close);
+                }
+
+                if action.is_some() {
+                    ui.close_current_popup();
+                }
+            });
+
+        match action {
+            Some(FaultAction::Reset) => {
+                if let Some(ref mut emu) = self.emu {
+                    if let Err(e) = emu.lock().unwrap().reset() {
+                        self.gui.error = Some(format!("Error resetting: {}", e));
+                    }
+                }
+            }
+            Some(FaultAction::LoadState) => self.load_state(),
+            Some(FaultAction::Debug) => self.open_debugger(),
+            _ => (),
+        }
+    }
+
+    /// Switches to debug mode if needed, and opens the debugger and disassembler.
+    fn open_debugger(&mut self) {
+        if !self.gui.debug {
+            self.gui.debug = true;
+            self.gui.window_size = Some((DEBUG_WIN_X_RES, DEBUG_WIN_Y_RES));
+        }
+        if let Some(ref emu) = self.emu {
+            emu.lock().unwrap().set_code_tracking(true);
+        }
+
+        self.gui
+            .views
+            .entry(View::Debugger)
+            .or_insert_with(|| box DebuggerView::new());
+        self.gui
+            .views
+            .entry(View::Disassembly)
+            .or_insert_with(|| box DisassemblyView::new());
+    }
+
+    /// Shows a modal dialog explaining why the last ROM could not be loaded, along with
+    /// the ways to recover, if any.
+    fn draw_load_error_popup(&mut self, ui: &Ui) {
+        if self.gui.load_error.is_some() && !self.gui.load_error_shown {
+            ui.open_popup(im_str!("Cannot load ROM"));
+            self.gui.load_error_shown = true;
+        }
+
+        let mut action = None;
+
+        ui.popup_modal(im_str!("Cannot load ROM"))
+            .always_auto_resize(true)
+            .build(|| {
+                if let Some((ref rom, ref e)) = self.gui.load_error {
+                    ui.text(format!("{}", rom.display()));
+                    ui.spacing();
+
+                    ui.text(load_error_hint(e));
+                    ui.text_colored(utils::DARK_GREY, im_str!("{}", e));
+                    ui.separator();
+
+                    if let gib_core::Error::InvalidBootRom { .. } = e {
+                        if ui.button(im_str!("Skip boot ROM"), (0.0, 0.0)) {
+                            action = Some(LoadErrorAction::SkipBoot);
+                        }
+                        ui.same_line(0.0);
+                    }
+                }
+
+                if ui.button(im_str!("Choose another ROM..."), (0.0, 0.0)) {
+                    action = Some(LoadErrorAction::ChooseRom);
+                }
+                ui.same_line(0.0);
+
+                if ui.button(im_str!("Close"), (0.0, 0.0)) {
+                    action = Some(LoadErrorAction::Close);
+                }
+
+                if action.is_some() {
+                    ui.close_current_popup();
+                }
+            });
+
+        if let Some(action) = action {
+            let load_error = self.gui.load_error.take();
+            self.gui.load_error_shown = false;
+
+            match (action, load_error) {
+                (LoadErrorAction::SkipBoot, Some((rom, _))) => {
+                    self.set_skip_boot(true);
+                    self.open_rom(&rom);
+                }
+                (LoadErrorAction::ChooseRom, _) => {
+                    self.open_file_dialog(FileAction::LoadRom);
+                }
+                _ => (),
             }
         }
     }
 
+    /// Shows a modal dialog reporting the last error, eg. when saving a state failed.
+    fn draw_error_popup(&mut self, ui: &Ui) {
+        if self.gui.error.is_some() && !self.gui.error_shown {
+            ui.open_popup(im_str!("Error"));
+            self.gui.error_shown = true;
+        }
+
+        let mut closed = false;
+
+        ui.popup_modal(im_str!("Error"))
+            .always_auto_resize(true)
+            .build(|| {
+                if let Some(ref error) = self.gui.error {
+                    ui.text(error);
+                }
+
+                ui.separator();
+
+                if ui.button(im_str!("Close"), (0.0, 0.0)) {
+                    closed = true;
+                    ui.close_current_popup();
+                }
+            });
+
+        if closed {
+            self.gui.error = None;
+            self.gui.error_shown = false;
+        }
+    }
+
     fn draw_screen_window(&mut self, ui: &Ui) {
         ui.window(im_str!("Screen"))
             .size(
@@ -0,0 +1,102 @@
+use gib_core::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use failure::Error;
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Writes `frame`, in U8U8U8U8 RGBA format, as a PNG image in `dir` named after `name`
+/// and the current time, eg. `tetris-20190412-183000-250.png`. Each pixel is scaled up
+/// to `scale`x`scale` pixels. Returns the path of the image.
+pub fn save(dir: &Path, name: &str, frame: &[u8], scale: usize) -> Result<PathBuf, Error> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}-{}.png", name, timestamp()));
+
+    write(&path, frame, scale)?;
+    Ok(path)
+}
+
+/// Writes `frame`, in U8U8U8U8 RGBA format, as a PNG image at `path`, scaling up each
+/// pixel to `scale`x`scale` pixels.
+pub fn write(path: &Path, frame: &[u8], scale: usize) -> Result<(), Error> {
+    let scale = scale.max(1);
+    let (width, height) = (SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale);
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        width as u32,
+        height as u32,
+    );
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&upscale(frame, scale))?;
+
+    Ok(())
+}
+
+/// Scales up a frame with nearest-neighbour sampling, to keep pixels sharp.
+fn upscale(frame: &[u8], scale: usize) -> Vec<u8> {
+    if scale == 1 {
+        return frame.to_vec();
+    }
+
+    let mut out = Vec::with_capacity(frame.len() * scale * scale);
+
+    for line in frame.chunks(SCREEN_WIDTH * 4) {
+        let start = out.len();
+
+        for pixel in line.chunks(4) {
+            for _ in 0..scale {
+                out.extend_from_slice(pixel);
+            }
+        }
+
+        // The other lines of the scaled pixels are the same
+        let row = out[start..].to_vec();
+        for _ in 1..scale {
+            out.extend_from_slice(&row);
+        }
+    }
+
+    out
+}
+
+/// Returns the current UTC time as `YYYYMMDD-HHMMSS-mmm`.
+pub fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days(secs / 86_400);
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year,
+        month,
+        day,
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        now.subsec_millis()
+    )
+}
+
+/// Converts a number of days since 1970-01-01 to a (year, month, day) date.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
@@ -1,62 +1,427 @@
-use gib_core::{bus::Bus, cpu::CPU, dbg, GameBoy};
+use gib_core::{
+    bus::{Bus, BusPolicy},
+    cart,
+    cpu::CPU,
+    dbg,
+    io::MixerControls,
+    mem::MemW,
+    Cheat, Cheats, DmgPalette, Event, EventKinds, GameBoy, HardwareConfig, Model, Movie, MovieMode,
+    CPU_CLOCK, CYCLES_PER_FRAME,
+};
 
 use crossbeam::queue::ArrayQueue;
 use failure::Error;
 
+use super::clip::ClipBuffer;
+use super::config::Config;
+use super::screenshot;
+use super::tracefile::TraceFile;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Read};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Number of instructions kept in the trace buffer.
+const TRACE_CAPACITY: usize = 100_000;
+
+/// Number of IO register accesses kept in the IO log.
+const IO_LOG_CAPACITY: usize = 16_384;
+
+/// Number of frames kept in the PPU timeline.
+const PPU_TIMELINE_FRAMES: u32 = 8;
+
+/// Maximum deviation from the nominal sample rate applied by dynamic rate control.
+const DRC_MAX_DELTA: f32 = 0.005;
+
+/// Maximum amount of time the emulator catches up on in a single step,
+/// to avoid running in bursts after a hiccup.
+const DRC_MAX_CATCH_UP: Duration = Duration::from_millis(50);
+
+/// Maximum number of video frames the emulator catches up on in frame pacing mode,
+/// beyond which the missed frames are skipped.
+const MAX_FRAMES_BEHIND: u32 = 3;
+
+/// How the emulation speed is kept in sync with the host.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Run until the audio queue is full, so that its consumption sets the pace.
+    Audio,
+    /// Run in real time, slightly adjusting the audio sample rate to keep the
+    /// audio queue half full instead of letting it drift between the two clocks.
+    DynamicRate,
+    /// Run whole video frames at the hardware frame rate (about 59.73 Hz), following
+    /// the host clock rather than the audio queue or the display refresh rate.
+    FramePacing,
+}
+
+impl SyncMode {
+    /// Returns a list of all the available sync modes.
+    pub fn all() -> &'static [SyncMode] {
+        &[
+            SyncMode::Audio,
+            SyncMode::DynamicRate,
+            SyncMode::FramePacing,
+        ]
+    }
+}
+
+impl fmt::Display for SyncMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncMode::Audio => write!(f, "Audio"),
+            SyncMode::DynamicRate => write!(f, "Dynamic rate"),
+            SyncMode::FramePacing => write!(f, "Frame pacing"),
+        }
+    }
+}
+
+/// Emulation speed, relative to real hardware.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Speed {
+    Quarter,
+    Half,
+    Normal,
+    Double,
+    Quadruple,
+    /// As fast as the host allows, without audio.
+    Uncapped,
+}
+
+impl Speed {
+    /// Returns a list of all the available speeds.
+    pub fn all() -> &'static [Speed] {
+        &[
+            Speed::Quarter,
+            Speed::Half,
+            Speed::Normal,
+            Speed::Double,
+            Speed::Quadruple,
+            Speed::Uncapped,
+        ]
+    }
+
+    /// Returns a list of the speeds above the hardware speed, used to fast-forward.
+    pub fn fast() -> &'static [Speed] {
+        &[Speed::Double, Speed::Quadruple, Speed::Uncapped]
+    }
+
+    /// Returns the speed as a multiple of the hardware speed.
+    pub fn factor(self) -> f32 {
+        match self {
+            Speed::Quarter => 0.25,
+            Speed::Half => 0.5,
+            Speed::Normal => 1.0,
+            Speed::Double => 2.0,
+            Speed::Quadruple => 4.0,
+            Speed::Uncapped => std::f32::INFINITY,
+        }
+    }
+}
+
+impl FromStr for Speed {
+    type Err = String;
+
+    /// Parses a percentage of the hardware speed, eg. `200%`, or `uncapped`.
+    fn from_str(s: &str) -> Result<Speed, String> {
+        let s = s.trim_end_matches('%');
+
+        Speed::all()
+            .iter()
+            .find(|speed| {
+                speed
+                    .to_string()
+                    .trim_end_matches('%')
+                    .eq_ignore_ascii_case(s)
+            })
+            .cloned()
+            .ok_or_else(|| format!("unknown speed: {}", s))
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Speed::Uncapped => write!(f, "Uncapped"),
+            _ => write!(f, "{}%", (self.factor() * 100.0) as u32),
+        }
+    }
+}
 
 pub struct EmuState {
     gb: GameBoy,
     rom_file: PathBuf,
+    boot_rom: Option<Vec<u8>>,
+    model: Option<Model>,
+    dmg_palette: DmgPalette,
+    frame_blending: f32,
 
     // Sound-related fields
     snd_sink: Option<Arc<ArrayQueue<i16>>>,
     snd_sample_rate: f32,
 
     // Emulation-related fields
-    turbo_mode: bool,
+    sync_mode: SyncMode,
+    speed: Speed,
+    fast_forward_speed: Speed,
+    fast_forward: bool,
+    last_sync: Option<Instant>,
+    suspended: bool,
     step_to_next: bool,
+    frame_advance: bool,
     run_to_breakpoint: bool,
+    step_out_depth: Option<usize>,
+    run_to: Option<(u16, usize)>,
+    run_until_cycle: Option<u64>,
     trace_event: Option<dbg::TraceEvent>,
+    stopwatch: dbg::Stopwatch,
+
+    // Unrecoverable event which froze the machine, and the PC it happened at
+    fault: Option<(dbg::TraceEvent, u16)>,
+
+    // Events raised by the emulator, handled after each step
+    events: Receiver<Event>,
+
+    // Bytes sent over the link port since the last reset
+    serial_output: Vec<u8>,
+
+    // Last frames displayed, to save as a GIF clip
+    clip: ClipBuffer,
+
+    // File the trace is being streamed to, and the last error writing it
+    trace_file: Option<TraceFile>,
+    trace_file_error: Option<String>,
+
+    // Address the memory editor has been asked to show, if any
+    mem_editor_goto: Option<u16>,
+
+    // Names of the memory locations, from the symbol file of the ROM
+    symbols: dbg::Symbols,
 }
 
 impl EmuState {
-    pub fn new<P: AsRef<Path>>(rom: P) -> Result<EmuState, Error> {
-        let mut gb = GameBoy::new();
-        let rom_buf = std::fs::read(rom.as_ref())?;
+    /// Creates a new emulator running `rom`. If `boot_rom` is `None`, the boot ROM is skipped.
+    /// If `model` is `None`, it is chosen depending on whether the cartridge supports CGB.
+    pub fn new<P: AsRef<Path>>(
+        rom: P,
+        boot_rom: Option<Vec<u8>>,
+        model: Option<Model>,
+    ) -> Result<EmuState, gib_core::Error> {
+        let dmg_palette = DmgPalette::default();
+        let mut gb = EmuState::power_on(rom.as_ref(), &boot_rom, model, dmg_palette)?;
+        gb.set_freezes(EmuState::find_freezes(rom.as_ref()));
+        gb.set_cheats(EmuState::find_cheats(rom.as_ref()));
 
-        gb.load_rom(&rom_buf[..])?;
+        let events = EmuState::subscribe(&mut gb);
+
+        let stopwatch = dbg::Stopwatch::new(gb.clock_cycles());
 
         Ok(EmuState {
             gb,
             rom_file: rom.as_ref().to_path_buf(),
+            boot_rom,
+            model,
+            dmg_palette,
+            frame_blending: 0.0,
 
             snd_sink: None,
             snd_sample_rate: 0f32,
 
-            turbo_mode: false,
+            sync_mode: SyncMode::Audio,
+            speed: Speed::Normal,
+            fast_forward_speed: Speed::Uncapped,
+            fast_forward: false,
+            last_sync: None,
+            suspended: false,
             step_to_next: false,
+            frame_advance: false,
             run_to_breakpoint: false,
+            step_out_depth: None,
+            run_to: None,
+            run_until_cycle: None,
             trace_event: None,
+            stopwatch,
+
+            fault: None,
+
+            events,
+            serial_output: vec![],
+            clip: ClipBuffer::new(),
+
+            trace_file: None,
+            trace_file_error: None,
+
+            mem_editor_goto: None,
+
+            symbols: EmuState::find_symbols(rom.as_ref()),
         })
     }
 
+    /// Forwards the events the UI reacts to from `gb`, to be handled after each step.
+    fn subscribe(gb: &mut GameBoy) -> Receiver<Event> {
+        let (tx, rx) = mpsc::channel();
+
+        gb.subscribe(
+            EventKinds::FRAME | EventKinds::SERIAL | EventKinds::BREAKPOINT | EventKinds::TRACE,
+            move |evt| tx.send(*evt).unwrap_or(()),
+        );
+        rx
+    }
+
+    /// Loads the symbols of `rom` from the `.sym` file next to it, if there is one.
+    fn find_symbols(rom: &Path) -> dbg::Symbols {
+        std::fs::read_to_string(rom.with_extension("sym"))
+            .map(|text| dbg::Symbols::parse(&text))
+            .unwrap_or_default()
+    }
+
+    /// Loads the freeze list of `rom` from the configuration directory, if there is one.
+    fn find_freezes(rom: &Path) -> dbg::FreezeList {
+        Config::freeze_path(rom)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|text| dbg::FreezeList::parse(&text))
+            .unwrap_or_default()
+    }
+
+    /// Loads the cheats of `rom` from the configuration directory, if there are any.
+    fn find_cheats(rom: &Path) -> Cheats {
+        Config::cheats_path(rom)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|text| Cheats::parse(&text))
+            .unwrap_or_default()
+    }
+
+    /// Reads the ROM in `path` which, as most ROM collections are zipped, can also be
+    /// a zip archive. In that case, the first `.gb` or `.gbc` file inside is read.
+    fn read_rom(path: &Path) -> Result<Vec<u8>, gib_core::Error> {
+        let data = std::fs::read(path)?;
+
+        if !data.starts_with(b"PK\x03\x04") {
+            return Ok(data);
+        }
+
+        let bad_zip = |e: zip::result::ZipError| gib_core::Error::InvalidRom(e.to_string());
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(bad_zip)?;
+
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(bad_zip)?;
+            let name = entry.name().to_lowercase();
+
+            if name.ends_with(".gb") || name.ends_with(".gbc") {
+                let mut rom = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut rom)?;
+                return Ok(rom);
+            }
+        }
+
+        Err(gib_core::Error::InvalidRom(format!(
+            "no .gb or .gbc file in {}",
+            path.display()
+        )))
+    }
+
+    fn power_on(
+        rom: &Path,
+        boot_rom: &Option<Vec<u8>>,
+        model: Option<Model>,
+        dmg_palette: DmgPalette,
+    ) -> Result<GameBoy, gib_core::Error> {
+        let rom = EmuState::read_rom(rom)?;
+
+        let model = match model {
+            Some(model) => model,
+            None if cart::Header::parse(&rom[..])?.cgb => Model::Cgb,
+            None => Model::Dmg,
+        };
+
+        let mut gb = GameBoy::with_config(HardwareConfig {
+            boot_rom: boot_rom.clone(),
+            dmg_palette,
+            ..HardwareConfig::new(model)
+        })?;
+        gb.load_rom(&rom[..])?;
+
+        Ok(gb)
+    }
+
+    /// Sets the boot ROM run on the next reset. If `None`, the boot ROM is skipped.
+    pub fn set_boot_rom(&mut self, boot_rom: Option<Vec<u8>>) {
+        self.boot_rom = boot_rom;
+    }
+
+    /// Sets the hardware model emulated from the next reset. If `None`, it is chosen
+    /// depending on the cartridge.
+    pub fn set_model(&mut self, model: Option<Model>) {
+        self.model = model;
+    }
+
+    /// Sets the colors used to display the shades of the monochrome models.
+    pub fn set_dmg_palette(&mut self, pal: DmgPalette) {
+        self.dmg_palette = pal;
+        self.gb.set_dmg_palette(pal);
+    }
+
+    /// Blends each frame with the previous ones, with the given `persistence` (0.0 to disable).
+    pub fn set_frame_blending(&mut self, persistence: f32) {
+        self.frame_blending = persistence;
+        self.gb.set_frame_blending(persistence);
+    }
+
     pub fn pause(&mut self) {
-        self.turbo_mode = false;
+        self.last_sync = None;
         self.step_to_next = false;
+        self.frame_advance = false;
         self.run_to_breakpoint = false;
+        self.step_out_depth = None;
+        self.run_to = None;
+        self.run_until_cycle = None;
+        self.gb.break_at_line(None);
         self.gb.cpu_mut().pause();
     }
 
+    /// Pauses the emulator if running, or resumes it if paused.
+    pub fn toggle_pause(&mut self) {
+        if self.paused() {
+            self.set_running();
+        } else {
+            self.pause();
+        }
+    }
+
+    /// Suspends or resumes stepping, eg. while a modal dialog is open, without
+    /// affecting whether the emulator is paused.
+    pub fn set_suspended(&mut self, suspend: bool) {
+        if self.suspended != suspend {
+            self.suspended = suspend;
+            self.last_sync = None;
+        }
+    }
+
+    /// Returns true if stepping is suspended.
+    pub fn suspended(&self) -> bool {
+        self.suspended
+    }
+
     /// Performs a single emulation step, depending on the emulator's state:
     ///
     /// * if we are in step mode, execute a single instruction
-    /// * if we are in run mode, run to audio sync (ie. audio queue full)
+    /// * if we are advancing by a frame, run for exactly one video frame
+    /// * if we are running uncapped, run for a video frame without any sync
+    /// * if we are in run mode, run to audio sync (ie. audio queue full),
+    ///   in real time with dynamic rate control, or for the video frames due
+    /// * if we are stepping out, run until the current function returns
+    /// * if we are running to an address, run until it is reached
+    /// * if we are running for some cycles, run until they have elapsed
     ///
-    /// In both cases, if an event happens, pause the emulator.
+    /// In both cases, if an event happens, pause the emulator. If the event is a fault,
+    /// the machine stays frozen until it is reset or a state is loaded.
+    /// Nothing is done while paused or suspended.
     pub fn do_step(&mut self) {
-        if self.paused() {
+        if self.paused() || self.suspended {
             return;
         }
 
@@ -66,31 +431,188 @@ impl EmuState {
             let r = self.gb.step();
             self.pause();
             r
-        } else if self.turbo_mode {
+        } else if self.frame_advance {
+            let r = self.gb.run_for_vblank();
+            self.pause();
+            r
+        } else if self.run_to_breakpoint && self.uncapped() {
             self.gb.run_for_vblank()
         } else if self.run_to_breakpoint {
-            self.run_to_audio_sync()
+            match self.sync_mode {
+                SyncMode::Audio => self.run_to_audio_sync(),
+                SyncMode::DynamicRate => self.run_with_dynamic_rate(),
+                SyncMode::FramePacing => self.run_frames_due(),
+            }
+        } else if let Some(depth) = self.step_out_depth {
+            self.run_to_return(depth)
+        } else if let Some((addr, depth)) = self.run_to {
+            self.run_to_addr(addr, depth)
+        } else if let Some(until) = self.run_until_cycle {
+            self.run_to_cycle(until)
         } else {
             Ok(())
         };
 
-        if let Err(ref evt) = res {
-            self.trace_event = Some(*evt);
+        if let Err(evt) = res {
             self.pause();
-        };
+
+            if evt.is_fault() {
+                let pc = match evt {
+                    dbg::TraceEvent::IllegalInstructionFault(pc, _) => pc,
+                    _ => self.cpu().pc,
+                };
+                self.fault = Some((evt, pc));
+            }
+        }
+
+        self.handle_events();
+        self.write_trace_stream();
+    }
+
+    /// Handles the events raised by the emulator during the last step.
+    fn handle_events(&mut self) {
+        let events: Vec<_> = self.events.try_iter().collect();
+
+        for evt in events {
+            match evt {
+                Event::Breakpoint(_) => self.stopwatch.hit(self.gb.clock_cycles()),
+                Event::Trace(evt) => self.trace_event = Some(evt),
+                Event::SerialOut(b) => self.serial_output.push(b),
+                Event::FrameCompleted => self.clip.frame_completed(&self.gb),
+                _ => (),
+            }
+        }
+    }
+
+    /// Writes the records streamed during the last step to the trace file.
+    /// Streaming stops on errors, eg. if the disk is full.
+    fn write_trace_stream(&mut self) {
+        if let Some(ref mut file) = self.trace_file {
+            if let Err(e) = file.write(&self.gb.take_trace_records()) {
+                self.trace_file_error = Some(format!("Error: {}", e));
+                self.trace_file = None;
+                self.gb.disable_trace_stream();
+            }
+        }
     }
 
     /// Runs the emulator until the audio queue is full, to avoid dropping
     /// audio samples and cause skipping/popping.
+    ///
+    /// No samples are produced in STOP mode, so in that case return as soon as possible.
     fn run_to_audio_sync(&mut self) -> Result<(), dbg::TraceEvent> {
         if let Some(ref sink) = self.snd_sink {
-            while sink.len() < sink.capacity() {
+            while sink.len() < sink.capacity() && !self.gb.cpu().stopped {
                 self.gb.step()?;
             }
         }
         Ok(())
     }
 
+    /// Runs the emulator for the time elapsed since the last step, scaled by the emulation
+    /// speed, steering the audio sample rate so that the audio queue stays half full.
+    ///
+    /// The time spent in STOP mode is skipped, as for audio sync.
+    fn run_with_dynamic_rate(&mut self) -> Result<(), dbg::TraceEvent> {
+        let now = Instant::now();
+        let elapsed = self
+            .last_sync
+            .map_or(Duration::default(), |last| now - last)
+            .min(DRC_MAX_CATCH_UP);
+
+        self.last_sync = Some(now);
+
+        // A fuller queue means samples are being produced too quickly, and vice versa
+        if let Some(ref sink) = self.snd_sink {
+            let fill = sink.len() as f32 / sink.capacity() as f32;
+            self.gb.set_audio_rate_adjust(
+                (1.0 + DRC_MAX_DELTA * (1.0 - 2.0 * fill)) / self.current_speed().factor(),
+            );
+        }
+
+        let speed = f64::from(self.current_speed().factor());
+        let until =
+            self.gb.clock_cycles() + (elapsed.as_float_secs() * speed * CPU_CLOCK as f64) as u64;
+
+        while self.gb.clock_cycles() < until && !self.gb.cpu().stopped {
+            self.gb.step()?;
+        }
+        Ok(())
+    }
+
+    /// Runs the video frames due since the last step, at the hardware frame rate scaled
+    /// by the emulation speed. Here `last_sync` is the time the next frame is due.
+    ///
+    /// If the emulator falls too far behind (eg. after a hiccup), the missed frames
+    /// are skipped rather than run in a burst.
+    fn run_frames_due(&mut self) -> Result<(), dbg::TraceEvent> {
+        let now = Instant::now();
+        let frame_rate = CPU_CLOCK as f64 / CYCLES_PER_FRAME as f64;
+        let period = Duration::from_float_secs(
+            1.0 / (frame_rate * f64::from(self.current_speed().factor())),
+        );
+
+        let mut due = self.last_sync.unwrap_or(now);
+
+        if now > due + period * MAX_FRAMES_BEHIND {
+            due = now;
+        }
+
+        while due <= now {
+            self.gb.run_for_vblank()?;
+            due += period;
+        }
+
+        self.last_sync = Some(due);
+        Ok(())
+    }
+
+    /// Runs the emulator until the call stack shrinks below `depth`, or for at most a frame.
+    fn run_to_return(&mut self, depth: usize) -> Result<(), dbg::TraceEvent> {
+        let limit = self.gb.clock_cycles() + CYCLES_PER_FRAME;
+
+        while self.gb.clock_cycles() < limit {
+            if self.gb.cpu().call_stack.len() < depth {
+                self.pause();
+                break;
+            }
+            self.gb.step()?;
+        }
+        Ok(())
+    }
+
+    /// Runs the emulator until PC reaches `addr` with at most `depth` frames on the
+    /// call stack, or for at most a frame.
+    fn run_to_addr(&mut self, addr: u16, depth: usize) -> Result<(), dbg::TraceEvent> {
+        let limit = self.gb.clock_cycles() + CYCLES_PER_FRAME;
+
+        while self.gb.clock_cycles() < limit {
+            self.gb.step()?;
+
+            let cpu = self.gb.cpu();
+
+            if cpu.pc == addr && cpu.call_stack.len() <= depth {
+                self.pause();
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the emulator until the clock reaches `until`, or for at most a frame.
+    fn run_to_cycle(&mut self, until: u64) -> Result<(), dbg::TraceEvent> {
+        let limit = self.gb.clock_cycles() + CYCLES_PER_FRAME;
+
+        while self.gb.clock_cycles() < until.min(limit) {
+            self.gb.step()?;
+        }
+
+        if self.gb.clock_cycles() >= until {
+            self.pause();
+        }
+        Ok(())
+    }
+
     /// Sets the emulator's audio sink and sample rate.
     pub fn set_audio_sink(&mut self, sink: Arc<ArrayQueue<i16>>, sample_rate: f32) {
         self.snd_sink = Some(sink.clone());
@@ -103,46 +625,252 @@ impl EmuState {
         &self.trace_event
     }
 
+    /// Returns the fault which froze the machine and the PC it happened at, if any.
+    pub fn fault(&self) -> Option<(dbg::TraceEvent, u16)> {
+        self.fault
+    }
+
+    /// Returns the stopwatch timing the breakpoint hits.
+    pub fn stopwatch(&self) -> &dbg::Stopwatch {
+        &self.stopwatch
+    }
+
+    /// Restarts the stopwatch from the current time.
+    pub fn reset_stopwatch(&mut self) {
+        self.stopwatch.reset(self.gb.clock_cycles());
+    }
+
     pub fn set_single_step(&mut self) {
         self.step_to_next = true;
     }
 
+    /// Runs exactly one video frame if paused, otherwise pauses the emulator.
+    pub fn set_frame_advance(&mut self) {
+        if self.paused() {
+            self.frame_advance = true;
+        } else {
+            self.pause();
+        }
+    }
+
+    /// Runs until the function currently being executed returns.
+    pub fn set_step_out(&mut self) {
+        let depth = self.gb.cpu().call_stack.len();
+
+        // There is nowhere to return to from the bottom frame
+        if depth > 1 {
+            self.step_out_depth = Some(depth);
+        }
+    }
+
+    /// Executes a single instruction, treating calls (including RSTs) as a single
+    /// step which runs until they return.
+    pub fn set_step_over(&mut self) {
+        let cpu = self.gb.cpu();
+        let instr = cpu.disasm(self.gb.bus(), cpu.pc);
+
+        match instr.opcode {
+            0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC | 0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7
+            | 0xFF => {
+                let next = cpu.pc.wrapping_add(u16::from(instr.size));
+                self.run_to = Some((next, cpu.call_stack.len()));
+            }
+            _ => self.step_to_next = true,
+        }
+    }
+
+    /// Runs until the instruction at `addr` is about to be executed.
+    pub fn set_run_to(&mut self, addr: u16) {
+        self.run_to = Some((addr, usize::max_value()));
+    }
+
+    /// Runs until the LCD controller starts line `ly`, which is reported as an event.
+    pub fn set_run_to_line(&mut self, ly: u8) {
+        self.gb.break_at_line(Some(ly));
+        self.run_to_breakpoint = true;
+    }
+
+    /// Runs until the beginning of the next V-Blank period.
+    pub fn set_run_to_vblank(&mut self) {
+        self.set_run_to_line(144);
+    }
+
+    /// Runs for `cycles` clock cycles, stopping at the first instruction boundary after them.
+    pub fn set_run_for_cycles(&mut self, cycles: u64) {
+        self.run_until_cycle = Some(self.gb.clock_cycles() + cycles);
+    }
+
     pub fn set_running(&mut self) {
         self.run_to_breakpoint = true;
     }
 
-    /// Sets or resets turbo mode.
-    ///
-    /// In turbo mode, the emulator runs to video-sync rather than audio-sync,
-    /// likely dropping audio samples.
-    pub fn set_turbo(&mut self, enable: bool) {
-        self.turbo_mode = enable;
+    /// Sets a breakpoint at `addr`, or clears it if already set.
+    /// Returns true if the breakpoint is now set.
+    pub fn toggle_breakpoint(&mut self, addr: u16) -> bool {
+        let cpu = self.gb.cpu_mut();
+
+        if cpu.breakpoint_at(addr) {
+            cpu.clear_breakpoint(addr);
+            false
+        } else {
+            cpu.set_breakpoint(addr);
+            true
+        }
+    }
+
+    /// Sets how the emulation speed is kept in sync with the host.
+    pub fn set_sync_mode(&mut self, mode: SyncMode) {
+        self.sync_mode = mode;
+
+        // Only dynamic rate control deviates from the nominal sample rate
+        self.apply_speed();
+    }
+
+    /// Returns how the emulation speed is kept in sync with the host.
+    pub fn sync_mode(&self) -> SyncMode {
+        self.sync_mode
+    }
+
+    /// Sets the emulation speed. Audio samples are produced at a proportionally
+    /// different rate, so that audio sync keeps working (with a shifted pitch).
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        self.apply_speed();
+    }
+
+    /// Returns the emulation speed.
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    /// Sets the speed the emulator runs at while fast-forwarding.
+    pub fn set_fast_forward_speed(&mut self, speed: Speed) {
+        self.fast_forward_speed = speed;
+
+        if self.fast_forward {
+            self.apply_speed();
+        }
+    }
+
+    /// Returns the speed the emulator runs at while fast-forwarding.
+    pub fn fast_forward_speed(&self) -> Speed {
+        self.fast_forward_speed
+    }
+
+    /// Enables or disables fast-forwarding, which overrides the emulation speed.
+    pub fn set_fast_forward(&mut self, enable: bool) {
+        if self.fast_forward != enable {
+            self.fast_forward = enable;
+            self.apply_speed();
+        }
+    }
+
+    /// Returns the speed the emulator is currently running at.
+    fn current_speed(&self) -> Speed {
+        if self.fast_forward {
+            self.fast_forward_speed
+        } else {
+            self.speed
+        }
+    }
+
+    /// Scales the production of audio samples to the current speed. When uncapped,
+    /// no samples are produced at all, as the audio queue can't keep up.
+    fn apply_speed(&mut self) {
+        self.last_sync = None;
+        self.gb
+            .set_audio_rate_adjust(1.0 / self.current_speed().factor());
     }
 
     pub fn paused(&mut self) -> bool {
-        self.gb.cpu().paused() && !(self.step_to_next || self.run_to_breakpoint)
+        if self.fault.is_some() {
+            return true;
+        }
+
+        self.gb.cpu().paused()
+            && !(self.step_to_next
+                || self.frame_advance
+                || self.run_to_breakpoint
+                || self.step_out_depth.is_some()
+                || self.run_to.is_some()
+                || self.run_until_cycle.is_some())
     }
 
-    /// Returns true if turbo mode is enabled, false otherwise.
-    pub fn turbo(&mut self) -> bool {
-        self.turbo_mode
+    /// Returns true if the emulator runs as fast as possible, false otherwise.
+    pub fn uncapped(&self) -> bool {
+        self.current_speed() == Speed::Uncapped
     }
 
     /// Reset the emulator's sate.
-    pub fn reset(&mut self) -> Result<(), Error> {
-        // Save breakpoints to restore after reset
+    pub fn reset(&mut self) -> Result<(), gib_core::Error> {
+        // Save breakpoints and tracing state to restore after reset
         let bkps = self.cpu().breakpoints().clone();
+        let wps = self.watchpoints();
+        let tracing = self.tracing();
+        let io_logging = self.io_logging();
+        let ppu_timeline = self.ppu_timeline();
+        let profiling = self.profiling();
+        let code_tracking = self.code_tracking();
+        let trap_dma_conflicts = self.trap_dma_conflicts();
+        let trap_ppu_conflicts = self.trap_ppu_conflicts();
+        let trap_mbc_ops = self.trap_mbc_ops();
+        let trap_cgb_ops = self.trap_cgb_ops();
+        let irq_breaks = self.irq_breaks();
+        let oam_bug = self.oam_bug();
+        let wave_ram_quirks = self.wave_ram_quirks();
+        let bus_policy = self.bus_policy();
+        let mixer_controls = self.mixer_controls();
+        let trace_filter = self.gb.trace_stream().map(|s| s.filter().clone());
+        let freezes = self.gb.freezes().clone();
+        let cheats = self.gb.cheats().clone();
 
-        self.gb = GameBoy::new();
-        self.gb.load_rom(&(std::fs::read(&self.rom_file)?)[..])?;
+        self.gb = EmuState::power_on(&self.rom_file, &self.boot_rom, self.model, self.dmg_palette)?;
+        self.events = EmuState::subscribe(&mut self.gb);
+        self.serial_output.clear();
+        self.clip.clear();
+        self.fault = None;
 
         if let Some(ref sink) = self.snd_sink {
             self.gb.set_audio_sink(sink.clone(), self.snd_sample_rate);
         }
+        self.apply_speed();
+        self.gb.set_frame_blending(self.frame_blending);
 
         for b in bkps.iter() {
             self.cpu_mut().set_breakpoint(*b);
         }
+        for wp in wps {
+            self.gb.add_watchpoint(wp);
+        }
+
+        if tracing {
+            self.gb.enable_tracing(TRACE_CAPACITY);
+        }
+        if io_logging {
+            self.gb.enable_io_log(IO_LOG_CAPACITY);
+        }
+        if ppu_timeline {
+            self.gb.enable_ppu_timeline(PPU_TIMELINE_FRAMES);
+        }
+        if profiling {
+            self.gb.enable_profiling();
+        }
+        self.gb.set_code_tracking(code_tracking);
+        if let Some(filter) = trace_filter {
+            self.gb.enable_trace_stream(filter);
+        }
+        self.set_trap_dma_conflicts(trap_dma_conflicts);
+        self.set_trap_ppu_conflicts(trap_ppu_conflicts);
+        self.set_trap_mbc_ops(trap_mbc_ops);
+        self.set_trap_cgb_ops(trap_cgb_ops);
+        self.set_irq_breaks(irq_breaks);
+        self.set_oam_bug(oam_bug);
+        self.set_wave_ram_quirks(wave_ram_quirks);
+        self.gb.set_bus_policy(bus_policy);
+        self.gb.set_freezes(freezes);
+        self.gb.set_cheats(cheats);
+        self.set_mixer_controls(mixer_controls);
+        self.reset_stopwatch();
 
         // Default to running state
         self.set_running();
@@ -150,6 +878,406 @@ impl EmuState {
         Ok(())
     }
 
+    /// Enables or disables instruction tracing.
+    pub fn set_tracing(&mut self, enable: bool) {
+        if enable && !self.tracing() {
+            self.gb.enable_tracing(TRACE_CAPACITY);
+        } else if !enable {
+            self.gb.disable_tracing();
+        }
+    }
+
+    /// Returns true if instruction tracing is enabled, false otherwise.
+    pub fn tracing(&self) -> bool {
+        self.gb.tracer().is_some()
+    }
+
+    /// Enables or disables logging of IO register accesses.
+    pub fn set_io_logging(&mut self, enable: bool) {
+        if enable && !self.io_logging() {
+            self.gb.enable_io_log(IO_LOG_CAPACITY);
+        } else if !enable {
+            self.gb.disable_io_log();
+        }
+    }
+
+    /// Returns true if IO register accesses are being logged, false otherwise.
+    pub fn io_logging(&self) -> bool {
+        self.gb.io_log().is_some()
+    }
+
+    /// Enables or disables recording the activity of the LCD controller.
+    pub fn set_ppu_timeline(&mut self, enable: bool) {
+        if enable && !self.ppu_timeline() {
+            self.gb.enable_ppu_timeline(PPU_TIMELINE_FRAMES);
+        } else if !enable {
+            self.gb.disable_ppu_timeline();
+        }
+    }
+
+    /// Returns true if the activity of the LCD controller is being recorded, false otherwise.
+    pub fn ppu_timeline(&self) -> bool {
+        self.gb.ppu_timeline().is_some()
+    }
+
+    /// Dumps the IO log next to the ROM file, returning the path of the log.
+    pub fn dump_io_log(&self) -> Result<PathBuf, Error> {
+        let path = self.rom_file.with_extension("io.log");
+
+        if let Some(log) = self.gb.io_log() {
+            log.dump(&mut BufWriter::new(File::create(&path)?))?;
+        }
+        Ok(path)
+    }
+
+    /// Enables or disables breaking on CPU accesses to memory locked by OAM DMA transfers.
+    pub fn set_trap_dma_conflicts(&mut self, enable: bool) {
+        self.gb.bus_mut().trap_dma_conflicts = enable;
+    }
+
+    /// Returns true if OAM DMA bus conflicts break execution, false otherwise.
+    pub fn trap_dma_conflicts(&self) -> bool {
+        self.gb.bus().trap_dma_conflicts
+    }
+
+    /// Enables or disables breaking on CPU accesses to VRAM/OAM while locked by the PPU.
+    pub fn set_trap_ppu_conflicts(&mut self, enable: bool) {
+        self.gb.bus_mut().trap_ppu_conflicts = enable;
+    }
+
+    /// Returns true if VRAM/OAM accesses during PPU modes 2 and 3 break execution.
+    pub fn trap_ppu_conflicts(&self) -> bool {
+        self.gb.bus().trap_ppu_conflicts
+    }
+
+    /// Enables or disables breaking on bank selects beyond the size of the cartridge.
+    pub fn set_trap_mbc_ops(&mut self, enable: bool) {
+        self.gb.bus_mut().trap_mbc_ops = enable;
+    }
+
+    /// Returns true if invalid MBC operations break execution.
+    pub fn trap_mbc_ops(&self) -> bool {
+        self.gb.bus().trap_mbc_ops
+    }
+
+    /// Enables or disables breaking on writes to CGB registers which are not emulated.
+    pub fn set_trap_cgb_ops(&mut self, enable: bool) {
+        self.gb.bus_mut().trap_cgb_ops = enable;
+    }
+
+    /// Returns true if unsupported CGB operations break execution.
+    pub fn trap_cgb_ops(&self) -> bool {
+        self.gb.bus().trap_cgb_ops
+    }
+
+    /// Sets the interrupts (as a mask laid out like IE) breaking execution when dispatched.
+    pub fn set_irq_breaks(&mut self, mask: u8) {
+        self.gb.set_irq_breaks(mask);
+    }
+
+    /// Returns the mask of interrupts breaking execution when dispatched.
+    pub fn irq_breaks(&self) -> u8 {
+        self.gb.irq_breaks()
+    }
+
+    /// Enables or disables the emulation of the DMG OAM corruption bug.
+    pub fn set_oam_bug(&mut self, enable: bool) {
+        self.gb.bus_mut().oam_bug = enable;
+    }
+
+    /// Returns true if 16-bit INC/DEC of OAM addresses during mode 2 corrupt OAM.
+    pub fn oam_bug(&self) -> bool {
+        self.gb.bus().oam_bug
+    }
+
+    /// Enables or disables the emulation of wave RAM accesses while channel 3 is playing.
+    pub fn set_wave_ram_quirks(&mut self, enable: bool) {
+        self.gb.bus_mut().apu.wave_ram_quirks = enable;
+    }
+
+    /// Returns true if wave RAM accesses hit the byte being played by channel 3.
+    pub fn wave_ram_quirks(&self) -> bool {
+        self.gb.bus().apu.wave_ram_quirks
+    }
+
+    /// Sets how accesses to unmapped memory are handled.
+    pub fn set_bus_policy(&mut self, policy: BusPolicy) {
+        self.gb.set_bus_policy(policy);
+    }
+
+    /// Returns how accesses to unmapped memory are handled.
+    pub fn bus_policy(&self) -> BusPolicy {
+        self.gb.bus().policy
+    }
+
+    /// Sets the mute, solo and volume controls applied to the audio output.
+    pub fn set_mixer_controls(&mut self, controls: MixerControls) {
+        self.gb.bus_mut().apu.set_mixer_controls(controls);
+    }
+
+    /// Returns the mute, solo and volume controls applied to the audio output.
+    pub fn mixer_controls(&self) -> MixerControls {
+        self.gb.bus().apu.mixer_controls()
+    }
+
+    /// Breaks execution when the CPU accesses the memory monitored by `wp`.
+    pub fn add_watchpoint(&mut self, wp: dbg::Watchpoint) {
+        self.gb.add_watchpoint(wp);
+    }
+
+    pub fn remove_watchpoint(&mut self, wp: &dbg::Watchpoint) {
+        self.gb.remove_watchpoint(wp);
+    }
+
+    /// Returns the currently set watchpoints.
+    pub fn watchpoints(&self) -> Vec<dbg::Watchpoint> {
+        self.gb.watchpoints().cloned().collect()
+    }
+
+    /// Dumps the instruction trace next to the ROM file, returning the path of the log.
+    pub fn dump_trace(&self) -> Result<PathBuf, Error> {
+        let path = self.rom_file.with_extension("trace.log");
+
+        if let Some(tracer) = self.gb.tracer() {
+            tracer.dump(&mut BufWriter::new(File::create(&path)?))?;
+        }
+        Ok(path)
+    }
+
+    /// Starts streaming the trace selected by `filter` to a file next to the ROM,
+    /// rotated every `max_size` bytes and keeping `max_files` old files, returning its path.
+    pub fn start_trace_stream(
+        &mut self,
+        filter: dbg::TraceFilter,
+        max_size: u64,
+        max_files: usize,
+    ) -> Result<PathBuf, Error> {
+        let path = self.rom_file.with_extension("stream.log");
+
+        self.trace_file = Some(TraceFile::create(&path, max_size, max_files)?);
+        self.trace_file_error = None;
+        self.gb.enable_trace_stream(filter);
+
+        Ok(path)
+    }
+
+    /// Stops streaming the trace, writing out whatever is left.
+    pub fn stop_trace_stream(&mut self) -> Result<(), Error> {
+        self.write_trace_stream();
+        self.gb.disable_trace_stream();
+
+        if let Some(mut file) = self.trace_file.take() {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Changes the filter of the trace being streamed, if any.
+    pub fn set_trace_filter(&mut self, filter: dbg::TraceFilter) {
+        if let Some(stream) = self.gb.trace_stream_mut() {
+            stream.set_filter(filter);
+        }
+    }
+
+    /// Returns the path of the file the trace is being streamed to, if any.
+    pub fn trace_stream_path(&self) -> Option<&Path> {
+        self.trace_file.as_ref().map(|f| f.path())
+    }
+
+    /// Returns the error which stopped the trace stream, if any.
+    pub fn trace_stream_error(&self) -> Option<&str> {
+        self.trace_file_error.as_ref().map(|e| e.as_str())
+    }
+
+    /// Dumps the report of the executed ROM bytes next to the ROM file, both as text
+    /// and JSON, returning the paths of the reports.
+    pub fn dump_coverage(&self) -> Result<(PathBuf, PathBuf), Error> {
+        let text_path = self.rom_file.with_extension("coverage.txt");
+        let json_path = self.rom_file.with_extension("coverage.json");
+
+        let code_map = self.gb.code_map();
+        let rom_banks = self.gb.bus().cart.rom_banks();
+
+        code_map.dump(&mut BufWriter::new(File::create(&text_path)?), rom_banks)?;
+        code_map.dump_json(&mut BufWriter::new(File::create(&json_path)?), rom_banks)?;
+
+        Ok((text_path, json_path))
+    }
+
+    /// Returns the bytes sent over the link port since the last reset.
+    pub fn serial_output(&self) -> &[u8] {
+        &self.serial_output
+    }
+
+    /// Discards the bytes sent over the link port so far.
+    pub fn clear_serial_output(&mut self) {
+        self.serial_output.clear();
+        self.gb.clear_serial_output();
+    }
+
+    /// Dumps the link port output next to the ROM file, returning the path of the file.
+    pub fn dump_serial_output(&self) -> Result<PathBuf, Error> {
+        let path = self.rom_file.with_extension("serial.txt");
+
+        std::fs::write(&path, &self.serial_output)?;
+        Ok(path)
+    }
+
+    /// Saves the state of the machine next to the ROM file, returning the path of the state.
+    pub fn save_state(&self) -> Result<PathBuf, Error> {
+        let path = self.rom_file.with_extension("state");
+
+        std::fs::write(&path, self.gb.save_state())?;
+        Ok(path)
+    }
+
+    /// Saves the frame being displayed as a PNG image, in a `screenshots` directory
+    /// next to the ROM file, scaled up by `scale`. Returns the path of the image.
+    pub fn save_screenshot(&self, scale: usize) -> Result<PathBuf, Error> {
+        let (dir, name) = self.screenshot_path();
+        screenshot::save(&dir, &name, &self.gb.screenshot(), scale)
+    }
+
+    /// Saves the last seconds of emulation as an animated GIF, next to the screenshots.
+    /// Returns the path of the clip.
+    pub fn save_clip(&self) -> Result<PathBuf, Error> {
+        let (dir, name) = self.screenshot_path();
+        self.clip.save(&dir, &name)
+    }
+
+    /// Returns the directory of the screenshots and the name they start with.
+    fn screenshot_path(&self) -> (PathBuf, String) {
+        let dir = match self.rom_file.parent() {
+            Some(dir) => dir.join("screenshots"),
+            None => PathBuf::from("screenshots"),
+        };
+        let name = self
+            .rom_file
+            .file_stem()
+            .map_or_else(|| "screenshot".into(), |s| s.to_string_lossy().into_owned());
+
+        (dir, name)
+    }
+
+    /// Restores the state previously saved next to the ROM file, returning its path.
+    pub fn load_state(&mut self) -> Result<PathBuf, gib_core::Error> {
+        let path = self.rom_file.with_extension("state");
+
+        self.load_state_from(&path)?;
+        Ok(path)
+    }
+
+    /// Restores the state saved in the file at `path`.
+    pub fn load_state_from(&mut self, path: &Path) -> Result<(), gib_core::Error> {
+        self.gb.load_state(&std::fs::read(path)?)?;
+        self.last_sync = None;
+        self.fault = None;
+        Ok(())
+    }
+
+    /// Starts recording the joypad inputs of every frame in a movie, from the current state.
+    pub fn start_recording(&mut self) {
+        self.gb.start_recording();
+    }
+
+    /// Replays the movie previously recorded next to the ROM file, returning its path.
+    pub fn start_playback(&mut self) -> Result<PathBuf, gib_core::Error> {
+        let path = self.rom_file.with_extension("movie");
+        let movie = Movie::from_bytes(&std::fs::read(&path)?)?;
+
+        self.gb.start_playback(movie)?;
+        self.last_sync = None;
+        Ok(path)
+    }
+
+    /// Stops the active movie. If it was being recorded, it is written next to the ROM
+    /// file and its path is returned.
+    pub fn stop_movie(&mut self) -> Result<Option<PathBuf>, Error> {
+        let recording = self.movie_status().map(|(mode, ..)| mode) == Some(MovieMode::Recording);
+
+        match self.gb.stop_movie() {
+            Some(ref movie) if recording => {
+                let path = self.rom_file.with_extension("movie");
+
+                std::fs::write(&path, movie.to_bytes())?;
+                Ok(Some(path))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the mode of the active movie, if any, along with the current frame
+    /// and the number of frames in the movie.
+    pub fn movie_status(&self) -> Option<(MovieMode, usize, usize)> {
+        self.gb.movie_status()
+    }
+
+    /// Enables or disables the execution profiler.
+    pub fn set_profiling(&mut self, enable: bool) {
+        if enable && !self.profiling() {
+            self.gb.enable_profiling();
+        } else if !enable {
+            self.gb.disable_profiling();
+        }
+    }
+
+    /// Returns true if the execution profiler is enabled, false otherwise.
+    pub fn profiling(&self) -> bool {
+        self.gb.profiler().is_some()
+    }
+
+    /// Enables or disables marking the bytes executed as code, for the disassembler.
+    pub fn set_code_tracking(&mut self, enable: bool) {
+        self.gb.set_code_tracking(enable);
+    }
+
+    pub fn code_tracking(&self) -> bool {
+        self.gb.code_tracking()
+    }
+
+    /// Asks the memory editor to show the memory at `addr`.
+    pub fn show_in_mem_editor(&mut self, addr: u16) {
+        self.mem_editor_goto = Some(addr);
+    }
+
+    /// Returns the address the memory editor has been asked to show, if any.
+    pub fn mem_editor_goto(&self) -> Option<u16> {
+        self.mem_editor_goto
+    }
+
+    /// Returns the address the memory editor has been asked to show, clearing the request.
+    pub fn take_mem_editor_goto(&mut self) -> Option<u16> {
+        self.mem_editor_goto.take()
+    }
+
+    /// Replaces the symbols with those listed in the RGBDS symbol file at `path`.
+    pub fn load_symbols<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        self.symbols = dbg::Symbols::parse(&std::fs::read_to_string(path)?);
+        Ok(())
+    }
+
+    pub fn symbols(&self) -> &dbg::Symbols {
+        &self.symbols
+    }
+
+    /// Returns the name of `addr` in the bank currently mapped there, if any.
+    pub fn label_at(&self, addr: u16) -> Option<&str> {
+        self.symbols.label(self.gb.bus().bank_at(addr), addr)
+    }
+
+    /// Parses a location typed by the user: either a symbol name or a hex address,
+    /// optionally prefixed by `$` or `0x`.
+    pub fn parse_location(&self, text: &str) -> Option<u16> {
+        let text = text.trim();
+
+        if let Some((_, addr)) = self.symbols.lookup(text) {
+            return Some(addr);
+        }
+
+        let hex = text.trim_start_matches('$').trim_start_matches("0x");
+        u16::from_str_radix(hex, 16).ok()
+    }
+
     pub fn gameboy(&self) -> &GameBoy {
         &self.gb
     }
@@ -169,4 +1297,83 @@ impl EmuState {
     pub fn bus(&self) -> &Bus {
         self.gb.bus()
     }
+
+    /// Rewrites `addr` with `val` every frame, and saves the freeze list of the ROM.
+    pub fn freeze(&mut self, addr: u16, val: u8) -> Result<(), Error> {
+        let mut freezes = self.gb.freezes().clone();
+        freezes.freeze(addr, val);
+
+        self.gb.set_freezes(freezes);
+        self.save_freezes()
+    }
+
+    /// Stops rewriting `addr`, and saves the freeze list of the ROM.
+    pub fn unfreeze(&mut self, addr: u16) -> Result<(), Error> {
+        let mut freezes = self.gb.freezes().clone();
+        freezes.unfreeze(addr);
+
+        self.gb.set_freezes(freezes);
+        self.save_freezes()
+    }
+
+    pub fn freezes(&self) -> &dbg::FreezeList {
+        self.gb.freezes()
+    }
+
+    /// Writes the freeze list of the ROM to the configuration directory.
+    fn save_freezes(&self) -> Result<(), Error> {
+        let path = match Config::freeze_path(&self.rom_file) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, self.gb.freezes().to_string())?;
+
+        Ok(())
+    }
+
+    /// Adds a Game Genie or GameShark code, and saves the cheats of the ROM.
+    pub fn add_cheat(&mut self, cheat: Cheat) -> Result<(), Error> {
+        self.update_cheats(|cheats| cheats.add(cheat))
+    }
+
+    pub fn remove_cheat(&mut self, index: usize) -> Result<(), Error> {
+        self.update_cheats(|cheats| cheats.remove(index))
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) -> Result<(), Error> {
+        self.update_cheats(|cheats| cheats.set_enabled(index, enabled))
+    }
+
+    pub fn cheats(&self) -> &Cheats {
+        self.gb.cheats()
+    }
+
+    /// Applies `f` to the cheats in effect, and writes them to the configuration directory.
+    fn update_cheats<F: FnOnce(&mut Cheats)>(&mut self, f: F) -> Result<(), Error> {
+        let mut cheats = self.gb.cheats().clone();
+        f(&mut cheats);
+        self.gb.set_cheats(cheats);
+
+        let path = match Config::cheats_path(&self.rom_file) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, self.gb.cheats().to_string())?;
+
+        Ok(())
+    }
+
+    /// Writes `val` to `addr` without any side effect, eg. to edit memory while debugging.
+    /// Locations which cannot be written, such as ROM, are left untouched.
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.gb.bus_mut().poke(addr, val);
+    }
 }
@@ -0,0 +1,265 @@
+use gib_core::{run_test_rom, TestOutcome};
+
+use super::utils;
+
+use failure::Error;
+use imgui::{im_str, ImGuiCond, ImString, Ui};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+/// Maximum number of cycles a single ROM is allowed to run for (~30 seconds).
+const CYCLE_BUDGET: u64 = 30 * 4_194_304;
+
+/// Test ROM of a suite, eg. Blargg's, mooneye-gb's or dmg-acid2.
+#[derive(Clone)]
+pub struct TestRom {
+    /// Path of the ROM, relative to the directory of the suite
+    pub name: String,
+    pub path: PathBuf,
+
+    // Image the last frame must match, from a `.bin` file next to the ROM
+    reference: Option<PathBuf>,
+}
+
+impl TestRom {
+    /// Finds the ROMs in `dir` and its subdirectories, sorted by name.
+    pub fn find_all(dir: &Path) -> Result<Vec<TestRom>, Error> {
+        let mut roms = vec![];
+        TestRom::find_in(dir, dir, &mut roms)?;
+
+        roms.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(roms)
+    }
+
+    fn find_in(root: &Path, dir: &Path, roms: &mut Vec<TestRom>) -> Result<(), Error> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                TestRom::find_in(root, &path, roms)?;
+            } else if path.extension() == Some("gb".as_ref())
+                || path.extension() == Some("gbc".as_ref())
+            {
+                let reference = Some(path.with_extension("bin")).filter(|p| p.is_file());
+
+                roms.push(TestRom {
+                    name: path
+                        .strip_prefix(root)
+                        .unwrap_or(&path)
+                        .display()
+                        .to_string(),
+                    path,
+                    reference,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the ROM headlessly until it reports a result.
+    pub fn run(&self) -> TestOutcome {
+        let rom = match fs::read(&self.path) {
+            Ok(rom) => rom,
+            Err(e) => return TestOutcome::Error(e.to_string()),
+        };
+
+        let reference = match self.reference {
+            Some(ref path) => match fs::read(path) {
+                Ok(reference) => Some(reference),
+                Err(e) => return TestOutcome::Error(e.to_string()),
+            },
+            None => None,
+        };
+
+        run_test_rom(&rom, reference.as_ref().map(|r| &r[..]), CYCLE_BUDGET)
+    }
+}
+
+/// Runs all the ROMs of the suite in `dir`, printing a table of the results.
+/// Returns the number of ROMs which did not pass.
+pub fn run_suite(dir: &Path) -> Result<usize, Error> {
+    let roms = TestRom::find_all(dir)?;
+    let mut failed = 0;
+
+    println!("{:<48} result", "ROM");
+
+    for rom in &roms {
+        let outcome = rom.run();
+
+        match outcome {
+            TestOutcome::Fail(ref output) | TestOutcome::Error(ref output) => {
+                println!("{:<48} {}\n{}", rom.name, outcome, output)
+            }
+            _ => println!("{:<48} {}", rom.name, outcome),
+        }
+
+        if !outcome.passed() {
+            failed += 1;
+        }
+    }
+
+    println!("{}/{} passed", roms.len() - failed, roms.len());
+    Ok(failed)
+}
+
+/// Dialog running a suite of test ROMs in the background, showing the results as they
+/// come. Failing ROMs can be opened in the debugger.
+pub struct TestSuiteDialog {
+    dir: ImString,
+    roms: Vec<TestRom>,
+    results: Vec<Option<TestOutcome>>,
+    running: Option<Receiver<(usize, TestOutcome)>>,
+
+    // Error finding the ROMs, if any
+    status: Option<String>,
+
+    // ROM to open in the debugger
+    debug_rom: Option<PathBuf>,
+}
+
+impl TestSuiteDialog {
+    pub fn new() -> TestSuiteDialog {
+        TestSuiteDialog {
+            dir: ImString::with_capacity(256),
+            roms: vec![],
+            results: vec![],
+            running: None,
+            status: None,
+            debug_rom: None,
+        }
+    }
+
+    /// Returns the ROM the user asked to open in the debugger, if any.
+    pub fn take_debug_rom(&mut self) -> Option<PathBuf> {
+        self.debug_rom.take()
+    }
+
+    /// Finds the ROMs in the chosen directory and runs them in a separate thread.
+    fn start(&mut self) {
+        let roms = match TestRom::find_all(Path::new(self.dir.to_str())) {
+            Ok(roms) => roms,
+            Err(e) => {
+                self.status = Some(format!("Cannot read the directory: {}", e));
+                return;
+            }
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let queue = roms.clone();
+
+        std::thread::spawn(move || {
+            for (i, rom) in queue.iter().enumerate() {
+                // Stop as soon as the dialog is closed or the suite restarted
+                if tx.send((i, rom.run())).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.results = vec![None; roms.len()];
+        self.roms = roms;
+        self.running = Some(rx);
+        self.status = None;
+    }
+
+    /// Collects the results of the ROMs completed since the last frame.
+    fn poll(&mut self) {
+        if let Some(ref rx) = self.running {
+            for (i, outcome) in rx.try_iter() {
+                self.results[i] = Some(outcome);
+            }
+        }
+
+        if self.results.iter().all(Option::is_some) {
+            self.running = None;
+        }
+    }
+
+    fn draw_controls(&mut self, ui: &Ui) {
+        ui.push_item_width(300.0);
+        ui.input_text(im_str!("Directory"), &mut self.dir).build();
+        ui.pop_item_width();
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Run"), (0.0, 0.0)) {
+            self.start();
+        }
+
+        if let Some(ref status) = self.status {
+            ui.text_colored(utils::RED, im_str!("{}", status));
+        } else if !self.roms.is_empty() {
+            let done = self.results.iter().filter(|r| r.is_some()).count();
+            let passed = self.results.iter().flatten().filter(|r| r.passed()).count();
+
+            ui.text(format!(
+                "{}/{} passed, {} to run",
+                passed,
+                self.roms.len(),
+                self.roms.len() - done
+            ));
+        }
+    }
+
+    fn draw_results(&mut self, ui: &Ui) {
+        ui.columns(3, im_str!("test_results"), true);
+
+        for (i, (rom, result)) in self.roms.iter().zip(self.results.iter()).enumerate() {
+            ui.text(&rom.name);
+            ui.next_column();
+
+            match result {
+                Some(outcome) => {
+                    let color = if outcome.passed() {
+                        utils::GREEN
+                    } else {
+                        utils::RED
+                    };
+                    ui.text_colored(color, im_str!("{}", outcome));
+
+                    // Show what the ROM reported when hovering its result
+                    match outcome {
+                        TestOutcome::Fail(output) | TestOutcome::Error(output) => {
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text(output);
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                None => ui.text_colored(utils::DARK_GREY, im_str!("...")),
+            }
+            ui.next_column();
+
+            if result.as_ref().map_or(false, |r| !r.passed())
+                && ui.small_button(im_str!("Debug##test{}", i))
+            {
+                self.debug_rom = Some(rom.path.clone());
+            }
+            ui.next_column();
+        }
+
+        ui.columns(1, im_str!("test_results"), false);
+    }
+
+    /// Draws the dialog, returning false once it is closed.
+    pub fn build(&mut self, ui: &Ui) -> bool {
+        let mut open = true;
+
+        self.poll();
+
+        ui.window(im_str!("Test ROMs"))
+            .size((560.0, 400.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_controls(ui);
+
+                ui.separator();
+
+                self.draw_results(ui);
+            });
+
+        open
+    }
+}
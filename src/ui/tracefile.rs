@@ -0,0 +1,89 @@
+use gib_core::dbg::TraceRecord;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// File the trace is streamed to, rotated once it grows past a size limit:
+/// `<file>` is renamed to `<file>.1`, `<file>.1` to `<file>.2` and so on,
+/// and the files beyond the limit are deleted.
+pub struct TraceFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+
+    out: BufWriter<File>,
+    size: u64,
+}
+
+impl TraceFile {
+    /// Creates the trace file at `path`, keeping at most `max_files` rotated files
+    /// of about `max_size` bytes besides the current one.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        max_size: u64,
+        max_files: usize,
+    ) -> io::Result<TraceFile> {
+        let path = path.as_ref().to_path_buf();
+        let out = BufWriter::new(File::create(&path)?);
+
+        Ok(TraceFile {
+            path,
+            max_size,
+            max_files,
+            out,
+            size: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `records` to the file, one per line, rotating it when full.
+    pub fn write(&mut self, records: &[TraceRecord]) -> io::Result<()> {
+        for record in records {
+            let line = format!("{}\n", record);
+
+            self.out.write_all(line.as_bytes())?;
+            self.size += line.len() as u64;
+
+            if self.size >= self.max_size {
+                self.rotate()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Moves the current file to `<file>.1`, shifting the older ones, and starts a new one.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+
+        if self.max_files > 0 {
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated_path(n);
+
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.out = BufWriter::new(File::create(&self.path)?);
+        self.size = 0;
+
+        Ok(())
+    }
+
+    /// Returns the path of the `n`-th most recent rotated file.
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+}
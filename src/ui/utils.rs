@@ -1,7 +1,9 @@
 use imgui::{im_str, ImStr, ImString, Ui};
 
+use glutin::VirtualKeyCode as Key;
+
 use std::ops::Range;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 pub const DARK_GREY: [f32; 4] = [0.6, 0.6, 0.6, 1.0];
@@ -10,77 +12,155 @@ pub const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
 pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 pub const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
 pub const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+pub const LIGHT_BLUE: [f32; 4] = [0.5, 0.8, 1.0, 1.0];
 
+/// Built-in file picker, used when the native dialog of the OS is disabled. Files are
+/// opened by double-clicking them, or from the keyboard (see `handle_key`).
 pub struct FileDialog {
     title: ImString,
     current_dir: PathBuf,
+    extensions: &'static [&'static str],
     file_list: Vec<ImString>,
+    selected: i32,
     click_timer: Option<Duration>,
+
+    // Error listing the last directory opened, if any
+    status: Option<String>,
+
+    // Set from the keyboard, handled on the next build
+    activate: bool,
+    cancelled: bool,
 }
 
 impl FileDialog {
-    pub fn new<T>(title: T) -> FileDialog
+    /// Creates a dialog listing the files in `dir`, or in the current directory if `None`.
+    /// Only the files with one of the `extensions` are listed, or all if empty.
+    pub fn new<T>(title: T, dir: Option<&Path>, extensions: &'static [&'static str]) -> FileDialog
     where
         T: Into<String>,
     {
-        use std::env::current_dir;
+        let dir = match dir {
+            Some(dir) if dir.is_dir() => dir.to_path_buf(),
+            _ => std::env::current_dir().unwrap_or_default(),
+        };
 
         let mut fd = FileDialog {
             title: ImString::new(title),
-            current_dir: current_dir().unwrap(),
-            file_list: vec![],
+            current_dir: dir.clone(),
+            extensions,
+            file_list: vec![ImString::new("../")],
+            selected: 0,
             click_timer: None,
+            status: None,
+            activate: false,
+            cancelled: false,
         };
 
-        fd.chdir();
+        fd.chdir(dir);
         fd
     }
 
-    fn is_dir(s: &ImStr) -> bool {
-        use std::str::pattern::Pattern;
-        "/".is_suffix_of(s.to_str())
+    fn is_dir(s: &str) -> bool {
+        s.ends_with('/')
+    }
+
+    /// Lists `dir`, keeping the current listing if it cannot be read.
+    fn chdir(&mut self, dir: PathBuf) {
+        match FileDialog::list(&dir, self.extensions) {
+            Ok(file_list) => {
+                self.current_dir = dir;
+                self.file_list = file_list;
+                self.selected = 0;
+                self.status = None;
+            }
+            Err(e) => self.status = Some(format!("Cannot open {}: {}", dir.display(), e)),
+        }
+    }
+
+    fn chdir_parent(&mut self) {
+        if let Some(parent) = self.current_dir.parent() {
+            self.chdir(parent.to_path_buf());
+        }
     }
 
-    fn chdir(&mut self) {
-        use std::cmp::Ordering;
+    /// Returns the parent directory, then the directories and the matching files
+    /// in `dir`, sorted by name. Entries which cannot be read are skipped.
+    fn list(dir: &Path, extensions: &[&str]) -> std::io::Result<Vec<ImString>> {
+        let mut dirs = vec![];
+        let mut files = vec![];
 
-        self.file_list = std::fs::read_dir(&self.current_dir)
-            .unwrap()
-            .map(|de| {
-                let de = de.unwrap();
-                let mut n = de.file_name().into_string().unwrap();
+        for entry in std::fs::read_dir(dir)?.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().into_owned();
 
-                if de.file_type().unwrap().is_dir() {
-                    n += "/";
-                }
-                ImString::from(n)
-            })
-            .collect::<Vec<_>>();
-
-        self.file_list.sort_by(|a, b| {
-            let a_is_dir = FileDialog::is_dir(a);
-            let b_is_dir = FileDialog::is_dir(b);
-
-            if (a_is_dir && b_is_dir) || (!a_is_dir && !b_is_dir) {
-                a.cmp(b)
-            } else if a_is_dir {
-                Ordering::Less
-            } else {
-                Ordering::Greater
+            if entry.path().is_dir() {
+                dirs.push(name + "/");
+            } else if FileDialog::has_extension(&name, extensions) {
+                files.push(name);
             }
-        });
+        }
+
+        dirs.sort();
+        files.sort();
+
+        Ok(std::iter::once("../".to_string())
+            .chain(dirs)
+            .chain(files)
+            .map(ImString::from)
+            .collect())
+    }
+
+    /// Returns true if the file named `name` has one of `extensions`, or if there are none.
+    fn has_extension(name: &str, extensions: &[&str]) -> bool {
+        match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+            _ if extensions.is_empty() => true,
+            Some(ext) => extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)),
+            None => false,
+        }
+    }
 
-        // Prepend the parent directory to the listing
-        self.file_list
-            .splice(0..0, [ImString::from(String::from("../"))].iter().cloned());
+    /// Opens the selected directory, or returns the path of the selected file.
+    fn open_selected(&mut self) -> Option<PathBuf> {
+        let selection = self
+            .file_list
+            .get(self.selected as usize)?
+            .to_str()
+            .to_string();
+
+        if selection == "../" {
+            self.chdir_parent();
+            None
+        } else if FileDialog::is_dir(&selection) {
+            let dir = self.current_dir.join(selection.trim_end_matches('/'));
+            self.chdir(dir);
+            None
+        } else {
+            Some(self.current_dir.join(selection))
+        }
+    }
+
+    /// Handles a key pressed while the dialog is open: the arrows move the selection,
+    /// enter opens it, backspace goes to the parent directory and escape cancels.
+    pub fn handle_key(&mut self, key: Key) {
+        let last = self.file_list.len() as i32 - 1;
+
+        match key {
+            Key::Up => self.selected = (self.selected - 1).max(0),
+            Key::Down => self.selected = (self.selected + 1).min(last),
+            Key::Home => self.selected = 0,
+            Key::End => self.selected = last,
+            Key::Return | Key::NumpadEnter => self.activate = true,
+            Key::Back => self.chdir_parent(),
+            Key::Escape => self.cancelled = true,
+            _ => (),
+        }
     }
 
     pub fn build<F>(&mut self, delta_s: f32, ui: &Ui, mut on_result: F)
     where
         F: FnMut(Option<PathBuf>),
     {
-        let mut selected = 0;
         let mut clicked = false;
+        let mut cancelled = self.cancelled;
 
         ui.open_popup(ImStr::new(&self.title));
 
@@ -88,20 +168,34 @@ impl FileDialog {
             .resizable(false)
             .always_auto_resize(true)
             .build(|| {
+                ui.text(self.current_dir.display().to_string());
+
                 let fl = self
                     .file_list
                     .iter()
                     .map(|s| s.as_ref())
                     .collect::<Vec<_>>();
 
-                clicked = ui.list_box(im_str!(""), &mut selected, &fl, 10);
+                clicked = ui.list_box(im_str!(""), &mut self.selected, &fl, 10);
+
+                if let Some(ref status) = self.status {
+                    ui.text_colored(RED, im_str!("{}", status));
+                }
 
                 if ui.button(im_str!("Cancel"), (0.0, 0.0)) {
+                    cancelled = true;
+                }
+
+                if cancelled {
                     ui.close_current_popup();
-                    on_result(None);
                 }
             });
 
+        if cancelled {
+            on_result(None);
+            return;
+        }
+
         // Update internal state
         self.click_timer = self.click_timer.map_or_else(
             || None,
@@ -111,20 +205,19 @@ impl FileDialog {
         // Check for double clicks
         if clicked {
             if self.click_timer.is_some() {
-                let selection = &self.file_list[selected as usize];
-
-                if FileDialog::is_dir(selection) {
-                    self.current_dir.push(selection.to_str());
-                    self.chdir();
-                } else {
-                    on_result(Some(
-                        PathBuf::from(&self.current_dir).join(selection.to_str()),
-                    ));
-                }
+                self.activate = true;
             } else {
                 self.click_timer = Some(Duration::from_millis(200));
             }
         }
+
+        if self.activate {
+            self.activate = false;
+
+            if let Some(file) = self.open_selected() {
+                on_result(Some(file));
+            }
+        }
     }
 }
 
@@ -185,6 +278,27 @@ pub fn input_addr(ui: &Ui, name: &str, val: &mut Option<u16>, editable: bool) {
     *val = u16::from_str_radix(buf.to_str(), 16).ok();
 }
 
+/// Same as `input_addr`, for 8-bit values.
+pub fn input_byte(ui: &Ui, name: &str, val: &mut Option<u8>, editable: bool) {
+    let mut buf = if let Some(v) = val {
+        ImString::from(format!("{:02X}", v))
+    } else {
+        ImString::with_capacity(2)
+    };
+
+    ui.push_item_width(22.0);
+    ui.input_text(ImStr::new(&ImString::from(String::from(name))), &mut buf)
+        .chars_hexadecimal(true)
+        .chars_noblank(true)
+        .chars_uppercase(true)
+        .auto_select_all(true)
+        .read_only(!editable)
+        .build();
+    ui.pop_item_width();
+
+    *val = u8::from_str_radix(buf.to_str(), 16).ok();
+}
+
 /// Converts a slice of bytes into its ASCII representation
 /// if the corresponding character is visible, otherwise into a '.'.
 pub fn format_ascii(data: &[u8]) -> String {
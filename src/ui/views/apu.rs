@@ -0,0 +1,164 @@
+use gib_core::io::ChannelStatus;
+use gib_core::mem::MemR;
+
+use super::utils;
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, Ui};
+
+/// Channel names, along with the address of their first register and the
+/// clock driving their frequency timer.
+const CHANNELS: [(&str, u16, u32); 3] = [
+    ("Square 1", 0xFF10, 131_072),
+    ("Square 2", 0xFF15, 131_072),
+    ("Wave", 0xFF1A, 65_536),
+];
+
+/// Height of the oscilloscope, in pixels.
+const SCOPE_HEIGHT: f32 = 80.0;
+
+/// Largest sample value shown in the oscilloscope.
+const SCOPE_RANGE: f32 = 400.0;
+
+/// View showing the state of the sound controller, along with its recent output.
+pub struct ApuView;
+
+impl ApuView {
+    pub fn new() -> ApuView {
+        ApuView
+    }
+
+    fn draw_control(&self, ui: &Ui, state: &EmuState) {
+        let bus = state.bus();
+
+        let (nr50, nr51, nr52) = (bus.peek(0xFF24), bus.peek(0xFF25), bus.peek(0xFF26));
+
+        ui.text(format!(
+            "NR50: {:02X}  NR51: {:02X}  NR52: {:02X}",
+            nr50, nr51, nr52
+        ));
+        ui.text(format!(
+            "Power: {}  Volume L: {}  R: {}",
+            if nr52 & 0x80 != 0 { "ON " } else { "OFF" },
+            (nr50 >> 4) & 0x07,
+            nr50 & 0x07,
+        ));
+        ui.text(format!(
+            "Frame sequencer step: {}",
+            bus.apu.frame_seq_step()
+        ));
+    }
+
+    fn draw_channel(&self, ui: &Ui, state: &EmuState, ch: usize, status: &ChannelStatus) {
+        let (name, base, clock) = CHANNELS[ch];
+        let bus = state.bus();
+        let regs = (0..5).map(|i| bus.peek(base + i)).collect::<Vec<_>>();
+
+        let color = if status.enabled {
+            utils::GREEN
+        } else {
+            utils::DARK_GREY
+        };
+        ui.text_colored(color, im_str!("{}", name));
+
+        ui.text(format!(
+            "NR{0}0: {1:02X}  NR{0}1: {2:02X}  NR{0}2: {3:02X}  NR{0}3: {4:02X}  NR{0}4: {5:02X}",
+            ch + 1,
+            regs[0],
+            regs[1],
+            regs[2],
+            regs[3],
+            regs[4]
+        ));
+
+        // Registers specific to each kind of channel
+        if ch < 2 {
+            if ch == 0 {
+                ui.text(format!(
+                    "Sweep: period {}  {}  shift {}",
+                    (regs[0] >> 4) & 0x07,
+                    if regs[0] & 0x08 != 0 { "DOWN" } else { "UP  " },
+                    regs[0] & 0x07,
+                ));
+            }
+            ui.text(format!(
+                "Duty: {:4.1}%  Envelope: start {}  {}  period {}",
+                [12.5, 25.0, 50.0, 75.0][usize::from(regs[1] >> 6)],
+                regs[2] >> 4,
+                if regs[2] & 0x08 != 0 { "UP  " } else { "DOWN" },
+                regs[2] & 0x07,
+            ));
+        } else {
+            ui.text(format!(
+                "Output level: {}",
+                ["0%", "100%", "50%", "25%"][usize::from((regs[2] >> 5) & 0x03)]
+            ));
+        }
+
+        let hz = clock as f32 / f32::from(2048 - status.frequency);
+
+        ui.text(format!(
+            "Freq: {:03X} ({:7.1}Hz)  Volume: {:2}  Length: {:3}{}",
+            status.frequency,
+            hz,
+            status.volume,
+            status.length,
+            if status.length_enabled { "" } else { " (off)" },
+        ));
+
+        if !status.dac_on {
+            ui.same_line(0.0);
+            ui.text_colored(utils::RED, im_str!("DAC OFF"));
+        }
+    }
+
+    fn draw_scope(&self, ui: &Ui, state: &EmuState) {
+        let samples = state.bus().apu.recent_samples().collect::<Vec<_>>();
+        let width = samples.len() as f32;
+
+        ui.child_frame(im_str!("apu_scope"), (width, SCOPE_HEIGHT))
+            .build(|| {
+                let origin = ui.get_cursor_screen_pos();
+                let draw_list = ui.get_window_draw_list();
+
+                let pos = |x: usize, s: i16| {
+                    let y = (f32::from(s) / SCOPE_RANGE).max(-1.0).min(1.0);
+                    (
+                        origin.0 + x as f32,
+                        origin.1 + (1.0 - y) * SCOPE_HEIGHT / 2.0,
+                    )
+                };
+
+                for (x, pair) in samples.windows(2).enumerate() {
+                    draw_list
+                        .add_line(pos(x, pair[0]), pos(x + 1, pair[1]), utils::GREEN)
+                        .build();
+                }
+            });
+    }
+}
+
+impl WindowView for ApuView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("APU"))
+            .size((540.0, 480.0), ImGuiCond::FirstUseEver)
+            .position((440.0, 30.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_control(ui, state);
+
+                for (ch, status) in state.bus().apu.channel_status().iter().enumerate() {
+                    ui.separator();
+                    self.draw_channel(ui, state, ch, status);
+                }
+
+                ui.separator();
+
+                self.draw_scope(ui, state);
+            });
+
+        open
+    }
+}
@@ -0,0 +1,165 @@
+use gib_core::io::BG_MAP_SIZE;
+use gib_core::mem::MemR;
+
+use super::utils;
+use super::{EmuState, UiContext, WindowView};
+
+use imgui::{im_str, ImGuiCond, ImTexture, Ui};
+
+/// Screen size, in pixels.
+const SCREEN_SIZE: (u16, u16) = (160, 144);
+
+pub struct BgMapView {
+    high_map: bool,
+    show_viewport: bool,
+    show_window: bool,
+    buffer: Vec<u8>,
+    texture: Option<ImTexture>,
+}
+
+impl BgMapView {
+    pub fn new() -> BgMapView {
+        BgMapView {
+            high_map: false,
+            show_viewport: true,
+            show_window: true,
+            buffer: vec![0xFF; BG_MAP_SIZE * BG_MAP_SIZE * 4],
+            texture: None,
+        }
+    }
+
+    fn draw_controls(&mut self, ui: &Ui, state: &EmuState) {
+        if ui.radio_button_bool(im_str!("0x9800"), !self.high_map) {
+            self.high_map = false;
+        }
+        ui.same_line(0.0);
+
+        if ui.radio_button_bool(im_str!("0x9C00"), self.high_map) {
+            self.high_map = true;
+        }
+        ui.same_line(0.0);
+
+        ui.checkbox(im_str!("Viewport"), &mut self.show_viewport);
+        ui.same_line(0.0);
+
+        ui.checkbox(im_str!("Window"), &mut self.show_window);
+
+        let ppu = &state.bus().ppu;
+
+        ui.text(format!(
+            "SCX: {:02X}  SCY: {:02X}  WX: {:02X}  WY: {:02X}",
+            ppu.peek(0xFF43),
+            ppu.peek(0xFF42),
+            ppu.peek(0xFF4B),
+            ppu.peek(0xFF4A),
+        ));
+    }
+
+    /// Draws the rectangles showing which part of the map ends up on screen.
+    fn draw_overlay(&self, ui: &Ui, state: &EmuState, origin: (f32, f32)) {
+        let ppu = &state.bus().ppu;
+        let lcdc = ppu.peek(0xFF40);
+
+        let draw_list = ui.get_window_draw_list();
+
+        // Draws a rectangle at (x, y) in map coordinates, splitting it in
+        // multiple pieces if it wraps around the edges of the map.
+        let draw_rect = |x: u16, y: u16, (w, h): (u16, u16), color: [f32; 4]| {
+            let split = |start: u16, len: u16| {
+                let size = BG_MAP_SIZE as u16;
+
+                if start + len > size {
+                    vec![(start, size), (0, start + len - size)]
+                } else {
+                    vec![(start, start + len)]
+                }
+            };
+
+            for &(x0, x1) in split(x, w).iter() {
+                for &(y0, y1) in split(y, h).iter() {
+                    draw_list
+                        .add_rect(
+                            (origin.0 + f32::from(x0), origin.1 + f32::from(y0)),
+                            (origin.0 + f32::from(x1), origin.1 + f32::from(y1)),
+                            color,
+                        )
+                        .build();
+                }
+            }
+        };
+
+        // LCDC bit 3 selects the BG map, and bit 6 the Window one
+        let bg_high = lcdc & 0x08 != 0;
+        let win_high = lcdc & 0x40 != 0;
+        let win_enabled = lcdc & 0x20 != 0;
+
+        // The viewport follows the scroll registers latched by each line, so that
+        // raster effects changing them during H-Blank are shown too.
+        if self.show_viewport && bg_high == self.high_map {
+            let regs = ppu.line_registers();
+            let size = BG_MAP_SIZE as u16;
+
+            for (ly, r) in regs.iter().enumerate() {
+                let x = u16::from(r.scx);
+                let y = (u16::from(r.scy) + ly as u16) % size;
+
+                if ly == 0 || ly == regs.len() - 1 {
+                    draw_rect(x, y, (SCREEN_SIZE.0, 1), utils::RED);
+                } else {
+                    draw_rect(x, y, (1, 1), utils::RED);
+                    draw_rect((x + SCREEN_SIZE.0 - 1) % size, y, (1, 1), utils::RED);
+                }
+            }
+        }
+
+        // The Window is drawn from the top-left corner of its map, starting at (WX-7, WY)
+        if self.show_window && win_enabled && win_high == self.high_map {
+            let wx = u16::from(ppu.peek(0xFF4B)).saturating_sub(7);
+            let wy = u16::from(ppu.peek(0xFF4A));
+
+            if wx < SCREEN_SIZE.0 && wy < SCREEN_SIZE.1 {
+                let size = (SCREEN_SIZE.0 - wx, SCREEN_SIZE.1 - wy);
+
+                draw_rect(0, 0, size, utils::YELLOW);
+            }
+        }
+    }
+}
+
+impl WindowView for BgMapView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("BG Map"))
+            .size((275.0, 340.0), ImGuiCond::FirstUseEver)
+            .position((440.0, 30.0), ImGuiCond::FirstUseEver)
+            .resizable(false)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_controls(ui, state);
+
+                ui.separator();
+
+                if let Some(texture) = self.texture {
+                    let origin = ui.get_cursor_screen_pos();
+
+                    ui.image(texture, (BG_MAP_SIZE as f32, BG_MAP_SIZE as f32))
+                        .build();
+
+                    self.draw_overlay(ui, state, origin);
+                }
+            });
+
+        open
+    }
+
+    fn prepare_textures(&mut self, ctx: &mut UiContext, state: &EmuState) {
+        state
+            .bus()
+            .ppu
+            .rasterize_bg_map(self.high_map, &mut self.buffer[..]);
+
+        self.texture =
+            Some(ctx.upload_texture(BG_MAP_SIZE, BG_MAP_SIZE, &self.buffer[..], self.texture));
+    }
+}
@@ -0,0 +1,131 @@
+use gib_core::{Cheat, CheatCode};
+
+use super::utils;
+use super::{EmuState, WindowView};
+
+use failure::Error;
+use imgui::{im_str, ImGuiCond, ImString, Ui};
+
+/// View adding, toggling and removing the Game Genie and GameShark codes of the
+/// game, which are saved along with the configuration.
+pub struct CheatsView {
+    code: ImString,
+    name: ImString,
+
+    // Error of the last change, if any
+    status: Option<String>,
+}
+
+impl CheatsView {
+    pub fn new() -> CheatsView {
+        CheatsView {
+            code: ImString::with_capacity(16),
+            name: ImString::with_capacity(64),
+            status: None,
+        }
+    }
+
+    fn set_result(&mut self, result: Result<(), Error>) {
+        self.status = result
+            .err()
+            .map(|e| format!("Cannot save the cheats: {}", e));
+    }
+
+    fn draw_controls(&mut self, ui: &Ui, state: &mut EmuState) {
+        ui.push_item_width(100.0);
+        ui.input_text(im_str!("Code"), &mut self.code)
+            .chars_uppercase(true)
+            .build();
+        ui.pop_item_width();
+        ui.same_line(0.0);
+
+        ui.push_item_width(150.0);
+        ui.input_text(im_str!("Name"), &mut self.name).build();
+        ui.pop_item_width();
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Add"), (0.0, 0.0)) {
+            match Cheat::new(self.code.to_str(), self.name.to_str().trim()) {
+                Some(cheat) => {
+                    let result = state.add_cheat(cheat);
+                    self.set_result(result);
+
+                    self.code.clear();
+                    self.name.clear();
+                }
+                None => {
+                    self.status = Some(format!("Invalid code: {}", self.code.to_str()));
+                }
+            }
+        }
+
+        ui.text("Game Genie: ABC-DEF or ABC-DEF-GHI, GameShark: ABCDEFGH");
+
+        if let Some(ref status) = self.status {
+            ui.text_colored(utils::RED, im_str!("{}", status));
+        }
+    }
+
+    fn draw_cheats(&mut self, ui: &Ui, state: &mut EmuState) {
+        let mut removed = None;
+        let mut toggled = None;
+
+        for (i, cheat) in state.cheats().iter().enumerate() {
+            if ui.button(im_str!("x##cheat{}", i), (0.0, 0.0)) {
+                removed = Some(i);
+            }
+            ui.same_line(0.0);
+
+            let mut enabled = cheat.enabled;
+
+            if ui.checkbox(im_str!("{}##cheat{}", cheat.code, i), &mut enabled) {
+                toggled = Some((i, enabled));
+            }
+            ui.same_line(0.0);
+
+            // Show what the code does, next to its description
+            let effect = match cheat.decoded() {
+                CheatCode::GameGenie {
+                    addr,
+                    val,
+                    compare: Some(cmp),
+                } => format!("ROM {:04X}: {:02X} -> {:02X}", addr, cmp, val),
+                CheatCode::GameGenie { addr, val, .. } => format!("ROM {:04X} = {:02X}", addr, val),
+                CheatCode::GameShark { addr, val, .. } => format!("RAM {:04X} = {:02X}", addr, val),
+            };
+
+            ui.text_colored(utils::DARK_GREY, im_str!("{}", effect));
+            ui.same_line(0.0);
+            ui.text(&cheat.name);
+        }
+
+        if let Some(i) = removed {
+            let result = state.remove_cheat(i);
+            self.set_result(result);
+        }
+        if let Some((i, enabled)) = toggled {
+            let result = state.set_cheat_enabled(i, enabled);
+            self.set_result(result);
+        }
+    }
+}
+
+impl WindowView for CheatsView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("Cheats"))
+            .size((480.0, 300.0), ImGuiCond::FirstUseEver)
+            .position((320.0, 30.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_controls(ui, state);
+
+                ui.separator();
+
+                self.draw_cheats(ui, state);
+            });
+
+        open
+    }
+}
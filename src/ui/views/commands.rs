@@ -0,0 +1,161 @@
+use super::utils;
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, ImString, Ui};
+
+/// Commands understood by the palette, with their arguments and a short description.
+const COMMANDS: [(&str, &str); 13] = [
+    ("c, continue", "Run until the next breakpoint"),
+    ("p, pause", "Pause the emulator"),
+    ("s, step", "Execute a single instruction"),
+    ("n, next", "Step over calls"),
+    ("finish", "Run until the current function returns"),
+    ("b, break [loc]", "Toggle a breakpoint (at PC by default)"),
+    ("d, delete <loc>", "Clear a breakpoint"),
+    ("until <loc>", "Run until the instruction at <loc>"),
+    ("vblank", "Run to the next V-Blank"),
+    ("ly <line>", "Run to the start of a line"),
+    ("cycles <n>", "Run for some clock cycles"),
+    ("mem <loc>", "Show a location in the memory editor"),
+    ("sw", "Reset the stopwatch"),
+];
+
+/// Text-driven access to the debugger commands, so that they can be run without the mouse.
+/// Locations are either symbol names or hex addresses.
+pub struct CommandView {
+    input: ImString,
+    status: Option<Result<String, String>>,
+
+    // Whether the input should take the keyboard focus on the next frame
+    focus: bool,
+}
+
+impl CommandView {
+    pub fn new() -> CommandView {
+        CommandView {
+            input: ImString::with_capacity(64),
+            status: None,
+            focus: true,
+        }
+    }
+
+    /// Runs `line`, returning a description of what happened or an error.
+    fn execute(&mut self, line: &str, state: &mut EmuState) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let cmd = words.next().unwrap_or("");
+        let arg = words.next();
+
+        if words.next().is_some() {
+            return Err(format!("Too many arguments: {}", line));
+        }
+
+        let location = |arg: Option<&str>| match arg {
+            Some(text) => state
+                .parse_location(text)
+                .ok_or_else(|| format!("Unknown location: {}", text)),
+            None => Err(format!("Missing location for {}", cmd)),
+        };
+
+        let number = |arg: Option<&str>| match arg {
+            Some(text) => text
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid number: {}", text)),
+            None => Err(format!("Missing argument for {}", cmd)),
+        };
+
+        match cmd {
+            "c" | "continue" => state.set_running(),
+            "p" | "pause" => state.pause(),
+            "s" | "step" => state.set_single_step(),
+            "n" | "next" => state.set_step_over(),
+            "finish" => state.set_step_out(),
+            "b" | "break" => {
+                let addr = match arg {
+                    Some(_) => location(arg)?,
+                    None => state.cpu().pc,
+                };
+
+                return Ok(if state.toggle_breakpoint(addr) {
+                    format!("Breakpoint set at {:04X}", addr)
+                } else {
+                    format!("Breakpoint cleared at {:04X}", addr)
+                });
+            }
+            "d" | "delete" => {
+                let addr = location(arg)?;
+                state.cpu_mut().clear_breakpoint(addr);
+                return Ok(format!("Breakpoint cleared at {:04X}", addr));
+            }
+            "until" => {
+                let addr = location(arg)?;
+                state.set_run_to(addr);
+            }
+            "vblank" => state.set_run_to_vblank(),
+            "ly" => match number(arg)? {
+                ly @ 0..=153 => state.set_run_to_line(ly as u8),
+                ly => return Err(format!("Invalid line: {}", ly)),
+            },
+            "cycles" => {
+                let cycles = number(arg)?;
+                state.set_run_for_cycles(cycles);
+            }
+            "mem" => {
+                let addr = location(arg)?;
+                state.show_in_mem_editor(addr);
+            }
+            "sw" => state.reset_stopwatch(),
+            "" => return Err("No command".to_string()),
+            _ => return Err(format!("Unknown command: {}", cmd)),
+        }
+
+        Ok(line.to_string())
+    }
+}
+
+impl WindowView for CommandView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("Command palette"))
+            .size((360.0, 300.0), ImGuiCond::FirstUseEver)
+            .position((320.0, 30.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                if self.focus {
+                    self.focus = false;
+                    unsafe {
+                        imgui_sys::igSetKeyboardFocusHere(0);
+                    }
+                }
+
+                ui.push_item_width(-1.0);
+                let entered = ui
+                    .input_text(im_str!("##command"), &mut self.input)
+                    .enter_returns_true(true)
+                    .build();
+                ui.pop_item_width();
+
+                if entered {
+                    let line = self.input.to_str().trim().to_string();
+
+                    self.status = Some(self.execute(&line, state));
+                    self.input.clear();
+                    self.focus = true;
+                }
+
+                match self.status {
+                    Some(Ok(ref status)) => ui.text(status),
+                    Some(Err(ref status)) => ui.text_colored(utils::RED, im_str!("{}", status)),
+                    None => ui.text(""),
+                }
+
+                ui.separator();
+
+                for (usage, help) in COMMANDS.iter() {
+                    ui.text(format!("{:16} {}", usage, help));
+                }
+            });
+
+        open
+    }
+}
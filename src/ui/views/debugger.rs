@@ -1,19 +1,75 @@
+use gib_core::bus::BusPolicy;
+use gib_core::dbg::{self, TraceEvent, TraceFilter, Watchpoint};
+
 use super::utils;
 use super::EmuState;
 use super::WindowView;
 
-use imgui::{im_str, ImGuiCol, ImGuiCond, Ui};
+use imgui::{im_str, ImGuiCol, ImGuiCond, ImString, Ui};
+
+/// Names of the interrupt sources, in the order of their bits in IE/IF.
+const IRQ_NAMES: [&str; 5] = ["VBlank", "STAT", "Timer", "Serial", "Joypad"];
+
+pub struct DebuggerView {
+    trace_status: Option<String>,
 
-pub struct DebuggerView;
+    // Location of the breakpoint being added, as an address or a symbol
+    bp_location: ImString,
+    bp_status: Option<String>,
+
+    // Watchpoint being edited
+    wp_start: Option<u16>,
+    wp_end: Option<u16>,
+    wp_read: bool,
+    wp_write: bool,
+    wp_change: bool,
+
+    // Arguments of the run commands
+    run_ly: i32,
+    run_cycles: i32,
+
+    // Filters and rotation of the trace streamed to a file
+    stream_instructions: bool,
+    stream_taken_branches: bool,
+    stream_io: bool,
+    stream_pc_ranges: ImString,
+    stream_io_ranges: ImString,
+    stream_file_mb: i32,
+    stream_old_files: i32,
+    stream_status: Option<String>,
+}
 
 impl DebuggerView {
     pub fn new() -> DebuggerView {
-        DebuggerView
+        DebuggerView {
+            trace_status: None,
+
+            bp_location: ImString::with_capacity(64),
+            bp_status: None,
+
+            wp_start: None,
+            wp_end: None,
+            wp_read: false,
+            wp_write: true,
+            wp_change: false,
+
+            run_ly: 0,
+            run_cycles: 456,
+
+            stream_instructions: true,
+            stream_taken_branches: false,
+            stream_io: false,
+            stream_pc_ranges: ImString::with_capacity(64),
+            stream_io_ranges: ImString::with_capacity(64),
+            stream_file_mb: 64,
+            stream_old_files: 3,
+            stream_status: None,
+        }
     }
 }
 
 impl DebuggerView {
-    fn draw_cpu_state(&mut self, ui: &Ui, state: &EmuState) {
+    fn draw_cpu_state(&mut self, ui: &Ui, state: &mut EmuState) {
         let cpu = state.cpu();
 
         ui.text(format!(
@@ -28,6 +84,13 @@ impl DebuggerView {
             });
         }
 
+        if cpu.stopped {
+            ui.same_line_spacing(0.0, 20.0);
+            ui.with_color_var(ImGuiCol::Text, utils::RED, || {
+                ui.text(im_str!("STOP"));
+            });
+        }
+
         if *cpu.intr_enabled.value() {
             ui.same_line_spacing(0.0, 20.0);
             ui.with_color_var(ImGuiCol::Text, utils::GREEN, || {
@@ -37,34 +100,345 @@ impl DebuggerView {
 
         ui.separator();
 
-        utils::input_addr(ui, "AF", &mut Some(cpu.af), false);
+        // Registers and flags can only be edited while paused, between instructions
+        let editable = state.paused();
+        let cpu = state.cpu_mut();
+
+        let mut regs = [
+            ("AF", &mut cpu.af),
+            ("BC", &mut cpu.bc),
+            ("DE", &mut cpu.de),
+            ("HL", &mut cpu.hl),
+            ("SP", &mut cpu.sp),
+            ("PC", &mut cpu.pc),
+        ];
+
+        for (i, (name, reg)) in regs.iter_mut().enumerate() {
+            if i > 0 {
+                ui.same_line(0.0);
+            }
+
+            let mut val = Some(**reg);
+            utils::input_addr(ui, name, &mut val, editable);
+
+            match val {
+                Some(v) if editable => **reg = v,
+                _ => (),
+            }
+        }
+
+        // The lower nibble of F is always zero
+        cpu.af &= 0xFFF0;
+
+        if editable {
+            self.draw_flag_editors(ui, state);
+        } else {
+            ui.text(format!(
+                "Flags: {} {} {} {}",
+                if cpu.zf() { 'Z' } else { '-' },
+                if cpu.sf() { 'N' } else { '-' },
+                if cpu.hc() { 'H' } else { '-' },
+                if cpu.cy() { 'C' } else { '-' },
+            ));
+
+            ui.same_line(150.0);
+        }
+
+        if let Some((evt, pc)) = state.fault() {
+            ui.with_color_var(ImGuiCol::Text, utils::RED, || {
+                ui.text(format!("Fault at ${:04X}: {}", pc, evt));
+            });
+        } else if let Some(ref evt) = state.last_event() {
+            ui.with_color_var(ImGuiCol::Text, utils::RED, || {
+                ui.text(evt.to_string());
+            });
+        } else {
+            ui.text("");
+        }
+    }
+
+    /// Draws checkboxes toggling the CPU flags, IME and HALT mode.
+    fn draw_flag_editors(&mut self, ui: &Ui, state: &mut EmuState) {
+        let cpu = state.cpu_mut();
+
+        let (mut z, mut n, mut h, mut c) = (cpu.zf(), cpu.sf(), cpu.hc(), cpu.cy());
+        let mut ime = *cpu.intr_enabled.value();
+        let mut halted = *cpu.halted.value();
+
+        ui.text("Flags:");
         ui.same_line(0.0);
-        utils::input_addr(ui, "BC", &mut Some(cpu.bc), false);
+
+        if ui.checkbox(im_str!("Z"), &mut z) {
+            cpu.set_zf(z);
+        }
         ui.same_line(0.0);
-        utils::input_addr(ui, "DE", &mut Some(cpu.de), false);
+
+        if ui.checkbox(im_str!("N"), &mut n) {
+            cpu.set_sf(n);
+        }
         ui.same_line(0.0);
-        utils::input_addr(ui, "HL", &mut Some(cpu.hl), false);
+
+        if ui.checkbox(im_str!("H"), &mut h) {
+            cpu.set_hc(h);
+        }
         ui.same_line(0.0);
-        utils::input_addr(ui, "SP", &mut Some(cpu.sp), false);
+
+        if ui.checkbox(im_str!("C"), &mut c) {
+            cpu.set_cy(c);
+        }
         ui.same_line(0.0);
-        utils::input_addr(ui, "PC", &mut Some(cpu.pc), false);
 
-        ui.text(format!(
-            "Flags: {} {} {} {}",
-            if cpu.zf() { 'Z' } else { '-' },
-            if cpu.sf() { 'N' } else { '-' },
-            if cpu.hc() { 'H' } else { '-' },
-            if cpu.cy() { 'C' } else { '-' },
-        ));
+        if ui.checkbox(im_str!("IME##edit"), &mut ime) {
+            cpu.set_ime(ime);
+        }
+        ui.same_line(0.0);
+
+        if ui.checkbox(im_str!("HALT##edit"), &mut halted) {
+            cpu.set_halted(halted);
+        }
+    }
 
-        ui.same_line(150.0);
+    /// Draws the input adding breakpoints by address or symbol name.
+    fn draw_breakpoint_input(&mut self, ui: &Ui, state: &mut EmuState) {
+        ui.push_item_width(150.0);
+        ui.input_text(im_str!("##bp_location"), &mut self.bp_location)
+            .build();
+        ui.pop_item_width();
+        ui.same_line(0.0);
 
-        if let Some(ref evt) = state.last_event() {
-            ui.with_color_var(ImGuiCol::Text, utils::RED, || {
-                ui.text(evt.to_string());
+        if ui.button(im_str!("Add breakpoint"), (0.0, 0.0)) {
+            let location = self.bp_location.to_str();
+
+            self.bp_status = match state.parse_location(location) {
+                Some(addr) => {
+                    state.cpu_mut().set_breakpoint(addr);
+                    None
+                }
+                None => Some(format!("Unknown location: {}", location)),
+            };
+        }
+
+        if let Some(ref status) = self.bp_status {
+            ui.same_line(0.0);
+            ui.text_colored(utils::RED, im_str!("{}", status));
+        }
+    }
+
+    /// Draws the commands running until a point in the video frame, or for some cycles.
+    fn draw_run_controls(&mut self, ui: &Ui, state: &mut EmuState) {
+        if ui.button(im_str!("Run to VBlank"), (0.0, 0.0)) {
+            state.set_run_to_vblank();
+        }
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Run to LY"), (0.0, 0.0)) {
+            state.set_run_to_line(self.run_ly as u8);
+        }
+        ui.same_line(0.0);
+
+        ui.push_item_width(70.0);
+        if ui.input_int(im_str!("##run_ly"), &mut self.run_ly).build() {
+            self.run_ly = self.run_ly.max(0).min(153);
+        }
+        ui.pop_item_width();
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Run for"), (0.0, 0.0)) {
+            state.set_run_for_cycles(self.run_cycles as u64);
+        }
+        ui.same_line(0.0);
+
+        ui.push_item_width(90.0);
+        if ui
+            .input_int(im_str!("cycles##run_cycles"), &mut self.run_cycles)
+            .build()
+        {
+            self.run_cycles = self.run_cycles.max(1);
+        }
+        ui.pop_item_width();
+    }
+
+    /// Draws the stopwatch, timing the code run since it was reset and between
+    /// the last two breakpoint hits.
+    fn draw_stopwatch(&mut self, ui: &Ui, state: &mut EmuState) {
+        if ui.button(im_str!("Reset stopwatch"), (0.0, 0.0)) {
+            state.reset_stopwatch();
+        }
+        ui.same_line(0.0);
+
+        let now = state.gameboy().clock_cycles();
+        ui.text(format!("{}", state.stopwatch().elapsed(now)));
+
+        match state.stopwatch().between_hits() {
+            Some(elapsed) => ui.text(format!("Between breakpoints: {}", elapsed)),
+            None => ui.text("Between breakpoints: -"),
+        }
+    }
+
+    fn draw_trace_controls(&mut self, ui: &Ui, state: &mut EmuState) {
+        let mut tracing = state.tracing();
+
+        if ui.checkbox(im_str!("Trace"), &mut tracing) {
+            state.set_tracing(tracing);
+        }
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Dump trace"), (0.0, 0.0)) {
+            self.trace_status = Some(match state.dump_trace() {
+                Ok(path) => format!("Saved to {}", path.display()),
+                Err(e) => format!("Error: {}", e),
             });
-        } else {
-            ui.text("");
+        }
+
+        if let Some(ref status) = self.trace_status {
+            ui.same_line(0.0);
+            ui.text(status);
+        }
+
+        let mut trap_dma_conflicts = state.trap_dma_conflicts();
+
+        if ui.checkbox(im_str!("Break on DMA bus conflicts"), &mut trap_dma_conflicts) {
+            state.set_trap_dma_conflicts(trap_dma_conflicts);
+        }
+        ui.same_line(0.0);
+
+        let mut trap_ppu_conflicts = state.trap_ppu_conflicts();
+
+        if ui.checkbox(im_str!("Break on VRAM/OAM conflicts"), &mut trap_ppu_conflicts) {
+            state.set_trap_ppu_conflicts(trap_ppu_conflicts);
+        }
+        ui.same_line(0.0);
+
+        let mut strict_bus = state.bus_policy() == BusPolicy::Strict;
+
+        if ui.checkbox(im_str!("Break on unmapped accesses"), &mut strict_bus) {
+            state.set_bus_policy(if strict_bus {
+                BusPolicy::Strict
+            } else {
+                BusPolicy::Lenient
+            });
+        }
+
+        let mut trap_mbc_ops = state.trap_mbc_ops();
+
+        if ui.checkbox(im_str!("Break on invalid MBC ops"), &mut trap_mbc_ops) {
+            state.set_trap_mbc_ops(trap_mbc_ops);
+        }
+        ui.same_line(0.0);
+
+        let mut trap_cgb_ops = state.trap_cgb_ops();
+
+        if ui.checkbox(im_str!("Break on unsupported CGB ops"), &mut trap_cgb_ops) {
+            state.set_trap_cgb_ops(trap_cgb_ops);
+        }
+
+        ui.text("Break on interrupts:");
+
+        let mut irq_breaks = state.irq_breaks();
+
+        for (i, name) in IRQ_NAMES.iter().enumerate() {
+            let mut enabled = irq_breaks & (1 << i) != 0;
+
+            ui.same_line(0.0);
+            if ui.checkbox(im_str!("{}##irq_break", name), &mut enabled) {
+                irq_breaks ^= 1 << i;
+            }
+        }
+        state.set_irq_breaks(irq_breaks);
+    }
+
+    /// Returns the filter of the streamed trace, or an error if the ranges are invalid.
+    fn trace_filter(&self) -> Result<TraceFilter, String> {
+        let ranges = |text: &ImString| {
+            dbg::parse_ranges(text.to_str())
+                .ok_or_else(|| format!("Invalid ranges: {}", text.to_str()))
+        };
+
+        Ok(TraceFilter {
+            instructions: self.stream_instructions,
+            taken_branches: self.stream_taken_branches,
+            io: self.stream_io,
+            io_ranges: ranges(&self.stream_io_ranges)?,
+            pc_ranges: ranges(&self.stream_pc_ranges)?,
+        })
+    }
+
+    /// Draws the controls streaming the trace to a file, and the filters selecting
+    /// what is streamed. Ranges are lists of hex addresses such as `FF40-FF4B,FF0F`.
+    fn draw_trace_stream(&mut self, ui: &Ui, state: &mut EmuState) {
+        let mut changed = ui.checkbox(im_str!("Instructions"), &mut self.stream_instructions);
+        ui.same_line(0.0);
+        changed |= ui.checkbox(
+            im_str!("Only taken branches"),
+            &mut self.stream_taken_branches,
+        );
+        ui.same_line(0.0);
+        changed |= ui.checkbox(im_str!("IO accesses"), &mut self.stream_io);
+
+        ui.push_item_width(150.0);
+        changed |= ui
+            .input_text(im_str!("PC ranges"), &mut self.stream_pc_ranges)
+            .build();
+        ui.same_line(0.0);
+        changed |= ui
+            .input_text(im_str!("IO ranges"), &mut self.stream_io_ranges)
+            .build();
+        ui.pop_item_width();
+
+        ui.push_item_width(90.0);
+        if ui
+            .input_int(im_str!("MB per file"), &mut self.stream_file_mb)
+            .build()
+        {
+            self.stream_file_mb = self.stream_file_mb.max(1);
+        }
+        ui.same_line(0.0);
+        if ui
+            .input_int(im_str!("old files kept"), &mut self.stream_old_files)
+            .build()
+        {
+            self.stream_old_files = self.stream_old_files.max(0);
+        }
+        ui.pop_item_width();
+
+        let filter = self.trace_filter();
+        let streaming = state.trace_stream_path().is_some();
+
+        // Filters apply immediately, rotation settings once streaming is restarted
+        if changed && streaming {
+            match filter {
+                Ok(ref filter) => state.set_trace_filter(filter.clone()),
+                Err(ref e) => self.stream_status = Some(e.clone()),
+            }
+        }
+
+        if streaming {
+            if ui.button(im_str!("Stop streaming"), (0.0, 0.0)) {
+                self.stream_status = match state.stop_trace_stream() {
+                    Ok(()) => None,
+                    Err(e) => Some(format!("Error: {}", e)),
+                };
+            }
+        } else if ui.button(im_str!("Stream to file"), (0.0, 0.0)) {
+            let max_size = self.stream_file_mb as u64 * 1024 * 1024;
+            let max_files = self.stream_old_files as usize;
+
+            self.stream_status = match filter {
+                Ok(filter) => match state.start_trace_stream(filter, max_size, max_files) {
+                    Ok(_) => None,
+                    Err(e) => Some(format!("Error: {}", e)),
+                },
+                Err(e) => Some(e),
+            };
+        }
+        ui.same_line(0.0);
+
+        if let Some(ref status) = self.stream_status {
+            ui.text(status);
+        } else if let Some(path) = state.trace_stream_path() {
+            ui.text(format!("Streaming to {}", path.display()));
+        } else if let Some(error) = state.trace_stream_error() {
+            ui.text(error);
         }
     }
 
@@ -76,7 +450,7 @@ impl DebuggerView {
                     .default_open(true)
                     .build()
                 {
-                    for (i, addr) in state.cpu().call_stack.iter().rev().enumerate() {
+                    for (i, frame) in state.cpu().call_stack.iter().rev().enumerate() {
                         let c = if i == 0 {
                             utils::WHITE
                         } else {
@@ -85,12 +459,78 @@ impl DebuggerView {
 
                         ui.text_colored(
                             c,
-                            im_str!(" {} 0x{:04X}", if i == 0 { '>' } else { ' ' }, addr),
+                            im_str!(
+                                " {} 0x{:04X}{}",
+                                if i == 0 { '>' } else { ' ' },
+                                frame.entry,
+                                if frame.interrupt { " (IRQ)" } else { "" }
+                            ),
                         );
                     }
                 }
             });
     }
+
+    fn draw_watchpoints(&mut self, ui: &Ui, state: &mut EmuState) {
+        ui.child_frame(im_str!("watchpoints_frame"), (0.0, 0.0))
+            .build(|| {
+                if ui
+                    .collapsing_header(im_str!("Watchpoints"))
+                    .default_open(true)
+                    .build()
+                {
+                    utils::input_addr(ui, "From", &mut self.wp_start, true);
+                    ui.same_line(0.0);
+                    utils::input_addr(ui, "To", &mut self.wp_end, true);
+                    ui.same_line(0.0);
+                    ui.checkbox(im_str!("R"), &mut self.wp_read);
+                    ui.same_line(0.0);
+                    ui.checkbox(im_str!("W"), &mut self.wp_write);
+                    ui.same_line(0.0);
+                    ui.checkbox(im_str!("C"), &mut self.wp_change);
+                    ui.same_line(0.0);
+
+                    if ui.button(im_str!("Add"), (0.0, 0.0)) {
+                        if let Some(start) = self.wp_start {
+                            // A missing end address watches a single location
+                            let end = self.wp_end.unwrap_or(start).max(start);
+
+                            if self.wp_read || self.wp_write || self.wp_change {
+                                state.add_watchpoint(Watchpoint {
+                                    on_change: self.wp_change,
+                                    ..Watchpoint::new(start..=end, self.wp_read, self.wp_write)
+                                });
+                            }
+                        }
+                    }
+
+                    // The access which tripped a watchpoint, if that's why execution stopped
+                    let hit = match *state.last_event() {
+                        Some(TraceEvent::Watchpoint(access, addr, val, pc)) => {
+                            Some((access, addr, val, pc))
+                        }
+                        _ => None,
+                    };
+
+                    for (i, wp) in state.watchpoints().iter().enumerate() {
+                        if ui.button(im_str!("x##wp{}", i), (0.0, 0.0)) {
+                            state.remove_watchpoint(wp);
+                        }
+                        ui.same_line(0.0);
+
+                        match hit {
+                            Some((access, addr, val, pc)) if wp.range.contains(&addr) => {
+                                ui.text_colored(
+                                    utils::RED,
+                                    im_str!("{}  {} {:02X} at PC 0x{:04X}", wp, access, val, pc),
+                                );
+                            }
+                            _ => ui.text(wp.to_string()),
+                        }
+                    }
+                }
+            });
+    }
 }
 
 impl WindowView for DebuggerView {
@@ -119,10 +559,37 @@ impl WindowView for DebuggerView {
                 if ui.button(im_str!("Step"), (0.0, 0.0)) {
                     state.set_single_step();
                 }
+                ui.same_line(0.0);
+
+                if ui.button(im_str!("Step over"), (0.0, 0.0)) {
+                    state.set_step_over();
+                }
+                ui.same_line(0.0);
+
+                if ui.button(im_str!("Step out"), (0.0, 0.0)) {
+                    state.set_step_out();
+                }
+
+                self.draw_run_controls(ui, state);
+                self.draw_breakpoint_input(ui, state);
+
+                ui.separator();
+
+                self.draw_trace_controls(ui, state);
+
+                if ui.collapsing_header(im_str!("Tracing")).build() {
+                    self.draw_trace_stream(ui, state);
+                }
+
+                ui.separator();
+
+                self.draw_stopwatch(ui, state);
 
                 ui.separator();
 
                 self.draw_call_stack(ui, state);
+                ui.same_line(0.0);
+                self.draw_watchpoints(ui, state);
             });
 
         open
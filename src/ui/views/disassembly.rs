@@ -1,3 +1,4 @@
+use gib_core::mem::MemR;
 use gib_core::{cpu::Immediate, dbg};
 
 use super::utils;
@@ -5,13 +6,42 @@ use super::{EmuState, WindowView};
 
 use std::collections::BTreeMap;
 
-use imgui::{im_str, ImGuiCol, ImGuiCond, ImStr, ImString, StyleVar, Ui};
+use imgui::{im_str, ImGuiCol, ImGuiCond, ImMouseButton, ImStr, ImString, StyleVar, Ui};
+
+/// A decoded line of the listing: either an instruction, or a data byte.
+struct Line {
+    text: ImString,
+    mnemonic: String,
+    data: bool,
+}
 
 pub struct DisassemblyView {
     section: dbg::MemoryType,
-    disasm: BTreeMap<u16, ImString>,
+    disasm: BTreeMap<u16, Line>,
     follow_pc: bool,
-    goto_addr: Option<u16>,
+    last_pc: Option<u16>,
+    last_fault_pc: Option<u16>,
+    goto_location: ImString,
+    search: ImString,
+    status: Option<String>,
+
+    // Instruction selected by right-clicking it, or found by searching
+    cursor: Option<u16>,
+
+    // Number of symbols the listing has been decoded with
+    symbols_len: usize,
+
+    // ROM bank shown at 0x4000-0x7FFF, unless following the mapped one
+    follow_bank: bool,
+    rom_bank: Option<u8>,
+    shown_bank: usize,
+
+    // Whether bytes never executed are shown as data
+    executed_only: bool,
+
+    // Whether executed instructions are tinted
+    coverage: bool,
+    coverage_status: Option<String>,
 }
 
 impl DisassemblyView {
@@ -20,7 +50,52 @@ impl DisassemblyView {
             section: dbg::MemoryType::RomBank(0),
             disasm: BTreeMap::new(),
             follow_pc: false,
-            goto_addr: Some(0),
+            last_pc: None,
+            last_fault_pc: None,
+            goto_location: ImString::with_capacity(64),
+            search: ImString::with_capacity(64),
+            status: None,
+
+            cursor: None,
+
+            symbols_len: 0,
+
+            follow_bank: true,
+            rom_bank: Some(1),
+            shown_bank: 1,
+
+            executed_only: false,
+
+            coverage: false,
+            coverage_status: None,
+        }
+    }
+
+    /// Returns the ROM bank to be shown at 0x4000-0x7FFF.
+    fn bank_to_show(&self, state: &EmuState) -> usize {
+        let mapped = state.bus().bank_at(0x4000);
+
+        match self.rom_bank {
+            Some(bank) if !self.follow_bank => usize::from(bank),
+            _ => mapped,
+        }
+    }
+
+    /// Shows another ROM bank, if it's been selected since the listing was decoded.
+    fn update_bank(&mut self, state: &EmuState) {
+        let bank = self.bank_to_show(state);
+
+        if bank != self.shown_bank {
+            self.shown_bank = bank;
+            self.disasm.clear();
+        }
+    }
+
+    /// Returns the name of `addr`, looking it up in the shown bank if it's banked ROM.
+    fn label_at<'a>(&self, state: &'a EmuState, addr: u16) -> Option<&'a str> {
+        match addr {
+            0x4000..=0x7FFF => state.symbols().label(self.shown_bank, addr),
+            _ => state.label_at(addr),
         }
     }
 
@@ -30,7 +105,10 @@ impl DisassemblyView {
     /// If `from` is outside the current memory space, swap it and reload disasm.
     fn realign_disasm(&mut self, state: &EmuState, mut from: u16) {
         let cpu = state.cpu();
-        let bus = state.bus();
+        let mem = state.bus().with_rom_bank(self.shown_bank);
+        let code_map = state.gameboy().code_map();
+        let bank = self.shown_bank;
+        let is_code = |addr: u16| code_map.is_code(dbg::CodeAddr::new(bank, addr));
 
         let mut mem_range = self.section.range();
 
@@ -43,39 +121,86 @@ impl DisassemblyView {
         }
 
         while from < *mem_range.end() {
-            let instr = match cpu.disasm(bus, from) {
-                Ok(instr) => instr,
-                Err(evt) => panic!("unexpected trace event during disassembly: {}", evt),
-            };
+            let instr = cpu.disasm(&mem, from);
 
-            let next = from + u16::from(instr.size);
+            // Bytes which haven't been executed, but would be decoded as part of an
+            // instruction overlapping known code, are data (eg. a table after a JP).
+            let data = !is_code(from)
+                && (self.executed_only
+                    || (1..u16::from(instr.size)).any(|i| is_code(from.wrapping_add(i))));
 
-            if self.disasm.get(&from).is_some() {
-                break;
+            let next = from + if data { 1 } else { u16::from(instr.size) };
+
+            match self.disasm.get(&from) {
+                Some(line) if line.data == data => break,
+                _ => (),
             }
             for addr in from..next {
                 self.disasm.remove(&addr);
             }
 
-            self.disasm.insert(
-                from,
-                ImString::from(format!(
-                    "{:04X}:  {:02X} {:5}    {}",
+            // Show labels in place of addresses, if any
+            let location = match self.label_at(state, from) {
+                Some(label) => format!("{}:", label),
+                None => format!("{}:", dbg::CodeAddr::new(self.shown_bank, from)),
+            };
+
+            if data {
+                self.disasm.insert(
                     from,
-                    instr.opcode,
-                    match instr.imm {
-                        Some(Immediate::Imm8(d8)) => format!("{:02X}", d8),
-                        Some(Immediate::Imm16(d16)) => format!("{:04X}", d16),
-                        None => String::new(),
+                    Line {
+                        text: ImString::from(format!(
+                            "{}  {:02X}          DB",
+                            location,
+                            mem.peek(from)
+                        )),
+                        mnemonic: String::from("DB"),
+                        data,
                     },
-                    instr.mnemonic
-                )),
+                );
+                from = next;
+                continue;
+            }
+
+            let target = match instr.imm {
+                Some(Immediate::Imm16(a16)) if instr.mnemonic.contains("a16") => Some(("a16", a16)),
+                Some(Immediate::Imm8(r8)) if instr.mnemonic.starts_with("JR") => {
+                    Some(("r8", next.wrapping_add(r8 as i8 as u16)))
+                }
+                _ => None,
+            };
+
+            let mnemonic = match target {
+                Some((operand, addr)) => match self.label_at(state, addr) {
+                    Some(label) => instr.mnemonic.replace(operand, label),
+                    None => instr.mnemonic.to_string(),
+                },
+                None => instr.mnemonic.to_string(),
+            };
+
+            self.disasm.insert(
+                from,
+                Line {
+                    text: ImString::from(format!(
+                        "{}  {:02X} {:5}    {}",
+                        location,
+                        instr.opcode,
+                        match instr.imm {
+                            Some(Immediate::Imm8(d8)) => format!("{:02X}", d8),
+                            Some(Immediate::Imm16(d16)) => format!("{:04X}", d16),
+                            None => String::new(),
+                        },
+                        mnemonic
+                    )),
+                    mnemonic,
+                    data,
+                },
             );
             from = next;
         }
     }
 
-    /// Scroll disassembly view to the desired address.
+    /// Scroll disassembly view to the desired address, centering it.
     fn goto(&mut self, ui: &Ui, state: &EmuState, dest: u16) {
         let (_, h) = ui.get_content_region_avail();
 
@@ -83,53 +208,184 @@ impl DisassemblyView {
             self.realign_disasm(state, dest);
         }
 
+        let line_h = ui.get_text_line_height_with_spacing();
+
         for (i, addr) in self.disasm.keys().enumerate() {
             if *addr == dest {
                 unsafe {
-                    imgui_sys::igSetScrollY(
-                        ui.get_text_line_height_with_spacing() * i as f32 - h / 3.0,
-                    );
+                    imgui_sys::igSetScrollY(line_h * i as f32 - (h - line_h) / 2.0);
                 }
                 break;
             }
         }
     }
 
-    fn draw_goto_bar(&mut self, ui: &Ui) -> (bool, bool) {
-        let goto_pc;
-        let goto_addr;
+    /// Resolves the address or symbol typed in the goto box. Symbols in switchable
+    /// ROM are shown in their own bank.
+    fn resolve_location(&mut self, state: &EmuState) -> Option<u16> {
+        let text = self.goto_location.to_str().trim().to_string();
+
+        if let Some((bank, addr @ 0x4000..=0x7FFF)) = state.symbols().lookup(&text) {
+            self.follow_bank = bank == state.bus().bank_at(addr);
+            self.rom_bank = Some(bank as u8);
+            self.update_bank(state);
+        }
+
+        let dest = state.parse_location(&text);
+
+        self.status = match dest {
+            Some(_) => None,
+            None => Some(format!("Unknown location: {}", text)),
+        };
+        dest
+    }
+
+    /// Finds the next instruction after the cursor whose mnemonic contains the search
+    /// text, ignoring case, wrapping around at the end of the listing.
+    fn find_next(&mut self) -> Option<u16> {
+        let query = self.search.to_str().trim().to_lowercase();
+
+        if query.is_empty() {
+            return None;
+        }
+
+        let start = self.cursor.map_or(0, |c| c.wrapping_add(1));
+
+        let found = self
+            .disasm
+            .range(start..)
+            .chain(self.disasm.range(..start))
+            .find(|(_, line)| line.mnemonic.to_lowercase().contains(&query))
+            .map(|(addr, _)| *addr);
+
+        self.status = match found {
+            Some(_) => None,
+            None => Some(format!("Not found: {}", query)),
+        };
+        self.cursor = found.or(self.cursor);
+        found
+    }
+
+    /// Draws the navigation controls, returning the address to scroll to, if any,
+    /// and whether to scroll to PC.
+    fn draw_goto_bar(&mut self, ui: &Ui, state: &mut EmuState) -> (Option<u16>, bool) {
+        let mut dest = None;
+
+        ui.push_item_width(100.0);
+        let enter = ui
+            .input_text(im_str!("##disasm_goto"), &mut self.goto_location)
+            .enter_returns_true(true)
+            .build();
+        ui.pop_item_width();
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Goto"), (0.0, 0.0)) || enter {
+            dest = self.resolve_location(state);
+        }
+        ui.same_line(0.0);
 
-        utils::input_addr(ui, "", &mut self.goto_addr, true);
+        let goto_pc = ui.button(im_str!("Goto PC"), (0.0, 0.0));
         ui.same_line(0.0);
 
-        goto_addr = ui.button(im_str!("Goto"), (0.0, 0.0));
+        if ui.checkbox(im_str!("Follow"), &mut self.follow_pc) {
+            self.last_pc = None;
+        }
+
+        ui.push_item_width(100.0);
+        let enter = ui
+            .input_text(im_str!("##disasm_search"), &mut self.search)
+            .enter_returns_true(true)
+            .build();
+        ui.pop_item_width();
         ui.same_line(0.0);
 
-        goto_pc = ui.button(im_str!("Goto PC"), (0.0, 0.0));
+        if ui.button(im_str!("Find"), (0.0, 0.0)) || enter {
+            dest = self.find_next().or(dest);
+        }
+
+        if let Some(cursor) = self.cursor {
+            ui.same_line(0.0);
+
+            if ui.button(im_str!("Run to cursor"), (0.0, 0.0)) {
+                state.set_run_to(cursor);
+            }
+        }
+
+        ui.checkbox(im_str!("Mapped bank"), &mut self.follow_bank);
+
+        if !self.follow_bank {
+            ui.same_line(0.0);
+            utils::input_byte(ui, "Bank##disasm_bank", &mut self.rom_bank, true);
+        }
         ui.same_line(0.0);
 
-        ui.checkbox(im_str!("Follow"), &mut self.follow_pc);
+        if ui.checkbox(im_str!("Executed only"), &mut self.executed_only) {
+            self.disasm.clear();
+        }
+
+        ui.checkbox(im_str!("Coverage"), &mut self.coverage);
+        ui.same_line(0.0);
 
-        (goto_addr, goto_pc)
+        if ui.button(im_str!("Export coverage"), (0.0, 0.0)) {
+            self.coverage_status = Some(match state.dump_coverage() {
+                Ok((text, json)) => format!("Saved to {} and {}", text.display(), json.display()),
+                Err(e) => format!("Error: {}", e),
+            });
+        }
+
+        if let Some(ref status) = self.coverage_status {
+            ui.text(status);
+        }
+
+        if let Some(ref status) = self.status {
+            ui.text_colored(utils::RED, im_str!("{}", status));
+        }
+
+        (dest, goto_pc)
     }
 
-    fn draw_disasm_view(&mut self, ui: &Ui, state: &mut EmuState, goto_addr: bool, goto_pc: bool) {
+    fn draw_disasm_view(
+        &mut self,
+        ui: &Ui,
+        state: &mut EmuState,
+        dest: Option<u16>,
+        goto_pc: bool,
+    ) {
         let pc = state.cpu().pc;
 
+        // PC only shows up in the listing if its bank is the one displayed
+        let pc_shown = match pc {
+            0x4000..=0x7FFF => state.bus().bank_at(pc) == self.shown_bank,
+            _ => true,
+        };
+
         let (_, h) = ui.get_content_region_avail();
 
-        ui.child_frame(im_str!("listing"), (285.0, h))
+        ui.child_frame(im_str!("listing"), (305.0, h))
             .always_show_vertical_scroll_bar(true)
             .show_borders(false)
             .build(|| {
-                if self.follow_pc || goto_pc {
+                // When following PC, only scroll as it moves so the listing can be browsed
+                let follow = self.follow_pc && self.last_pc != Some(pc);
+                self.last_pc = Some(pc);
+
+                if follow || goto_pc {
                     self.goto(ui, state, pc);
-                } else if goto_addr && self.goto_addr.is_some() {
-                    self.goto(ui, state, self.goto_addr.unwrap());
+                } else if let Some(dest) = dest {
+                    self.goto(ui, state, dest);
                 }
 
                 // Only render currently visible instructions
                 utils::list_clipper(ui, self.disasm.len(), |range| {
+                    let code_map = state.gameboy().code_map();
+                    let executed: Vec<bool> = self
+                        .disasm
+                        .keys()
+                        .skip(range.start)
+                        .take(range.end - range.start)
+                        .map(|a| code_map.is_code(dbg::CodeAddr::new(self.shown_bank, *a)))
+                        .collect();
+
                     let instrs = self
                         .disasm
                         .iter_mut()
@@ -140,13 +396,23 @@ impl DisassemblyView {
 
                     let style = &[StyleVar::FrameRounding(15.0)];
 
-                    for (addr, instr) in instrs {
+                    for ((addr, line), executed) in instrs.zip(executed) {
+                        let mut bk = cpu.breakpoint_at(*addr);
+
+                        // Highlight the instruction executing next, then the cursor
+                        // and breakpoints. With coverage, executed code is tinted.
                         let color = &[(
                             ImGuiCol::Text,
-                            if *addr < pc {
-                                utils::DARK_GREY
-                            } else if *addr == pc {
+                            if *addr == pc && pc_shown {
                                 utils::GREEN
+                            } else if self.cursor == Some(*addr) {
+                                utils::YELLOW
+                            } else if bk {
+                                utils::RED
+                            } else if self.coverage && executed {
+                                utils::LIGHT_BLUE
+                            } else if self.coverage || *addr < pc || line.data {
+                                utils::DARK_GREY
                             } else {
                                 utils::WHITE
                             },
@@ -154,9 +420,7 @@ impl DisassemblyView {
 
                         // Render breakpoing and instruction
                         ui.with_style_and_color_vars(style, color, || {
-                            let mut bk = cpu.breakpoint_at(*addr);
-
-                            if ui.checkbox(ImStr::new(instr), &mut bk) {
+                            if ui.checkbox(ImStr::new(&line.text), &mut bk) {
                                 if bk {
                                     cpu.set_breakpoint(*addr);
                                 } else {
@@ -164,6 +428,12 @@ impl DisassemblyView {
                                 }
                             }
                         });
+
+                        // Right-clicking an instruction moves the cursor to it
+                        if ui.is_item_hovered() && ui.imgui().is_mouse_clicked(ImMouseButton::Right)
+                        {
+                            self.cursor = Some(*addr);
+                        }
                     }
                 });
             });
@@ -176,19 +446,37 @@ impl WindowView for DisassemblyView {
 
         // 99.9% of the time this does nothing, so it's cool
         // to have it called every draw loop.
+        // Decode the listing again whenever symbols are loaded
+        if state.symbols().len() != self.symbols_len {
+            self.symbols_len = state.symbols().len();
+            self.disasm.clear();
+        }
+
+        // And whenever another ROM bank is to be shown
+        self.update_bank(state);
+
         let pc = state.cpu().pc;
         self.realign_disasm(state, pc);
 
         ui.window(im_str!("Disassembly"))
-            .size((300.0, 650.0), ImGuiCond::FirstUseEver)
+            .size((320.0, 650.0), ImGuiCond::FirstUseEver)
             .position((10.0, 30.0), ImGuiCond::FirstUseEver)
             .opened(&mut open)
             .build(|| {
-                let (goto_addr, goto_pc) = self.draw_goto_bar(ui);
+                let (mut dest, goto_pc) = self.draw_goto_bar(ui, state);
+
+                // Show where the machine faulted, once per fault
+                let fault_pc = state.fault().map(|(_, pc)| pc);
+
+                if fault_pc.is_some() && fault_pc != self.last_fault_pc {
+                    dest = fault_pc;
+                    self.cursor = fault_pc;
+                }
+                self.last_fault_pc = fault_pc;
 
                 ui.separator();
 
-                self.draw_disasm_view(ui, state, goto_addr, goto_pc);
+                self.draw_disasm_view(ui, state, dest, goto_pc);
             });
 
         open
@@ -0,0 +1,124 @@
+use gib_core::dbg::{IoAccess, MemAccess, Peripheral};
+
+use super::utils;
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, Ui};
+
+use std::collections::HashSet;
+
+/// Number of accesses shown in the log tail.
+const TAIL_LENGTH: usize = 1000;
+
+pub struct IoLogView {
+    shown: HashSet<Peripheral>,
+    follow: bool,
+    dump_status: Option<String>,
+}
+
+impl IoLogView {
+    pub fn new() -> IoLogView {
+        IoLogView {
+            shown: Peripheral::all().iter().cloned().collect(),
+            follow: true,
+            dump_status: None,
+        }
+    }
+
+    fn draw_controls(&mut self, ui: &Ui, state: &mut EmuState) {
+        let mut logging = state.io_logging();
+
+        if ui.checkbox(im_str!("Enabled"), &mut logging) {
+            state.set_io_logging(logging);
+        }
+        ui.same_line(0.0);
+
+        ui.checkbox(im_str!("Follow"), &mut self.follow);
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Clear"), (0.0, 0.0)) {
+            if let Some(log) = state.gameboy_mut().io_log_mut() {
+                log.clear();
+            }
+        }
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Dump"), (0.0, 0.0)) {
+            self.dump_status = Some(match state.dump_io_log() {
+                Ok(path) => format!("Saved to {}", path.display()),
+                Err(e) => format!("Error: {}", e),
+            });
+        }
+
+        if let Some(ref status) = self.dump_status {
+            ui.same_line(0.0);
+            ui.text(status);
+        }
+
+        for (i, p) in Peripheral::all().iter().enumerate() {
+            let mut shown = self.shown.contains(p);
+
+            if i > 0 {
+                ui.same_line(0.0);
+            }
+
+            if ui.checkbox(im_str!("{}", p), &mut shown) {
+                if shown {
+                    self.shown.insert(*p);
+                } else {
+                    self.shown.remove(p);
+                }
+            }
+        }
+    }
+
+    fn draw_log(&self, ui: &Ui, entries: &[IoAccess]) {
+        ui.child_frame(im_str!("iolog_frame"), (0.0, 0.0))
+            .build(|| {
+                utils::list_clipper(ui, entries.len(), |range| {
+                    for e in entries[range].iter() {
+                        let c = match e.access {
+                            MemAccess::Read => utils::WHITE,
+                            MemAccess::Write => utils::YELLOW,
+                        };
+                        ui.text_colored(c, im_str!("{}", e));
+                    }
+                });
+
+                if self.follow {
+                    unsafe {
+                        imgui_sys::igSetScrollY(imgui_sys::igGetScrollMaxY());
+                    }
+                }
+            });
+    }
+}
+
+impl WindowView for IoLogView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("IO Log"))
+            .size((400.0, 400.0), ImGuiCond::FirstUseEver)
+            .position((720.0, 30.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_controls(ui, state);
+
+                ui.separator();
+
+                if let Some(log) = state.gameboy().io_log() {
+                    let shown = &self.shown;
+                    let entries = log.tail(TAIL_LENGTH, |e| {
+                        e.peripheral().map_or(false, |p| shown.contains(&p))
+                    });
+
+                    self.draw_log(ui, &entries);
+                } else {
+                    ui.text("IO logging is disabled.");
+                }
+            });
+
+        open
+    }
+}
@@ -0,0 +1,93 @@
+use gib_core::dbg::{Peripheral, IO_REGISTERS};
+use gib_core::mem::{MemR, MemW};
+
+use super::utils;
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, Ui};
+
+/// View listing the IO registers with their bitfields decoded.
+///
+/// While the emulator is paused, register values can be typed in and flags toggled.
+pub struct IoRegView;
+
+impl IoRegView {
+    pub fn new() -> IoRegView {
+        IoRegView
+    }
+
+    fn draw_registers(&self, ui: &Ui, state: &mut EmuState, peripheral: Peripheral) {
+        let editable = state.paused();
+
+        for reg in IO_REGISTERS
+            .iter()
+            .filter(|r| r.peripheral() == Some(peripheral))
+        {
+            let val = state.bus().peek(reg.addr);
+            let mut new_val = Some(val);
+
+            utils::input_byte(
+                ui,
+                &format!("##ioreg{:04X}", reg.addr),
+                &mut new_val,
+                editable,
+            );
+            ui.same_line(0.0);
+            ui.text(format!("{:04X} {:6} {:08b}", reg.addr, reg.name, val));
+
+            for f in reg.fields.iter() {
+                let field = f.extract(val);
+
+                if f.is_flag() && editable {
+                    let mut set = field != 0;
+
+                    if ui.checkbox(
+                        im_str!("{} {}##ioreg{:04X}", f.lsb, f.name, reg.addr),
+                        &mut set,
+                    ) {
+                        new_val = Some(val ^ f.mask());
+                    }
+                } else if f.is_flag() {
+                    ui.text(format!("    {}    {}: {}", f.lsb, f.name, field));
+                } else {
+                    ui.text(format!("    {}-{}  {}: {}", f.msb, f.lsb, f.name, field));
+                }
+            }
+
+            match new_val {
+                Some(v) if editable && v != val => state.gameboy_mut().bus_mut().poke(reg.addr, v),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl WindowView for IoRegView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("IO Registers"))
+            .size((300.0, 600.0), ImGuiCond::FirstUseEver)
+            .position((650.0, 30.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                let groups = [
+                    (Peripheral::Joypad, im_str!("Joypad")),
+                    (Peripheral::Serial, im_str!("Serial")),
+                    (Peripheral::Timer, im_str!("Timer")),
+                    (Peripheral::Interrupts, im_str!("Interrupts")),
+                    (Peripheral::Apu, im_str!("Sound")),
+                    (Peripheral::Ppu, im_str!("Video")),
+                    (Peripheral::Cgb, im_str!("CGB functions")),
+                ];
+
+                for (peripheral, name) in groups.iter() {
+                    if ui.collapsing_header(name).default_open(true).build() {
+                        self.draw_registers(ui, state, *peripheral);
+                    }
+                }
+            });
+
+        open
+    }
+}
@@ -1,19 +1,60 @@
-use gib_core::dbg;
+use gib_core::bus::Bus;
+use gib_core::dbg::{self, Watchpoint};
 use gib_core::mem::MemR;
 
 use super::utils;
 use super::EmuState;
 use super::WindowView;
 
-use imgui::{im_str, ImGuiCond, ImString, Ui};
+use imgui::{im_str, ImGuiCond, ImMouseButton, ImString, Ui};
 
-/// View containing an hexadecimal dump of a selectable memory region.
+use std::ops::RangeInclusive;
+
+/// Number of characters preceding the first byte of a line, eg. "C000:  ".
+const BYTES_COLUMN: usize = 7;
+
+/// Height reserved below the listing for the data inspector.
+const INSPECTOR_HEIGHT: f32 = 70.0;
+
+/// Maximum number of selected bytes decoded by the data inspector.
+const INSPECTOR_MAX_BYTES: usize = 32;
+
+/// Background of the selected bytes.
+const SELECTION_COLOR: [f32; 4] = [0.2, 0.4, 0.8, 0.6];
+
+/// Background of the bytes frozen to a value.
+const FROZEN_COLOR: [f32; 4] = [0.0, 0.6, 0.6, 0.5];
+
+/// View containing an hexadecimal dump of a selectable memory region,
+/// which can be edited byte by byte.
 pub struct MemEditView {
     section: dbg::MemoryType,
     content: Vec<ImString>,
 
     search_string: ImString,
     matched_lines: Vec<usize>,
+
+    selected_line: Option<usize>,
+    scroll_to_selected: bool,
+
+    // Selected bytes, as the clicked address and the one the selection was extended to
+    selection: Option<(u16, u16)>,
+
+    // Bank shown by the ROM bank button
+    rom_bank: i32,
+
+    goto_location: ImString,
+
+    // Error of the last go-to or freeze, if any
+    status: Option<String>,
+
+    // Address right-clicked to be watched or frozen
+    context_addr: Option<u16>,
+
+    // Address being edited, and the value typed so far
+    edit_addr: Option<u16>,
+    edit_value: ImString,
+    edit_focus: bool,
 }
 
 impl MemEditView {
@@ -26,6 +67,56 @@ impl MemEditView {
 
             search_string: ImString::with_capacity(128),
             matched_lines: Vec::with_capacity(max_bank_size),
+
+            selected_line: None,
+            scroll_to_selected: false,
+
+            selection: None,
+
+            rom_bank: 1,
+
+            goto_location: ImString::with_capacity(64),
+            status: None,
+
+            context_addr: None,
+
+            edit_addr: None,
+            edit_value: ImString::with_capacity(8),
+            edit_focus: false,
+        }
+    }
+
+    /// Switches to the memory section containing `addr`, and scrolls to its line.
+    /// Switchable ROM is shown from the bank currently mapped.
+    fn goto(&mut self, state: &EmuState, addr: u16) {
+        self.section = match dbg::MemoryType::at(addr) {
+            dbg::MemoryType::RomBank(n) if n > 0 => {
+                dbg::MemoryType::RomBank(state.bus().bank_at(addr) as u8)
+            }
+            section => section,
+        };
+        self.refresh_memory(state);
+
+        let start = *self.section.range().start();
+
+        self.selected_line = Some(usize::from(addr - start) / 16);
+        self.scroll_to_selected = true;
+    }
+
+    /// Switches to another memory section, dropping the selection.
+    fn show_section(&mut self, state: &EmuState, section: dbg::MemoryType) {
+        self.section = section;
+        self.selected_line = None;
+        self.selection = None;
+        self.refresh_memory(state);
+    }
+
+    /// Reads the byte at `addr` in the section being shown. Switchable ROM is read
+    /// from the bank being shown, rather than the one currently mapped.
+    fn peek(&self, bus: &Bus, addr: u16) -> u8 {
+        match self.section {
+            dbg::MemoryType::RomBank(n) if n > 0 => bus.with_rom_bank(usize::from(n)).peek(addr),
+            _ => bus.peek(addr),
         }
     }
 
@@ -48,10 +139,7 @@ impl MemEditView {
             let mut data = [0u8; 16];
 
             for addr in ptr..(ptr + 16).min(end) {
-                match bus.read(addr as u16) {
-                    Ok(b) => data[(addr - ptr) as usize] = b,
-                    Err(e) => panic!("unexpected trace event during memory access: {}", e),
-                };
+                data[(addr - ptr) as usize] = self.peek(bus, addr as u16);
             }
 
             // Eg: "0xFF00:  00 01 02 03 04 05  |...123|"
@@ -68,30 +156,254 @@ impl MemEditView {
         }
     }
 
-    // Draw the memory change buttons and search input box on top of the memory viewer.
+    /// Returns the range of selected addresses, if any.
+    fn selected_range(&self) -> Option<RangeInclusive<u16>> {
+        self.selection.map(|(from, to)| from.min(to)..=from.max(to))
+    }
+
+    /// Returns the address of the byte under the mouse cursor, given the line it is on
+    /// and the screen position the line starts at.
+    fn addr_under_mouse(&self, ui: &Ui, line: usize, origin: (f32, f32)) -> Option<u16> {
+        let (mx, _) = ui.imgui().mouse_pos();
+        let char_width = ui.calc_text_size(im_str!("0"), false, -1.0).x;
+        let column = ((mx - origin.0) / char_width) as usize;
+
+        // Each byte takes two digits and a space
+        if column < BYTES_COLUMN || column >= BYTES_COLUMN + 16 * 3 {
+            return None;
+        }
+
+        let addr = u32::from(*self.section.range().start())
+            + (line * 16 + (column - BYTES_COLUMN) / 3) as u32;
+
+        if addr <= u32::from(*self.section.range().end()) {
+            Some(addr as u16)
+        } else {
+            None
+        }
+    }
+
+    /// Highlights the bytes of `line` within `bytes`, with `line` starting at the
+    /// screen position `origin`.
+    fn highlight(
+        &self,
+        ui: &Ui,
+        line: usize,
+        origin: (f32, f32),
+        bytes: RangeInclusive<u16>,
+        color: [f32; 4],
+    ) {
+        let start = u32::from(*self.section.range().start()) + line as u32 * 16;
+        let first = u32::from(*bytes.start()).max(start);
+        let last = u32::from(*bytes.end()).min(start + 15);
+
+        if first > last {
+            return;
+        }
+
+        let char_width = ui.calc_text_size(im_str!("0"), false, -1.0).x;
+        let x = |col: u32| origin.0 + (BYTES_COLUMN as f32 + col as f32 * 3.0) * char_width;
+
+        let p0 = (x(first - start), origin.1);
+        let p1 = (
+            x(last - start) + 2.0 * char_width,
+            origin.1 + ui.get_text_line_height(),
+        );
+
+        ui.get_window_draw_list()
+            .add_rect(p0, p1, color)
+            .filled(true)
+            .build();
+    }
+
+    /// Highlights the frozen and selected bytes of `line`.
+    fn draw_highlights(&self, ui: &Ui, state: &EmuState, line: usize, origin: (f32, f32)) {
+        for f in state.freezes().iter() {
+            self.highlight(ui, line, origin, f.addr..=f.addr, FROZEN_COLOR);
+        }
+
+        if let Some(range) = self.selected_range() {
+            self.highlight(ui, line, origin, range, SELECTION_COLOR);
+        }
+    }
+
+    /// Draws the context menu of the right-clicked address, adding watchpoints
+    /// or freezing it to its current value.
+    fn draw_context_menu(&mut self, ui: &Ui, state: &mut EmuState) {
+        let addr = match self.context_addr {
+            Some(addr) => addr,
+            None => return,
+        };
+
+        let mut result = None;
+
+        ui.popup(im_str!("memedit_context"), || {
+            ui.text(format!("0x{:04X}", addr));
+            ui.separator();
+
+            if ui.menu_item(im_str!("Watch reads")).build() {
+                state.add_watchpoint(Watchpoint::new(addr..=addr, true, false));
+            }
+            if ui.menu_item(im_str!("Watch writes")).build() {
+                state.add_watchpoint(Watchpoint::new(addr..=addr, false, true));
+            }
+            if ui.menu_item(im_str!("Watch changes")).build() {
+                state.add_watchpoint(Watchpoint::changes(addr..=addr));
+            }
+
+            ui.separator();
+
+            // Frozen locations are rewritten every frame, which is pointless for ROM
+            if let Some(val) = state.freezes().get(addr) {
+                if ui.menu_item(im_str!("Unfreeze ({:02X})", val)).build() {
+                    result = Some(state.unfreeze(addr));
+                }
+            } else {
+                let val = state.bus().peek(addr);
+
+                if ui
+                    .menu_item(im_str!("Freeze at {:02X}", val))
+                    .enabled(addr >= 0x8000)
+                    .build()
+                {
+                    result = Some(state.freeze(addr, val));
+                }
+            }
+
+            if !state.freezes().is_empty() {
+                let freezes: Vec<_> = state.freezes().iter().cloned().collect();
+
+                ui.menu(im_str!("Frozen locations")).build(|| {
+                    for f in freezes {
+                        if ui.menu_item(im_str!("Unfreeze {}", f)).build() {
+                            result = Some(state.unfreeze(f.addr));
+                        }
+                    }
+                });
+            }
+        });
+
+        if let Some(Err(e)) = result {
+            self.status = Some(format!("Cannot save the freeze list: {}", e));
+        }
+    }
+
+    /// Draws the input editing the double-clicked byte. Once a value is entered,
+    /// it is written and editing moves on to the next byte.
+    fn draw_edit_popup(&mut self, ui: &Ui, state: &mut EmuState) {
+        let addr = match self.edit_addr {
+            Some(addr) => addr,
+            None => return,
+        };
+
+        let mut written = None;
+
+        ui.popup(im_str!("memedit_edit"), || {
+            ui.text(format!("0x{:04X}", addr));
+            ui.same_line(0.0);
+
+            if self.edit_focus {
+                self.edit_focus = false;
+                unsafe {
+                    imgui_sys::igSetKeyboardFocusHere(0);
+                }
+            }
+
+            ui.push_item_width(30.0);
+            let entered = ui
+                .input_text(im_str!("##memedit_value"), &mut self.edit_value)
+                .chars_hexadecimal(true)
+                .chars_uppercase(true)
+                .enter_returns_true(true)
+                .build();
+            ui.pop_item_width();
+
+            if entered {
+                if let Ok(val) = u8::from_str_radix(self.edit_value.to_str().trim(), 16) {
+                    written = Some(val);
+                }
+            }
+        });
+
+        if let Some(val) = written {
+            state.poke(addr, val);
+            self.refresh_memory(state);
+
+            self.edit_value.clear();
+            self.edit_focus = true;
+
+            if addr < *self.section.range().end() {
+                self.edit_addr = Some(addr + 1);
+                self.selection = Some((addr + 1, addr + 1));
+            }
+        }
+    }
+
+    /// Draws the region buttons, the go-to box and the search input box on top of
+    /// the memory viewer.
     fn draw_toolbar(&mut self, ui: &Ui, state: &EmuState) {
         use dbg::MemoryType::*;
 
         for (label, region) in [
             (im_str!("ROM00"), RomBank(0)),
-            (im_str!("ROM01"), RomBank(1)),
             (im_str!("VRAM"), VideoRam),
             (im_str!("ERAM"), ExternalRam),
             (im_str!("WRAM00"), WorkRamBank(0)),
             (im_str!("WRAM01"), WorkRamBank(1)),
+            (im_str!("OAM"), SpriteMemory),
+            (im_str!("IO"), IoSpace),
             (im_str!("HRAM"), HighRam),
         ]
         .iter()
         {
             if ui.button(label, (0.0, 0.0)) {
-                self.section = *region;
-                self.refresh_memory(state);
+                self.show_section(state, *region);
             }
             ui.same_line(0.0);
         }
 
+        // Switchable ROM banks are picked by number
+        let banks = state.bus().cart.rom_banks().max(2) as i32;
+
+        if ui.button(im_str!("ROM bank"), (0.0, 0.0)) {
+            self.show_section(state, RomBank(self.rom_bank as u8));
+        }
+        ui.same_line(0.0);
+
+        ui.push_item_width(70.0);
+        if ui
+            .input_int(im_str!("##memedit_rom_bank"), &mut self.rom_bank)
+            .build()
+        {
+            self.rom_bank = self.rom_bank.max(1).min((banks - 1).min(0xFF));
+        }
+        ui.pop_item_width();
+
+        ui.push_item_width(120.0);
+        let entered = ui
+            .input_text(im_str!("##memedit_goto"), &mut self.goto_location)
+            .enter_returns_true(true)
+            .build();
+        ui.pop_item_width();
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Go to"), (0.0, 0.0)) || entered {
+            let location = self.goto_location.to_str().to_string();
+
+            self.status = match state.parse_location(&location) {
+                Some(addr) => {
+                    self.goto(state, addr);
+                    self.selection = Some((addr, addr));
+                    None
+                }
+                None => Some(format!("Unknown location: {}", location)),
+            };
+        }
+        ui.same_line(0.0);
+
         // Check to see if the search string has changed,
         // and if it has, update the search results
+        ui.push_item_width(150.0);
         if ui
             .input_text(im_str!("memedit_search"), &mut self.search_string)
             .build()
@@ -115,9 +427,66 @@ impl MemEditView {
                     .collect();
             }
         }
+        ui.pop_item_width();
+
+        if let Some(ref status) = self.status {
+            ui.text_colored(utils::RED, im_str!("{}", status));
+        }
+    }
+
+    /// Draws the data inspector, decoding the selected bytes in various formats.
+    fn draw_inspector(&self, ui: &Ui, state: &EmuState) {
+        let range = match self.selected_range() {
+            Some(range) => range,
+            None => {
+                ui.text("Click to select a byte, Shift+click to extend the selection,");
+                ui.text("double-click to edit it.");
+                return;
+            }
+        };
+
+        let bus = state.bus();
+        let (start, end) = (*range.start(), *range.end());
+        let bytes: Vec<u8> = range
+            .clone()
+            .take(INSPECTOR_MAX_BYTES)
+            .map(|addr| self.peek(bus, addr))
+            .collect();
+
+        let lo = bytes[0];
+        let hi = self.peek(bus, start.wrapping_add(1));
+        let word = u16::from(hi) << 8 | u16::from(lo);
+
+        ui.text(format!(
+            "{}:{:04X}-{:04X} ({} bytes)",
+            self.section,
+            start,
+            end,
+            usize::from(end - start) + 1
+        ));
+        ui.text(format!(
+            "u8: {:3}  i8: {:4}  u16: {:04X} ({})  BCD: {}",
+            lo,
+            lo as i8,
+            word,
+            word,
+            bcd(&bytes)
+        ));
+        ui.text(format!("Text: {}", utils::format_ascii(&bytes)));
     }
 }
 
+/// Formats `bytes` as binary-coded decimal digits, in address order.
+/// Nibbles which are not decimal digits are shown as `?`.
+fn bcd(bytes: &[u8]) -> String {
+    let digit = |n: u8| if n < 10 { (b'0' + n) as char } else { '?' };
+
+    bytes
+        .iter()
+        .flat_map(|b| vec![digit(b >> 4), digit(b & 0x0F)])
+        .collect()
+}
+
 impl WindowView for MemEditView {
     fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
         let mut open = true;
@@ -127,8 +496,13 @@ impl WindowView for MemEditView {
             self.refresh_memory(state);
         }
 
+        // Jump to the address requested by other views
+        if let Some(addr) = state.take_mem_editor_goto() {
+            self.goto(state, addr);
+        }
+
         ui.window(im_str!("Memory Editor"))
-            .size((555.0, 400.0), ImGuiCond::FirstUseEver)
+            .size((555.0, 470.0), ImGuiCond::FirstUseEver)
             .position((320.0, 280.0), ImGuiCond::FirstUseEver)
             .opened(&mut open)
             .build(|| {
@@ -138,21 +512,83 @@ impl WindowView for MemEditView {
 
                 let (_, h) = ui.get_content_region_avail();
 
-                ui.child_frame(im_str!("memedit_listing"), (540.0, h))
+                ui.child_frame(im_str!("memedit_listing"), (540.0, h - INSPECTOR_HEIGHT))
                     .always_show_vertical_scroll_bar(true)
                     .show_borders(false)
                     .build(|| {
+                        if self.scroll_to_selected {
+                            if let Some(line) = self.selected_line {
+                                let y = line as f32 * ui.get_text_line_height_with_spacing();
+
+                                unsafe {
+                                    imgui_sys::igSetScrollY(y);
+                                }
+                            }
+                            self.scroll_to_selected = false;
+                        }
+
                         utils::list_clipper(ui, self.content.len(), |rng| {
                             for i in rng {
+                                let origin = ui.get_cursor_screen_pos();
+
+                                self.draw_highlights(ui, state, i, origin);
+
                                 // Right now we are highlighting the entire line
-                                if self.matched_lines.contains(&i) {
+                                if self.selected_line == Some(i) {
+                                    ui.text_colored(utils::GREEN, &self.content[i]);
+                                } else if self.matched_lines.contains(&i) {
                                     ui.text_colored(utils::YELLOW, &self.content[i]);
                                 } else {
                                     ui.text(&self.content[i]);
                                 }
+
+                                if !ui.is_item_hovered() {
+                                    continue;
+                                }
+
+                                let addr = self.addr_under_mouse(ui, i, origin);
+
+                                // Clicking a byte selects it, Shift extends the selection
+                                if ui.imgui().is_mouse_clicked(ImMouseButton::Left) {
+                                    let shift = unsafe { (*imgui_sys::igGetIO()).key_shift };
+
+                                    self.selection = match (addr, self.selection) {
+                                        (Some(addr), Some((from, _))) if shift => {
+                                            Some((from, addr))
+                                        }
+                                        (Some(addr), _) => Some((addr, addr)),
+                                        (None, selection) => selection,
+                                    };
+                                }
+
+                                // Double-clicking a byte edits it
+                                if ui.imgui().is_mouse_double_clicked(ImMouseButton::Left)
+                                    && addr.is_some()
+                                {
+                                    self.edit_addr = addr;
+                                    self.edit_value.clear();
+                                    self.edit_focus = true;
+                                    ui.open_popup(im_str!("memedit_edit"));
+                                }
+
+                                // Right-clicking a byte offers to watch or freeze it
+                                if ui.imgui().is_mouse_clicked(ImMouseButton::Right) {
+                                    self.context_addr = addr;
+
+                                    if self.context_addr.is_some() {
+                                        ui.open_popup(im_str!("memedit_context"));
+                                    }
+                                }
                             }
                         });
+
+                        self.draw_context_menu(ui, state);
+                        self.draw_edit_popup(ui, state);
                     });
+
+                ui.separator();
+
+                self.draw_inspector(ui, state);
             });
 
         open
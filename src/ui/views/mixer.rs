@@ -0,0 +1,70 @@
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, Ui};
+
+/// Names of the mixed sound channels.
+const CHANNELS: [&str; 3] = ["Square 1", "Square 2", "Wave"];
+
+/// View to mute or solo single sound channels and trim the master volume,
+/// eg. to debug the emulation of a channel by listening to it alone.
+pub struct MixerView;
+
+impl MixerView {
+    pub fn new() -> MixerView {
+        MixerView
+    }
+}
+
+impl WindowView for MixerView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("Mixer"))
+            .size((260.0, 170.0), ImGuiCond::FirstUseEver)
+            .position((440.0, 400.0), ImGuiCond::FirstUseEver)
+            .resizable(false)
+            .opened(&mut open)
+            .build(|| {
+                let mut controls = state.mixer_controls();
+                let mut changed = false;
+
+                for (ch, name) in CHANNELS.iter().enumerate() {
+                    let mut solo = controls.solo == Some(ch);
+
+                    changed |= ui.checkbox(im_str!("Mute##{}", ch), &mut controls.muted[ch]);
+                    ui.same_line(0.0);
+
+                    if ui.checkbox(im_str!("Solo##{}", ch), &mut solo) {
+                        controls.solo = if solo { Some(ch) } else { None };
+                        changed = true;
+                    }
+                    ui.same_line(0.0);
+
+                    if controls.audible(ch) {
+                        ui.text(name);
+                    } else {
+                        ui.text_disabled(im_str!("{}", name));
+                    }
+                }
+
+                ui.text_disabled(im_str!("Noise channel not emulated yet"));
+
+                ui.separator();
+
+                changed |= ui
+                    .slider_float(
+                        im_str!("Master volume"),
+                        &mut controls.master_volume,
+                        0.0,
+                        2.0,
+                    )
+                    .build();
+
+                if changed {
+                    state.set_mixer_controls(controls);
+                }
+            });
+
+        open
+    }
+}
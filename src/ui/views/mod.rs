@@ -1,29 +1,78 @@
+mod apu;
+mod bgmap;
+mod cheats;
+mod commands;
 mod debugger;
 mod disassembly;
+mod iolog;
+mod ioregs;
 mod memedit;
 mod memmap;
+mod mixer;
+mod oam;
+mod palette;
 mod peripherals;
+mod profiler;
+mod ramsearch;
+mod serial;
+mod snapshots;
+mod timeline;
+mod timer;
 
+pub use apu::*;
+pub use bgmap::*;
+pub use cheats::*;
+pub use commands::*;
 pub use debugger::*;
 pub use disassembly::*;
+pub use iolog::*;
+pub use ioregs::*;
 pub use memedit::*;
 pub use memmap::*;
+pub use mixer::*;
+pub use oam::*;
+pub use palette::*;
 pub use peripherals::*;
+pub use profiler::*;
+pub use ramsearch::*;
+pub use serial::*;
+pub use snapshots::*;
+pub use timeline::*;
+pub use timer::*;
 
 use super::utils;
 use super::EmuState;
+use super::UiContext;
 
 use imgui::Ui;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum View {
+    Apu,
+    BgMap,
+    Cheats,
+    Commands,
     Debugger,
     Disassembly,
+    IoLog,
+    IoRegs,
     MemEditor,
     MemMap,
+    Mixer,
+    Oam,
+    Palettes,
     Peripherals,
+    Profiler,
+    RamSearch,
+    Serial,
+    Snapshots,
+    Timeline,
+    Timer,
 }
 
 pub trait WindowView {
     fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool;
+
+    /// Uploads the textures displayed by the view, before the UI is drawn.
+    fn prepare_textures(&mut self, _ctx: &mut UiContext, _state: &EmuState) {}
 }
@@ -0,0 +1,118 @@
+use gib_core::io::OAM_ENTRIES;
+use gib_core::mem::MemR;
+
+use super::utils;
+use super::{EmuState, UiContext, WindowView};
+
+use imgui::{im_str, ImGuiCond, ImTexture, Ui};
+
+/// View listing the sprites in OAM, along with their decoded attributes.
+pub struct OamView {
+    buffer: Vec<u8>,
+    texture: Option<ImTexture>,
+}
+
+impl OamView {
+    pub fn new() -> OamView {
+        OamView {
+            buffer: vec![0; OAM_ENTRIES * 8 * 16 * 4],
+            texture: None,
+        }
+    }
+
+    /// Returns a description of the sprite attributes (OAM byte 3).
+    fn format_attributes(attr: u8, cgb: bool) -> String {
+        let mut s = String::new();
+
+        s.push(if attr & 0x80 != 0 { 'P' } else { '-' });
+        s.push(if attr & 0x40 != 0 { 'Y' } else { '-' });
+        s.push(if attr & 0x20 != 0 { 'X' } else { '-' });
+
+        if cgb {
+            s.push_str(&format!(" BANK{} PAL{}", (attr >> 3) & 0x01, attr & 0x07));
+        } else {
+            s.push_str(&format!(" OBP{}", (attr >> 4) & 0x01));
+        }
+        s
+    }
+
+    fn draw_sprite(&self, ui: &Ui, state: &mut EmuState, i: usize) {
+        let bus = state.bus();
+        let base = 0xFE00 + (i as u16) * 4;
+
+        let (y, x, tid, attr) = (
+            bus.peek(base),
+            bus.peek(base + 1),
+            bus.peek(base + 2),
+            bus.peek(base + 3),
+        );
+
+        // Highlight the sprites overlapping the line being drawn
+        let height = if bus.peek(0xFF40) & 0x04 != 0 { 16 } else { 8 };
+        let row = i16::from(bus.peek(0xFF44)) - (i16::from(y) - 16);
+        let on_line = row >= 0 && row < height;
+
+        if let Some(texture) = self.texture {
+            let (u0, u1) = (
+                i as f32 / OAM_ENTRIES as f32,
+                (i + 1) as f32 / OAM_ENTRIES as f32,
+            );
+
+            ui.image(texture, (16.0, 32.0))
+                .uv0((u0, 0.0))
+                .uv1((u1, 1.0))
+                .build();
+            ui.same_line(0.0);
+        }
+
+        let text = im_str!(
+            "{:02} {:04X}  Y:{:02X} X:{:02X} T:{:02X} {}",
+            i,
+            base,
+            y,
+            x,
+            tid,
+            OamView::format_attributes(attr, bus.cgb),
+        );
+
+        if on_line {
+            ui.text_colored(utils::GREEN, text);
+        } else {
+            ui.text(text);
+        }
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Mem##oam{}", i), (0.0, 0.0)) {
+            state.show_in_mem_editor(base);
+        }
+    }
+}
+
+impl WindowView for OamView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("OAM"))
+            .size((380.0, 500.0), ImGuiCond::FirstUseEver)
+            .position((500.0, 200.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                ui.text(format!("LY: {:02X}", state.bus().peek(0xFF44)));
+
+                ui.separator();
+
+                for i in 0..OAM_ENTRIES {
+                    self.draw_sprite(ui, state, i);
+                }
+            });
+
+        open
+    }
+
+    fn prepare_textures(&mut self, ctx: &mut UiContext, state: &EmuState) {
+        state.bus().ppu.rasterize_sprites(&mut self.buffer[..]);
+
+        self.texture =
+            Some(ctx.upload_texture(OAM_ENTRIES * 8, 16, &self.buffer[..], self.texture));
+    }
+}
@@ -0,0 +1,136 @@
+use gib_core::io::Palette;
+use gib_core::mem::MemR;
+
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, Ui};
+
+/// View showing the palettes as color swatches, which can be edited live.
+///
+/// Clicking a swatch of a monochrome palette cycles through the four shades, while
+/// clicking one of a CGB palette selects it for editing its RGB components.
+pub struct PaletteView {
+    selected: Option<(Palette, u8)>,
+}
+
+impl PaletteView {
+    pub fn new() -> PaletteView {
+        PaletteView { selected: None }
+    }
+
+    /// Draws the four colors of `pal`, returning the pixel value of the one clicked, if any.
+    fn draw_swatches(&self, ui: &Ui, state: &EmuState, pal: Palette, id: &str) -> Option<u8> {
+        let colors = state.bus().ppu.palette_colors(pal);
+        let mut clicked = None;
+
+        for (pixel, [r, g, b]) in colors.iter().enumerate() {
+            let color = [
+                f32::from(*r) / 255.0,
+                f32::from(*g) / 255.0,
+                f32::from(*b) / 255.0,
+                1.0,
+            ];
+
+            ui.same_line(0.0);
+            if ui
+                .color_button(im_str!("##{}{}", id, pixel), color)
+                .size((20.0, 20.0))
+                .build()
+            {
+                clicked = Some(pixel as u8);
+            }
+        }
+
+        clicked
+    }
+
+    fn draw_dmg_palettes(&self, ui: &Ui, state: &mut EmuState) {
+        let palettes = [
+            (Palette::Bgp, "BGP ", 0xFF47),
+            (Palette::Obp0, "OBP0", 0xFF48),
+            (Palette::Obp1, "OBP1", 0xFF49),
+        ];
+
+        for &(pal, name, addr) in palettes.iter() {
+            let reg = state.bus().peek(addr);
+
+            ui.text(format!("{} {:02X} ({:08b})", name, reg, reg));
+
+            // Cycle through the shades of the clicked color
+            if let Some(pixel) = self.draw_swatches(ui, state, pal, name) {
+                let ppu = &mut state.gameboy_mut().bus_mut().ppu;
+                let shade = ppu.palette_entry(pal, pixel);
+
+                ppu.set_palette_entry(pal, pixel, (shade + 1) % 4);
+            }
+        }
+    }
+
+    fn draw_cgb_palettes(&mut self, ui: &Ui, state: &mut EmuState) {
+        for n in 0..8 {
+            ui.text(format!("BG{} ", n));
+            if let Some(pixel) = self.draw_swatches(ui, state, Palette::CgbBg(n), "bg") {
+                self.selected = Some((Palette::CgbBg(n), pixel));
+            }
+
+            ui.same_line_spacing(0.0, 20.0);
+
+            ui.text(format!("OBJ{}", n));
+            if let Some(pixel) = self.draw_swatches(ui, state, Palette::CgbObj(n), "obj") {
+                self.selected = Some((Palette::CgbObj(n), pixel));
+            }
+        }
+
+        if let Some((pal, pixel)) = self.selected {
+            ui.separator();
+
+            ui.text(format!("Editing {:?}, color {}", pal, pixel));
+
+            let ppu = &mut state.gameboy_mut().bus_mut().ppu;
+            let rgb = ppu.palette_entry(pal, pixel);
+
+            // Edit each 5 bit component of the RGB555 color
+            let mut changed = false;
+            let mut components = [0; 3];
+
+            for (i, label) in [im_str!("R"), im_str!("G"), im_str!("B")]
+                .iter()
+                .enumerate()
+            {
+                components[i] = i32::from((rgb >> (i * 5)) & 0x1F);
+                changed |= ui.slider_int(label, &mut components[i], 0, 31).build();
+            }
+
+            if changed {
+                let rgb = components
+                    .iter()
+                    .enumerate()
+                    .fold(0, |rgb, (i, c)| rgb | ((*c as u16) << (i * 5)));
+
+                ppu.set_palette_entry(pal, pixel, rgb);
+            }
+        }
+    }
+}
+
+impl WindowView for PaletteView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("Palettes"))
+            .size((330.0, 420.0), ImGuiCond::FirstUseEver)
+            .position((600.0, 250.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_dmg_palettes(ui, state);
+
+                if state.bus().cgb {
+                    ui.separator();
+
+                    self.draw_cgb_palettes(ui, state);
+                }
+            });
+
+        open
+    }
+}
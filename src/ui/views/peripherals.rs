@@ -21,6 +21,14 @@ impl WindowView for PeripheralView {
             .position((955.0, 30.0), ImGuiCond::FirstUseEver)
             .opened(&mut open)
             .build(|| {
+                if ui
+                    .collapsing_header(im_str!("Cartridge"))
+                    .default_open(true)
+                    .build()
+                {
+                    self.draw_cartridge(ui, state);
+                }
+
                 if ui.collapsing_header(im_str!("Video Display")).build() {
                     ui.text("NOT IMPLEMENTED YET!");
                 }
@@ -59,6 +67,20 @@ impl WindowView for PeripheralView {
 }
 
 impl PeripheralView {
+    fn draw_cartridge(&self, ui: &Ui, state: &EmuState) {
+        let cart = &state.bus().cart;
+
+        ui.text(format!("MBC: {}", cart.mbc_name()));
+        ui.same_line_spacing(0.0, 40.0);
+        ui.text(format!("ROM bank: {:02X}", cart.rom_bank()));
+        ui.same_line_spacing(0.0, 40.0);
+
+        match cart.ram_bank() {
+            Some(bank) => ui.text(format!("RAM bank: {:02X}", bank)),
+            None => ui.text_disabled(im_str!("RAM disabled")),
+        }
+    }
+
     fn draw_timer(&self, ui: &Ui, state: &EmuState) {
         let timer = &state.bus().tim;
 
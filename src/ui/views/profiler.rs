@@ -0,0 +1,84 @@
+use gib_core::dbg::{CodeAddr, ProfileStats};
+
+use super::utils;
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, Ui};
+
+/// Number of entries shown in each list.
+const HOTTEST_COUNT: usize = 20;
+
+pub struct ProfilerView;
+
+impl ProfilerView {
+    pub fn new() -> ProfilerView {
+        ProfilerView
+    }
+
+    fn draw_stats(&self, ui: &Ui, stats: &[(CodeAddr, ProfileStats)], total: u64) {
+        ui.text_colored(utils::DARK_GREY, im_str!("  Address        Cycles       Hits      %"));
+
+        for (addr, s) in stats.iter() {
+            ui.text(format!(
+                "  {}  {:12} {:10}  {:5.1}",
+                addr,
+                s.cycles,
+                s.hits,
+                100.0 * s.cycles as f64 / total.max(1) as f64
+            ));
+        }
+    }
+}
+
+impl WindowView for ProfilerView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("Profiler"))
+            .size((340.0, 520.0), ImGuiCond::FirstUseEver)
+            .position((320.0, 280.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                let mut profiling = state.profiling();
+
+                if ui.checkbox(im_str!("Enabled"), &mut profiling) {
+                    state.set_profiling(profiling);
+                }
+                ui.same_line(0.0);
+
+                if ui.button(im_str!("Reset"), (0.0, 0.0)) {
+                    if let Some(p) = state.gameboy_mut().profiler_mut() {
+                        p.reset();
+                    }
+                }
+
+                ui.separator();
+
+                if let Some(p) = state.gameboy().profiler() {
+                    let total = p.total_cycles();
+
+                    ui.text(format!("Total cycles: {}", total));
+
+                    if ui
+                        .collapsing_header(im_str!("Hottest functions"))
+                        .default_open(true)
+                        .build()
+                    {
+                        self.draw_stats(ui, &p.hottest_functions(HOTTEST_COUNT), total);
+                    }
+
+                    if ui
+                        .collapsing_header(im_str!("Hottest addresses"))
+                        .default_open(true)
+                        .build()
+                    {
+                        self.draw_stats(ui, &p.hottest_addrs(HOTTEST_COUNT), total);
+                    }
+                } else {
+                    ui.text("Profiling is disabled.");
+                }
+            });
+
+        open
+    }
+}
@@ -0,0 +1,157 @@
+use gib_core::dbg::{Comparison, RamSearch, Watchpoint};
+use gib_core::mem::MemR;
+
+use super::utils;
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, ImMouseButton, Ui};
+
+/// View narrowing down the WRAM and HRAM locations holding a value, by comparing
+/// them across scans. This is the usual way to find eg. the number of lives.
+pub struct RamSearchView {
+    search: RamSearch,
+
+    // Value compared by the scans, if any
+    value: Option<u8>,
+
+    // Result right-clicked to be watched
+    watch_addr: Option<u16>,
+}
+
+impl RamSearchView {
+    pub fn new() -> RamSearchView {
+        RamSearchView {
+            search: RamSearch::new(),
+            value: None,
+            watch_addr: None,
+        }
+    }
+
+    fn draw_controls(&mut self, ui: &Ui, state: &EmuState) {
+        utils::input_byte(ui, "Value", &mut self.value, true);
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("First scan"), (0.0, 0.0)) {
+            self.search.first_scan(state.bus(), self.value);
+        }
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Clear"), (0.0, 0.0)) {
+            self.search.clear();
+        }
+
+        // Refining requires a first scan to compare against
+        if self.search.scans() == 0 {
+            return;
+        }
+
+        let mut comparisons = vec![
+            Comparison::Unchanged,
+            Comparison::Changed,
+            Comparison::Increased,
+            Comparison::Decreased,
+        ];
+        if let Some(val) = self.value {
+            comparisons.push(Comparison::EqualTo(val));
+        }
+
+        for (i, cmp) in comparisons.into_iter().enumerate() {
+            if i > 0 {
+                ui.same_line(0.0);
+            }
+            if ui.button(im_str!("{}", cmp), (0.0, 0.0)) {
+                self.search.refine(state.bus(), cmp);
+            }
+        }
+
+        ui.text(format!(
+            "{} results after {} scans",
+            self.search.results().len(),
+            self.search.scans()
+        ));
+    }
+
+    /// Draws the context menu adding watchpoints on the right-clicked result.
+    fn draw_watch_popup(&mut self, ui: &Ui, state: &mut EmuState) {
+        let addr = match self.watch_addr {
+            Some(addr) => addr,
+            None => return,
+        };
+
+        ui.popup(im_str!("ramsearch_watch"), || {
+            ui.text(format!("0x{:04X}", addr));
+            ui.separator();
+
+            if ui.menu_item(im_str!("Watch writes")).build() {
+                state.add_watchpoint(Watchpoint::new(addr..=addr, false, true));
+            }
+            if ui.menu_item(im_str!("Watch changes")).build() {
+                state.add_watchpoint(Watchpoint::changes(addr..=addr));
+            }
+        });
+    }
+
+    fn draw_results(&mut self, ui: &Ui, state: &mut EmuState) {
+        ui.child_frame(im_str!("ramsearch_results"), (0.0, 0.0))
+            .build(|| {
+                let results = self.search.results();
+                let mut clicked = None;
+                let mut right_clicked = None;
+
+                utils::list_clipper(ui, results.len(), |range| {
+                    for r in &results[range] {
+                        let now = state.bus().peek(r.addr);
+                        let text = format!("{:04X}:  {:02X} (was {:02X})", r.addr, now, r.val);
+
+                        if now != r.val {
+                            ui.text_colored(utils::YELLOW, im_str!("{}", text));
+                        } else {
+                            ui.text(text);
+                        }
+
+                        if ui.is_item_hovered() {
+                            if ui.imgui().is_mouse_clicked(ImMouseButton::Left) {
+                                clicked = Some(r.addr);
+                            }
+                            if ui.imgui().is_mouse_clicked(ImMouseButton::Right) {
+                                right_clicked = Some(r.addr);
+                            }
+                        }
+                    }
+                });
+
+                // Clicking a result shows it in the memory editor
+                if let Some(addr) = clicked {
+                    state.show_in_mem_editor(addr);
+                }
+
+                // Right-clicking it offers to watch it
+                if right_clicked.is_some() {
+                    self.watch_addr = right_clicked;
+                    ui.open_popup(im_str!("ramsearch_watch"));
+                }
+
+                self.draw_watch_popup(ui, state);
+            });
+    }
+}
+
+impl WindowView for RamSearchView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("RAM Search"))
+            .size((360.0, 400.0), ImGuiCond::FirstUseEver)
+            .position((720.0, 30.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_controls(ui, state);
+
+                ui.separator();
+
+                self.draw_results(ui, state);
+            });
+
+        open
+    }
+}
@@ -0,0 +1,79 @@
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, Ui};
+
+/// View showing the bytes sent over the link port as text,
+/// which is where most test ROMs print their results.
+pub struct SerialView {
+    follow: bool,
+    dump_status: Option<String>,
+}
+
+impl SerialView {
+    pub fn new() -> SerialView {
+        SerialView {
+            follow: true,
+            dump_status: None,
+        }
+    }
+
+    fn draw_controls(&mut self, ui: &Ui, state: &mut EmuState) {
+        ui.checkbox(im_str!("Follow"), &mut self.follow);
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Clear"), (0.0, 0.0)) {
+            state.clear_serial_output();
+            self.dump_status = None;
+        }
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Dump"), (0.0, 0.0)) {
+            self.dump_status = Some(match state.dump_serial_output() {
+                Ok(path) => format!("Saved to {}", path.display()),
+                Err(e) => format!("Error: {}", e),
+            });
+        }
+
+        if let Some(ref status) = self.dump_status {
+            ui.same_line(0.0);
+            ui.text(status);
+        }
+    }
+
+    fn draw_output(&self, ui: &Ui, state: &EmuState) {
+        ui.child_frame(im_str!("serial_frame"), (0.0, 0.0))
+            .build(|| {
+                let output = String::from_utf8_lossy(state.serial_output());
+
+                for line in output.lines() {
+                    ui.text(line);
+                }
+
+                if self.follow {
+                    unsafe {
+                        imgui_sys::igSetScrollY(imgui_sys::igGetScrollMaxY());
+                    }
+                }
+            });
+    }
+}
+
+impl WindowView for SerialView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("Serial"))
+            .size((400.0, 300.0), ImGuiCond::FirstUseEver)
+            .position((720.0, 30.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_controls(ui, state);
+
+                ui.separator();
+
+                self.draw_output(ui, state);
+            });
+
+        open
+    }
+}
@@ -0,0 +1,175 @@
+use gib_core::dbg::{MemSnapshot, MemoryType};
+
+use super::utils;
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, ImMouseButton, ImString, Ui};
+
+/// Regions which can be captured, with their labels.
+const REGIONS: [(&str, MemoryType); 7] = [
+    ("VRAM", MemoryType::VideoRam),
+    ("ERAM", MemoryType::ExternalRam),
+    ("WRAM00", MemoryType::WorkRamBank(0)),
+    ("WRAM01", MemoryType::WorkRamBank(1)),
+    ("OAM", MemoryType::SpriteMemory),
+    ("IO", MemoryType::IoSpace),
+    ("HRAM", MemoryType::HighRam),
+];
+
+/// View taking named snapshots of memory regions, and showing the bytes which
+/// changed since then, eg. to find out where a game keeps the score.
+pub struct SnapshotView {
+    snapshots: Vec<MemSnapshot>,
+
+    // Region and name of the next snapshot
+    region: usize,
+    name: ImString,
+
+    // Snapshot compared against live memory
+    selected: Option<usize>,
+}
+
+impl SnapshotView {
+    pub fn new() -> SnapshotView {
+        SnapshotView {
+            snapshots: vec![],
+            region: 2,
+            name: ImString::with_capacity(32),
+            selected: None,
+        }
+    }
+
+    fn draw_controls(&mut self, ui: &Ui, state: &EmuState) {
+        for (i, (label, _)) in REGIONS.iter().enumerate() {
+            let mut checked = self.region == i;
+
+            if ui.checkbox(im_str!("{}##snapshot_region", label), &mut checked) {
+                self.region = i;
+            }
+            ui.same_line(0.0);
+        }
+        ui.new_line();
+
+        ui.push_item_width(150.0);
+        ui.input_text(im_str!("##snapshot_name"), &mut self.name)
+            .build();
+        ui.pop_item_width();
+        ui.same_line(0.0);
+
+        if ui.button(im_str!("Take snapshot"), (0.0, 0.0)) {
+            let (label, region) = REGIONS[self.region];
+
+            let name = match self.name.to_str().trim() {
+                "" => format!("{} #{}", label, self.snapshots.len() + 1),
+                name => name.to_string(),
+            };
+
+            self.snapshots
+                .push(MemSnapshot::take(name, state.bus(), region.range()));
+            self.selected = Some(self.snapshots.len() - 1);
+            self.name.clear();
+        }
+    }
+
+    fn draw_snapshots(&mut self, ui: &Ui) {
+        let mut removed = None;
+
+        for (i, snapshot) in self.snapshots.iter().enumerate() {
+            if ui.button(im_str!("x##snapshot{}", i), (0.0, 0.0)) {
+                removed = Some(i);
+            }
+            ui.same_line(0.0);
+
+            let range = snapshot.range();
+            let text = format!(
+                "{} ({:04X}-{:04X})",
+                snapshot.name,
+                range.start(),
+                range.end()
+            );
+
+            if self.selected == Some(i) {
+                ui.text_colored(utils::GREEN, im_str!("{}", text));
+            } else {
+                ui.text(text);
+            }
+
+            if ui.is_item_hovered() && ui.imgui().is_mouse_clicked(ImMouseButton::Left) {
+                self.selected = Some(i);
+            }
+        }
+
+        if let Some(i) = removed {
+            self.snapshots.remove(i);
+
+            self.selected = match self.selected {
+                Some(s) if s == i => None,
+                Some(s) if s > i => Some(s - 1),
+                s => s,
+            };
+        }
+    }
+
+    /// Lists the bytes which changed since the selected snapshot, with their old
+    /// and new values. Clicking one shows it in the memory editor.
+    fn draw_diff(&self, ui: &Ui, state: &mut EmuState) {
+        let snapshot = match self.selected.and_then(|i| self.snapshots.get(i)) {
+            Some(snapshot) => snapshot,
+            None => {
+                ui.text("Select a snapshot to compare it with memory.");
+                return;
+            }
+        };
+
+        let changes = snapshot.diff(state.bus());
+        ui.text(format!(
+            "{} bytes changed since {}",
+            changes.len(),
+            snapshot.name
+        ));
+
+        ui.child_frame(im_str!("snapshot_diff"), (0.0, 0.0))
+            .build(|| {
+                let mut clicked = None;
+
+                utils::list_clipper(ui, changes.len(), |range| {
+                    for change in &changes[range] {
+                        ui.text(change.to_string());
+
+                        if ui.is_item_hovered() && ui.imgui().is_mouse_clicked(ImMouseButton::Left)
+                        {
+                            clicked = Some(change.addr);
+                        }
+                    }
+                });
+
+                if let Some(addr) = clicked {
+                    state.show_in_mem_editor(addr);
+                }
+            });
+    }
+}
+
+impl WindowView for SnapshotView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("Memory Snapshots"))
+            .size((420.0, 400.0), ImGuiCond::FirstUseEver)
+            .position((720.0, 30.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_controls(ui, state);
+
+                ui.separator();
+
+                self.draw_snapshots(ui);
+
+                ui.separator();
+
+                self.draw_diff(ui, state);
+            });
+
+        open
+    }
+}
@@ -0,0 +1,204 @@
+use gib_core::dbg::{PpuEvent, PpuEventKind};
+
+use super::utils;
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, Ui};
+
+/// Number of dots in a scanline, and number of scanlines in a frame.
+const DOTS_PER_LINE: u16 = 456;
+const LINES_PER_FRAME: u8 = 154;
+
+/// Height of a scanline in the plot, in pixels.
+const LINE_HEIGHT: f32 = 3.0;
+
+/// Colors of the four LCD modes in the plot.
+const MODE_COLORS: [[f32; 4]; 4] = [
+    [0.2, 0.3, 0.6, 1.0],
+    [0.3, 0.3, 0.3, 1.0],
+    [0.2, 0.6, 0.2, 1.0],
+    [0.8, 0.5, 0.1, 1.0],
+];
+
+/// View plotting the activity of the LCD controller during the last few frames,
+/// similar to a logic analyzer trace: each row is a scanline, colored by mode,
+/// with markers showing LY=LYC coincidences and requested interrupts.
+pub struct TimelineView {
+    frames_ago: i32,
+}
+
+impl TimelineView {
+    pub fn new() -> TimelineView {
+        TimelineView { frames_ago: 1 }
+    }
+
+    fn event_color(kind: PpuEventKind) -> [f32; 4] {
+        match kind {
+            PpuEventKind::Mode(mode) => MODE_COLORS[usize::from(mode & 0x03)],
+            PpuEventKind::LycMatch => utils::YELLOW,
+            PpuEventKind::VBlankIrq => utils::RED,
+            PpuEventKind::StatIrq => utils::WHITE,
+        }
+    }
+
+    fn draw_controls(&mut self, ui: &Ui, state: &mut EmuState) {
+        let mut enabled = state.ppu_timeline();
+
+        if ui.checkbox(im_str!("Enabled"), &mut enabled) {
+            state.set_ppu_timeline(enabled);
+        }
+
+        for mode in 0..4 {
+            ui.same_line(0.0);
+            ui.text_colored(MODE_COLORS[mode], im_str!("MODE {}", mode));
+        }
+
+        for &kind in [
+            PpuEventKind::LycMatch,
+            PpuEventKind::VBlankIrq,
+            PpuEventKind::StatIrq,
+        ]
+        .iter()
+        {
+            ui.same_line(0.0);
+            ui.text_colored(TimelineView::event_color(kind), im_str!("{}", kind));
+        }
+    }
+
+    fn draw_plot(&self, ui: &Ui, events: &[&PpuEvent]) {
+        let size = (
+            f32::from(DOTS_PER_LINE),
+            f32::from(LINES_PER_FRAME) * LINE_HEIGHT,
+        );
+
+        ui.child_frame(im_str!("timeline_plot"), size).build(|| {
+            let origin = ui.get_cursor_screen_pos();
+            let draw_list = ui.get_window_draw_list();
+
+            let pos = |line: u8, dot: u16| {
+                (
+                    origin.0 + f32::from(dot),
+                    origin.1 + f32::from(line) * LINE_HEIGHT,
+                )
+            };
+
+            // Fills the dots between two events, which may be on different lines
+            let fill = |(l0, d0): (u8, u16), (l1, d1): (u8, u16), color: [f32; 4]| {
+                for line in l0..=l1.min(LINES_PER_FRAME - 1) {
+                    let x0 = if line == l0 { d0 } else { 0 };
+                    let x1 = if line == l1 { d1 } else { DOTS_PER_LINE };
+
+                    if x1 > x0 {
+                        let (p0, mut p1) = (pos(line, x0), pos(line, x1));
+                        p1.1 += LINE_HEIGHT;
+
+                        draw_list.add_rect(p0, p1, color).filled(true).build();
+                    }
+                }
+            };
+
+            // Mode segments, from the previous mode change to the next one
+            let mut current = None;
+
+            for e in events.iter() {
+                if let PpuEventKind::Mode(_) = e.kind {
+                    if let Some((start, color)) = current {
+                        fill(start, (e.line, e.dot), color);
+                    }
+                    current = Some(((e.line, e.dot), TimelineView::event_color(e.kind)));
+                }
+            }
+            if let Some((start, color)) = current {
+                fill(start, (LINES_PER_FRAME, 0), color);
+            }
+
+            // Markers for coincidences and interrupts
+            for e in events.iter() {
+                if let PpuEventKind::Mode(_) = e.kind {
+                    continue;
+                }
+
+                let (p0, mut p1) = (pos(e.line, e.dot), pos(e.line, e.dot + 2));
+                p1.1 += LINE_HEIGHT;
+
+                draw_list
+                    .add_rect(p0, p1, TimelineView::event_color(e.kind))
+                    .filled(true)
+                    .build();
+            }
+
+            // Show the position under the mouse cursor
+            if ui.is_window_hovered() {
+                let (mx, my) = ui.imgui().mouse_pos();
+                let line = ((my - origin.1) / LINE_HEIGHT) as i32;
+                let dot = (mx - origin.0) as i32;
+
+                if line >= 0 && line < i32::from(LINES_PER_FRAME) && dot >= 0 {
+                    ui.tooltip_text(format!("LY:{:3} DOT:{:3}", line, dot));
+                }
+            }
+        });
+    }
+
+    fn draw_events(&self, ui: &Ui, events: &[&PpuEvent]) {
+        let events = events
+            .iter()
+            .filter(|e| match e.kind {
+                PpuEventKind::Mode(_) => false,
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+
+        ui.child_frame(im_str!("timeline_events"), (0.0, 0.0))
+            .build(|| {
+                utils::list_clipper(ui, events.len(), |range| {
+                    for e in events[range].iter() {
+                        ui.text_colored(TimelineView::event_color(e.kind), im_str!("{}", e));
+                    }
+                });
+            });
+    }
+}
+
+impl WindowView for TimelineView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("PPU Timeline"))
+            .size((480.0, 640.0), ImGuiCond::FirstUseEver)
+            .position((400.0, 30.0), ImGuiCond::FirstUseEver)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_controls(ui, state);
+
+                ui.separator();
+
+                if let Some(tl) = state.gameboy().ppu_timeline() {
+                    // The last frame is still being drawn, only show complete ones
+                    let frames = tl.frames();
+                    let recorded = (frames.end() - frames.start()) as i32;
+
+                    if recorded == 0 {
+                        ui.text("No complete frame recorded yet.");
+                        return;
+                    }
+
+                    self.frames_ago = self.frames_ago.max(1).min(recorded);
+                    ui.slider_int(im_str!("Frames ago"), &mut self.frames_ago, 1, recorded)
+                        .build();
+
+                    let frame = frames.end() - self.frames_ago as u32;
+                    let events = tl.events(frame).collect::<Vec<_>>();
+
+                    ui.text(format!("Frame {}: {} events", frame, events.len()));
+
+                    self.draw_plot(ui, &events);
+                    self.draw_events(ui, &events);
+                } else {
+                    ui.text("PPU timeline is disabled.");
+                }
+            });
+
+        open
+    }
+}
@@ -0,0 +1,87 @@
+use super::utils;
+use super::{EmuState, WindowView};
+
+use imgui::{im_str, ImGuiCond, Ui};
+
+/// View showing the state of the timer, decoded, along with TIMA overflow statistics.
+pub struct TimerView;
+
+impl TimerView {
+    pub fn new() -> TimerView {
+        TimerView
+    }
+
+    fn draw_counter(&self, ui: &Ui, state: &EmuState) {
+        let timer = &state.bus().tim;
+        let counter = timer.sys_counter.0;
+
+        ui.text(format!(
+            "DIV: {:02X}  Counter: {:04X}",
+            timer.div().0,
+            counter
+        ));
+        ui.text(format!("{:08b} {:08b}", counter >> 8, counter & 0xFF));
+    }
+
+    fn draw_registers(&self, ui: &Ui, state: &EmuState) {
+        let timer = &state.bus().tim;
+
+        ui.text(format!(
+            "TIMA: {:02X}  TMA: {:02X}  TAC: {:02X}",
+            timer.tima.0, timer.tma.0, timer.tac.0
+        ));
+
+        let (color, status) = if timer.running() {
+            (utils::GREEN, "RUNNING")
+        } else {
+            (utils::DARK_GREY, "STOPPED")
+        };
+        ui.text_colored(color, im_str!("{}", status));
+        ui.same_line(0.0);
+        ui.text(format!("Input clock: {} Hz", timer.input_clock()));
+
+        match timer.cycles_to_increment() {
+            Some(cycles) => ui.text(format!("Next increment in {} cycles", cycles)),
+            None => ui.text_disabled(im_str!("No increments while stopped")),
+        }
+    }
+
+    fn draw_overflows(&self, ui: &Ui, state: &EmuState) {
+        let timer = &state.bus().tim;
+
+        // Each overflow reloads TMA, so IRQs fire every 256 - TMA increments
+        let irq_rate = timer.input_clock() as f32 / (256 - u32::from(timer.tma.0)) as f32;
+
+        ui.text(format!("Overflows: {}", timer.overflows()));
+        ui.text(format!(
+            "Cycles since last overflow: {}",
+            timer.cycles_since_overflow()
+        ));
+        ui.text(format!("IRQ rate with TMA: {:.2} Hz", irq_rate));
+    }
+}
+
+impl WindowView for TimerView {
+    fn draw(&mut self, ui: &Ui, state: &mut EmuState) -> bool {
+        let mut open = true;
+
+        ui.window(im_str!("Timer"))
+            .size((300.0, 220.0), ImGuiCond::FirstUseEver)
+            .position((440.0, 30.0), ImGuiCond::FirstUseEver)
+            .resizable(false)
+            .opened(&mut open)
+            .build(|| {
+                self.draw_counter(ui, state);
+
+                ui.separator();
+
+                self.draw_registers(ui, state);
+
+                ui.separator();
+
+                self.draw_overflows(ui, state);
+            });
+
+        open
+    }
+}
@@ -0,0 +1,95 @@
+//! Runs Blargg's test ROMs headlessly, checking the results they print over the link port.
+//! Newer ROMs which only report to cartridge RAM (eg. `mem_timing-2`) are supported too.
+//!
+//! The ROMs are not part of the repository, so the test is ignored by default: point
+//! `GIB_BLARGG_ROMS` to a directory containing them (eg. the individual `cpu_instrs` ROMs)
+//! and run it with `cargo test --test blargg_serial -- --ignored`.
+
+use gib_core::mem::MemR;
+use gib_core::GameBoy;
+
+use std::fs;
+use std::path::Path;
+
+/// Maximum number of frames a single ROM is allowed to run for (~2 minutes).
+const FRAME_BUDGET: usize = 60 * 120;
+
+#[test]
+#[ignore]
+fn passes_blargg_serial_output() {
+    let dir = std::env::var("GIB_BLARGG_ROMS").expect("GIB_BLARGG_ROMS is not set");
+    assert!(Path::new(&dir).is_dir(), "GIB_BLARGG_ROMS is not a directory: {}", dir);
+
+    let mut roms: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension() == Some("gb".as_ref()))
+        .collect();
+    roms.sort();
+
+    let mut failed = vec![];
+
+    for rom in roms.iter() {
+        let name = rom.file_name().unwrap().to_string_lossy();
+        let output = run_rom(rom);
+
+        if output.contains("Passed") {
+            println!("{:<32} passed", name);
+        } else {
+            println!("{:<32} FAILED\n{}", name, output.trim());
+            failed.push(name.into_owned());
+        }
+    }
+
+    if !failed.is_empty() {
+        panic!("failing ROMs: {}", failed.join(", "));
+    }
+}
+
+/// Runs the ROM until it reports its result over serial, or the frame budget runs out.
+fn run_rom(path: &Path) -> String {
+    let mut gb = GameBoy::new();
+
+    gb.load_rom(&fs::read(path).unwrap()).unwrap();
+
+    for _ in 0..FRAME_BUDGET {
+        if let Err(e) = gb.run_for_vblank() {
+            return format!("emulation error: {}", e);
+        }
+
+        let output = String::from_utf8_lossy(gb.serial_output());
+
+        if output.contains("Passed") || output.contains("Failed") {
+            return output.into_owned();
+        }
+        if let Some(output) = cart_ram_output(&gb) {
+            return output;
+        }
+    }
+
+    format!(
+        "{}\n(no result after {} frames)",
+        String::from_utf8_lossy(gb.serial_output()),
+        FRAME_BUDGET
+    )
+}
+
+/// Returns the text reported in cartridge RAM, once the test has completed.
+///
+/// The signature $DE,$B0,$61 at $A001 marks valid output, while $A000 holds the
+/// test status ($80 while running). The text itself starts at $A004.
+fn cart_ram_output(gb: &GameBoy) -> Option<String> {
+    let bus = gb.bus();
+    let read = |addr| bus.read(addr).unwrap_or(0);
+
+    if [read(0xA001), read(0xA002), read(0xA003)] != [0xDE, 0xB0, 0x61] || read(0xA000) == 0x80 {
+        return None;
+    }
+
+    let text: Vec<u8> = (0xA004..0xC000)
+        .map(read)
+        .take_while(|&b| b != 0)
+        .collect();
+
+    Some(String::from_utf8_lossy(&text).into_owned())
+}
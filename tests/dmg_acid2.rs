@@ -0,0 +1,63 @@
+//! Runs Matt Currie's dmg-acid2 headlessly, comparing the rendered frame with the reference.
+//!
+//! The ROM is not part of the repository, so the test is ignored by default: point
+//! `GIB_DMG_ACID2` to a directory containing `dmg-acid2.gb`, along with `dmg-acid2.bin`,
+//! the reference image dumped as 160x144 RGBA pixels with the default grayscale palette,
+//! and run it with `cargo test --test dmg_acid2 -- --ignored`.
+//! When done, the ROM executes `LD B,B`.
+
+use gib_core::GameBoy;
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Maximum number of cycles the ROM is allowed to run for (~5 seconds).
+const CYCLE_BUDGET: u64 = 5 * 4_194_304;
+
+/// Number of cycles in a video frame.
+const CYCLES_PER_FRAME: u64 = 70_224;
+
+/// Returns the FNV-1a hash of a frame, to keep mismatches easy to compare.
+fn frame_hash(frame: &[u8]) -> u64 {
+    frame.iter().fold(0xCBF2_9CE4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[test]
+#[ignore]
+fn passes_dmg_acid2() {
+    let dir = PathBuf::from(std::env::var("GIB_DMG_ACID2").expect("GIB_DMG_ACID2 is not set"));
+    assert!(dir.is_dir(), "GIB_DMG_ACID2 is not a directory: {}", dir.display());
+
+    let rom = fs::read(dir.join("dmg-acid2.gb")).unwrap();
+    let reference = fs::read(dir.join("dmg-acid2.bin")).unwrap();
+
+    let mut gb = GameBoy::new();
+    gb.load_rom(&rom).unwrap();
+
+    while gb.clock_cycles() < CYCLE_BUDGET {
+        gb.step().unwrap();
+
+        let cpu = gb.cpu();
+        if cpu.opcode == 0x40 && !cpu.cb_mode {
+            break;
+        }
+    }
+    assert!(gb.clock_cycles() < CYCLE_BUDGET, "dmg-acid2 timed out");
+
+    // Let the last frame be drawn completely
+    let until = gb.clock_cycles() + CYCLES_PER_FRAME;
+    while gb.clock_cycles() < until {
+        gb.step().unwrap();
+    }
+
+    let mut vbuf = vec![0xFF; 160 * 144 * 4];
+    gb.rasterize(&mut vbuf[..]);
+
+    assert_eq!(
+        frame_hash(&vbuf),
+        frame_hash(&reference),
+        "rendered frame differs from the reference"
+    );
+}
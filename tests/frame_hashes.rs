@@ -0,0 +1,130 @@
+//! Runs some test ROMs for a fixed number of frames, comparing hashes of the last frame and
+//! of the whole audio stream with the golden values stored in `tests/frame_hashes`.
+//! Unlike the pass/fail tests, these catch any change in rendering or timing, even if the
+//! ROM output stays correct, so that they are made on purpose.
+//!
+//! A missing golden value is a failure. After an intended change, or when adding a ROM,
+//! run the tests with `GIB_BLESS_HASHES=1` to record the new values.
+
+use gib_core::GameBoy;
+
+use crossbeam::queue::ArrayQueue;
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Sample rate of the hashed audio stream.
+const SAMPLE_RATE: f32 = 48_000.0;
+
+/// Incremental FNV-1a hash.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xCBF2_9CE4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+}
+
+/// Runs `rom` for `frames` video frames from power-on and checks the hashes of its
+/// output against the golden ones named after `name`.
+fn check_hashes(name: &str, rom: &[u8], frames: usize) {
+    let sink = Arc::new(ArrayQueue::new(8192));
+
+    let mut gb = GameBoy::new();
+    gb.load_rom(rom).unwrap();
+    gb.set_audio_sink(sink.clone(), SAMPLE_RATE);
+
+    let mut audio = Fnv::new();
+
+    for _ in 0..frames {
+        gb.run_for_vblank().unwrap();
+
+        while let Ok(s) = sink.pop() {
+            audio.write(&[s as u8, (s >> 8) as u8]);
+        }
+    }
+
+    let mut video = Fnv::new();
+    video.write(&gb.screenshot());
+
+    let hashes = format!("video = {:016X}\naudio = {:016X}\n", video.0, audio.0);
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/frame_hashes")
+        .join(name)
+        .with_extension("txt");
+
+    if std::env::var_os("GIB_BLESS_HASHES").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &hashes).unwrap();
+        eprintln!("recorded the hashes of {} in {}", name, path.display());
+        return;
+    }
+
+    let golden = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "no golden hashes for {} in {} ({}), run with GIB_BLESS_HASHES=1 to record them",
+            name,
+            path.display(),
+            e
+        )
+    });
+
+    assert_eq!(
+        hashes, golden,
+        "{} diverged after {} frames, run with GIB_BLESS_HASHES=1 if intended",
+        name, frames
+    );
+}
+
+#[test]
+fn blargg_cpu_instrs_hashes_match() {
+    check_hashes(
+        "blargg_cpu_instrs",
+        include_bytes!("../roms/blargg/cpu_instrs.gb"),
+        600,
+    );
+}
+
+#[test]
+fn blargg_instr_timing_hashes_match() {
+    check_hashes(
+        "blargg_instr_timing",
+        include_bytes!("../roms/blargg/instr_timing.gb"),
+        60,
+    );
+}
+
+#[test]
+fn blargg_mem_timing_hashes_match() {
+    check_hashes(
+        "blargg_mem_timing",
+        include_bytes!("../roms/blargg/mem_timing.gb"),
+        120,
+    );
+}
+
+#[test]
+fn gekkio_oam_dma_timing_hashes_match() {
+    check_hashes(
+        "gekkio_oam_dma_timing",
+        include_bytes!("../roms/gekkio/acceptance/oam_dma_timing.gb"),
+        60,
+    );
+}
+
+#[test]
+fn gekkio_intr_timing_hashes_match() {
+    check_hashes(
+        "gekkio_intr_timing",
+        include_bytes!("../roms/gekkio/acceptance/intr_timing.gb"),
+        60,
+    );
+}
@@ -0,0 +1,107 @@
+//! Runs mooneye-gb's test ROMs headlessly, reporting the results as a table.
+//!
+//! The ROMs are not part of the repository, so their test is ignored by default: point
+//! `GIB_MOONEYE_ROMS` to a directory containing them (eg. `acceptance/`, subdirectories are
+//! searched too) and run it with `cargo test --test mooneye -- --ignored`.
+//! The timer ROMs are shipped in `roms/gekkio/acceptance/timer` and always run.
+//! When a test completes, it executes `LD B,B` with the Fibonacci sequence
+//! 3/5/8/13/21/34 in B/C/D/E/H/L on success, or 0x42 in all of them on failure.
+
+use gib_core::GameBoy;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of cycles a single ROM is allowed to run for (~20 seconds).
+const CYCLE_BUDGET: u64 = 20 * 4_194_304;
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Pass,
+    Fail,
+    Timeout,
+    Error(String),
+}
+
+#[test]
+#[ignore]
+fn passes_mooneye_test_roms() {
+    let dir = std::env::var("GIB_MOONEYE_ROMS").expect("GIB_MOONEYE_ROMS is not set");
+    let dir = PathBuf::from(dir);
+    assert!(dir.is_dir(), "GIB_MOONEYE_ROMS is not a directory: {}", dir.display());
+
+    run_suite(&dir);
+}
+
+/// The timer tests are part of the repository, so they always run.
+#[test]
+fn passes_mooneye_timer_roms() {
+    run_suite(&Path::new(env!("CARGO_MANIFEST_DIR")).join("roms/gekkio/acceptance/timer"));
+}
+
+/// Runs all the ROMs found in `dir`, panicking if any of them does not pass.
+fn run_suite(dir: &Path) {
+    let mut roms = vec![];
+    find_roms(dir, &mut roms);
+    roms.sort();
+
+    let mut passed = 0;
+
+    println!("{:<48} result", "ROM");
+
+    for rom in roms.iter() {
+        let outcome = run_rom(rom);
+        let name = rom.strip_prefix(dir).unwrap_or(rom).display();
+
+        println!("{:<48} {:?}", name, outcome);
+
+        if outcome == Outcome::Pass {
+            passed += 1;
+        }
+    }
+
+    println!("{}/{} passed", passed, roms.len());
+
+    if passed != roms.len() {
+        panic!("{} mooneye-gb ROMs failing", roms.len() - passed);
+    }
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    for path in fs::read_dir(dir).unwrap().filter_map(|e| e.ok().map(|e| e.path())) {
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension() == Some("gb".as_ref()) {
+            roms.push(path);
+        }
+    }
+}
+
+/// Runs the ROM until it hits the `LD B,B` breakpoint, or the cycle budget runs out.
+fn run_rom(path: &Path) -> Outcome {
+    let mut gb = GameBoy::new();
+
+    if let Err(e) = gb.load_rom(&fs::read(path).unwrap()) {
+        return Outcome::Error(e.to_string());
+    }
+
+    while gb.clock_cycles() < CYCLE_BUDGET {
+        if let Err(e) = gb.step() {
+            return Outcome::Error(e.to_string());
+        }
+
+        let cpu = gb.cpu();
+
+        if cpu.opcode == 0x40 && !cpu.cb_mode {
+            let regs = [cpu.b(), cpu.c(), cpu.d(), cpu.e(), cpu.h(), cpu.l()];
+
+            return if regs == [3, 5, 8, 13, 21, 34] {
+                Outcome::Pass
+            } else {
+                Outcome::Fail
+            };
+        }
+    }
+
+    Outcome::Timeout
+}
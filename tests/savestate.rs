@@ -0,0 +1,118 @@
+//! Checks that savestates restore the whole machine, by comparing the state reached
+//! after running from a restored state with the one reached without interruptions.
+//! Movies are checked the same way, as they are replayed from a savestate.
+
+use gib_core::io::JoypadState;
+use gib_core::{GameBoy, HardwareConfig, Model, Movie};
+
+use std::fs;
+use std::path::Path;
+
+/// Number of cycles in a video frame.
+const CYCLES_PER_FRAME: u64 = 70_224;
+
+fn load_rom(name: &str) -> Vec<u8> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("roms/gekkio/acceptance");
+    fs::read(dir.join(name)).unwrap()
+}
+
+fn run_frames(gb: &mut GameBoy, frames: u64) {
+    let until = gb.clock_cycles() + frames * CYCLES_PER_FRAME;
+
+    while gb.clock_cycles() < until {
+        gb.step().unwrap();
+    }
+}
+
+#[test]
+fn restored_states_run_identically() {
+    let mut gb = GameBoy::new();
+    gb.load_rom(&load_rom("instr/daa.gb")).unwrap();
+
+    run_frames(&mut gb, 10);
+    let saved = gb.save_state();
+
+    run_frames(&mut gb, 10);
+    let expected = gb.save_state();
+
+    gb.load_state(&saved).unwrap();
+    assert_eq!(gb.save_state(), saved);
+
+    run_frames(&mut gb, 10);
+    assert_eq!(gb.save_state(), expected);
+}
+
+#[test]
+fn mismatching_states_are_rejected() {
+    let mut gb = GameBoy::new();
+    gb.load_rom(&load_rom("instr/daa.gb")).unwrap();
+
+    let state = gb.save_state();
+
+    assert!(gb.load_state(&state[..state.len() - 1]).is_err());
+    assert!(gb.load_state(b"not a savestate").is_err());
+
+    let mut other = GameBoy::new();
+    other.load_rom(&load_rom("timer/tim00.gb")).unwrap();
+    assert!(other.load_state(&state).is_err());
+
+    let mut cgb = GameBoy::with_config(HardwareConfig::new(Model::Cgb)).unwrap();
+    cgb.load_rom(&load_rom("instr/daa.gb")).unwrap();
+    assert!(cgb.load_state(&state).is_err());
+}
+
+#[test]
+fn rejected_states_leave_the_machine_untouched() {
+    let mut gb = GameBoy::new();
+    gb.load_rom(&load_rom("instr/daa.gb")).unwrap();
+
+    let state = gb.save_state();
+    run_frames(&mut gb, 10);
+    let current = gb.save_state();
+
+    // Everything but the last field is loaded before these are found to be corrupted
+    assert!(gb.load_state(&state[..state.len() - 1]).is_err());
+    assert_eq!(gb.save_state(), current);
+
+    let mut trailing = state.clone();
+    trailing.push(0x00);
+    assert!(gb.load_state(&trailing).is_err());
+    assert_eq!(gb.save_state(), current);
+}
+
+#[test]
+fn movies_replay_identically() {
+    let mut gb = GameBoy::new();
+    gb.load_rom(&load_rom("instr/daa.gb")).unwrap();
+    run_frames(&mut gb, 5);
+
+    gb.start_recording();
+    run_frames(&mut gb, 5);
+    gb.press_key(JoypadState::A);
+    run_frames(&mut gb, 5);
+    gb.release_key(JoypadState::A);
+    run_frames(&mut gb, 5);
+
+    let end = gb.clock_cycles();
+    let expected = gb.save_state();
+    let movie = gb.stop_movie().unwrap();
+
+    assert!(movie.frames() >= 15);
+    assert_eq!(movie.input(2), Some(JoypadState::DEFAULT));
+    assert_eq!(movie.input(8), Some(!JoypadState::A));
+    assert_eq!(movie.input(13), Some(JoypadState::DEFAULT));
+
+    let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+    gb.start_playback(movie).unwrap();
+
+    // Keys pressed during playback are ignored
+    gb.press_key(JoypadState::B);
+
+    while gb.clock_cycles() < end {
+        gb.step().unwrap();
+    }
+    assert_eq!(gb.save_state(), expected);
+
+    run_frames(&mut gb, 1);
+    assert!(gb.movie_status().is_none());
+}
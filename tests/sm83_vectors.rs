@@ -0,0 +1,408 @@
+//! Runs the community single-instruction SM83 test vectors against the CPU.
+//!
+//! The vectors are not part of the repository, so the test is ignored by default: point
+//! `GIB_SM83_TESTS` to a directory containing the `*.json` files (one per opcode, eg.
+//! `00.json` or `cb 7e.json`) and run it with `cargo test --test sm83_vectors -- --ignored`.
+//! Each test describes the initial CPU/RAM state, the expected final state and the bus
+//! activity for every machine cycle of the instruction.
+
+use gib_core::cpu::{CpuState, CPU};
+use gib_core::dbg;
+use gib_core::mem::{MemR, MemRW, MemW};
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+
+/// Flat 64KB memory that records every access performed through it.
+struct TestBus {
+    mem: Vec<u8>,
+    log: RefCell<Vec<(u16, u8, bool)>>,
+}
+
+impl MemR for TestBus {
+    fn read(&self, addr: u16) -> Result<u8, dbg::TraceEvent> {
+        let v = self.mem[addr as usize];
+        self.log.borrow_mut().push((addr, v, false));
+        Ok(v)
+    }
+}
+
+impl MemW for TestBus {
+    fn write(&mut self, addr: u16, val: u8) -> Result<(), dbg::TraceEvent> {
+        self.mem[addr as usize] = val;
+        self.log.borrow_mut().push((addr, val, true));
+        Ok(())
+    }
+}
+
+impl MemRW for TestBus {}
+
+#[test]
+#[ignore]
+fn passes_sm83_test_vectors() {
+    let dir = std::env::var("GIB_SM83_TESTS").expect("GIB_SM83_TESTS is not set");
+    assert!(Path::new(&dir).is_dir(), "GIB_SM83_TESTS is not a directory: {}", dir);
+
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension() == Some("json".as_ref()))
+        .collect();
+    files.sort();
+
+    let mut failed_opcodes = vec![];
+
+    println!("{:<10} {:>6} {:>6}  first failure", "opcode", "pass", "total");
+
+    for path in files.iter() {
+        let (passed, total, first_failure) = run_vector_file(path);
+        let name = path.file_stem().unwrap().to_string_lossy();
+
+        println!(
+            "{:<10} {:>6} {:>6}  {}",
+            name,
+            passed,
+            total,
+            first_failure.unwrap_or_default()
+        );
+
+        if passed != total {
+            failed_opcodes.push(name.into_owned());
+        }
+    }
+
+    if !failed_opcodes.is_empty() {
+        panic!("failing opcodes: {}", failed_opcodes.join(", "));
+    }
+}
+
+fn run_vector_file(path: &Path) -> (usize, usize, Option<String>) {
+    let text = fs::read_to_string(path).unwrap();
+    let tests = json::parse(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+    let mut passed = 0;
+    let mut first_failure = None;
+
+    for test in tests.as_array() {
+        match run_vector(test) {
+            Ok(()) => passed += 1,
+            Err(e) => {
+                if first_failure.is_none() {
+                    first_failure = Some(format!("{}: {}", test["name"].as_str(), e));
+                }
+            }
+        }
+    }
+
+    (passed, tests.as_array().len(), first_failure)
+}
+
+fn run_vector(test: &json::Value) -> Result<(), String> {
+    let initial = &test["initial"];
+    let expected = &test["final"];
+
+    let mut cpu = CPU::new();
+    let mut bus = TestBus {
+        mem: vec![0; 0x10000],
+        log: RefCell::new(vec![]),
+    };
+
+    cpu.af = reg_pair(initial, "a", "f");
+    cpu.bc = reg_pair(initial, "b", "c");
+    cpu.de = reg_pair(initial, "d", "e");
+    cpu.hl = reg_pair(initial, "h", "l");
+    cpu.sp = initial["sp"].as_u16();
+    cpu.pc = initial["pc"].as_u16();
+    cpu.intr_enabled.reset(initial["ime"].as_u16() != 0);
+
+    for entry in initial["ram"].as_array() {
+        let entry = entry.as_array();
+        bus.mem[entry[0].as_u16() as usize] = entry[1].as_u16() as u8;
+    }
+
+    // Run a whole instruction, one machine cycle at a time
+    let mut accesses = vec![];
+    let mut cycles = 0;
+
+    loop {
+        cpu.tick(&mut bus).map_err(|e| e.to_string())?;
+        cycles += 1;
+
+        accesses.extend(bus.log.borrow_mut().drain(..));
+
+        if (!cpu.executing && cpu.state == CpuState::FetchOpcode) || cycles > 8 {
+            break;
+        }
+    }
+
+    // Compare the final state
+    let regs = [
+        ("AF", cpu.af, reg_pair(expected, "a", "f")),
+        ("BC", cpu.bc, reg_pair(expected, "b", "c")),
+        ("DE", cpu.de, reg_pair(expected, "d", "e")),
+        ("HL", cpu.hl, reg_pair(expected, "h", "l")),
+        ("SP", cpu.sp, expected["sp"].as_u16()),
+        ("PC", cpu.pc, expected["pc"].as_u16()),
+    ];
+
+    for (name, actual, expected) in regs.iter() {
+        if actual != expected {
+            return Err(format!("{} = {:04X}, expected {:04X}", name, actual, expected));
+        }
+    }
+
+    if *cpu.intr_enabled.loaded() != (expected["ime"].as_u16() != 0) {
+        return Err(format!("IME = {}", cpu.intr_enabled.loaded()));
+    }
+
+    for entry in expected["ram"].as_array() {
+        let entry = entry.as_array();
+        let (addr, val) = (entry[0].as_u16(), entry[1].as_u16() as u8);
+
+        if bus.mem[addr as usize] != val {
+            return Err(format!(
+                "(${:04X}) = {:02X}, expected {:02X}",
+                addr, bus.mem[addr as usize], val
+            ));
+        }
+    }
+
+    // Compare the bus activity, ignoring internal cycles
+    let expected_cycles = test["cycles"].as_array();
+    let expected_accesses: Vec<_> = expected_cycles
+        .iter()
+        .filter_map(|c| match c.as_array() {
+            [_, json::Value::Null, _] => None,
+            [addr, v, kind] if kind.as_str().contains('r') => {
+                Some((addr.as_u16(), v.as_u16() as u8, false))
+            }
+            [addr, v, kind] if kind.as_str().contains('w') => {
+                Some((addr.as_u16(), v.as_u16() as u8, true))
+            }
+            _ => None,
+        })
+        .collect();
+
+    if cycles != expected_cycles.len() {
+        return Err(format!("took {} cycles, expected {}", cycles, expected_cycles.len()));
+    }
+
+    if accesses != expected_accesses {
+        return Err(format!(
+            "bus activity {:X?}, expected {:X?}",
+            accesses, expected_accesses
+        ));
+    }
+
+    Ok(())
+}
+
+fn reg_pair(state: &json::Value, hi: &str, lo: &str) -> u16 {
+    (state[hi].as_u16() << 8) | state[lo].as_u16()
+}
+
+/// Minimal JSON reader, just enough for the test vector files.
+mod json {
+    use std::ops::Index;
+
+    #[derive(Debug, PartialEq)]
+    pub enum Value {
+        Null,
+        Bool(bool),
+        Number(f64),
+        String(String),
+        Array(Vec<Value>),
+        Object(Vec<(String, Value)>),
+    }
+
+    static NULL: Value = Value::Null;
+
+    impl Value {
+        pub fn as_u16(&self) -> u16 {
+            match *self {
+                Value::Number(n) => n as u16,
+                Value::Bool(b) => b as u16,
+                _ => panic!("not a number: {:?}", self),
+            }
+        }
+
+        pub fn as_str(&self) -> &str {
+            match *self {
+                Value::String(ref s) => s,
+                _ => "",
+            }
+        }
+
+        pub fn as_array(&self) -> &[Value] {
+            match *self {
+                Value::Array(ref v) => v,
+                _ => &[],
+            }
+        }
+    }
+
+    impl Index<&str> for Value {
+        type Output = Value;
+
+        fn index(&self, key: &str) -> &Value {
+            match *self {
+                Value::Object(ref fields) => fields
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map_or(&NULL, |(_, v)| v),
+                _ => &NULL,
+            }
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Value, String> {
+        let mut p = Parser {
+            s: text.as_bytes(),
+            pos: 0,
+        };
+
+        let v = p.value()?;
+        p.skip_ws();
+
+        if p.pos != p.s.len() {
+            return Err(format!("trailing characters at {}", p.pos));
+        }
+        Ok(v)
+    }
+
+    struct Parser<'a> {
+        s: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> Parser<'a> {
+        fn skip_ws(&mut self) {
+            while self.pos < self.s.len() && (self.s[self.pos] as char).is_whitespace() {
+                self.pos += 1;
+            }
+        }
+
+        fn peek(&mut self) -> Option<u8> {
+            self.skip_ws();
+            self.s.get(self.pos).cloned()
+        }
+
+        fn expect(&mut self, c: u8) -> Result<(), String> {
+            if self.peek() == Some(c) {
+                self.pos += 1;
+                Ok(())
+            } else {
+                Err(format!("expected '{}' at {}", c as char, self.pos))
+            }
+        }
+
+        fn literal(&mut self, lit: &str, v: Value) -> Result<Value, String> {
+            if self.s[self.pos..].starts_with(lit.as_bytes()) {
+                self.pos += lit.len();
+                Ok(v)
+            } else {
+                Err(format!("invalid literal at {}", self.pos))
+            }
+        }
+
+        fn value(&mut self) -> Result<Value, String> {
+            match self.peek() {
+                Some(b'{') => self.object(),
+                Some(b'[') => self.array(),
+                Some(b'"') => self.string().map(Value::String),
+                Some(b'n') => self.literal("null", Value::Null),
+                Some(b't') => self.literal("true", Value::Bool(true)),
+                Some(b'f') => self.literal("false", Value::Bool(false)),
+                Some(_) => self.number(),
+                None => Err("unexpected end of input".into()),
+            }
+        }
+
+        fn object(&mut self) -> Result<Value, String> {
+            let mut fields = vec![];
+
+            self.expect(b'{')?;
+            if self.peek() == Some(b'}') {
+                self.pos += 1;
+                return Ok(Value::Object(fields));
+            }
+
+            loop {
+                let key = self.string()?;
+                self.expect(b':')?;
+                fields.push((key, self.value()?));
+
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    _ => break,
+                }
+            }
+
+            self.expect(b'}')?;
+            Ok(Value::Object(fields))
+        }
+
+        fn array(&mut self) -> Result<Value, String> {
+            let mut items = vec![];
+
+            self.expect(b'[')?;
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+
+            loop {
+                items.push(self.value()?);
+
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    _ => break,
+                }
+            }
+
+            self.expect(b']')?;
+            Ok(Value::Array(items))
+        }
+
+        fn string(&mut self) -> Result<String, String> {
+            self.expect(b'"')?;
+
+            let mut out = String::new();
+
+            while let Some(&c) = self.s.get(self.pos) {
+                self.pos += 1;
+
+                match c {
+                    b'"' => return Ok(out),
+                    b'\\' => {
+                        let e = self.s.get(self.pos).cloned().unwrap_or(b'\\');
+                        self.pos += 1;
+                        out.push(match e {
+                            b'n' => '\n',
+                            b't' => '\t',
+                            e => e as char,
+                        });
+                    }
+                    c => out.push(c as char),
+                }
+            }
+
+            Err("unterminated string".into())
+        }
+
+        fn number(&mut self) -> Result<Value, String> {
+            let start = self.pos;
+
+            while self.pos < self.s.len() && b"+-.eE0123456789".contains(&self.s[self.pos]) {
+                self.pos += 1;
+            }
+
+            std::str::from_utf8(&self.s[start..self.pos])
+                .ok()
+                .and_then(|n| n.parse().ok())
+                .map(Value::Number)
+                .ok_or_else(|| format!("invalid number at {}", start))
+        }
+    }
+}